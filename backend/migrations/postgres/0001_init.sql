@@ -0,0 +1,445 @@
+-- PostgreSQL schema, equivalent to SQLite migrations 0001 to 0019 with
+-- native types: UUID ids, TIMESTAMPTZ instants, BIGINT amounts and counts,
+-- BOOLEAN flags and BYTEA bodies. JSON documents stay TEXT, as in SQLite.
+
+CREATE TABLE products (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    price_cents BIGINT NOT NULL,
+    cost_price_cents BIGINT NOT NULL DEFAULT 0,
+    stock BIGINT NOT NULL,
+    product_type TEXT NOT NULL,
+    reorder_point BIGINT NOT NULL DEFAULT 0,
+    reorder_quantity BIGINT NOT NULL DEFAULT 0,
+    -- Deleting a product, customer or staff member archives it instead, so
+    -- sales and reports can still resolve the id
+    archived_at TIMESTAMPTZ
+);
+
+CREATE TABLE product_details (
+    id UUID PRIMARY KEY,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    detail_name TEXT NOT NULL,
+    detail_value TEXT NOT NULL
+);
+
+CREATE INDEX idx_product_details_product ON product_details (product_id);
+
+CREATE TABLE customers (
+    id UUID PRIMARY KEY,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    middle_name TEXT,
+    mobile_number TEXT NOT NULL,
+    date_of_birth DATE NOT NULL,
+    email TEXT NOT NULL,
+    archived_at TIMESTAMPTZ
+);
+
+CREATE TABLE customer_details (
+    id UUID PRIMARY KEY,
+    customer_id UUID NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    detail_name TEXT NOT NULL,
+    detail_value TEXT NOT NULL
+);
+
+CREATE INDEX idx_customer_details_customer ON customer_details (customer_id);
+
+CREATE TABLE staff (
+    id UUID PRIMARY KEY,
+    staff_id TEXT UNIQUE NOT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    mobile_number TEXT NOT NULL,
+    photo_link TEXT NOT NULL,
+    username TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    archived_at TIMESTAMPTZ
+);
+
+INSERT INTO staff (id, staff_id, first_name, last_name, mobile_number, photo_link, username, password_hash)
+VALUES (
+    '550e8400-e29b-41d4-a716-446655440000',
+    'staff-0001',
+    'Default',
+    'Admin',
+    '0000000000',
+    '',
+    'admin',
+    '$argon2id$v=19$m=19456,t=2,p=1$oCHxIpzb3utwH9Su3ATNJg$vRV/UtfIN3f6iKEHrgKgQhoJXM5CoTIFXf9wD9uvrSE'
+)
+ON CONFLICT DO NOTHING;
+
+-- Till sessions. A register, and a staff member, has at most one open shift.
+CREATE TABLE shifts (
+    id UUID PRIMARY KEY,
+    register TEXT NOT NULL,
+    company_branch TEXT NOT NULL DEFAULT '',
+    opened_by UUID NOT NULL,
+    opened_at TIMESTAMPTZ NOT NULL,
+    opening_float_cents BIGINT NOT NULL CHECK (opening_float_cents >= 0),
+    closed_by UUID,
+    closed_at TIMESTAMPTZ,
+    counted_cash_cents BIGINT,
+    note TEXT,
+    -- JSON-encoded ShiftReport, set when the shift closes
+    z_report TEXT
+);
+
+CREATE UNIQUE INDEX idx_shifts_open_register ON shifts (company_branch, register) WHERE closed_at IS NULL;
+CREATE UNIQUE INDEX idx_shifts_open_staff ON shifts (opened_by) WHERE closed_at IS NULL;
+CREATE INDEX idx_shifts_opened_at ON shifts (opened_at);
+
+CREATE TABLE cash_movements (
+    id UUID PRIMARY KEY,
+    shift_id UUID NOT NULL REFERENCES shifts(id),
+    kind TEXT NOT NULL CHECK (kind IN ('cash_in', 'payout', 'drop')),
+    amount_cents BIGINT NOT NULL CHECK (amount_cents > 0),
+    reason TEXT NOT NULL,
+    recorded_by UUID NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_cash_movements_shift ON cash_movements (shift_id, recorded_at);
+
+CREATE TABLE sales (
+    id UUID PRIMARY KEY,
+    customer_id UUID REFERENCES customers(id),
+    date_and_time TIMESTAMPTZ NOT NULL,
+    total_cents BIGINT NOT NULL,
+    discount BIGINT NOT NULL,
+    total_resolved BIGINT NOT NULL,
+    sales_channel TEXT NOT NULL,
+    staff_responsible UUID NOT NULL,
+    company_branch TEXT NOT NULL,
+    car_number TEXT NOT NULL,
+    receipt_number TEXT NOT NULL,
+    payment_method TEXT NOT NULL DEFAULT 'cash',
+    shift_id UUID REFERENCES shifts(id)
+);
+
+CREATE INDEX idx_sales_date_and_time ON sales (date_and_time);
+CREATE INDEX idx_sales_staff ON sales (staff_responsible, date_and_time);
+CREATE INDEX idx_sales_shift ON sales (shift_id);
+
+CREATE TABLE sale_items (
+    id UUID PRIMARY KEY,
+    sale_id UUID REFERENCES sales(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES products(id),
+    customer_id UUID REFERENCES customers(id) ON DELETE SET NULL,
+    date_of_sale TIMESTAMPTZ NOT NULL,
+    quantity BIGINT NOT NULL,
+    discount BIGINT NOT NULL,
+    total_cents BIGINT NOT NULL,
+    total_resolved BIGINT NOT NULL,
+    note TEXT,
+    -- Snapshots taken when the item was sold
+    product_name TEXT,
+    price_per_item BIGINT,
+    cost_per_item BIGINT
+);
+
+CREATE INDEX idx_sale_items_sale_id ON sale_items (sale_id);
+CREATE INDEX idx_sale_items_product_id ON sale_items (product_id);
+CREATE INDEX idx_sale_items_date_of_sale ON sale_items (date_of_sale);
+
+CREATE TABLE voided_sale_items (
+    -- Id the item had in sale_items
+    id UUID PRIMARY KEY,
+    sale_id UUID REFERENCES sales(id),
+    product_id UUID NOT NULL,
+    date_of_sale TIMESTAMPTZ NOT NULL,
+    quantity BIGINT NOT NULL,
+    total_resolved BIGINT NOT NULL,
+    voided_by UUID NOT NULL,
+    voided_at TIMESTAMPTZ NOT NULL,
+    shift_id UUID REFERENCES shifts(id)
+);
+
+CREATE INDEX idx_voided_sale_items_sale ON voided_sale_items (sale_id, voided_at);
+CREATE INDEX idx_voided_sale_items_shift ON voided_sale_items (shift_id);
+
+CREATE TABLE stock_alerts (
+    id UUID PRIMARY KEY,
+    product_id UUID NOT NULL,
+    product_name TEXT NOT NULL,
+    stock BIGINT NOT NULL,
+    reorder_point BIGINT NOT NULL,
+    reorder_quantity BIGINT NOT NULL,
+    sale_id UUID,
+    created_at TIMESTAMPTZ NOT NULL,
+    acknowledged BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+-- Every price a product has had or is scheduled to have. products.price_cents
+-- keeps the price currently in effect; rows with a future effective_from are
+-- copied onto it once they come due.
+CREATE TABLE product_prices (
+    id UUID PRIMARY KEY,
+    product_id UUID NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    price_cents BIGINT NOT NULL,
+    effective_from TIMESTAMPTZ NOT NULL,
+    changed_by UUID,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_product_prices_product_effective ON product_prices (product_id, effective_from);
+
+-- Responses to POST requests sent with an Idempotency-Key header, replayed
+-- when a client retries the same request. Keys are scoped to the token
+-- subject that sent them; status_code stays NULL while the first request is
+-- running.
+CREATE TABLE idempotency_keys (
+    staff_id TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    status_code BIGINT,
+    content_type TEXT,
+    response_body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (staff_id, idempotency_key)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys (created_at);
+
+CREATE TABLE webhooks (
+    id UUID PRIMARY KEY,
+    url TEXT NOT NULL,
+    description TEXT,
+    -- Comma-separated event types, e.g. 'sale.created,stock.low'
+    event_types TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE webhook_events (
+    id UUID PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    -- Set once deliveries have been created for every matching webhook
+    dispatched_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_webhook_events_undispatched ON webhook_events (created_at) WHERE dispatched_at IS NULL;
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY,
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL REFERENCES webhook_events(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'succeeded', 'failed')),
+    attempts BIGINT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ,
+    last_attempt_at TIMESTAMPTZ,
+    response_status BIGINT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_webhook_deliveries_due ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX idx_webhook_deliveries_webhook ON webhook_deliveries (webhook_id, created_at);
+
+CREATE TABLE report_subscriptions (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    report_type TEXT NOT NULL CHECK (report_type IN ('daily_takings', 'top_products', 'low_stock')),
+    -- JSON-encoded ReportParameters
+    parameters TEXT NOT NULL DEFAULT '{}',
+    frequency TEXT NOT NULL CHECK (frequency IN ('daily', 'weekly', 'monthly')),
+    -- Hour of the day the report is sent at, 0-23
+    send_hour BIGINT NOT NULL CHECK (send_hour BETWEEN 0 AND 23),
+    -- Comma-separated email addresses
+    recipients TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    last_sent_at TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_report_subscriptions_due ON report_subscriptions (next_run_at) WHERE active;
+
+-- Change log behind the offline sync API. Every write to a product or
+-- customer (or their details) moves the record to a new, higher version;
+-- devices ask for everything changed after the last version they saw.
+-- Records that are gone from their table but still in the log are reported
+-- to devices as deleted.
+CREATE TABLE sync_changes (
+    version BIGSERIAL PRIMARY KEY,
+    entity TEXT NOT NULL CHECK (entity IN ('product', 'customer')),
+    entity_id UUID NOT NULL,
+    UNIQUE (entity, entity_id)
+);
+
+CREATE FUNCTION record_sync_change(change_entity TEXT, change_entity_id UUID) RETURNS VOID AS $$
+BEGIN
+    -- Versions must become visible in the order they are handed out, or a
+    -- device could read version 12 and never see 11 commit. Writers to the
+    -- tracked tables take turns until they commit, as they do in SQLite.
+    PERFORM pg_advisory_xact_lock(hashtext('sync_changes'));
+    INSERT INTO sync_changes (entity, entity_id) VALUES (change_entity, change_entity_id)
+    ON CONFLICT (entity, entity_id) DO UPDATE SET version = nextval(pg_get_serial_sequence('sync_changes', 'version'));
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION sync_record() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM record_sync_change(TG_ARGV[0], OLD.id);
+    ELSE
+        PERFORM record_sync_change(TG_ARGV[0], NEW.id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION sync_product_detail() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM record_sync_change('product', OLD.product_id);
+    ELSE
+        PERFORM record_sync_change('product', NEW.product_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION sync_customer_detail() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM record_sync_change('customer', OLD.customer_id);
+    ELSE
+        PERFORM record_sync_change('customer', NEW.customer_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER products_sync AFTER INSERT OR UPDATE OR DELETE ON products
+    FOR EACH ROW EXECUTE FUNCTION sync_record('product');
+CREATE TRIGGER customers_sync AFTER INSERT OR UPDATE OR DELETE ON customers
+    FOR EACH ROW EXECUTE FUNCTION sync_record('customer');
+CREATE TRIGGER product_details_sync AFTER INSERT OR UPDATE OR DELETE ON product_details
+    FOR EACH ROW EXECUTE FUNCTION sync_product_detail();
+CREATE TRIGGER customer_details_sync AFTER INSERT OR UPDATE OR DELETE ON customer_details
+    FOR EACH ROW EXECUTE FUNCTION sync_customer_detail();
+
+-- Full-text search indexes, kept in sync with their source tables by
+-- triggers. Matching and ranking use `document`, weighted like SQLite's bm25
+-- column weights: names and identifiers over free text.
+
+CREATE TABLE products_fts (
+    product_id UUID PRIMARY KEY REFERENCES products(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    details TEXT,
+    document TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', description), 'C') ||
+        setweight(to_tsvector('simple', COALESCE(details, '')), 'D')
+    ) STORED
+);
+
+CREATE INDEX idx_products_fts_document ON products_fts USING GIN (document);
+
+CREATE TABLE customers_fts (
+    customer_id UUID PRIMARY KEY REFERENCES customers(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    mobile_number TEXT NOT NULL,
+    email TEXT NOT NULL,
+    details TEXT,
+    document TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', mobile_number || ' ' || email), 'B') ||
+        setweight(to_tsvector('simple', COALESCE(details, '')), 'D')
+    ) STORED
+);
+
+CREATE INDEX idx_customers_fts_document ON customers_fts USING GIN (document);
+
+CREATE TABLE sales_fts (
+    sale_id UUID PRIMARY KEY REFERENCES sales(id) ON DELETE CASCADE,
+    receipt_number TEXT NOT NULL,
+    car_number TEXT NOT NULL,
+    document TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', receipt_number), 'A') ||
+        setweight(to_tsvector('simple', car_number), 'B')
+    ) STORED
+);
+
+CREATE INDEX idx_sales_fts_document ON sales_fts USING GIN (document);
+
+CREATE FUNCTION products_fts_refresh() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO products_fts (product_id, name, description, details)
+    VALUES (
+        NEW.id,
+        NEW.name,
+        NEW.description,
+        (SELECT string_agg(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = NEW.id)
+    )
+    ON CONFLICT (product_id) DO UPDATE SET name = EXCLUDED.name, description = EXCLUDED.description;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION product_details_fts_refresh() RETURNS TRIGGER AS $$
+DECLARE
+    changed UUID := CASE WHEN TG_OP = 'DELETE' THEN OLD.product_id ELSE NEW.product_id END;
+BEGIN
+    UPDATE products_fts
+    SET details = (SELECT string_agg(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = changed)
+    WHERE product_id = changed;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION customers_fts_refresh() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO customers_fts (customer_id, name, mobile_number, email, details)
+    VALUES (
+        NEW.id,
+        NEW.first_name || ' ' || COALESCE(NEW.middle_name || ' ', '') || NEW.last_name,
+        NEW.mobile_number,
+        NEW.email,
+        (SELECT string_agg(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = NEW.id)
+    )
+    ON CONFLICT (customer_id) DO UPDATE
+    SET name = EXCLUDED.name, mobile_number = EXCLUDED.mobile_number, email = EXCLUDED.email;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION customer_details_fts_refresh() RETURNS TRIGGER AS $$
+DECLARE
+    changed UUID := CASE WHEN TG_OP = 'DELETE' THEN OLD.customer_id ELSE NEW.customer_id END;
+BEGIN
+    UPDATE customers_fts
+    SET details = (SELECT string_agg(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = changed)
+    WHERE customer_id = changed;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION sales_fts_refresh() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO sales_fts (sale_id, receipt_number, car_number)
+    VALUES (NEW.id, NEW.receipt_number, NEW.car_number)
+    ON CONFLICT (sale_id) DO UPDATE SET receipt_number = EXCLUDED.receipt_number, car_number = EXCLUDED.car_number;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Index rows go away with their source rows through ON DELETE CASCADE.
+CREATE TRIGGER products_fts AFTER INSERT OR UPDATE OF name, description ON products
+    FOR EACH ROW EXECUTE FUNCTION products_fts_refresh();
+CREATE TRIGGER product_details_fts AFTER INSERT OR UPDATE OR DELETE ON product_details
+    FOR EACH ROW EXECUTE FUNCTION product_details_fts_refresh();
+CREATE TRIGGER customers_fts AFTER INSERT OR UPDATE OF first_name, middle_name, last_name, mobile_number, email ON customers
+    FOR EACH ROW EXECUTE FUNCTION customers_fts_refresh();
+CREATE TRIGGER customer_details_fts AFTER INSERT OR UPDATE OR DELETE ON customer_details
+    FOR EACH ROW EXECUTE FUNCTION customer_details_fts_refresh();
+CREATE TRIGGER sales_fts AFTER INSERT OR UPDATE OF receipt_number, car_number ON sales
+    FOR EACH ROW EXECUTE FUNCTION sales_fts_refresh();
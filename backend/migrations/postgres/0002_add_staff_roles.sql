@@ -0,0 +1,6 @@
+-- SQLite migration 0021: staff roles, with the seeded staff member as the
+-- first admin.
+
+ALTER TABLE staff ADD COLUMN role TEXT NOT NULL DEFAULT 'staff' CHECK (role IN ('staff', 'admin'));
+
+UPDATE staff SET role = 'admin' WHERE id = '550e8400-e29b-41d4-a716-446655440000';
@@ -0,0 +1,17 @@
+-- Per-product reorder point (stock level at which to reorder) and reorder quantity.
+-- A reorder point of 0 disables low-stock tracking for the product.
+ALTER TABLE products ADD COLUMN reorder_point INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE products ADD COLUMN reorder_quantity INTEGER NOT NULL DEFAULT 0;
+
+-- Raised when a sale drops a physical good to or below its reorder point.
+CREATE TABLE IF NOT EXISTS stock_alerts (
+    id TEXT PRIMARY KEY NOT NULL,
+    product_id TEXT NOT NULL,
+    product_name TEXT NOT NULL,
+    stock INTEGER NOT NULL,
+    reorder_point INTEGER NOT NULL,
+    reorder_quantity INTEGER NOT NULL,
+    sale_id TEXT,
+    created_at TEXT NOT NULL,
+    acknowledged INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,144 @@
+-- Full-text search indexes, kept in sync with their source tables by triggers.
+-- Each index row carries the source id in an UNINDEXED column so rows can be
+-- looked up without depending on rowids, which VACUUM may renumber.
+
+CREATE VIRTUAL TABLE IF NOT EXISTS products_fts USING fts5(
+    product_id UNINDEXED,
+    name,
+    description,
+    details,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS customers_fts USING fts5(
+    customer_id UNINDEXED,
+    name,
+    mobile_number,
+    email,
+    details,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS sales_fts USING fts5(
+    sale_id UNINDEXED,
+    receipt_number,
+    car_number,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+-- Products
+
+CREATE TRIGGER IF NOT EXISTS products_fts_insert AFTER INSERT ON products BEGIN
+    INSERT INTO products_fts (product_id, name, description, details)
+    VALUES (
+        new.id,
+        new.name,
+        new.description,
+        (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = new.id)
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_fts_update AFTER UPDATE OF name, description ON products BEGIN
+    UPDATE products_fts SET name = new.name, description = new.description WHERE product_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_fts_delete AFTER DELETE ON products BEGIN
+    DELETE FROM products_fts WHERE product_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_fts_insert AFTER INSERT ON product_details BEGIN
+    UPDATE products_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = new.product_id)
+    WHERE product_id = new.product_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_fts_update AFTER UPDATE ON product_details BEGIN
+    UPDATE products_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = new.product_id)
+    WHERE product_id = new.product_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_fts_delete AFTER DELETE ON product_details BEGIN
+    UPDATE products_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = old.product_id)
+    WHERE product_id = old.product_id;
+END;
+
+-- Customers
+
+CREATE TRIGGER IF NOT EXISTS customers_fts_insert AFTER INSERT ON customers BEGIN
+    INSERT INTO customers_fts (customer_id, name, mobile_number, email, details)
+    VALUES (
+        new.id,
+        new.first_name || ' ' || COALESCE(new.middle_name || ' ', '') || new.last_name,
+        new.mobile_number,
+        new.email,
+        (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = new.id)
+    );
+END;
+
+CREATE TRIGGER IF NOT EXISTS customers_fts_update AFTER UPDATE OF first_name, middle_name, last_name, mobile_number, email ON customers BEGIN
+    UPDATE customers_fts
+    SET name = new.first_name || ' ' || COALESCE(new.middle_name || ' ', '') || new.last_name,
+        mobile_number = new.mobile_number,
+        email = new.email
+    WHERE customer_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS customers_fts_delete AFTER DELETE ON customers BEGIN
+    DELETE FROM customers_fts WHERE customer_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_fts_insert AFTER INSERT ON customer_details BEGIN
+    UPDATE customers_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = new.customer_id)
+    WHERE customer_id = new.customer_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_fts_update AFTER UPDATE ON customer_details BEGIN
+    UPDATE customers_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = new.customer_id)
+    WHERE customer_id = new.customer_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_fts_delete AFTER DELETE ON customer_details BEGIN
+    UPDATE customers_fts
+    SET details = (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = old.customer_id)
+    WHERE customer_id = old.customer_id;
+END;
+
+-- Sales
+
+CREATE TRIGGER IF NOT EXISTS sales_fts_insert AFTER INSERT ON sales BEGIN
+    INSERT INTO sales_fts (sale_id, receipt_number, car_number)
+    VALUES (new.id, new.receipt_number, new.car_number);
+END;
+
+CREATE TRIGGER IF NOT EXISTS sales_fts_update AFTER UPDATE OF receipt_number, car_number ON sales BEGIN
+    UPDATE sales_fts SET receipt_number = new.receipt_number, car_number = new.car_number WHERE sale_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS sales_fts_delete AFTER DELETE ON sales BEGIN
+    DELETE FROM sales_fts WHERE sale_id = old.id;
+END;
+
+-- Backfill existing rows
+
+INSERT INTO products_fts (product_id, name, description, details)
+SELECT id, name, description,
+       (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM product_details WHERE product_id = products.id)
+FROM products;
+
+INSERT INTO customers_fts (customer_id, name, mobile_number, email, details)
+SELECT id,
+       first_name || ' ' || COALESCE(middle_name || ' ', '') || last_name,
+       mobile_number,
+       email,
+       (SELECT group_concat(detail_name || ' ' || detail_value, ' ') FROM customer_details WHERE customer_id = customers.id)
+FROM customers;
+
+INSERT INTO sales_fts (sale_id, receipt_number, car_number)
+SELECT id, receipt_number, car_number FROM sales;
@@ -0,0 +1,29 @@
+-- Every price a product has had or is scheduled to have. products.price_cents
+-- keeps the price currently in effect; rows with a future effective_from are
+-- copied onto it once they come due.
+CREATE TABLE IF NOT EXISTS product_prices (
+    id TEXT PRIMARY KEY NOT NULL,
+    product_id TEXT NOT NULL,
+    price_cents INTEGER NOT NULL,
+    effective_from TEXT NOT NULL,
+    changed_by TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_product_prices_product_effective
+    ON product_prices (product_id, effective_from);
+
+-- When existing prices were set is unknown, so they are recorded as in effect
+-- since the epoch with no author.
+INSERT INTO product_prices (id, product_id, price_cents, effective_from, changed_by, created_at)
+SELECT lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' ||
+       substr(lower(hex(randomblob(2))), 2) || '-' ||
+       substr('89ab', 1 + (abs(random()) % 4), 1) || substr(lower(hex(randomblob(2))), 2) || '-' ||
+       lower(hex(randomblob(6))),
+       id,
+       price_cents,
+       '1970-01-01T00:00:00+00:00',
+       NULL,
+       strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now')
+FROM products;
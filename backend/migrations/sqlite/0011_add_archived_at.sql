@@ -0,0 +1,6 @@
+-- Deleting a product, customer or staff member archives it instead, so sales
+-- and reports can still resolve the id. Archived rows are hidden from lists
+-- unless explicitly requested.
+ALTER TABLE products ADD COLUMN archived_at TEXT;
+ALTER TABLE customers ADD COLUMN archived_at TEXT;
+ALTER TABLE staff ADD COLUMN archived_at TEXT;
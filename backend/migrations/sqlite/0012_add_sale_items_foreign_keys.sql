@@ -0,0 +1,29 @@
+-- Rebuild sale_items with foreign keys to sales, products and customers.
+-- Orphaned rows make this migration fail instead of being dropped silently;
+-- the server runs `check-integrity` first and refuses to migrate until
+-- `backend check-integrity --repair` has fixed them.
+
+CREATE TABLE sale_items_new (
+    id TEXT PRIMARY KEY NOT NULL,
+    sale_id TEXT REFERENCES sales(id) ON DELETE CASCADE,
+    product_id TEXT NOT NULL REFERENCES products(id),
+    customer_id TEXT REFERENCES customers(id) ON DELETE SET NULL,
+    date_of_sale TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    discount INTEGER NOT NULL,
+    total_cents INTEGER NOT NULL,
+    total_resolved INTEGER NOT NULL,
+    note TEXT,
+    product_name TEXT,
+    price_per_item INTEGER
+);
+
+INSERT INTO sale_items_new (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note, product_name, price_per_item)
+SELECT id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note, product_name, price_per_item
+FROM sale_items;
+
+DROP TABLE sale_items;
+ALTER TABLE sale_items_new RENAME TO sale_items;
+
+CREATE INDEX IF NOT EXISTS idx_sale_items_sale_id ON sale_items (sale_id);
+CREATE INDEX IF NOT EXISTS idx_sale_items_product_id ON sale_items (product_id);
@@ -0,0 +1,15 @@
+-- Responses to POST requests sent with an Idempotency-Key header, replayed
+-- when a client retries the same request. Keys are scoped to the staff member
+-- who sent them; status_code stays NULL while the first request is running.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    staff_id TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    status_code INTEGER,
+    content_type TEXT,
+    response_body BLOB,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (staff_id, idempotency_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys (created_at);
@@ -0,0 +1,65 @@
+-- Change log behind the offline sync API. Every write to a product or
+-- customer (or their details) moves the record to a new, higher version;
+-- devices ask for everything changed after the last version they saw.
+-- Only the latest version of each record is kept, so the log stays as small
+-- as the tables it tracks. Records that are gone from their table but still
+-- in the log are reported to devices as deleted.
+
+CREATE TABLE IF NOT EXISTS sync_changes (
+    version INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity TEXT NOT NULL CHECK (entity IN ('product', 'customer')),
+    entity_id TEXT NOT NULL,
+    UNIQUE (entity, entity_id)
+);
+
+-- Products
+
+CREATE TRIGGER IF NOT EXISTS products_sync_insert AFTER INSERT ON products BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_sync_update AFTER UPDATE ON products BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS products_sync_delete AFTER DELETE ON products BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', old.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_sync_insert AFTER INSERT ON product_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', new.product_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_sync_update AFTER UPDATE ON product_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', new.product_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS product_details_sync_delete AFTER DELETE ON product_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('product', old.product_id);
+END;
+
+-- Customers
+
+CREATE TRIGGER IF NOT EXISTS customers_sync_insert AFTER INSERT ON customers BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS customers_sync_update AFTER UPDATE ON customers BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS customers_sync_delete AFTER DELETE ON customers BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', old.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_sync_insert AFTER INSERT ON customer_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', new.customer_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_sync_update AFTER UPDATE ON customer_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', new.customer_id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS customer_details_sync_delete AFTER DELETE ON customer_details BEGIN
+    INSERT OR REPLACE INTO sync_changes (entity, entity_id) VALUES ('customer', old.customer_id);
+END;
@@ -0,0 +1,17 @@
+-- Sale items removed through DELETE /api/sales/{id}. Deleting an item is how
+-- a sale is refunded, so a copy is kept to report refunds against the staff
+-- member responsible for the original sale.
+
+CREATE TABLE IF NOT EXISTS voided_sale_items (
+    -- Id the item had in sale_items
+    id TEXT PRIMARY KEY NOT NULL,
+    sale_id TEXT REFERENCES sales(id),
+    product_id TEXT NOT NULL,
+    date_of_sale TEXT NOT NULL,
+    quantity INTEGER NOT NULL,
+    total_resolved INTEGER NOT NULL,
+    voided_by TEXT NOT NULL,
+    voided_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_voided_sale_items_sale ON voided_sale_items (sale_id, voided_at);
@@ -0,0 +1,8 @@
+-- What a product costs us, so sales can be reported with their gross margin.
+-- Sale items snapshot the cost at sale time, the same way price_per_item
+-- snapshots the sell price; items sold before this migration have no
+-- snapshot and are reported at the product's current cost.
+
+ALTER TABLE products ADD COLUMN cost_price_cents INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE sale_items ADD COLUMN cost_per_item INTEGER;
@@ -0,0 +1,23 @@
+-- Reports emailed on a schedule. The scheduler sends every active
+-- subscription whose next_run_at has passed and then moves next_run_at on to
+-- the following occurrence.
+
+CREATE TABLE IF NOT EXISTS report_subscriptions (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    report_type TEXT NOT NULL CHECK (report_type IN ('daily_takings', 'top_products', 'low_stock')),
+    -- JSON-encoded ReportParameters
+    parameters TEXT NOT NULL DEFAULT '{}',
+    frequency TEXT NOT NULL CHECK (frequency IN ('daily', 'weekly', 'monthly')),
+    -- Hour of the day the report is sent at, 0-23
+    send_hour INTEGER NOT NULL CHECK (send_hour BETWEEN 0 AND 23),
+    -- Comma-separated email addresses
+    recipients TEXT NOT NULL,
+    active INTEGER NOT NULL DEFAULT 1,
+    next_run_at TEXT NOT NULL,
+    last_sent_at TEXT,
+    last_error TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_report_subscriptions_due ON report_subscriptions (next_run_at) WHERE active = 1;
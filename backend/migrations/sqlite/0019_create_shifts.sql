@@ -0,0 +1,45 @@
+-- Till sessions. A shift is opened with a float, collects the sales rung up
+-- on its register and the cash put in or taken out of the drawer, and is
+-- closed with the counted cash. The Z report taken at close is stored as
+-- JSON so it reads the same however the underlying records change later.
+
+CREATE TABLE IF NOT EXISTS shifts (
+    id TEXT PRIMARY KEY NOT NULL,
+    register TEXT NOT NULL,
+    company_branch TEXT NOT NULL DEFAULT '',
+    opened_by TEXT NOT NULL,
+    opened_at TEXT NOT NULL,
+    opening_float_cents INTEGER NOT NULL CHECK (opening_float_cents >= 0),
+    closed_by TEXT,
+    closed_at TEXT,
+    counted_cash_cents INTEGER,
+    note TEXT,
+    -- JSON-encoded ShiftReport, set when the shift closes
+    z_report TEXT
+);
+
+-- One open shift per till, and per staff member
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shifts_open_register ON shifts (company_branch, register) WHERE closed_at IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shifts_open_staff ON shifts (opened_by) WHERE closed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_shifts_opened_at ON shifts (opened_at);
+
+CREATE TABLE IF NOT EXISTS cash_movements (
+    id TEXT PRIMARY KEY NOT NULL,
+    shift_id TEXT NOT NULL REFERENCES shifts(id),
+    kind TEXT NOT NULL CHECK (kind IN ('cash_in', 'payout', 'drop')),
+    amount_cents INTEGER NOT NULL CHECK (amount_cents > 0),
+    reason TEXT NOT NULL,
+    recorded_by TEXT NOT NULL,
+    recorded_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_cash_movements_shift ON cash_movements (shift_id, recorded_at);
+
+-- Sales made before tenders were recorded are taken to have been paid in cash
+ALTER TABLE sales ADD COLUMN payment_method TEXT NOT NULL DEFAULT 'cash';
+ALTER TABLE sales ADD COLUMN shift_id TEXT REFERENCES shifts(id);
+CREATE INDEX IF NOT EXISTS idx_sales_shift ON sales (shift_id);
+
+-- Shift the refund or void was made in, whose drawer paid it back
+ALTER TABLE voided_sale_items ADD COLUMN shift_id TEXT REFERENCES shifts(id);
+CREATE INDEX IF NOT EXISTS idx_voided_sale_items_shift ON voided_sale_items (shift_id);
@@ -0,0 +1,6 @@
+-- The distinct words in each full-text index, read to correct misspelt
+-- search terms.
+
+CREATE VIRTUAL TABLE IF NOT EXISTS products_fts_vocab USING fts5vocab(products_fts, row);
+CREATE VIRTUAL TABLE IF NOT EXISTS customers_fts_vocab USING fts5vocab(customers_fts, row);
+CREATE VIRTUAL TABLE IF NOT EXISTS sales_fts_vocab USING fts5vocab(sales_fts, row);
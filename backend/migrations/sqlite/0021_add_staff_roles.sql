@@ -0,0 +1,7 @@
+-- What a staff member may do beyond ringing up sales. Admins can take,
+-- list and download database backups and run integrity checks; the staff
+-- member seeded by the first migration is the first admin.
+
+ALTER TABLE staff ADD COLUMN role TEXT NOT NULL DEFAULT 'staff' CHECK (role IN ('staff', 'admin'));
+
+UPDATE staff SET role = 'admin' WHERE id = '550e8400-e29b-41d4-a716-446655440000';
@@ -1,29 +1,72 @@
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::Request,
     middleware::Next,
     response::Response,
 };
 use jsonwebtoken::{DecodingKey, Validation};
+use shared::models::StaffRole;
+use uuid::Uuid;
 
 use crate::AppState;
+use crate::db::Db;
+use crate::error::AppError;
+use crate::repo;
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
     mut request: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, AppError> {
     let headers = request.headers();
-    let token = extract_bearer_token(headers).ok_or(StatusCode::UNAUTHORIZED)?;
-    let key = DecodingKey::from_secret(state.jwt_secret.as_bytes());
-    let claims = jsonwebtoken::decode::<Claims>(&token, &key, &Validation::default())
-        .map_err(|_| StatusCode::UNAUTHORIZED)?
-        .claims;
+    let token = extract_bearer_token(headers).ok_or(AppError::Unauthorized)?;
+    let claims = authenticate(&state, &token).await?;
     request.extensions_mut().insert(claims);
     Ok(next.run(request).await)
 }
 
+/// Lets only admins through. Runs after [`auth_middleware`], which sets the
+/// claims it reads.
+pub async fn admin_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    let claims = request.extensions().get::<Claims>().ok_or(AppError::Unauthorized)?;
+    if !is_admin(&state.db, claims).await? {
+        return Err(AppError::Forbidden);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Whether the staff member signed in with `claims` has the admin role.
+pub async fn is_admin(db: &Db, claims: &Claims) -> Result<bool, AppError> {
+    let Ok(id) = Uuid::parse_str(&claims.sub) else {
+        return Ok(false);
+    };
+    Ok(repo::staff::role(db, id).await? == Some(StaffRole::Admin))
+}
+
+/// Verifies a token issued by `login` and returns its claims, provided the
+/// staff member it was issued to has not been archived since.
+pub async fn authenticate(state: &AppState, token: &str) -> Result<Claims, AppError> {
+    let claims = decode_claims(&state.jwt_secret, token)?;
+    let staff_id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
+    if repo::staff::archived(&state.db, staff_id).await? != Some(false) {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(claims)
+}
+
+/// Verifies a token's signature and expiry and returns its claims.
+fn decode_claims(jwt_secret: &str, token: &str) -> Result<Claims, AppError> {
+    let key = DecodingKey::from_secret(jwt_secret.as_bytes());
+    jsonwebtoken::decode::<Claims>(token, &key, &Validation::default())
+        .map(|data| data.claims)
+        .map_err(|_| AppError::Unauthorized)
+}
+
 fn extract_bearer_token(headers: &axum::http::HeaderMap) -> Option<String> {
     let header = headers.get(axum::http::header::AUTHORIZATION)?.to_str().ok()?;
     let token = header.strip_prefix("Bearer ")?;
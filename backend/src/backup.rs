@@ -0,0 +1,381 @@
+//! Online backups of the SQLite database, and restoring them. A backup is a
+//! `VACUUM INTO` copy, a consistent snapshot taken while the server keeps
+//! serving, which is only named `fastsales-<timestamp>.db` in the backup
+//! directory once it passes `PRAGMA integrity_check`. Backups are taken by
+//! `backend backup`, `POST /api/admin/backups` and, with
+//! `backup.interval_hours` set, on a schedule that keeps the newest
+//! `backup.keep`. PostgreSQL databases are backed up with `pg_dump` instead.
+//!
+//! `backend restore <file>` swaps a backup in for the database after checking
+//! its migrations against this build. The server must be stopped first.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use shared::models::Backup;
+use sqlx::sqlite::SqliteConnectOptions;
+
+use crate::config::BackupConfig;
+use crate::db::{self, Backend, Db, SQLITE_MIGRATOR};
+use crate::error::AppError;
+use crate::integrity;
+
+const FILE_PREFIX: &str = "fastsales-";
+const FILE_SUFFIX: &str = ".db";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+/// How often the scheduler looks for a backup being due
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum BackupError {
+    /// The database is PostgreSQL
+    Unsupported,
+    /// The file is not a backup this build can restore
+    Invalid(String),
+    Database(sqlx::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::Unsupported => {
+                write!(f, "only SQLite databases are backed up by FastSales; use pg_dump for PostgreSQL")
+            }
+            BackupError::Invalid(message) => write!(f, "{}", message),
+            BackupError::Database(err) => write!(f, "{}", err),
+            BackupError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(err: sqlx::Error) -> Self {
+        BackupError::Database(err)
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(err: std::io::Error) -> Self {
+        BackupError::Io(err)
+    }
+}
+
+impl From<BackupError> for AppError {
+    fn from(err: BackupError) -> Self {
+        match err {
+            BackupError::Unsupported => AppError::BadRequest(
+                "Only SQLite databases are backed up by FastSales; use pg_dump for PostgreSQL".to_string(),
+            ),
+            err => AppError::internal(err),
+        }
+    }
+}
+
+/// What `restore` did.
+pub struct Restored {
+    /// Newest migration applied in the backup
+    pub version: i64,
+    /// Where the database that was replaced was saved, if there was one
+    pub previous: Option<PathBuf>,
+}
+
+fn file_name(created_at: DateTime<Utc>) -> String {
+    format!("{}{}{}", FILE_PREFIX, created_at.format(TIMESTAMP_FORMAT), FILE_SUFFIX)
+}
+
+fn parse_file_name(file_name: &str) -> Option<DateTime<Utc>> {
+    let timestamp = file_name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|created_at| created_at.and_utc())
+}
+
+/// Takes a backup into `dir`, creating the directory if needed.
+pub async fn create(db: &Db, dir: &Path) -> Result<Backup, BackupError> {
+    if db.backend() != Backend::Sqlite {
+        return Err(BackupError::Unsupported);
+    }
+    tokio::fs::create_dir_all(dir).await?;
+    let created_at = Utc::now().trunc_subsecs(3);
+    let file_name = file_name(created_at);
+    let path = dir.join(&file_name);
+    // Written under another name so that a failed or unchecked copy is
+    // never mistaken for a backup
+    let partial = dir.join(format!("{}.partial", file_name));
+    let result = async {
+        db::query("VACUUM INTO ?")
+            .bind(partial.to_string_lossy().into_owned())
+            .execute(db)
+            .await?;
+        verify(&partial).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok::<_, BackupError>(())
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+    }
+    result?;
+    let size_bytes = tokio::fs::metadata(&path).await?.len();
+    Ok(Backup { file_name, size_bytes, created_at })
+}
+
+/// The backups in `dir`, newest first. A missing directory has none.
+pub async fn list(dir: &Path) -> Result<Vec<Backup>, BackupError> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let Some(created_at) = parse_file_name(&file_name) else {
+            continue;
+        };
+        let size_bytes = entry.metadata().await?.len();
+        backups.push(Backup { file_name, size_bytes, created_at });
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Where the backup named `file_name` is in `dir`, if that is a backup's
+/// name; never a path outside `dir`.
+pub fn path(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    parse_file_name(file_name).map(|_| dir.join(file_name))
+}
+
+/// Deletes all but the newest `keep` backups in `dir` and returns how many
+/// were deleted.
+pub async fn rotate(dir: &Path, keep: usize) -> Result<usize, BackupError> {
+    let backups = list(dir).await?;
+    for backup in backups.iter().skip(keep) {
+        tokio::fs::remove_file(dir.join(&backup.file_name)).await?;
+    }
+    Ok(backups.len().saturating_sub(keep))
+}
+
+/// Checks that `path` is a sound SQLite file whose migrations are all ones
+/// this build has, and returns the newest applied.
+pub async fn verify(path: &Path) -> Result<i64, BackupError> {
+    if !tokio::fs::try_exists(path).await? {
+        return Err(BackupError::Invalid(format!("{} does not exist", path.display())));
+    }
+    let db = Db::connect_sqlite(SqliteConnectOptions::new().filename(path).read_only(true), 1).await?;
+    let result = check_backup(&db).await;
+    db.close().await;
+    result
+}
+
+async fn check_backup(db: &Db) -> Result<i64, BackupError> {
+    let errors = integrity::storage_errors(db).await?;
+    if !errors.is_empty() {
+        return Err(BackupError::Invalid(format!("the file is damaged: {}", errors.join("; "))));
+    }
+    let migrated: i64 = db::query_scalar(db.backend().table_exists())
+        .bind("_sqlx_migrations")
+        .fetch_one(db)
+        .await?;
+    if migrated == 0 {
+        return Err(BackupError::Invalid(
+            "not a FastSales database: it has no migration history".to_string(),
+        ));
+    }
+    let applied: Vec<(i64, Vec<u8>)> =
+        db::query_as("SELECT version, checksum FROM _sqlx_migrations WHERE success = TRUE ORDER BY version")
+            .fetch_all(db)
+            .await?;
+    for (version, checksum) in &applied {
+        match SQLITE_MIGRATOR.iter().find(|migration| migration.version == *version) {
+            None => {
+                return Err(BackupError::Invalid(format!(
+                    "it has migration {}, which this build does not; restore it with the FastSales version that took it",
+                    version
+                )));
+            }
+            Some(migration) if *migration.checksum != checksum[..] => {
+                return Err(BackupError::Invalid(format!(
+                    "its migration {} differs from this build's",
+                    version
+                )));
+            }
+            Some(_) => {}
+        }
+    }
+    applied
+        .last()
+        .map(|(version, _)| *version)
+        .ok_or_else(|| BackupError::Invalid("it has no migrations applied".to_string()))
+}
+
+/// Verifies the backup at `backup` and replaces the database `options`
+/// opens with it. The database being replaced is first saved alongside as
+/// `<file>.before-restore-<timestamp>`. Nothing may use the database
+/// meanwhile.
+pub async fn restore(options: SqliteConnectOptions, backup: &Path) -> Result<Restored, BackupError> {
+    let version = verify(backup).await?;
+    let target = options.get_filename().to_path_buf();
+    if target == Path::new(":memory:") {
+        return Err(BackupError::Invalid("cannot restore into an in-memory database".to_string()));
+    }
+
+    let mut previous = None;
+    if tokio::fs::try_exists(&target).await? {
+        let saved = PathBuf::from(format!(
+            "{}.before-restore-{}",
+            target.display(),
+            Utc::now().format(TIMESTAMP_FORMAT)
+        ));
+        let db = Db::connect_sqlite(options, 1).await?;
+        let result = async {
+            db::query("VACUUM INTO ?")
+                .bind(saved.to_string_lossy().into_owned())
+                .execute(&db)
+                .await?;
+            // Empties the write-ahead log, which would otherwise be
+            // replayed over the restored file
+            db::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&db).await?;
+            Ok::<_, BackupError>(())
+        }
+        .await;
+        db.close().await;
+        result?;
+        previous = Some(saved);
+    }
+
+    let incoming = PathBuf::from(format!("{}.restoring", target.display()));
+    tokio::fs::copy(backup, &incoming).await?;
+    tokio::fs::rename(&incoming, &target).await?;
+    for suffix in ["-wal", "-shm"] {
+        match tokio::fs::remove_file(format!("{}{}", target.display(), suffix)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(Restored { version, previous })
+}
+
+/// Takes a backup whenever the newest in the backup directory is
+/// `interval_hours` old, then deletes all but the newest `keep`. Returns at
+/// once without an interval.
+pub async fn run_scheduler(db: Db, config: BackupConfig) {
+    let Some(hours) = config.interval_hours else {
+        return;
+    };
+    if db.backend() != Backend::Sqlite {
+        tracing::warn!("Scheduled backups are for SQLite only; back PostgreSQL up with pg_dump");
+        return;
+    }
+    let interval = chrono::Duration::hours(hours as i64);
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        poll.tick().await;
+        if let Err(err) = back_up_if_due(&db, &config, interval).await {
+            tracing::error!("Scheduled backup failed: {}", err);
+        }
+    }
+}
+
+async fn back_up_if_due(db: &Db, config: &BackupConfig, interval: chrono::Duration) -> Result<(), BackupError> {
+    let newest = list(&config.dir).await?.first().map(|backup| backup.created_at);
+    if newest.is_some_and(|created_at| Utc::now() - created_at < interval) {
+        return Ok(());
+    }
+    let backup = create(db, &config.dir).await?;
+    let deleted = rotate(&config.dir, config.keep).await?;
+    tracing::info!("Backed up the database to {} and deleted {} old backups", backup.file_name, deleted);
+    Ok(())
+}
+
+/// Entry point for `backend backup`; returns the process exit code.
+pub async fn run_backup_command(db: &Db, dir: &Path) -> i32 {
+    match create(db, dir).await {
+        Ok(backup) => {
+            println!("Backed up to {} ({} bytes).", dir.join(&backup.file_name).display(), backup.size_bytes);
+            0
+        }
+        Err(err) => {
+            eprintln!("Backup failed: {}", err);
+            1
+        }
+    }
+}
+
+/// Entry point for `backend restore <file> [--check]`, given the SQLite
+/// database to restore into (`None` for PostgreSQL); returns the process
+/// exit code.
+pub async fn run_restore_command(options: Option<SqliteConnectOptions>, file: &Path, check_only: bool) -> i32 {
+    let Some(options) = options else {
+        eprintln!("Restore failed: {}", BackupError::Unsupported);
+        return 1;
+    };
+    let result = if check_only {
+        verify(file).await.map(|version| Restored { version, previous: None })
+    } else {
+        restore(options, file).await
+    };
+    match result {
+        Ok(restored) => {
+            if check_only {
+                println!("{} is a sound backup at migration {}.", file.display(), restored.version);
+            } else {
+                println!("Restored {} (migration {}).", file.display(), restored.version);
+                if let Some(previous) = restored.previous {
+                    println!("The database it replaced was saved as {}.", previous.display());
+                }
+            }
+            let latest = SQLITE_MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or_default();
+            if restored.version < latest {
+                println!("Migrations up to {} are applied when the server next starts.", latest);
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("Restore failed: {}", err);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_round_trip() {
+        let created_at = DateTime::parse_from_rfc3339("2026-10-18T23:05:09.123Z").unwrap().to_utc();
+        let name = file_name(created_at);
+        assert_eq!(name, "fastsales-20261018T230509123Z.db");
+        assert_eq!(parse_file_name(&name), Some(created_at));
+        assert_eq!(parse_file_name("fastsales-20261018T230509123Z.db.partial"), None);
+        assert_eq!(path(Path::new("backups"), "fastsales-../../etc/passwd.db"), None);
+    }
+
+    #[tokio::test]
+    async fn backups_restore_over_the_database() {
+        let dir = std::env::temp_dir().join(format!("fastsales-backup-test-{}", uuid::Uuid::new_v4().simple()));
+        let options = SqliteConnectOptions::new().filename(dir.join("live.db"));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let db = Db::connect_sqlite(options.clone(), 1).await.unwrap();
+        db.migrate().await.unwrap();
+        let backup = create(&db, &dir.join("backups")).await.unwrap();
+        db::query("DELETE FROM staff").execute(&db).await.unwrap();
+        db.close().await;
+
+        let restored = restore(options.clone(), &dir.join("backups").join(&backup.file_name)).await.unwrap();
+        assert!(restored.previous.is_some());
+        let db = Db::connect_sqlite(options, 1).await.unwrap();
+        let staff: i64 = db::query_scalar("SELECT COUNT(*) FROM staff").fetch_one(&db).await.unwrap();
+        assert_eq!(staff, 1);
+        db.close().await;
+
+        assert_eq!(rotate(&dir.join("backups"), 0).await.unwrap(), 1);
+        assert!(matches!(verify(&dir.join("live.db.missing")).await, Err(BackupError::Invalid(_))));
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
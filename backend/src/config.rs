@@ -0,0 +1,441 @@
+//! Server configuration. Settings are layered, each overriding the last:
+//! built-in defaults, the TOML file (`fastsales.toml` in the working
+//! directory, or `--config` / `FASTSALES_CONFIG`), environment variables and
+//! command line flags.
+//!
+//! ```toml
+//! [server]
+//! bind_address = "0.0.0.0:3000"    # BIND_ADDRESS, --bind-address
+//! upload_dir = "uploads"           # UPLOAD_DIR, --upload-dir
+//! cors_origins = ["https://pos.example.com"] # CORS_ORIGINS (comma separated), --cors-origin
+//! log_level = "info"               # LOG_LEVEL, --log-level
+//!
+//! [database]
+//! url = "sqlite:///var/lib/fastsales/fastsales.db" # or postgres://...; DATABASE_URL, --database-url
+//! max_connections = 5              # DATABASE_MAX_CONNECTIONS, --database-max-connections
+//!
+//! [auth]
+//! jwt_secret = "..."               # JWT_SECRET
+//! password_pepper = "..."          # PASSWORD_PEPPER
+//! token_ttl_secs = 3600            # TOKEN_TTL_SECS, --token-ttl-secs
+//!
+//! [backup]
+//! dir = "backups"                  # BACKUP_DIR, --backup-dir
+//! interval_hours = 24              # BACKUP_INTERVAL_HOURS, --backup-interval-hours
+//! keep = 7                         # BACKUP_KEEP, --backup-keep
+//!
+//! [business]
+//! timezone = "Africa/Accra"        # IANA name; BUSINESS_TIMEZONE, --business-timezone
+//!
+//! [smtp]                           # scheduled report emails; none are sent without a host
+//! host = "smtp.example.com"        # SMTP_HOST, --smtp-host
+//! port = 587                       # SMTP_PORT, --smtp-port; defaults to 587, 465 or 25 by tls
+//! tls = "starttls"                 # starttls, tls or none; SMTP_TLS, --smtp-tls
+//! username = "reports"             # SMTP_USERNAME, --smtp-username
+//! password = "..."                 # SMTP_PASSWORD
+//! from = "FastSales <reports@localhost>" # SMTP_FROM, --smtp-from
+//! ```
+//!
+//! Secrets have no command line flags, so they do not show up in process
+//! listings. Release builds refuse to start with the development JWT secret.
+
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand, ValueEnum};
+use lettre::message::Mailbox;
+use serde::Deserialize;
+
+const DEFAULT_CONFIG_FILE: &str = "fastsales.toml";
+/// Signs tokens in development; never accepted by a release build
+pub const DEV_JWT_SECRET: &str = "dev-secret";
+/// Placeholders from the README's production example
+const PLACEHOLDER_SECRETS: [&str; 2] = ["change-me-prod-secret", "change-me-prod-pepper"];
+
+#[derive(Parser, Debug)]
+#[command(name = "backend", about = "FastSales server")]
+pub struct Cli {
+    /// TOML configuration file [default: fastsales.toml, if present]
+    #[arg(long, env = "FASTSALES_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on [default: site-addr from Cargo.toml]
+    #[arg(long, env = "BIND_ADDRESS")]
+    pub bind_address: Option<SocketAddr>,
+    /// sqlite:// or postgres:// database URL [default: fastsales.db in the working directory]
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+    #[arg(long, env = "DATABASE_MAX_CONNECTIONS")]
+    pub database_max_connections: Option<u32>,
+    /// Directory uploaded files are written to and served from
+    #[arg(long, env = "UPLOAD_DIR")]
+    pub upload_dir: Option<PathBuf>,
+    /// Lifetime of the tokens issued at login, in seconds
+    #[arg(long, env = "TOKEN_TTL_SECS")]
+    pub token_ttl_secs: Option<u64>,
+    /// Origin allowed to call the API from a browser; repeat for several, or `*` for any
+    #[arg(long = "cors-origin", env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+    /// Log filter, e.g. `info` or `backend=debug,tower_http=debug`
+    #[arg(long, env = "LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Directory SQLite backups are written to and listed from
+    #[arg(long, env = "BACKUP_DIR")]
+    pub backup_dir: Option<PathBuf>,
+    /// Take a backup this many hours after the last one [default: no scheduled backups]
+    #[arg(long, env = "BACKUP_INTERVAL_HOURS")]
+    pub backup_interval_hours: Option<u64>,
+    /// Scheduled backups delete all but this many of the newest backups
+    #[arg(long, env = "BACKUP_KEEP")]
+    pub backup_keep: Option<usize>,
+    /// IANA timezone "today", date filters and day or hour groupings follow [default: UTC]
+    #[arg(long, env = "BUSINESS_TIMEZONE")]
+    pub business_timezone: Option<Tz>,
+    /// SMTP relay report emails are sent through [default: none, reports are not sent]
+    #[arg(long, env = "SMTP_HOST")]
+    pub smtp_host: Option<String>,
+    /// [default: 587, 465 or 25 depending on --smtp-tls]
+    #[arg(long, env = "SMTP_PORT")]
+    pub smtp_port: Option<u16>,
+    #[arg(long, env = "SMTP_TLS")]
+    pub smtp_tls: Option<SmtpTls>,
+    #[arg(long, env = "SMTP_USERNAME")]
+    pub smtp_username: Option<String>,
+    /// Sender mailbox of report emails
+    #[arg(long, env = "SMTP_FROM")]
+    pub smtp_from: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Look for rows whose foreign keys point nowhere
+    CheckIntegrity {
+        /// Delete or detach the orphaned rows
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Back up the SQLite database into the backup directory; safe while the server runs
+    Backup,
+    /// Replace the SQLite database with a backup; stop the server first
+    Restore {
+        /// Backup file to restore
+        file: PathBuf,
+        /// Only check that the file is a backup this build can restore
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub backup: BackupConfig,
+    pub business: BusinessConfig,
+    pub smtp: SmtpConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `None` listens on the Leptos `site-addr`
+    pub bind_address: Option<SocketAddr>,
+    pub upload_dir: PathBuf,
+    /// Empty allows same-origin requests only
+    pub cors_origins: Vec<String>,
+    pub log_level: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: None,
+            upload_dir: PathBuf::from("uploads"),
+            cors_origins: Vec::new(),
+            log_level: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `None` opens `fastsales.db` in the working directory
+    pub url: Option<String>,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig { url: None, max_connections: 5 }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    /// Changing it invalidates every stored password hash
+    pub password_pepper: String,
+    pub token_ttl_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: DEV_JWT_SECRET.to_string(),
+            password_pepper: String::new(),
+            token_ttl_secs: 3600,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    /// `None` takes backups only when asked to
+    pub interval_hours: Option<u64>,
+    /// Backups kept by the schedule, newest first
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: PathBuf::from("backups"),
+            interval_hours: None,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct BusinessConfig {
+    pub timezone: Tz,
+}
+
+#[derive(Deserialize, ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    #[default]
+    Starttls,
+    Tls,
+    /// Plain SMTP, for local sinks
+    None,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    /// `None` sends no report emails; each attempt records an error instead
+    pub host: Option<String>,
+    /// `None` uses the usual port for `tls`
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: String,
+    pub from: String,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        SmtpConfig {
+            host: None,
+            port: None,
+            tls: SmtpTls::Starttls,
+            username: None,
+            password: String::new(),
+            from: "FastSales <reports@localhost>".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file named by `cli` (a missing default file is
+    /// fine, a missing named one is not), then applies `JWT_SECRET`,
+    /// `PASSWORD_PEPPER`, `SMTP_PASSWORD` and the flags and variables `cli`
+    /// was parsed from.
+    pub fn load(cli: &Cli) -> Result<Config, String> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Config::default(),
+        };
+        if let Ok(secret) = env::var("JWT_SECRET") {
+            config.auth.jwt_secret = secret;
+        }
+        if let Ok(pepper) = env::var("PASSWORD_PEPPER") {
+            config.auth.password_pepper = pepper;
+        }
+        if let Ok(password) = env::var("SMTP_PASSWORD") {
+            config.smtp.password = password;
+        }
+        config.apply(cli);
+        config.validate(!cfg!(debug_assertions))?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        toml::from_str(&text).map_err(|err| format!("invalid {}: {}", path.display(), err))
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        if let Some(address) = cli.bind_address {
+            self.server.bind_address = Some(address);
+        }
+        if let Some(dir) = &cli.upload_dir {
+            self.server.upload_dir = dir.clone();
+        }
+        if let Some(origins) = &cli.cors_origins {
+            self.server.cors_origins = origins.clone();
+        }
+        if let Some(level) = &cli.log_level {
+            self.server.log_level = level.clone();
+        }
+        if let Some(url) = &cli.database_url {
+            self.database.url = Some(url.clone());
+        }
+        if let Some(max) = cli.database_max_connections {
+            self.database.max_connections = max;
+        }
+        if let Some(ttl) = cli.token_ttl_secs {
+            self.auth.token_ttl_secs = ttl;
+        }
+        if let Some(dir) = &cli.backup_dir {
+            self.backup.dir = dir.clone();
+        }
+        if let Some(hours) = cli.backup_interval_hours {
+            self.backup.interval_hours = Some(hours);
+        }
+        if let Some(keep) = cli.backup_keep {
+            self.backup.keep = keep;
+        }
+        if let Some(timezone) = cli.business_timezone {
+            self.business.timezone = timezone;
+        }
+        if let Some(host) = &cli.smtp_host {
+            self.smtp.host = Some(host.clone());
+        }
+        if let Some(port) = cli.smtp_port {
+            self.smtp.port = Some(port);
+        }
+        if let Some(tls) = cli.smtp_tls {
+            self.smtp.tls = tls;
+        }
+        if let Some(username) = &cli.smtp_username {
+            self.smtp.username = Some(username.clone());
+        }
+        if let Some(from) = &cli.smtp_from {
+            self.smtp.from = from.clone();
+        }
+    }
+
+    fn validate(&self, release: bool) -> Result<(), String> {
+        if self.database.max_connections == 0 {
+            return Err("database.max_connections must be at least 1".to_string());
+        }
+        if self.auth.token_ttl_secs == 0 {
+            return Err("auth.token_ttl_secs must be at least 1".to_string());
+        }
+        if self.backup.interval_hours == Some(0) {
+            return Err("backup.interval_hours must be at least 1".to_string());
+        }
+        if self.backup.keep == 0 {
+            return Err("backup.keep must be at least 1".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                return Err(format!("CORS origin {:?} must be `*` or start with http:// or https://", origin));
+            }
+            if axum::http::HeaderValue::from_str(origin).is_err() {
+                return Err(format!("CORS origin {:?} is not a valid header value", origin));
+            }
+        }
+        if self.smtp.from.parse::<Mailbox>().is_err() {
+            return Err(format!("smtp.from {:?} is not a valid mailbox", self.smtp.from));
+        }
+        if self.smtp.host.as_deref() == Some("") {
+            return Err("smtp.host must not be empty".to_string());
+        }
+        if self.smtp.port == Some(0) {
+            return Err("smtp.port must not be 0".to_string());
+        }
+        if self.auth.jwt_secret.is_empty() {
+            return Err("auth.jwt_secret must not be empty".to_string());
+        }
+        if release {
+            if self.auth.jwt_secret == DEV_JWT_SECRET || PLACEHOLDER_SECRETS.contains(&self.auth.jwt_secret.as_str()) {
+                return Err("set JWT_SECRET (or auth.jwt_secret) to a secret of your own; release builds do not run with the default".to_string());
+            }
+            if PLACEHOLDER_SECRETS.contains(&self.auth.password_pepper.as_str()) {
+                return Err("set PASSWORD_PEPPER (or auth.password_pepper) to a secret of your own, not the README placeholder".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_file() {
+        let mut config: Config = toml::from_str(
+            "[server]\nupload_dir = \"/srv/uploads\"\nlog_level = \"warn\"\n[auth]\ntoken_ttl_secs = 600\n",
+        )
+        .unwrap();
+        let cli = Cli::parse_from(["backend", "--log-level", "debug", "--cors-origin", "https://a.example,https://b.example"]);
+        config.apply(&cli);
+
+        assert_eq!(config.server.upload_dir, PathBuf::from("/srv/uploads"));
+        assert_eq!(config.server.log_level, "debug");
+        assert_eq!(config.server.cors_origins, ["https://a.example", "https://b.example"]);
+        assert_eq!(config.auth.token_ttl_secs, 600);
+        assert_eq!(config.database.max_connections, 5);
+        assert!(toml::from_str::<Config>("[server]\nport = 80\n").is_err());
+        config.server.cors_origins = vec!["pos.example.com".to_string()];
+        assert!(config.validate(false).is_err());
+    }
+
+    #[test]
+    fn business_and_smtp_settings_are_layered_and_checked() {
+        let mut config: Config = toml::from_str(
+            "[business]\ntimezone = \"Africa/Accra\"\n[smtp]\nhost = \"smtp.example.com\"\ntls = \"tls\"\n",
+        )
+        .unwrap();
+        let cli = Cli::parse_from(["backend", "--business-timezone", "Europe/London", "--smtp-port", "2525", "--smtp-tls", "none"]);
+        config.apply(&cli);
+
+        assert_eq!(config.business.timezone, chrono_tz::Europe::London);
+        assert_eq!(config.smtp.host.as_deref(), Some("smtp.example.com"));
+        assert_eq!((config.smtp.port, config.smtp.tls), (Some(2525), SmtpTls::None));
+        assert!(config.validate(false).is_ok());
+        assert!(toml::from_str::<Config>("[business]\ntimezone = \"Mars/Olympus\"\n").is_err());
+        assert!(Cli::try_parse_from(["backend", "--business-timezone", "Mars/Olympus"]).is_err());
+        config.smtp.from = "not a mailbox".to_string();
+        assert!(config.validate(false).is_err());
+    }
+
+    #[test]
+    fn release_builds_refuse_default_secrets() {
+        let mut config = Config::default();
+        assert!(config.validate(false).is_ok());
+        assert!(config.validate(true).is_err());
+
+        config.auth.jwt_secret = "change-me-prod-secret".to_string();
+        assert!(config.validate(true).is_err());
+
+        config.auth.jwt_secret = "a long random secret".to_string();
+        assert!(config.validate(true).is_ok());
+    }
+}
@@ -0,0 +1,1062 @@
+//! Database access that runs the same queries on SQLite and PostgreSQL.
+//!
+//! Queries are written once, in SQL both databases accept, with `?`
+//! placeholders; PostgreSQL gets them as `$1`, `$2`, … . Values are bound
+//! with their type ([`Arg`]), so PostgreSQL stores ids as `UUID`, instants as
+//! `TIMESTAMPTZ` and flags as `BOOLEAN`, while SQLite keeps the text and
+//! integers it always had. Reading a row converts back the same way, so a
+//! UUID column reads as a `String` and a PostgreSQL `SUM` (a `NUMERIC`) as an
+//! `i64`. The few constructs the two spell differently, such as date
+//! arithmetic and full-text search, come from [`Backend`].
+//!
+//! Each backend has its own migrations: `migrations/sqlite` and
+//! `migrations/postgres`.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions, PgRow, PgValueFormat, PgValueRef};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{ColumnIndex, Postgres, Row as _, Sqlite, TypeInfo, ValueRef};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use crate::timezone::BusinessTz;
+
+/// The SQLite migrations, also what a backup's schema is checked against
+/// before it is restored.
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+/// A connection pool for either backend.
+#[derive(Clone, Debug)]
+pub enum Db {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl Db {
+    /// Opens a pool for `url`: PostgreSQL for `postgres://` and
+    /// `postgresql://` URLs, SQLite otherwise. SQLite databases are created
+    /// if missing and enforce foreign keys on every connection.
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Db, sqlx::Error> {
+        if is_postgres_url(url) {
+            let options = PgConnectOptions::from_str(url)?;
+            Ok(Db::Postgres(
+                PgPoolOptions::new().max_connections(max_connections).connect_with(options).await?,
+            ))
+        } else {
+            Db::connect_sqlite(SqliteConnectOptions::from_str(url)?, max_connections).await
+        }
+    }
+
+    pub async fn connect_sqlite(options: SqliteConnectOptions, max_connections: u32) -> Result<Db, sqlx::Error> {
+        Ok(Db::Sqlite(
+            SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .connect_with(options.create_if_missing(true).foreign_keys(true))
+                .await?,
+        ))
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Db::Sqlite(_) => Backend::Sqlite,
+            Db::Postgres(_) => Backend::Postgres,
+        }
+    }
+
+    /// Applies the backend's pending migrations.
+    pub async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        match self {
+            Db::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await,
+            Db::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await,
+        }
+    }
+
+    pub async fn begin(&self) -> Result<Tx, sqlx::Error> {
+        Ok(match self {
+            Db::Sqlite(pool) => Tx::Sqlite(pool.begin().await?),
+            Db::Postgres(pool) => Tx::Postgres(pool.begin().await?),
+        })
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Db::Sqlite(pool) => pool.close().await,
+            Db::Postgres(pool) => pool.close().await,
+        }
+    }
+}
+
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// A transaction on either backend; rolled back if dropped uncommitted.
+pub enum Tx {
+    Sqlite(sqlx::Transaction<'static, Sqlite>),
+    Postgres(sqlx::Transaction<'static, Postgres>),
+}
+
+impl Tx {
+    pub fn backend(&self) -> Backend {
+        match self {
+            Tx::Sqlite(_) => Backend::Sqlite,
+            Tx::Postgres(_) => Backend::Postgres,
+        }
+    }
+
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            Tx::Sqlite(tx) => tx.commit().await,
+            Tx::Postgres(tx) => tx.commit().await,
+        }
+    }
+}
+
+/// Where a query runs: a pool, or the connection behind a transaction.
+/// Functions that run several queries take `impl Into<Conn>` and pass
+/// `&mut conn` to each.
+pub enum Conn<'c> {
+    SqlitePool(&'c SqlitePool),
+    PgPool(&'c PgPool),
+    Sqlite(&'c mut SqliteConnection),
+    Postgres(&'c mut PgConnection),
+}
+
+impl Conn<'_> {
+    pub fn backend(&self) -> Backend {
+        match self {
+            Conn::SqlitePool(_) | Conn::Sqlite(_) => Backend::Sqlite,
+            Conn::PgPool(_) | Conn::Postgres(_) => Backend::Postgres,
+        }
+    }
+}
+
+impl<'c> From<&'c Db> for Conn<'c> {
+    fn from(db: &'c Db) -> Self {
+        match db {
+            Db::Sqlite(pool) => Conn::SqlitePool(pool),
+            Db::Postgres(pool) => Conn::PgPool(pool),
+        }
+    }
+}
+
+impl<'c> From<&'c mut Tx> for Conn<'c> {
+    fn from(tx: &'c mut Tx) -> Self {
+        match tx {
+            Tx::Sqlite(tx) => Conn::Sqlite(tx),
+            Tx::Postgres(tx) => Conn::Postgres(tx),
+        }
+    }
+}
+
+impl<'c, 'a: 'c> From<&'c mut Conn<'a>> for Conn<'c> {
+    fn from(conn: &'c mut Conn<'a>) -> Self {
+        match conn {
+            Conn::SqlitePool(pool) => Conn::SqlitePool(pool),
+            Conn::PgPool(pool) => Conn::PgPool(pool),
+            Conn::Sqlite(conn) => Conn::Sqlite(conn),
+            Conn::Postgres(conn) => Conn::Postgres(conn),
+        }
+    }
+}
+
+/// A bound value. `None` binds a NULL of the value's type, which PostgreSQL
+/// needs to accept it for a typed column.
+#[derive(Clone, Debug)]
+pub enum Arg {
+    Text(Option<String>),
+    Int(Option<i64>),
+    Float(Option<f64>),
+    Bool(Option<bool>),
+    Bytes(Option<Vec<u8>>),
+    Timestamp(Option<DateTime<Utc>>),
+    Date(Option<NaiveDate>),
+    /// Text in SQLite, `UUID` in PostgreSQL
+    Uuid(Option<Uuid>),
+}
+
+pub trait IntoArg {
+    fn into_arg(self) -> Arg;
+    fn null() -> Arg;
+}
+
+macro_rules! into_arg {
+    ($variant:ident: $($ty:ty => $convert:expr),+ $(,)?) => {
+        $(
+            impl IntoArg for $ty {
+                fn into_arg(self) -> Arg {
+                    let convert = $convert;
+                    Arg::$variant(Some(convert(self)))
+                }
+                fn null() -> Arg {
+                    Arg::$variant(None)
+                }
+            }
+        )+
+    };
+}
+
+into_arg!(Text: String => |value| value, &str => str::to_string, &String => String::clone);
+into_arg!(Int: i64 => |value| value, i32 => i64::from, u32 => i64::from, u16 => i64::from, u8 => i64::from);
+into_arg!(Float: f64 => |value| value);
+into_arg!(Bool: bool => |value| value);
+into_arg!(Bytes: Vec<u8> => |value| value, &[u8] => <[u8]>::to_vec);
+into_arg!(Timestamp: DateTime<Utc> => |value| value, &DateTime<Utc> => |value: &DateTime<Utc>| *value);
+into_arg!(Date: NaiveDate => |value| value);
+into_arg!(Uuid: Uuid => |value| value, &Uuid => |value: &Uuid| *value);
+
+impl<T: IntoArg> IntoArg for Option<T> {
+    fn into_arg(self) -> Arg {
+        match self {
+            Some(value) => value.into_arg(),
+            None => T::null(),
+        }
+    }
+    fn null() -> Arg {
+        T::null()
+    }
+}
+
+impl IntoArg for &Option<String> {
+    fn into_arg(self) -> Arg {
+        Arg::Text(self.clone())
+    }
+    fn null() -> Arg {
+        Arg::Text(None)
+    }
+}
+
+/// A statement and its arguments, run with `execute` or one of the fetches.
+pub struct Query {
+    sql: String,
+    args: Vec<Arg>,
+}
+
+pub fn query(sql: impl Into<String>) -> Query {
+    Query {
+        sql: sql.into(),
+        args: Vec::new(),
+    }
+}
+
+/// A query whose rows are read as tuples, like `sqlx::query_as`.
+pub fn query_as<T: FromRow>(sql: impl Into<String>) -> QueryAs<T> {
+    QueryAs(query(sql), std::marker::PhantomData)
+}
+
+/// A query whose rows are read by their first column, like `sqlx::query_scalar`.
+pub fn query_scalar<T: FromSql>(sql: impl Into<String>) -> QueryScalar<T> {
+    QueryScalar(query(sql), std::marker::PhantomData)
+}
+
+macro_rules! fetch {
+    ($self:ident, $conn:ident, $method:ident) => {
+        match $conn.into() {
+            Conn::SqlitePool(pool) => $self.sqlite().$method(pool).await.map(Into::into),
+            Conn::Sqlite(conn) => $self.sqlite().$method(conn).await.map(Into::into),
+            Conn::PgPool(pool) => {
+                let sql = postgres_sql(&$self.sql);
+                $self.postgres(&sql).$method(pool).await.map(Into::into)
+            }
+            Conn::Postgres(conn) => {
+                let sql = postgres_sql(&$self.sql);
+                $self.postgres(&sql).$method(conn).await.map(Into::into)
+            }
+        }
+    };
+}
+
+impl Query {
+    pub fn bind(mut self, value: impl IntoArg) -> Self {
+        self.args.push(value.into_arg());
+        self
+    }
+
+    /// Runs the statement and returns the number of rows it changed.
+    pub async fn execute<'c>(self, conn: impl Into<Conn<'c>>) -> Result<u64, sqlx::Error> {
+        let rows_affected = match conn.into() {
+            Conn::SqlitePool(pool) => self.sqlite().execute(pool).await?.rows_affected(),
+            Conn::Sqlite(conn) => self.sqlite().execute(conn).await?.rows_affected(),
+            Conn::PgPool(pool) => self.postgres(&postgres_sql(&self.sql)).execute(pool).await?.rows_affected(),
+            Conn::Postgres(conn) => self.postgres(&postgres_sql(&self.sql)).execute(conn).await?.rows_affected(),
+        };
+        Ok(rows_affected)
+    }
+
+    pub async fn fetch_all<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Vec<Row>, sqlx::Error> {
+        let rows: Rows = fetch!(self, conn, fetch_all)?;
+        Ok(rows.0)
+    }
+
+    pub async fn fetch_one<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Row, sqlx::Error> {
+        fetch!(self, conn, fetch_one)
+    }
+
+    pub async fn fetch_optional<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Option<Row>, sqlx::Error> {
+        let row: OptionalRow = fetch!(self, conn, fetch_optional)?;
+        Ok(row.0)
+    }
+
+    /// The rows as the database cursor yields them, for results too large
+    /// to collect. The query runs on its own task and pool connection, a few
+    /// rows ahead of the reader, and stops when the stream is dropped.
+    pub fn stream(self, db: &Db) -> RowStream {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let db = db.clone();
+        tokio::spawn(async move {
+            match &db {
+                Db::Sqlite(pool) => forward(self.sqlite().fetch(pool), sender).await,
+                Db::Postgres(pool) => {
+                    let sql = postgres_sql(&self.sql);
+                    forward(self.postgres(&sql).fetch(pool), sender).await
+                }
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+
+    fn sqlite(&self) -> sqlx::query::Query<'_, Sqlite, sqlx::sqlite::SqliteArguments<'_>> {
+        self.args.iter().cloned().fold(sqlx::query(&self.sql), |query, arg| match arg {
+            Arg::Text(value) => query.bind(value),
+            Arg::Int(value) => query.bind(value),
+            Arg::Float(value) => query.bind(value),
+            Arg::Bool(value) => query.bind(value),
+            Arg::Bytes(value) => query.bind(value),
+            Arg::Timestamp(value) => query.bind(value),
+            Arg::Date(value) => query.bind(value),
+            Arg::Uuid(value) => query.bind(value.map(|id| id.to_string())),
+        })
+    }
+
+    fn postgres<'q>(&self, sql: &'q str) -> sqlx::query::Query<'q, Postgres, sqlx::postgres::PgArguments> {
+        self.args.iter().cloned().fold(sqlx::query(sql), |query, arg| match arg {
+            Arg::Text(value) => query.bind(value),
+            Arg::Int(value) => query.bind(value),
+            Arg::Float(value) => query.bind(value),
+            Arg::Bool(value) => query.bind(value),
+            Arg::Bytes(value) => query.bind(value),
+            Arg::Timestamp(value) => query.bind(value),
+            Arg::Date(value) => query.bind(value),
+            Arg::Uuid(value) => query.bind(value),
+        })
+    }
+}
+
+/// Rows read ahead of a [`Query::stream`] reader.
+const STREAM_BUFFER: usize = 64;
+
+pub type RowStream = ReceiverStream<Result<Row, sqlx::Error>>;
+
+async fn forward<R: Into<Row>>(
+    mut rows: impl Stream<Item = Result<R, sqlx::Error>> + Unpin,
+    sender: mpsc::Sender<Result<Row, sqlx::Error>>,
+) {
+    while let Some(row) = rows.next().await {
+        let failed = row.is_err();
+        if sender.send(row.map(Into::into)).await.is_err() || failed {
+            break;
+        }
+    }
+}
+
+struct Rows(Vec<Row>);
+struct OptionalRow(Option<Row>);
+
+impl From<Vec<SqliteRow>> for Rows {
+    fn from(rows: Vec<SqliteRow>) -> Self {
+        Rows(rows.into_iter().map(Row::Sqlite).collect())
+    }
+}
+
+impl From<Vec<PgRow>> for Rows {
+    fn from(rows: Vec<PgRow>) -> Self {
+        Rows(rows.into_iter().map(Row::Postgres).collect())
+    }
+}
+
+impl From<Option<SqliteRow>> for OptionalRow {
+    fn from(row: Option<SqliteRow>) -> Self {
+        OptionalRow(row.map(Row::Sqlite))
+    }
+}
+
+impl From<Option<PgRow>> for OptionalRow {
+    fn from(row: Option<PgRow>) -> Self {
+        OptionalRow(row.map(Row::Postgres))
+    }
+}
+
+impl From<SqliteRow> for Row {
+    fn from(row: SqliteRow) -> Self {
+        Row::Sqlite(row)
+    }
+}
+
+impl From<PgRow> for Row {
+    fn from(row: PgRow) -> Self {
+        Row::Postgres(row)
+    }
+}
+
+pub struct QueryAs<T>(Query, std::marker::PhantomData<T>);
+
+impl<T: FromRow> QueryAs<T> {
+    pub fn bind(self, value: impl IntoArg) -> Self {
+        QueryAs(self.0.bind(value), self.1)
+    }
+
+    pub async fn fetch_all<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Vec<T>, sqlx::Error> {
+        self.0.fetch_all(conn).await?.iter().map(T::from_row).collect()
+    }
+
+    pub async fn fetch_optional<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Option<T>, sqlx::Error> {
+        self.0.fetch_optional(conn).await?.as_ref().map(T::from_row).transpose()
+    }
+}
+
+pub struct QueryScalar<T>(Query, std::marker::PhantomData<T>);
+
+impl<T: FromSql> QueryScalar<T> {
+    pub fn bind(self, value: impl IntoArg) -> Self {
+        QueryScalar(self.0.bind(value), self.1)
+    }
+
+    pub async fn fetch_all<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Vec<T>, sqlx::Error> {
+        self.0.fetch_all(conn).await?.iter().map(|row| row.try_get(0)).collect()
+    }
+
+    pub async fn fetch_one<'c>(self, conn: impl Into<Conn<'c>>) -> Result<T, sqlx::Error> {
+        self.0.fetch_one(conn).await?.try_get(0)
+    }
+
+    pub async fn fetch_optional<'c>(self, conn: impl Into<Conn<'c>>) -> Result<Option<T>, sqlx::Error> {
+        self.0.fetch_optional(conn).await?.map(|row| row.try_get(0)).transpose()
+    }
+}
+
+/// Rewrites `?` placeholders as `$1`, `$2`, … , leaving string literals,
+/// quoted identifiers and comments alone. SQLite reads a negative `LIMIT` as
+/// no limit but PostgreSQL rejects one, so `LIMIT ?` takes NULL (no limit)
+/// for `-1`.
+fn postgres_sql(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len() + 16);
+    let mut chars = sql.chars().peekable();
+    let mut placeholders = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                out.push(c);
+                for quoted in chars.by_ref() {
+                    out.push(quoted);
+                    if quoted == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                out.push(c);
+                for commented in chars.by_ref() {
+                    out.push(commented);
+                    if commented == '\n' {
+                        break;
+                    }
+                }
+            }
+            '?' => {
+                placeholders += 1;
+                let placeholder = format!("${}", placeholders);
+                if out.trim_end().to_ascii_uppercase().ends_with("LIMIT") {
+                    out.push_str(&format!("NULLIF({}, -1)", placeholder));
+                } else {
+                    out.push_str(&placeholder);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A result row from either backend.
+pub enum Row {
+    Sqlite(SqliteRow),
+    Postgres(PgRow),
+}
+
+/// A column name or position, valid for rows of both backends.
+pub trait Index: ColumnIndex<SqliteRow> + ColumnIndex<PgRow> + Copy {}
+
+impl<I: ColumnIndex<SqliteRow> + ColumnIndex<PgRow> + Copy> Index for I {}
+
+impl Row {
+    /// Reads a column, panicking if it is missing or of the wrong type.
+    pub fn get<T: FromSql, I: Index>(&self, index: I) -> T {
+        self.try_get(index)
+            .unwrap_or_else(|err| panic!("error reading column {:?}: {}", index, err))
+    }
+
+    pub fn try_get<T: FromSql, I: Index>(&self, index: I) -> Result<T, sqlx::Error> {
+        match self {
+            Row::Sqlite(row) => T::from_sqlite(row, index),
+            Row::Postgres(row) => {
+                if row.try_get_raw(index)?.is_null() {
+                    T::from_pg_null()
+                } else {
+                    T::from_pg(row, index)
+                }
+            }
+        }
+    }
+}
+
+/// A Rust type a column can be read as on either backend.
+pub trait FromSql: Sized {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error>;
+    /// Reads a non-NULL value.
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error>;
+
+    fn from_pg_null() -> Result<Self, sqlx::Error> {
+        Err(sqlx::Error::Decode("unexpected NULL".into()))
+    }
+}
+
+fn pg_type<I: Index>(row: &PgRow, index: I) -> Result<String, sqlx::Error> {
+    Ok(row.try_get_raw(index)?.type_info().name().to_string())
+}
+
+fn decode_error(index: impl std::fmt::Debug, err: impl std::fmt::Display) -> sqlx::Error {
+    sqlx::Error::ColumnDecode {
+        index: format!("{:?}", index),
+        source: err.to_string().into(),
+    }
+}
+
+impl<T: FromSql> FromSql for Option<T> {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+        if row.try_get_raw(index)?.is_null() {
+            Ok(None)
+        } else {
+            T::from_sqlite(row, index).map(Some)
+        }
+    }
+
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+        T::from_pg(row, index).map(Some)
+    }
+
+    fn from_pg_null() -> Result<Self, sqlx::Error> {
+        Ok(None)
+    }
+}
+
+/// Types both drivers decode the same way.
+macro_rules! from_sql {
+    ($($ty:ty),+) => {
+        $(
+            impl FromSql for $ty {
+                fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+                    row.try_get(index)
+                }
+                fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+                    row.try_get(index)
+                }
+            }
+        )+
+    };
+}
+
+from_sql!(bool, Vec<u8>, DateTime<Utc>, NaiveDate);
+
+impl FromSql for String {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+        row.try_get(index)
+    }
+
+    /// Also reads UUIDs and dates, which SQLite stores as text.
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+        match pg_type(row, index)?.as_str() {
+            "UUID" => row.try_get::<Uuid, _>(index).map(|id| id.to_string()),
+            "DATE" => row.try_get::<NaiveDate, _>(index).map(|date| date.to_string()),
+            _ => row.try_get(index),
+        }
+    }
+}
+
+impl FromSql for Uuid {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+        let text: String = row.try_get(index)?;
+        Uuid::parse_str(&text).map_err(|err| decode_error(index, err))
+    }
+
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+        row.try_get(index)
+    }
+}
+
+impl FromSql for i64 {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+        row.try_get(index)
+    }
+
+    /// Also reads the narrower integer types and the `NUMERIC` that `SUM`
+    /// returns.
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+        match pg_type(row, index)?.as_str() {
+            "INT2" => row.try_get::<i16, _>(index).map(i64::from),
+            "INT4" => row.try_get::<i32, _>(index).map(i64::from),
+            "NUMERIC" => {
+                let numeric = Numeric::read(row.try_get_raw(index)?).map_err(|err| decode_error(index, err))?;
+                i64::try_from(numeric.integer()).map_err(|err| decode_error(index, err))
+            }
+            _ => row.try_get(index),
+        }
+    }
+}
+
+impl FromSql for f64 {
+    fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+        row.try_get(index)
+    }
+
+    fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+        match pg_type(row, index)?.as_str() {
+            "FLOAT4" => row.try_get::<f32, _>(index).map(f64::from),
+            "NUMERIC" => Numeric::read(row.try_get_raw(index)?)
+                .map(|numeric| numeric.float())
+                .map_err(|err| decode_error(index, err)),
+            "INT2" | "INT4" | "INT8" => i64::from_pg(row, index).map(|value| value as f64),
+            _ => row.try_get(index),
+        }
+    }
+}
+
+/// Narrower integers, read as `i64` and range checked.
+macro_rules! from_sql_int {
+    ($($ty:ty),+) => {
+        $(
+            impl FromSql for $ty {
+                fn from_sqlite<I: Index>(row: &SqliteRow, index: I) -> Result<Self, sqlx::Error> {
+                    <$ty>::try_from(i64::from_sqlite(row, index)?).map_err(|err| decode_error(index, err))
+                }
+                fn from_pg<I: Index>(row: &PgRow, index: I) -> Result<Self, sqlx::Error> {
+                    <$ty>::try_from(i64::from_pg(row, index)?).map_err(|err| decode_error(index, err))
+                }
+            }
+        )+
+    };
+}
+
+from_sql_int!(i32, u8, u16, u32);
+
+/// A PostgreSQL `NUMERIC`, which sqlx only decodes with a decimal crate.
+/// Binary values are base-10000 digits: `value = Σ digit[i] × 10000^(weight − i)`.
+struct Numeric {
+    negative: bool,
+    weight: i16,
+    digits: Vec<i16>,
+}
+
+impl Numeric {
+    fn read(value: PgValueRef<'_>) -> Result<Numeric, String> {
+        if value.format() == PgValueFormat::Text {
+            let text = value.as_str().map_err(|err| err.to_string())?;
+            return Numeric::parse(text);
+        }
+        let bytes = value.as_bytes().map_err(|err| err.to_string())?;
+        let word = |at: usize| -> Result<i16, String> {
+            bytes
+                .get(at..at + 2)
+                .map(|pair| i16::from_be_bytes([pair[0], pair[1]]))
+                .ok_or_else(|| "truncated NUMERIC".to_string())
+        };
+        let count = word(0)?.max(0) as usize;
+        let sign = word(4)? as u16;
+        if sign == 0xC000 {
+            return Err("NUMERIC is NaN".to_string());
+        }
+        Ok(Numeric {
+            negative: sign == 0x4000,
+            weight: word(2)?,
+            digits: (0..count).map(|i| word(8 + 2 * i)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Reads the text form, for values the server sent as text.
+    fn parse(text: &str) -> Result<Numeric, String> {
+        let float: f64 = text.parse().map_err(|_| format!("invalid NUMERIC {:?}", text))?;
+        let integer = float.trunc().abs();
+        let mut digits = Vec::new();
+        let mut rest = integer;
+        while rest >= 1.0 {
+            digits.insert(0, (rest % 10000.0) as i16);
+            rest = (rest / 10000.0).trunc();
+        }
+        Ok(Numeric {
+            negative: float < 0.0,
+            weight: digits.len() as i16 - 1,
+            digits,
+        })
+    }
+
+    /// The value with any fraction dropped.
+    fn integer(&self) -> i128 {
+        let magnitude = (0..=self.weight.max(-1))
+            .fold(0i128, |value, i| value * 10000 + i128::from(*self.digits.get(i as usize).unwrap_or(&0)));
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    fn float(&self) -> f64 {
+        let magnitude: f64 = self
+            .digits
+            .iter()
+            .enumerate()
+            .map(|(i, digit)| f64::from(*digit) * 10000f64.powi(i32::from(self.weight) - i as i32))
+            .sum();
+        if self.negative { -magnitude } else { magnitude }
+    }
+}
+
+/// Rows read as a tuple of columns, in order.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, sqlx::Error>;
+}
+
+macro_rules! from_row_tuple {
+    ($($ty:ident: $index:tt),+) => {
+        impl<$($ty: FromSql),+> FromRow for ($($ty,)+) {
+            fn from_row(row: &Row) -> Result<Self, sqlx::Error> {
+                Ok(($(row.try_get::<$ty, usize>($index)?,)+))
+            }
+        }
+    };
+}
+
+from_row_tuple!(A: 0);
+from_row_tuple!(A: 0, B: 1);
+from_row_tuple!(A: 0, B: 1, C: 2);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3);
+
+impl Backend {
+    /// `expr` as an instant that compares correctly. SQLite compares text,
+    /// so timestamps written with different offsets or precision are first
+    /// normalised with `datetime()`.
+    pub fn instant(self, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("datetime({})", expr),
+            Backend::Postgres => expr.to_string(),
+        }
+    }
+
+    /// The instant `expr` as whole seconds since the Unix epoch, like
+    /// `DateTime::timestamp`.
+    pub fn unix_seconds(self, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("CAST(strftime('%s', {}) AS INTEGER)", expr),
+            Backend::Postgres => format!("CAST(FLOOR(EXTRACT(EPOCH FROM {})) AS BIGINT)", expr),
+        }
+    }
+
+    /// Appended to a `SELECT` in a transaction to lock the rows it reads
+    /// until the transaction ends. SQLite has no row locks: a transaction
+    /// takes the database write lock when it first writes, and fails rather
+    /// than writes if another write committed after it began reading.
+    pub fn for_update(self) -> &'static str {
+        match self {
+            Backend::Sqlite => "",
+            Backend::Postgres => " FOR UPDATE",
+        }
+    }
+
+    /// `column` within the `[from, to)` instants bound next, as from
+    /// [`BusinessTz::day_bounds`].
+    pub fn in_range(self, column: &str) -> String {
+        let (column, bound) = (self.instant(column), self.instant("?"));
+        format!("{} >= {} AND {} < {}", column, bound, column, bound)
+    }
+
+    /// The local wall-clock time of the instant in `column`, exact for the
+    /// days `start..=end`. PostgreSQL has the timezone database; SQLite
+    /// spells out the offsets ([`BusinessTz::local_sql`]).
+    pub fn local_time(self, tz: BusinessTz, column: &str, start: NaiveDate, end: NaiveDate) -> String {
+        match self {
+            Backend::Sqlite => tz.local_sql(column, start, end),
+            Backend::Postgres => format!("({} AT TIME ZONE '{}')", column, tz.name()),
+        }
+    }
+
+    /// Formats the local time `expr` with a `strftime` pattern using `%Y`,
+    /// `%m`, `%d` and `%H` only.
+    pub fn strftime(self, format: &str, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("strftime('{}', {})", format, expr),
+            Backend::Postgres => {
+                let mut pattern = String::new();
+                let mut chars = format.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '%' => pattern.push_str(match chars.next() {
+                            Some('Y') => "YYYY",
+                            Some('m') => "MM",
+                            Some('d') => "DD",
+                            Some('H') => "HH24",
+                            other => panic!("unsupported strftime field {:?}", other),
+                        }),
+                        c if c.is_ascii_alphabetic() => pattern.push_str(&format!("\"{}\"", c)),
+                        c => pattern.push(c),
+                    }
+                }
+                format!("to_char({}, '{}')", expr, pattern)
+            }
+        }
+    }
+
+    /// The date of the local time `expr`, as `YYYY-MM-DD` text.
+    pub fn date(self, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("date({})", expr),
+            Backend::Postgres => self.strftime("%Y-%m-%d", expr),
+        }
+    }
+
+    /// The date the week containing the local time `expr` starts on, for
+    /// weeks starting `first_day` days after Sunday.
+    pub fn week_start(self, expr: &str, first_day: u32) -> String {
+        match self {
+            // Back six days, then forward to the week's first day
+            Backend::Sqlite => format!("date({}, '-6 days', 'weekday {}')", expr, first_day),
+            Backend::Postgres => format!(
+                "to_char(CAST({0} AS DATE) - (CAST(EXTRACT(DOW FROM {0}) AS INTEGER) + {1}) % 7, 'YYYY-MM-DD')",
+                expr,
+                7 - first_day % 7
+            ),
+        }
+    }
+
+    /// Day of the week of the local time `expr`, Monday 0 to Sunday 6.
+    pub fn weekday(self, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("(CAST(strftime('%w', {}) AS INTEGER) + 6) % 7", expr),
+            Backend::Postgres => format!("(CAST(EXTRACT(DOW FROM {}) AS INTEGER) + 6) % 7", expr),
+        }
+    }
+
+    /// Hour of the local time `expr`, 0 to 23.
+    pub fn hour(self, expr: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("CAST(strftime('%H', {}) AS INTEGER)", expr),
+            Backend::Postgres => format!("CAST(EXTRACT(HOUR FROM {}) AS INTEGER)", expr),
+        }
+    }
+
+    /// Whether the comma-separated list in `column` has the value bound next.
+    pub fn list_contains(self, column: &str) -> String {
+        let function = match self {
+            Backend::Sqlite => "instr",
+            Backend::Postgres => "strpos",
+        };
+        format!("{}(',' || {} || ',', ',' || ? || ',') > 0", function, column)
+    }
+
+    /// Turns free text into a full-text query where every word must match as
+    /// a prefix, or, for a word in `corrections`, be one of its replacements.
+    /// User input cannot inject query syntax: SQLite gets each word quoted
+    /// with quotes stripped, PostgreSQL only its letters and digits, split
+    /// where the index splits words.
+    pub fn fts_query(self, input: &str, corrections: &HashMap<String, Vec<String>>) -> Option<String> {
+        let words = input.split_whitespace().filter(|word| word.chars().any(char::is_alphanumeric));
+        let terms: Vec<String> = match self {
+            Backend::Sqlite => words
+                .map(|word| match corrections.get(&word.to_lowercase()) {
+                    Some(replacements) => format!("(\"{}\")", replacements.join("\" OR \"")),
+                    None => format!("\"{}\"*", word.replace('"', "")),
+                })
+                .collect(),
+            Backend::Postgres => words
+                .flat_map(|word| match corrections.get(&word.to_lowercase()) {
+                    Some(replacements) => vec![format!("('{}')", replacements.join("' | '"))],
+                    None => word
+                        .split(|c: char| !c.is_alphanumeric())
+                        .filter(|part| !part.is_empty())
+                        .map(|part| format!("'{}':*", part))
+                        .collect(),
+                })
+                .collect(),
+        };
+        if terms.is_empty() {
+            return None;
+        }
+        Some(match self {
+            Backend::Sqlite => terms.join(" AND "),
+            Backend::Postgres => terms.join(" & "),
+        })
+    }
+
+    /// Selects every distinct word in the full-text index `fts`.
+    pub fn fts_vocabulary(self, fts: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("SELECT term FROM {}_vocab", fts),
+            Backend::Postgres => format!("SELECT word FROM ts_stat('SELECT document FROM {}')", fts),
+        }
+    }
+
+    /// Joined onto a `FROM` clause that includes a full-text index to make
+    /// the query bound next available to [`Backend::fts_match`]; empty for
+    /// SQLite, which binds it in the match itself.
+    pub fn fts_source(self) -> &'static str {
+        match self {
+            Backend::Sqlite => "",
+            Backend::Postgres => " CROSS JOIN to_tsquery('simple', ?) AS fts_query",
+        }
+    }
+
+    /// Whether the row of the index `fts` (`products_fts`, `customers_fts` or
+    /// `sales_fts`) matches the query.
+    pub fn fts_match(self, fts: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("{} MATCH ?", fts),
+            Backend::Postgres => format!("{}.document @@ fts_query", fts),
+        }
+    }
+
+    /// How well the row of `fts` matches, lower is better. `weights` are
+    /// SQLite's bm25 column weights; PostgreSQL weighs the columns in its
+    /// index definition.
+    pub fn fts_rank(self, fts: &str, weights: &[f64]) -> String {
+        match self {
+            Backend::Sqlite if weights.is_empty() => format!("{}.rank", fts),
+            Backend::Sqlite => format!(
+                "bm25({}, {})",
+                fts,
+                weights.iter().map(|weight| format!("{:.1}", weight)).collect::<Vec<_>>().join(", ")
+            ),
+            Backend::Postgres => format!("CAST(-ts_rank({}.document, fts_query) AS DOUBLE PRECISION)", fts),
+        }
+    }
+
+    /// A short extract of the indexed `columns` of `fts` around the match,
+    /// with matched words between `\u{2}` and `\u{3}`.
+    pub fn fts_snippet(self, fts: &str, columns: &[&str]) -> String {
+        match self {
+            Backend::Sqlite => format!("snippet({}, -1, char(2), char(3), '…', 12)", fts),
+            Backend::Postgres => format!(
+                "ts_headline('simple', concat_ws(' ', {}), fts_query, \
+                 'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxWords=12, MinWords=4')",
+                columns.iter().map(|column| format!("{}.{}", fts, column)).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    /// Counts the tables named by the value bound next.
+    pub fn table_exists(self) -> &'static str {
+        match self {
+            Backend::Sqlite => "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+            Backend::Postgres => {
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = ?"
+            }
+        }
+    }
+
+    /// Counts the columns of `table` named by the value bound next.
+    pub fn column_exists(self, table: &str) -> String {
+        match self {
+            Backend::Sqlite => format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?", table),
+            Backend::Postgres => format!(
+                "SELECT COUNT(*) FROM information_schema.columns
+                 WHERE table_schema = current_schema() AND table_name = '{}' AND column_name = ?",
+                table
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{databases, drop_database};
+
+    #[test]
+    fn postgres_numbers_placeholders() {
+        assert_eq!(
+            postgres_sql("SELECT '?' FROM t WHERE a = ? -- b = ?\nAND c = ? LIMIT ? OFFSET ?"),
+            "SELECT '?' FROM t WHERE a = $1 -- b = ?\nAND c = $2 LIMIT NULLIF($3, -1) OFFSET $4"
+        );
+    }
+
+    #[tokio::test]
+    async fn values_round_trip() {
+        for db in databases().await {
+            let backend = db.backend();
+            let id = Uuid::new_v4();
+            let now = Utc::now();
+            let mut tx = db.begin().await.unwrap();
+            for (name, price) in [("Cola Zero", 250), ("Bread", 400)] {
+                query(
+                    "INSERT INTO products (id, name, description, price_cents, stock, product_type, archived_at) VALUES (?, ?, '', ?, 1, 'physical_good', ?)",
+                )
+                .bind(if price == 250 { id } else { Uuid::new_v4() })
+                .bind(name)
+                .bind(price)
+                .bind(None::<DateTime<Utc>>)
+                .execute(&mut tx)
+                .await
+                .unwrap();
+            }
+            query("INSERT INTO stock_alerts (id, product_id, product_name, stock, reorder_point, reorder_quantity, created_at) VALUES (?, ?, 'Cola Zero', 1, 5, 10, ?)")
+                .bind(Uuid::new_v4())
+                .bind(id)
+                .bind(now)
+                .execute(&mut tx)
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+
+            let (text_id, uuid_id): (String, Uuid) = query_as("SELECT id, id FROM products WHERE name = ?")
+                .bind("Cola Zero")
+                .fetch_optional(&db)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!((text_id, uuid_id), (id.to_string(), id), "{:?}", backend);
+
+            let row = query("SELECT created_at, acknowledged FROM stock_alerts").fetch_one(&db).await.unwrap();
+            let created_at: DateTime<Utc> = row.get("created_at");
+            assert_eq!(created_at.timestamp_micros(), now.timestamp_micros(), "{:?}", backend);
+            assert!(!row.get::<bool, _>("acknowledged"), "{:?}", backend);
+
+            let total: i64 = query_scalar("SELECT SUM(price_cents) FROM products").fetch_one(&db).await.unwrap();
+            assert_eq!(total, 650, "{:?}", backend);
+            let all: Vec<String> = query_scalar("SELECT name FROM products ORDER BY name LIMIT ?")
+                .bind(-1)
+                .fetch_all(&db)
+                .await
+                .unwrap();
+            assert_eq!(all, ["Bread", "Cola Zero"], "{:?}", backend);
+
+            let found: Vec<String> = query_scalar(format!(
+                "SELECT products.name FROM products_fts JOIN products ON products.id = products_fts.product_id{} WHERE {}",
+                backend.fts_source(),
+                backend.fts_match("products_fts")
+            ))
+            .bind(backend.fts_query("col", &HashMap::new()).unwrap())
+            .fetch_all(&db)
+            .await
+            .unwrap();
+            assert_eq!(found, ["Cola Zero"], "{:?}", backend);
+
+            drop_database(db).await;
+        }
+    }
+}
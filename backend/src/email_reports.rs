@@ -0,0 +1,514 @@
+//! Scheduled email reports: rendering a subscription's report to HTML (and
+//! optionally a CSV attachment), the SMTP [`Mailer`] that sends it, and the
+//! scheduler that sends subscriptions as they come due. Subscriptions are
+//! managed through the `/api/report_subscriptions` handlers.
+//!
+//! The transport is set up from the `[smtp]` section of the configuration,
+//! see [`crate::config`].
+
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveTime, Utc};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use shared::models::{ReportFrequency, ReportSubscription, ReportType};
+
+use crate::config::{SmtpConfig, SmtpTls};
+use crate::db::Db;
+use crate::error::AppError;
+use crate::export::{Cell, Exportable, Sheet, cell_text, csv_text};
+use crate::handlers::daily_sales;
+use crate::repo;
+use crate::timezone::BusinessTz;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Wait before retrying a failed send, unless the next run comes sooner
+const RETRY_DELAY_MINUTES: i64 = 15;
+/// Rows in `top_products` when the subscription does not set a limit
+const DEFAULT_TOP_PRODUCTS: usize = 10;
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// Sends report emails through the configured SMTP relay.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    /// A mailer for `config`; without a host it sends nothing. Fails on a
+    /// sender or host that cannot be used, so a misconfiguration is noticed
+    /// at startup rather than at 7am.
+    pub fn new(config: &SmtpConfig) -> Result<Mailer, String> {
+        let from = config
+            .from
+            .parse()
+            .map_err(|err| format!("smtp.from {:?} is not a valid mailbox: {}", config.from, err))?;
+        let Some(host) = &config.host else {
+            return Ok(Mailer { transport: None, from });
+        };
+
+        let (builder, default_port) = match config.tls {
+            SmtpTls::Starttls => (AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host), 587),
+            SmtpTls::Tls => (AsyncSmtpTransport::<Tokio1Executor>::relay(host), 465),
+            SmtpTls::None => (Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)), 25),
+        };
+        let port = config.port.unwrap_or(default_port);
+        let mut builder = builder
+            .map_err(|err| format!("smtp.host {:?} cannot be used: {}", host, err))?
+            .port(port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if let Some(username) = &config.username {
+            builder = builder.credentials(Credentials::new(username.clone(), config.password.clone()));
+        }
+        tracing::info!("Sending report emails through {}:{} ({:?})", host, port, config.tls);
+        Ok(Mailer { transport: Some(builder.build()), from })
+    }
+
+    async fn send(&self, recipients: &[String], report: RenderedReport) -> Result<(), String> {
+        let transport = self.transport.as_ref().ok_or("SMTP is not configured")?;
+
+        let mut builder = Message::builder().from(self.from.clone()).subject(report.subject);
+        for recipient in recipients {
+            let mailbox: Mailbox = recipient
+                .parse()
+                .map_err(|err| format!("invalid recipient {:?}: {}", recipient, err))?;
+            builder = builder.to(mailbox);
+        }
+        let html = SinglePart::html(report.html);
+        let message = match report.csv {
+            Some((filename, csv)) => builder.multipart(
+                MultiPart::mixed().singlepart(html).singlepart(
+                    Attachment::new(filename)
+                        .body(csv, ContentType::parse("text/csv; charset=utf-8").expect("valid content type")),
+                ),
+            ),
+            None => builder.singlepart(html),
+        }
+        .map_err(|err| err.to_string())?;
+
+        transport.send(message).await.map(|_| ()).map_err(|err| err.to_string())
+    }
+}
+
+pub struct RenderedReport {
+    pub subject: String,
+    pub html: String,
+    /// Attachment file name and contents
+    pub csv: Option<(String, String)>,
+}
+
+/// Runs forever, sending every active subscription whose time has come.
+pub async fn run_scheduler(db: Db, mailer: Mailer, tz: BusinessTz) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = send_due(&db, &mailer, tz).await {
+            tracing::error!("Sending scheduled reports failed: {:?}", err);
+        }
+    }
+}
+
+async fn send_due(db: &Db, mailer: &Mailer, tz: BusinessTz) -> Result<(), AppError> {
+    for subscription in repo::report_subscriptions::due(db, Utc::now()).await? {
+        send(db, mailer, tz, &subscription, true).await?;
+    }
+    Ok(())
+}
+
+/// Renders and emails a subscription's report now and records the outcome
+/// on it. A scheduled run also moves `next_run_at` on: to the following
+/// occurrence, or a retry a little later if the send failed. Only database
+/// errors are returned; a failed send is recorded in `last_error`.
+pub async fn send(
+    db: &Db,
+    mailer: &Mailer,
+    tz: BusinessTz,
+    subscription: &ReportSubscription,
+    scheduled: bool,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let result = match render(db, tz, subscription, now).await {
+        Ok(report) => mailer.send(&subscription.recipients, report).await,
+        Err(err) => Err(err.into_body().message),
+    };
+
+    let next_run_at = if scheduled {
+        let next = next_run(tz, subscription.frequency, subscription.hour, now);
+        match result {
+            Ok(()) => next,
+            Err(_) => next.min(now + chrono::Duration::minutes(RETRY_DELAY_MINUTES)),
+        }
+    } else {
+        subscription.next_run_at
+    };
+    let (last_sent_at, last_error) = match result {
+        Ok(()) => (Some(now), None),
+        Err(err) => (
+            subscription.last_sent_at,
+            Some(err.chars().take(MAX_ERROR_LENGTH).collect::<String>()),
+        ),
+    };
+    repo::report_subscriptions::record_send(db, subscription.id, next_run_at, last_sent_at, last_error).await
+}
+
+/// First time after `after` that a subscription sending at the local `hour`
+/// runs.
+pub fn next_run(tz: BusinessTz, frequency: ReportFrequency, hour: u32, after: DateTime<Utc>) -> DateTime<Utc> {
+    let time = NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or(NaiveTime::MIN);
+    let mut day = tz.date_of(after);
+    loop {
+        let due = match frequency {
+            ReportFrequency::Daily => true,
+            ReportFrequency::Weekly => day.weekday() == chrono::Weekday::Mon,
+            ReportFrequency::Monthly => day.day() == 1,
+        };
+        let at = tz.at(day, time);
+        if due && at > after {
+            return at;
+        }
+        day = day + Days::new(1);
+    }
+}
+
+/// Days a report sent on `today` covers, inclusive: yesterday, the seven
+/// days up to yesterday, or the previous calendar month.
+fn report_period(frequency: ReportFrequency, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let yesterday = today - Days::new(1);
+    match frequency {
+        ReportFrequency::Daily => (yesterday, yesterday),
+        ReportFrequency::Weekly => (today - Days::new(7), yesterday),
+        ReportFrequency::Monthly => {
+            let first_of_month = today.with_day(1).unwrap_or(today);
+            (first_of_month - Months::new(1), first_of_month - Days::new(1))
+        }
+    }
+}
+
+/// The email a subscription would send at `now`.
+pub async fn render(
+    db: &Db,
+    tz: BusinessTz,
+    subscription: &ReportSubscription,
+    now: DateTime<Utc>,
+) -> Result<RenderedReport, AppError> {
+    let report_type = subscription.report_type;
+    let today = tz.date_of(now);
+    let (start, end) = report_period(subscription.frequency, today);
+    let (period, summary, sheets) = match report_type {
+        ReportType::DailyTakings => {
+            let days = daily_sales(db, tz, start, end).await?;
+            let total: i64 = days.iter().map(|day| day.total_sales_cents).sum();
+            let count: i64 = days.iter().map(|day| day.count).sum();
+            let summary = format!(
+                "Takings of {} from {} item{} sold.",
+                cell_text(&Cell::Money(total)),
+                count,
+                if count == 1 { "" } else { "s" }
+            );
+            (Some((start, end)), Some(summary), days.sheets())
+        }
+        ReportType::TopProducts => {
+            let limit = subscription
+                .parameters
+                .limit
+                .map(|limit| limit.max(1) as usize)
+                .unwrap_or(DEFAULT_TOP_PRODUCTS);
+            let products: Vec<_> = repo::reports::product_sales(db, tz, start, end)
+                .await?
+                .into_iter()
+                .take(limit)
+                .map(|(_, summary)| summary)
+                .collect();
+            (Some((start, end)), None, products.sheets())
+        }
+        ReportType::LowStock => {
+            let (products, total) = repo::products::low_stock(db, -1, 0).await?;
+            let summary = if total == 0 {
+                "No products are at or below their reorder point.".to_string()
+            } else {
+                format!("{} product{} at or below the reorder point.", total, if total == 1 { " is" } else { "s are" })
+            };
+            (None, Some(summary), products.sheets())
+        }
+    };
+
+    let period_text = match period {
+        Some((start, end)) if start == end => start.to_string(),
+        Some((start, end)) => format!("{} to {}", start, end),
+        None => today.to_string(),
+    };
+    let subject = format!("{}: {} for {}", subscription.name, report_type.title(), period_text);
+    let csv = subscription.parameters.attach_csv.then(|| {
+        let filename = format!("{}-{}.csv", report_type.as_str().replace('_', "-"), period_text.replace(" to ", "_"));
+        (filename, csv_text(sheets.clone()))
+    });
+    let html = html_document(&subject, summary.as_deref(), &sheets);
+    Ok(RenderedReport { subject, html, csv })
+}
+
+/// A standalone page with the report's sheets as tables, titled ones under
+/// their title.
+pub(crate) fn html_document(heading: &str, summary: Option<&str>, sheets: &[Sheet]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body style=\"font-family: sans-serif; color: #222;\">\n<h2>{0}</h2>\n",
+        escape(heading)
+    );
+    if let Some(summary) = summary {
+        html.push_str(&format!("<p>{}</p>\n", escape(summary)));
+    }
+    for sheet in sheets {
+        if let Some(title) = sheet.title {
+            html.push_str(&format!("<h3>{}</h3>\n", escape(title)));
+        }
+        html.push_str(&html_table(sheet));
+    }
+    html.push_str(&format!(
+        "<p style=\"color: #888; font-size: 12px;\">Amounts in {}.</p>\n</body></html>\n",
+        escape(frontend::utils::CURRENCY)
+    ));
+    html
+}
+
+/// Columns without a value in any row, such as comparison columns that a
+/// report never fills, are left out.
+fn html_table(sheet: &Sheet) -> String {
+    if sheet.rows.is_empty() {
+        return "<p><em>Nothing to report.</em></p>\n".to_string();
+    }
+    let columns: Vec<usize> = (0..sheet.headers.len())
+        .filter(|&column| {
+            sheet
+                .rows
+                .iter()
+                .any(|row| row.get(column).is_some_and(|cell| !matches!(cell, Cell::Empty)))
+        })
+        .collect();
+
+    let mut html = String::from("<table style=\"border-collapse: collapse;\" cellpadding=\"6\">\n<tr>");
+    for &column in &columns {
+        html.push_str(&format!(
+            "<th style=\"border-bottom: 2px solid #ccc; text-align: left;\">{}</th>",
+            escape(sheet.headers[column])
+        ));
+    }
+    html.push_str("</tr>\n");
+    for row in &sheet.rows {
+        html.push_str("<tr>");
+        for &column in &columns {
+            let cell = row.get(column).unwrap_or(&Cell::Empty);
+            let align = if matches!(cell, Cell::Text(_)) { "left" } else { "right" };
+            html.push_str(&format!(
+                "<td style=\"border-bottom: 1px solid #eee; text-align: {};\">{}</td>",
+                align,
+                escape(&cell_text(cell))
+            ));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TestApp, create_product, open_shift, sale_input};
+    use chrono::TimeZone;
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn instant(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn runs_come_round_at_the_local_hour() {
+        let tz = BusinessTz::new(chrono_tz::America::New_York);
+        let daily = |after| next_run(tz, ReportFrequency::Daily, 7, instant(after));
+        assert_eq!(daily("2026-03-06T11:00:00Z"), instant("2026-03-06T12:00:00Z"));
+        assert_eq!(daily("2026-03-06T12:00:00Z"), instant("2026-03-07T12:00:00Z"));
+        // Clocks go forward on 8 March
+        assert_eq!(daily("2026-03-07T12:00:00Z"), instant("2026-03-08T11:00:00Z"));
+
+        // 2026-03-06 is a Friday
+        assert_eq!(next_run(tz, ReportFrequency::Weekly, 7, instant("2026-03-06T11:00:00Z")), instant("2026-03-09T11:00:00Z"));
+        assert_eq!(next_run(tz, ReportFrequency::Monthly, 0, instant("2026-03-01T04:59:59Z")), instant("2026-03-01T05:00:00Z"));
+        assert_eq!(next_run(tz, ReportFrequency::Monthly, 0, instant("2026-03-01T05:00:00Z")), instant("2026-04-01T04:00:00Z"));
+    }
+
+    #[test]
+    fn reports_cover_the_days_before_they_are_sent() {
+        let period = |frequency, today| report_period(frequency, date(today));
+        assert_eq!(period(ReportFrequency::Daily, "2026-03-01"), (date("2026-02-28"), date("2026-02-28")));
+        assert_eq!(period(ReportFrequency::Weekly, "2026-03-02"), (date("2026-02-23"), date("2026-03-01")));
+        assert_eq!(period(ReportFrequency::Monthly, "2026-03-01"), (date("2026-02-01"), date("2026-02-28")));
+        assert_eq!(period(ReportFrequency::Monthly, "2026-01-15"), (date("2025-12-01"), date("2025-12-31")));
+    }
+
+    async fn subscription(app: &TestApp, input: Value) -> ReportSubscription {
+        serde_json::from_value(app.create("/api/report_subscriptions", input).await).unwrap()
+    }
+
+    async fn stored(app: &TestApp, subscription: &ReportSubscription) -> ReportSubscription {
+        let (_, stored) = app.get(&format!("/api/report_subscriptions/{}", subscription.id)).await;
+        serde_json::from_value(stored).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_render_the_period_before_now() {
+        for app in testing::apps().await {
+            let tea = create_product(&app, "Tea", 250, 10).await;
+            open_shift(&app).await;
+            for at in ["2026-03-01T12:00:00Z", "2026-02-24T12:00:00Z"] {
+                let mut sale = sale_input(&[(&tea, 2)]);
+                sale["date_and_time"] = json!(at);
+                sale["sale_items"][0]["date_of_sale"] = json!(at);
+                app.create("/api/sales_transactions", sale).await;
+            }
+            let tz = app.state.timezone;
+            let now = Utc.with_ymd_and_hms(2026, 3, 2, 8, 0, 0).unwrap();
+
+            let takings = subscription(
+                &app,
+                json!({
+                    "name": "Till <1> & 2",
+                    "report_type": "daily_takings",
+                    "parameters": { "attach_csv": true },
+                    "frequency": "daily",
+                    "hour": 7,
+                    "recipients": ["boss@example.com"],
+                }),
+            )
+            .await;
+            let report = render(app.db(), tz, &takings, now).await.unwrap();
+            assert_eq!(report.subject, "Till <1> & 2: Daily takings for 2026-03-01");
+            assert!(report.html.contains("<h2>Till &lt;1&gt; &amp; 2: Daily takings for 2026-03-01</h2>"), "{}", report.html);
+            assert!(report.html.contains("Takings of 5.00 from"), "{:?}: {}", app.backend(), report.html);
+            let (filename, csv) = report.csv.unwrap();
+            assert_eq!(filename, "daily-takings-2026-03-01.csv");
+            assert!(csv.starts_with("Date,") && csv.contains("2026-03-01,5.00,"), "{:?}: {}", app.backend(), csv);
+
+            let top = subscription(
+                &app,
+                json!({
+                    "name": "Weekly",
+                    "report_type": "top_products",
+                    "frequency": "weekly",
+                    "hour": 7,
+                    "recipients": ["boss@example.com"],
+                }),
+            )
+            .await;
+            let report = render(app.db(), tz, &top, now).await.unwrap();
+            assert_eq!(report.subject, "Weekly: Top products for 2026-02-23 to 2026-03-01");
+            assert!(report.html.contains("<td style=\"border-bottom: 1px solid #eee; text-align: left;\">Tea</td>"), "{}", report.html);
+            assert!(report.csv.is_none());
+
+            app.finish().await;
+        }
+    }
+
+    /// A loopback SMTP server that accepts one connection's messages and
+    /// returns everything the client sent.
+    async fn smtp_sink() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let session = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (read, mut write) = socket.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+            let mut transcript = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                transcript.push_str(&line);
+                transcript.push('\n');
+                let command = line.get(..4).unwrap_or(&line).to_ascii_uppercase();
+                let reply: &[u8] = match command.as_str() {
+                    _ if in_data && line != "." => continue,
+                    _ if in_data => {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    }
+                    "DATA" => {
+                        in_data = true;
+                        b"354 end with .\r\n"
+                    }
+                    "QUIT" => {
+                        write.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 ok\r\n",
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, session)
+    }
+
+    #[tokio::test]
+    async fn sends_record_the_outcome_and_schedule_the_next_run() {
+        for app in testing::apps().await {
+            let tz = app.state.timezone;
+            let subscription = subscription(
+                &app,
+                json!({
+                    "name": "Stock",
+                    "report_type": "low_stock",
+                    "parameters": { "attach_csv": true },
+                    "frequency": "daily",
+                    "hour": 7,
+                    "recipients": ["boss@example.com", "ops@example.com"],
+                }),
+            )
+            .await;
+
+            let (port, session) = smtp_sink().await;
+            let config = SmtpConfig {
+                host: Some("127.0.0.1".to_string()),
+                port: Some(port),
+                tls: SmtpTls::None,
+                ..Default::default()
+            };
+            let before = Utc::now();
+            send(app.db(), &Mailer::new(&config).unwrap(), tz, &subscription, true).await.unwrap();
+            let transcript = session.await.unwrap();
+            assert!(transcript.contains("MAIL FROM:<reports@localhost>"), "{}", transcript);
+            assert!(transcript.contains("RCPT TO:<boss@example.com>") && transcript.contains("RCPT TO:<ops@example.com>"), "{}", transcript);
+            assert!(transcript.contains("Subject: Stock: Low stock for "), "{}", transcript);
+            assert!(transcript.contains("filename=\"low-stock-"), "{}", transcript);
+
+            let sent = stored(&app, &subscription).await;
+            assert_eq!(sent.last_error, None, "{:?}", app.backend());
+            assert!(sent.last_sent_at.is_some_and(|at| at >= before - chrono::Duration::seconds(1)), "{:?}", app.backend());
+            assert_eq!(sent.next_run_at, next_run(tz, ReportFrequency::Daily, 7, sent.last_sent_at.unwrap()));
+
+            // Without a relay the failure is kept and retried sooner
+            send(app.db(), &Mailer::new(&SmtpConfig::default()).unwrap(), tz, &sent, true).await.unwrap();
+            let failed = stored(&app, &sent).await;
+            assert_eq!(failed.last_error.as_deref(), Some("SMTP is not configured"), "{:?}", app.backend());
+            assert_eq!(failed.last_sent_at, sent.last_sent_at, "{:?}", app.backend());
+            assert!(failed.next_run_at <= Utc::now() + chrono::Duration::minutes(RETRY_DELAY_MINUTES), "{:?}", app.backend());
+
+            app.finish().await;
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use axum::{
+    async_trait,
+    extract::{
+        FromRequest, FromRequestParts, Request,
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::request::Parts,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use shared::models::{ApiError, ErrorCode, FieldError};
+use shared::validation::ValidationErrors;
+use sqlx::error::DatabaseError;
+use sqlx::postgres::PgDatabaseError;
+
+/// Error returned by every handler; rendered as an [`ApiError`] JSON body.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized,
+    /// Signed in, but not allowed to do this
+    Forbidden,
+    NotFound(String),
+    Conflict(String, Vec<FieldError>),
+    Validation(String, Vec<FieldError>),
+    /// Details are logged server-side and never sent to the client.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn not_found(what: &str) -> Self {
+        AppError::NotFound(format!("{} not found", what))
+    }
+
+    pub fn invalid_field(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation(
+            "Request validation failed".to_string(),
+            vec![FieldError {
+                field: field.to_string(),
+                message: message.into(),
+            }],
+        )
+    }
+
+    pub fn internal(err: impl std::fmt::Display) -> Self {
+        AppError::Internal(err.to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(..) => StatusCode::CONFLICT,
+            AppError::Validation(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn into_body(self) -> ApiError {
+        let (code, message, details) = match self {
+            AppError::BadRequest(message) => (ErrorCode::BadRequest, message, vec![]),
+            AppError::Unauthorized => (
+                ErrorCode::Unauthorized,
+                "Missing or invalid credentials".to_string(),
+                vec![],
+            ),
+            AppError::Forbidden => (
+                ErrorCode::Forbidden,
+                "Your account is not allowed to do this".to_string(),
+                vec![],
+            ),
+            AppError::NotFound(message) => (ErrorCode::NotFound, message, vec![]),
+            AppError::Conflict(message, details) => (ErrorCode::Conflict, message, details),
+            AppError::Validation(message, details) => {
+                (ErrorCode::ValidationFailed, message, details)
+            }
+            AppError::Internal(cause) => {
+                tracing::error!("Internal error: {}", cause);
+                (
+                    ErrorCode::Internal,
+                    "Internal server error".to_string(),
+                    vec![],
+                )
+            }
+        };
+        ApiError {
+            code,
+            message,
+            details,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        (status, axum::Json(self.into_body())).into_response()
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => AppError::NotFound("Record not found".to_string()),
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                let details = constraint_columns(db_err.as_ref())
+                    .into_iter()
+                    .map(|field| FieldError {
+                        field,
+                        message: "is already in use".to_string(),
+                    })
+                    .collect();
+                AppError::Conflict("A record with the same value already exists".to_string(), details)
+            }
+            sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => AppError::Validation(
+                "A referenced record does not exist".to_string(),
+                vec![],
+            ),
+            sqlx::Error::Database(db_err) if db_err.is_check_violation() => AppError::Validation(
+                db_err.message().to_string(),
+                vec![],
+            ),
+            _ => AppError::internal(err),
+        }
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let body = ApiError::from(errors);
+        AppError::Validation(body.message, body.details)
+    }
+}
+
+/// SQLite reports "UNIQUE constraint failed: staff.staff_id[, ...]", while
+/// PostgreSQL puts "Key (staff_id[, ...])=(...) already exists." in the detail.
+fn constraint_columns(err: &dyn DatabaseError) -> Vec<String> {
+    let columns = match err.try_downcast_ref::<PgDatabaseError>() {
+        Some(pg_err) => pg_err
+            .detail()
+            .and_then(|detail| detail.strip_prefix("Key ("))
+            .and_then(|detail| detail.split_once(")="))
+            .map(|(columns, _)| columns),
+        None => err.message().split_once(':').map(|(_, columns)| columns),
+    };
+    columns
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|column| column.trim().rsplit('.').next())
+        .filter(|column| !column.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Drop-in replacement for [`axum::Json`] whose rejections are reported as
+/// `422 validation_failed` JSON errors instead of plain text.
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Json::<T>::from_request(req, state).await {
+            Ok(axum::Json(value)) => Ok(Json(value)),
+            Err(rejection) => Err(json_rejection(rejection)),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+fn json_rejection(rejection: JsonRejection) -> AppError {
+    match rejection {
+        JsonRejection::MissingJsonContentType(_) => AppError::BadRequest(rejection.body_text()),
+        JsonRejection::JsonSyntaxError(_) => AppError::BadRequest(rejection.body_text()),
+        _ => AppError::Validation(rejection.body_text(), vec![]),
+    }
+}
+
+/// Drop-in replacement for [`axum::extract::Query`] that reports unparsable
+/// query strings as `422 validation_failed` JSON errors.
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Query(value)),
+            Err(rejection) => Err(query_rejection(rejection)),
+        }
+    }
+}
+
+fn query_rejection(rejection: QueryRejection) -> AppError {
+    AppError::Validation(rejection.body_text(), vec![])
+}
+
+/// Drop-in replacement for [`axum::extract::Path`] that reports malformed
+/// ids as `400 bad_request` JSON errors.
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(rejection) => Err(path_rejection(rejection)),
+        }
+    }
+}
+
+fn path_rejection(rejection: PathRejection) -> AppError {
+    match rejection {
+        PathRejection::FailedToDeserializePathParams(_) => AppError::BadRequest(rejection.body_text()),
+        _ => AppError::internal(rejection.body_text()),
+    }
+}
@@ -0,0 +1,975 @@
+//! CSV and XLSX downloads of report and list endpoints. A handler takes an
+//! [`Export`] and finishes with [`Export::respond`], which renders its usual
+//! JSON unless `?format=csv|xlsx` or a matching `Accept` header asked for a
+//! file. Exports ignore pagination and contain every matching row.
+//!
+//! A list endpoint sends its CSV with [`csv_stream`], a line per row as the
+//! database cursor yields it, so a download of any size holds only a few
+//! rows in memory. Reports are aggregated before they are rendered, and an
+//! XLSX workbook can only be written whole, so both are built in memory
+//! first.
+
+use std::io;
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::FromRequestParts,
+    http::{HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use tokio_stream::{self as stream, Stream, StreamExt};
+use utoipa::{IntoParams, ToSchema};
+
+use shared::models::{
+    Customer, CustomerSegmentReport, CustomerStats, DailySales, HeatmapCell, MarginLine,
+    MarginReport, Page, Product, ProductPriceHistory, ProductSalesSummary, ReportSubscription,
+    Sale, SaleItem, SalesItemsListResponse, SalesStats, SearchHit, Staff, StaffPerformance,
+    Shift, ShiftReport, StaffPerformanceDetail, StockAlert, TimeSeries, TopProduct, Webhook, WebhookDelivery,
+};
+
+use crate::error::{AppError, Json, Query};
+
+pub const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+pub const XLSX_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportParams {
+    /// Download every matching row as a file instead of a JSON page
+    pub format: Option<ExportFormat>,
+}
+
+/// The export format a request asked for, if any. The `format` query
+/// parameter wins over the `Accept` header.
+pub struct Export(pub Option<ExportFormat>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Export {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<ExportParams>::from_request_parts(parts, state).await?;
+        if params.format.is_some() {
+            return Ok(Export(params.format));
+        }
+        let accept = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let format = accept.split(',').find_map(|media| {
+            match media.split(';').next().unwrap_or_default().trim() {
+                "text/csv" => Some(ExportFormat::Csv),
+                XLSX_CONTENT_TYPE => Some(ExportFormat::Xlsx),
+                _ => None,
+            }
+        });
+        Ok(Export(format))
+    }
+}
+
+impl Export {
+    /// Whether a file was asked for, and so every row is wanted.
+    pub fn is_file(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Whether a CSV file was asked for, which a list streams with
+    /// [`csv_stream`].
+    pub fn is_csv(&self) -> bool {
+        self.0 == Some(ExportFormat::Csv)
+    }
+
+    /// Renders `value` as JSON, or as `<name>-<date>.csv|xlsx`.
+    pub fn respond<T: Serialize + Exportable>(self, name: &str, value: T) -> Result<Response, AppError> {
+        let Some(format) = self.0 else {
+            return Ok(Json(value).into_response());
+        };
+        let sheets = value.sheets();
+        match format {
+            ExportFormat::Csv => attachment(name, ExportFormat::Csv, Body::from(csv_text(sheets))),
+            ExportFormat::Xlsx => attachment(name, ExportFormat::Xlsx, Body::from(xlsx_bytes(name, sheets)?)),
+        }
+    }
+}
+
+/// `<name>-<date>.csv` with a line for each of `rows`, sent as they arrive.
+/// A row that fails to load is logged and cuts the download short, since
+/// the status has already been sent.
+pub fn csv_stream<T: ExportRow>(
+    name: &str,
+    rows: impl Stream<Item = Result<T, AppError>> + Send + 'static,
+) -> Result<Response, AppError> {
+    let headers = csv_line(T::HEADERS.iter().map(|heading| csv_field(heading)));
+    let lines = rows.map(|row| match row {
+        Ok(row) => Ok(csv_line(row.cells().iter().map(|cell| csv_field(&cell_text(cell))))),
+        Err(err) => {
+            tracing::error!("CSV export failed: {:?}", err);
+            Err(io::Error::other("the export could not be read"))
+        }
+    });
+    let body = Body::from_stream(stream::once(Ok(headers)).chain(lines));
+    attachment(name, ExportFormat::Csv, body)
+}
+
+fn attachment(name: &str, format: ExportFormat, body: Body) -> Result<Response, AppError> {
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => (CSV_CONTENT_TYPE, "csv"),
+        ExportFormat::Xlsx => (XLSX_CONTENT_TYPE, "xlsx"),
+    };
+    let filename = format!("{}-{}.{}", name, Utc::now().format("%Y-%m-%d"), extension);
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", filename))
+        .map_err(AppError::internal)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[derive(Clone)]
+pub enum Cell {
+    Text(String),
+    Integer(i64),
+    /// Cents, written as a decimal amount
+    Money(i64),
+    /// A percentage such as a margin, written as is
+    Percent(f64),
+    Empty,
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_string())
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(value: i64) -> Self {
+        Cell::Integer(value)
+    }
+}
+
+impl From<DateTime<Utc>> for Cell {
+    fn from(value: DateTime<Utc>) -> Self {
+        Cell::Text(value.to_rfc3339())
+    }
+}
+
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Cell::Empty)
+    }
+}
+
+fn money(cents: impl Into<Option<i64>>) -> Cell {
+    cents.into().map(Cell::Money).unwrap_or(Cell::Empty)
+}
+
+fn percent(value: Option<f64>) -> Cell {
+    value.map(Cell::Percent).unwrap_or(Cell::Empty)
+}
+
+fn text(value: impl ToString) -> Cell {
+    Cell::Text(value.to_string())
+}
+
+/// One table of an export; CSV files hold the sheets one after another.
+#[derive(Clone)]
+pub struct Sheet {
+    /// Worksheet name; the export's name when `None`
+    pub title: Option<&'static str>,
+    pub headers: &'static [&'static str],
+    pub rows: Vec<Vec<Cell>>,
+}
+
+pub trait Exportable {
+    fn sheets(&self) -> Vec<Sheet>;
+}
+
+/// A type listed one per row.
+pub trait ExportRow {
+    const HEADERS: &'static [&'static str];
+    fn cells(&self) -> Vec<Cell>;
+}
+
+fn sheet<T: ExportRow>(title: Option<&'static str>, rows: &[T]) -> Sheet {
+    Sheet {
+        title,
+        headers: T::HEADERS,
+        rows: rows.iter().map(ExportRow::cells).collect(),
+    }
+}
+
+impl<T: ExportRow> Exportable for Vec<T> {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![sheet(None, self)]
+    }
+}
+
+impl<T: ExportRow> Exportable for Page<T> {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![sheet(None, &self.items)]
+    }
+}
+
+/// The sheets as one CSV document, also attached to report emails.
+pub fn csv_text(sheets: Vec<Sheet>) -> String {
+    sheets.into_iter().enumerate().flat_map(|(index, sheet)| {
+        // A blank line between tables
+        let separator = (index > 0).then(|| "\r\n".to_string());
+        let headers = csv_line(sheet.headers.iter().map(|h| csv_field(h)));
+        let rows = sheet.rows.into_iter().map(|row| {
+            csv_line(row.iter().map(|cell| csv_field(&cell_text(cell))))
+        });
+        separator.into_iter().chain(std::iter::once(headers).chain(rows))
+    }).collect()
+}
+
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    fields.collect::<Vec<_>>().join(",") + "\r\n"
+}
+
+pub fn cell_text(cell: &Cell) -> String {
+    match cell {
+        Cell::Text(value) => value.clone(),
+        Cell::Integer(value) => value.to_string(),
+        Cell::Money(cents) => format_cents(*cents),
+        Cell::Percent(value) => format!("{:.2}", value),
+        Cell::Empty => String::new(),
+    }
+}
+
+/// `-1234` cents as `-12.34`, without float rounding.
+fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let abs = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+fn csv_field(value: &str) -> String {
+    // Leading formula characters are neutralised so spreadsheets show them as text
+    let value = match value.chars().next() {
+        Some('=' | '+' | '@') => format!("'{}", value),
+        Some('-') if value.parse::<f64>().is_err() => format!("'{}", value),
+        _ => value.to_string(),
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn xlsx_bytes(name: &str, sheets: Vec<Sheet>) -> Result<Vec<u8>, AppError> {
+    let header = Format::new().set_bold();
+    let amount = Format::new().set_num_format("#,##0.00");
+    let percentage = Format::new().set_num_format("0.00");
+
+    let mut workbook = Workbook::new();
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        // Worksheet names are limited to 31 characters
+        let title: String = sheet.title.unwrap_or(name).chars().take(31).collect();
+        worksheet.set_name(title).map_err(AppError::internal)?;
+        for (col, heading) in sheet.headers.iter().enumerate() {
+            worksheet
+                .write_string_with_format(0, col as u16, *heading, &header)
+                .map_err(AppError::internal)?;
+        }
+        for (index, row) in sheet.rows.iter().enumerate() {
+            let row_number = index as u32 + 1;
+            for (col, cell) in row.iter().enumerate() {
+                let col = col as u16;
+                let written = match cell {
+                    Cell::Text(value) => worksheet.write_string(row_number, col, value),
+                    Cell::Integer(value) => worksheet.write_number(row_number, col, *value as f64),
+                    Cell::Money(cents) => {
+                        worksheet.write_number_with_format(row_number, col, *cents as f64 / 100.0, &amount)
+                    }
+                    Cell::Percent(value) => {
+                        worksheet.write_number_with_format(row_number, col, *value, &percentage)
+                    }
+                    Cell::Empty => continue,
+                };
+                written.map_err(AppError::internal)?;
+            }
+        }
+        worksheet.set_freeze_panes(1, 0).map_err(AppError::internal)?;
+        worksheet.autofit();
+    }
+    workbook.save_to_buffer().map_err(AppError::internal)
+}
+
+impl ExportRow for TopProduct {
+    const HEADERS: &'static [&'static str] = &["Product", "Total Sales"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![text(&self.product_name), money(self.total_sales_cents)]
+    }
+}
+
+impl ExportRow for ProductSalesSummary {
+    const HEADERS: &'static [&'static str] = &[
+        "Product",
+        "Quantity",
+        "Total Amount",
+        "Cost",
+        "Gross Profit",
+        "Margin %",
+        "Previous Quantity",
+        "Previous Amount",
+        "Amount Change %",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(&self.product_name),
+            self.total_quantity.into(),
+            money(self.total_amount_cents),
+            money(self.total_cost_cents),
+            money(self.gross_profit_cents),
+            percent(self.margin_percent),
+            self.quantity_change.map(|change| change.previous).into(),
+            money(self.amount_change.map(|change| change.previous)),
+            percent(self.amount_change.and_then(|change| change.percent_change)),
+        ]
+    }
+}
+
+impl Exportable for SalesStats {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![Sheet {
+            title: None,
+            headers: &["Total Sales", "Count", "Previous Total", "Total Change %", "Previous Count", "Count Change %"],
+            rows: vec![vec![
+                money(self.total_sales_cents),
+                self.count.into(),
+                money(self.total_change.map(|change| change.previous)),
+                percent(self.total_change.and_then(|change| change.percent_change)),
+                self.count_change.map(|change| change.previous).into(),
+                percent(self.count_change.and_then(|change| change.percent_change)),
+            ]],
+        }]
+    }
+}
+
+impl ExportRow for DailySales {
+    const HEADERS: &'static [&'static str] =
+        &["Date", "Total Sales", "Count", "Compared Date", "Previous Total", "Total Change %"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(&self.date),
+            money(self.total_sales_cents),
+            self.count.into(),
+            self.compared_date.clone().into(),
+            money(self.total_change.map(|change| change.previous)),
+            percent(self.total_change.and_then(|change| change.percent_change)),
+        ]
+    }
+}
+
+impl Exportable for TimeSeries {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![Sheet {
+            title: None,
+            headers: &["Series", "Bucket Start", "Total Sales", "Count"],
+            rows: self
+                .series
+                .iter()
+                .flat_map(|series| {
+                    series.points.iter().map(|point| {
+                        vec![text(&series.label), text(&point.start), money(point.total_sales_cents), point.count.into()]
+                    })
+                })
+                .collect(),
+        }]
+    }
+}
+
+impl ExportRow for MarginLine {
+    const HEADERS: &'static [&'static str] = &["Label", "Quantity", "Revenue", "Cost", "Gross Profit", "Margin %"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(&self.label),
+            self.quantity.into(),
+            money(self.revenue_cents),
+            money(self.cost_cents),
+            money(self.gross_profit_cents),
+            percent(self.margin_percent),
+        ]
+    }
+}
+
+impl Exportable for MarginReport {
+    fn sheets(&self) -> Vec<Sheet> {
+        let mut sheet = sheet(None, &self.lines);
+        let mut total = self.total.cells();
+        total[0] = text("Total");
+        sheet.rows.push(total);
+        vec![sheet]
+    }
+}
+
+impl ExportRow for HeatmapCell {
+    const HEADERS: &'static [&'static str] = &["Weekday", "Hour", "Total Sales", "Transactions"];
+    fn cells(&self) -> Vec<Cell> {
+        const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+        vec![
+            text(WEEKDAYS.get(self.weekday as usize).copied().unwrap_or_default()),
+            (self.hour as i64).into(),
+            money(self.total_sales_cents),
+            self.count.into(),
+        ]
+    }
+}
+
+impl ExportRow for StaffPerformance {
+    const HEADERS: &'static [&'static str] = &[
+        "Rank",
+        "Staff",
+        "Revenue",
+        "Transactions",
+        "Average Basket",
+        "Discounts",
+        "Refunds",
+        "Net Revenue",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            self.rank.into(),
+            text(&self.staff_name),
+            money(self.revenue_cents),
+            self.transactions.into(),
+            money(self.average_basket_cents),
+            money(self.discount_cents),
+            money(self.refunds_cents),
+            money(self.net_revenue_cents),
+        ]
+    }
+}
+
+impl Exportable for StaffPerformanceDetail {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![
+            sheet(Some("Summary"), std::slice::from_ref(&self.performance)),
+            sheet(Some("Daily"), &self.daily),
+            sheet(Some("Products"), &self.products),
+        ]
+    }
+}
+
+impl ExportRow for CustomerStats {
+    const HEADERS: &'static [&'static str] = &[
+        "Customer",
+        "Segment",
+        "Recency",
+        "Frequency",
+        "Monetary",
+        "Visits",
+        "Lifetime Spend",
+        "Average Basket",
+        "First Purchase",
+        "Last Purchase",
+        "Days Since Last Purchase",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        let score = |value: Option<u8>| Cell::from(value.map(i64::from));
+        vec![
+            text(&self.customer_name),
+            self.segment.map(|segment| segment.to_string()).into(),
+            score(self.rfm.map(|rfm| rfm.recency)),
+            score(self.rfm.map(|rfm| rfm.frequency)),
+            score(self.rfm.map(|rfm| rfm.monetary)),
+            self.visit_count.into(),
+            money(self.total_spend_cents),
+            money(self.average_basket_cents),
+            self.first_purchase.into(),
+            self.last_purchase.into(),
+            self.days_since_last_purchase.into(),
+        ]
+    }
+}
+
+impl Exportable for CustomerStats {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![sheet(None, std::slice::from_ref(self))]
+    }
+}
+
+impl Exportable for CustomerSegmentReport {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![
+            Sheet {
+                title: Some("Segments"),
+                headers: &["Segment", "Customers", "Total Spend"],
+                rows: self
+                    .segments
+                    .iter()
+                    .map(|summary| vec![text(summary.segment), summary.customers.into(), money(summary.total_spend_cents)])
+                    .collect(),
+            },
+            sheet(Some("Customers"), &self.customers.items),
+        ]
+    }
+}
+
+impl ExportRow for Product {
+    const HEADERS: &'static [&'static str] = &[
+        "Id",
+        "Name",
+        "Description",
+        "Type",
+        "Price",
+        "Cost Price",
+        "Stock",
+        "Reorder Point",
+        "Reorder Quantity",
+        "Archived At",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(&self.name),
+            text(&self.description),
+            text(self.product_type.as_str()),
+            money(self.price_cents),
+            money(self.cost_price_cents),
+            self.stock.into(),
+            self.reorder_point.into(),
+            self.reorder_quantity.into(),
+            self.archived_at.into(),
+        ]
+    }
+}
+
+impl ExportRow for StockAlert {
+    const HEADERS: &'static [&'static str] =
+        &["Created At", "Product", "Stock", "Reorder Point", "Reorder Quantity", "Sale Id", "Acknowledged"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            self.created_at.into(),
+            text(&self.product_name),
+            self.stock.into(),
+            self.reorder_point.into(),
+            self.reorder_quantity.into(),
+            self.sale_id.map(|id| id.to_string()).into(),
+            text(if self.acknowledged { "yes" } else { "no" }),
+        ]
+    }
+}
+
+impl Exportable for ProductPriceHistory {
+    fn sheets(&self) -> Vec<Sheet> {
+        let headers = &["Effective From", "Price", "Changed By", "Created At"];
+        let rows = self
+            .page
+            .items
+            .iter()
+            .map(|price| {
+                vec![
+                    price.effective_from.into(),
+                    money(price.price_cents),
+                    price.changed_by_name.clone().into(),
+                    price.created_at.into(),
+                ]
+            })
+            .collect();
+        vec![Sheet { title: None, headers, rows }]
+    }
+}
+
+impl ExportRow for Customer {
+    const HEADERS: &'static [&'static str] = &[
+        "Id",
+        "First Name",
+        "Last Name",
+        "Middle Name",
+        "Mobile Number",
+        "Email",
+        "Date of Birth",
+        "Archived At",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(&self.first_name),
+            text(&self.last_name),
+            self.middle_name.clone().into(),
+            text(&self.mobile_number),
+            text(&self.email),
+            text(self.date_of_birth),
+            self.archived_at.into(),
+        ]
+    }
+}
+
+impl ExportRow for SaleItem {
+    const HEADERS: &'static [&'static str] = &[
+        "Date",
+        "Product",
+        "Quantity",
+        "Unit Price",
+        "Total",
+        "Discount",
+        "Amount Resolved",
+        "Unit Cost",
+        "Sale Id",
+        "Customer Id",
+        "Note",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            self.date_of_sale.into(),
+            self.product_name.clone().unwrap_or_else(|| self.product_id.to_string()).into(),
+            self.quantity.into(),
+            money(self.price_per_item),
+            money(self.total_cents),
+            money(self.discount),
+            money(self.total_resolved),
+            money(self.cost_per_item),
+            self.sale_id.map(|id| id.to_string()).into(),
+            self.customer_id.map(|id| id.to_string()).into(),
+            self.note.clone().into(),
+        ]
+    }
+}
+
+impl Exportable for SalesItemsListResponse {
+    fn sheets(&self) -> Vec<Sheet> {
+        self.page.sheets()
+    }
+}
+
+impl ExportRow for Sale {
+    const HEADERS: &'static [&'static str] = &[
+        "Receipt Number",
+        "Date",
+        "Total",
+        "Discount",
+        "Amount Resolved",
+        "Channel",
+        "Payment Method",
+        "Branch",
+        "Staff Id",
+        "Customer Id",
+        "Car Number",
+        "Shift Id",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(&self.receipt_number),
+            self.date_and_time.into(),
+            money(self.total_cents),
+            money(self.discount),
+            money(self.total_resolved),
+            text(&self.sales_channel),
+            text(self.payment_method.title()),
+            text(&self.company_branch),
+            text(self.staff_responsible),
+            self.customer_id.map(|id| id.to_string()).into(),
+            text(&self.car_number),
+            self.shift_id.map(|id| id.to_string()).into(),
+        ]
+    }
+}
+
+impl ExportRow for Shift {
+    const HEADERS: &'static [&'static str] = &[
+        "Id",
+        "Register",
+        "Branch",
+        "Opened By",
+        "Opened At",
+        "Opening Float",
+        "Closed By",
+        "Closed At",
+        "Counted Cash",
+        "Note",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(&self.register),
+            text(&self.company_branch),
+            text(self.opened_by),
+            self.opened_at.into(),
+            money(self.opening_float_cents),
+            self.closed_by.map(|id| id.to_string()).into(),
+            self.closed_at.into(),
+            money(self.counted_cash_cents),
+            self.note.clone().into(),
+        ]
+    }
+}
+
+impl Exportable for ShiftReport {
+    fn sheets(&self) -> Vec<Sheet> {
+        let line = |label: &str, value: Cell| vec![text(label), value];
+        let summary = vec![
+            line("Register", text(&self.shift.register)),
+            line("Branch", text(&self.shift.company_branch)),
+            line("Opened At", self.shift.opened_at.into()),
+            line("Closed At", self.shift.closed_at.into()),
+            line("Sales", self.sales_count.into()),
+            line("Gross Sales", money(self.gross_sales_cents)),
+            line("Discounts", money(self.discount_cents)),
+            line("Refunds", money(self.refunds_cents)),
+            line("Voids", money(self.voids_cents)),
+            line("Net Sales", money(self.net_sales_cents)),
+            line("Opening Float", money(self.opening_float_cents)),
+            line("Cash Sales", money(self.cash_sales_cents)),
+            line("Cash Returned", money(self.cash_returned_cents)),
+            line("Cash In", money(self.cash_in_cents)),
+            line("Payouts", money(self.payouts_cents)),
+            line("Drops", money(self.drops_cents)),
+            line("Expected Cash", money(self.expected_cash_cents)),
+            line("Counted Cash", money(self.counted_cash_cents)),
+            line("Over/Short", money(self.over_short_cents)),
+        ];
+        vec![
+            Sheet {
+                title: Some("Summary"),
+                headers: &["Item", "Value"],
+                rows: summary,
+            },
+            Sheet {
+                title: Some("Tenders"),
+                headers: &["Payment Method", "Sales", "Total"],
+                rows: self
+                    .by_tender
+                    .iter()
+                    .map(|tender| vec![text(tender.payment_method.title()), tender.count.into(), money(tender.total_cents)])
+                    .collect(),
+            },
+            Sheet {
+                title: Some("Channels"),
+                headers: &["Channel", "Sales", "Total"],
+                rows: self
+                    .by_channel
+                    .iter()
+                    .map(|channel| vec![text(&channel.sales_channel), channel.count.into(), money(channel.total_cents)])
+                    .collect(),
+            },
+            Sheet {
+                title: Some("Cash Movements"),
+                headers: &["Recorded At", "Kind", "Amount", "Reason", "Recorded By"],
+                rows: self
+                    .cash_movements
+                    .iter()
+                    .map(|movement| {
+                        vec![
+                            movement.recorded_at.into(),
+                            text(movement.kind.title()),
+                            money(movement.amount_cents),
+                            text(&movement.reason),
+                            text(movement.recorded_by),
+                        ]
+                    })
+                    .collect(),
+            },
+        ]
+    }
+}
+
+impl ExportRow for Staff {
+    const HEADERS: &'static [&'static str] =
+        &["Id", "Staff Id", "First Name", "Last Name", "Username", "Mobile Number", "Archived At"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(&self.staff_id),
+            text(&self.first_name),
+            text(&self.last_name),
+            text(&self.username),
+            text(&self.mobile_number),
+            self.archived_at.into(),
+        ]
+    }
+}
+
+impl ExportRow for SearchHit {
+    const HEADERS: &'static [&'static str] = &["Kind", "Id", "Title", "Snippet"];
+    fn cells(&self) -> Vec<Cell> {
+        vec![text(self.kind.as_str()), text(self.id), text(&self.title), text(&self.snippet)]
+    }
+}
+
+impl ExportRow for Webhook {
+    const HEADERS: &'static [&'static str] = &["Id", "Url", "Description", "Events", "Active", "Created At"];
+    fn cells(&self) -> Vec<Cell> {
+        let events = self.event_types.iter().map(|event| event.as_str()).collect::<Vec<_>>().join(" ");
+        vec![
+            text(self.id),
+            text(&self.url),
+            self.description.clone().into(),
+            events.into(),
+            text(if self.active { "yes" } else { "no" }),
+            self.created_at.into(),
+        ]
+    }
+}
+
+impl ExportRow for WebhookDelivery {
+    const HEADERS: &'static [&'static str] = &[
+        "Id",
+        "Event Id",
+        "Event",
+        "Status",
+        "Attempts",
+        "Response Status",
+        "Last Error",
+        "Last Attempt At",
+        "Next Attempt At",
+        "Created At",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(self.event_id),
+            text(self.event_type.as_str()),
+            text(self.status.as_str()),
+            self.attempts.into(),
+            self.response_status.into(),
+            self.last_error.clone().into(),
+            self.last_attempt_at.into(),
+            self.next_attempt_at.into(),
+            self.created_at.into(),
+        ]
+    }
+}
+
+impl ExportRow for ReportSubscription {
+    const HEADERS: &'static [&'static str] = &[
+        "Id",
+        "Name",
+        "Report",
+        "Frequency",
+        "Hour",
+        "Recipients",
+        "Active",
+        "Next Run At",
+        "Last Sent At",
+        "Last Error",
+    ];
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            text(self.id),
+            text(&self.name),
+            text(self.report_type.as_str()),
+            text(self.frequency.as_str()),
+            i64::from(self.hour).into(),
+            text(self.recipients.join(", ")),
+            text(if self.active { "yes" } else { "no" }),
+            self.next_run_at.into(),
+            self.last_sent_at.into(),
+            self.last_error.clone().into(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, create_product};
+    use axum::http::StatusCode;
+
+    #[test]
+    fn csv_fields_are_quoted_and_formulas_neutralised() {
+        let sheets = vec![
+            Sheet {
+                title: None,
+                headers: &["Name", "Amount", "Note"],
+                rows: vec![
+                    vec!["Tea, green".into(), money(-1234), "said \"hi\"".into()],
+                    vec!["=SUM(A1:A2)".into(), Cell::Integer(-5), "two\nlines".into()],
+                    vec!["-rate".into(), percent(Some(12.5)), Cell::Empty],
+                ],
+            },
+            Sheet { title: Some("Other"), headers: &["@who"], rows: vec![vec!["+1".into()]] },
+        ];
+        assert_eq!(
+            csv_text(sheets),
+            "Name,Amount,Note\r\n\
+             \"Tea, green\",-12.34,\"said \"\"hi\"\"\"\r\n\
+             '=SUM(A1:A2),-5,\"two\nlines\"\r\n\
+             '-rate,12.50,\r\n\
+             \r\n\
+             '@who\r\n\
+             '+1\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn csv_exports_every_matching_row() {
+        for app in testing::apps().await {
+            create_product(&app, "Tea, \"green\"", 1250, 1).await;
+            create_product(&app, "=cmd", 300, 1).await;
+            let request = axum::http::Request::builder()
+                .uri("/api/products?limit=1&format=csv")
+                .header(header::AUTHORIZATION, format!("Bearer {}", testing::token_for(testing::ADMIN_ID, 60)))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.send(request).await;
+            assert_eq!(response.headers()[header::CONTENT_TYPE], CSV_CONTENT_TYPE);
+            let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().to_string();
+            assert!(disposition.starts_with("attachment; filename=\"products-"), "{}", disposition);
+            let (status, body) = testing::text(response).await;
+            assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), body);
+            let lines: Vec<&str> = body.lines().collect();
+            assert_eq!(lines.len(), 3, "{:?}: {}", app.backend(), body);
+            assert!(lines[0].starts_with("Id,Name,"), "{}", lines[0]);
+            assert!(body.contains(",\"Tea, \"\"green\"\"\",") && body.contains(",'=cmd,"), "{:?}: {}", app.backend(), body);
+            assert!(body.contains(",12.50,") && body.contains(",3.00,"), "{:?}: {}", app.backend(), body);
+
+            app.finish().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_stream_and_dashboard_stats_download() {
+        for app in testing::apps().await {
+            create_product(&app, "Green Tea", 300, 1).await;
+            for (uri, first_line) in [
+                ("/api/products?format=csv", "Id,Name,"),
+                ("/api/search?q=tea&format=csv", "Kind,Id,Title,Snippet\r\nproduct,"),
+                ("/api/customers?format=csv", "Id,First Name,"),
+                ("/api/staff?format=csv", "Id,Staff Id,"),
+                ("/api/shifts?format=csv", "Id,Register,"),
+                ("/api/stock_alerts?format=csv", "Created At,"),
+                ("/api/sales/stats/today?format=csv", "Total Sales,Count,"),
+                ("/api/sales/stats/week?format=csv", "Date,Total Sales,"),
+            ] {
+                let request = axum::http::Request::builder()
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Bearer {}", testing::token_for(testing::ADMIN_ID, 60)))
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.send(request).await;
+                let (status, body) = testing::text(response).await;
+                assert_eq!(status, StatusCode::OK, "{} on {:?}: {}", uri, app.backend(), body);
+                assert!(body.starts_with(first_line) && body.ends_with("\r\n"), "{} on {:?}: {}", uri, app.backend(), body);
+            }
+
+            app.finish().await;
+        }
+    }
+}
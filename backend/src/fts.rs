@@ -0,0 +1,120 @@
+//! Typo-tolerant full-text queries.
+//!
+//! A search word that matches nothing in the indexes searched is replaced by
+//! the indexed words closest to it, so `tae` or `tee` still find "Tea". Words
+//! that match as typed are left alone, and the index vocabulary is only read
+//! when one doesn't.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db::{self, Db};
+use crate::error::AppError;
+
+/// Shorter words are not corrected; too many other words are one edit away.
+const MIN_CORRECTED_LEN: usize = 3;
+/// Replacements offered for one misspelt word, closest first.
+const MAX_REPLACEMENTS: usize = 5;
+
+/// `Backend::fts_query` for a search over `indexes`, with misspelt words
+/// replaced by their closest matches in those indexes.
+pub async fn search_query(db: &Db, indexes: &[&str], input: &str) -> Result<Option<String>, AppError> {
+    let backend = db.backend();
+    let mut misspelt = Vec::new();
+    for word in input.split_whitespace() {
+        let word = word.to_lowercase();
+        if word.chars().count() < MIN_CORRECTED_LEN || !word.chars().all(char::is_alphabetic) {
+            continue;
+        }
+        let query = backend.fts_query(&word, &HashMap::new());
+        let mut found = false;
+        for fts in indexes {
+            let sql = format!("SELECT 1 FROM {}{} WHERE {} LIMIT 1", fts, backend.fts_source(), backend.fts_match(fts));
+            let hit: Option<i64> = db::query_scalar(sql).bind(&query).fetch_optional(db).await?;
+            if hit.is_some() {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            misspelt.push(word);
+        }
+    }
+
+    let mut corrections = HashMap::new();
+    if !misspelt.is_empty() {
+        let mut vocabulary = BTreeSet::new();
+        for fts in indexes {
+            let words: Vec<String> = db::query_scalar(backend.fts_vocabulary(fts)).fetch_all(db).await?;
+            vocabulary.extend(words.into_iter().filter(|word| word.chars().all(char::is_alphanumeric)));
+        }
+        for word in misspelt {
+            let replacements = closest(&word, &vocabulary);
+            if !replacements.is_empty() {
+                corrections.insert(word, replacements);
+            }
+        }
+    }
+    Ok(backend.fts_query(input, &corrections))
+}
+
+/// The words of `vocabulary` nearest to `word`: one edit away for short
+/// words, up to two for longer ones.
+fn closest(word: &str, vocabulary: &BTreeSet<String>) -> Vec<String> {
+    let max_distance = if word.chars().count() <= 4 { 1 } else { 2 };
+    let mut candidates: Vec<(usize, &String)> = vocabulary
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    candidates.sort();
+    candidates.into_iter().take(MAX_REPLACEMENTS).map(|(_, candidate)| candidate.clone()).collect()
+}
+
+/// Damerau-Levenshtein distance (optimal string alignment): insertions,
+/// deletions, substitutions and swaps of neighbouring letters count one each.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_count_as_one_edit() {
+        assert_eq!(edit_distance("tae", "tea"), 1);
+        assert_eq!(edit_distance("tee", "tea"), 1);
+        assert_eq!(edit_distance("cofee", "coffee"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "tea"), 3);
+    }
+
+    #[test]
+    fn closest_words_come_first() {
+        let vocabulary: BTreeSet<String> =
+            ["tea", "tear", "ten", "coffee", "toffee"].into_iter().map(String::from).collect();
+        assert_eq!(closest("tae", &vocabulary), ["tea"]);
+        assert_eq!(closest("tee", &vocabulary), ["tea", "ten"]);
+        assert_eq!(closest("cofee", &vocabulary), ["coffee", "toffee"]);
+        assert!(closest("biscuit", &vocabulary).is_empty());
+    }
+}
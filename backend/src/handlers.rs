@@ -1,8 +1,12 @@
 use axum::{
-    Json,
-    extract::{Path, State, Extension, Query},
-    http::StatusCode,
+    body::Body,
+    extract::{State, Extension},
+    http::{HeaderValue, StatusCode, header},
+    response::{Html, IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
 };
+use std::convert::Infallible;
+use tokio_stream::{Stream, StreamExt, wrappers::{BroadcastStream, IntervalStream}};
+use tokio_util::io::ReaderStream;
 
 
 use argon2::{
@@ -13,23 +17,42 @@ use serde::Deserialize;
 use utoipa::IntoParams;
 
 use jsonwebtoken::{EncodingKey, Header};
-use sqlx::sqlite::SqliteRow;
-use sqlx::Row;
 use rand_core::OsRng;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
-use chrono::{Utc, Datelike};
+use shared::validation::Validate;
+use chrono::{DateTime, Utc, Datelike, Months, NaiveDate};
 
 use crate::AppState;
-use crate::auth::Claims;
+use crate::db::{Conn, Db, Tx};
+use crate::error::{AppError, Json, Path, Query};
+use crate::auth::{self, Claims};
+use crate::export::{Export, ExportParams, Exportable, csv_stream};
+use crate::live::{self, LiveUpdate};
+use crate::webhooks;
+use crate::email_reports;
+use crate::{backup, fts, integrity};
+use crate::timezone::BusinessTz;
+use crate::repo::{self, parse_uuid};
 use shared::models::{
-    Customer, CustomerInput, CustomerDetails, Product, ProductDetails, ProductInput, ProductType,
+    Customer, CustomerInput, CustomerDetails, Product, ProductDetails, ProductInput,
     SaleItem, SaleItemInput, Staff, StaffInput, UploadResponse, SalesStats, DailySales, SalesItemsListResponse,
-    TopProduct, Sale, SaleInput, ProductSalesSummary,
+    TopProduct, Sale, SaleInput, ProductSalesSummary, StockAlert, ProductPrice, ProductPriceInput,
+    ProductPriceHistory, Page, ApiError, SearchHit, SearchKind, SyncChanges, SyncConflict,
+    SyncConflictKind, SyncSaleInput, SyncSaleResult, SyncSaleStatus, SyncUpload, SyncUploadResult,
+    Webhook, WebhookDelivery, WebhookDeliveryStatus, WebhookEventType, WebhookInput, DashboardStats,
+    HeatmapCell, SalesChannel, StaffPerformance, StaffPerformanceDetail, StaffRanking, MarginGrouping,
+    MarginLine, MarginReport, Change, ComparisonPeriod, CustomerStats, CustomerSegment,
+    CustomerSegmentReport, RfmScore, SegmentSummary, ReportSubscription,
+    ReportSubscriptionInput, BusinessCalendar, SalesSeries, SeriesBreakdown, SeriesPoint,
+    TimeBucket, TimeSeries, WeekStart, PaymentMethod, Shift, ShiftInput, ShiftCloseInput, ShiftReport,
+    ShiftReportKind, CashMovement, CashMovementInput, FieldError, StaffRole, StaffRoleInput,
+    Backup, IntegrityReport, OrphanCount,
+    DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
 };
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct SalesSearchParams {
     pub start_date: Option<String>,
     pub end_date: Option<String>,
@@ -40,130 +63,176 @@ pub struct SalesSearchParams {
 
 
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 pub struct SearchParams {
     pub search: Option<String>,
+    /// Include archived records (default false)
+    pub include_archived: Option<bool>,
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PageParams {
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ArchivedParams {
+    /// Include archived records (default false)
+    pub include_archived: Option<bool>,
+}
+
+/// Validates the requested page window, returning `(page, limit, offset)`.
+fn page_window(page: Option<i64>, limit: Option<i64>) -> Result<(i64, i64, i64), AppError> {
+    let page = page.unwrap_or(1);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if page < 1 {
+        return Err(AppError::invalid_field("page", "must be 1 or greater"));
+    }
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(AppError::invalid_field(
+            "limit",
+            format!("must be between 1 and {}", MAX_PAGE_LIMIT),
+        ));
+    }
+    let offset = (page - 1)
+        .checked_mul(limit)
+        .ok_or_else(|| AppError::invalid_field("page", "is out of range"))?;
+    Ok((page, limit, offset))
+}
+
+/// `page_window` for endpoints that can export: a file gets every row, as
+/// one page without a limit (`LIMIT -1` in SQLite).
+fn export_window(export: &Export, page: Option<i64>, limit: Option<i64>) -> Result<(i64, i64, i64), AppError> {
+    if export.is_file() {
+        Ok((1, -1, 0))
+    } else {
+        page_window(page, limit)
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct GlobalSearchParams {
+    /// Free-text query; each word is matched as a prefix
+    pub q: String,
+    /// Restrict results to one of `product`, `customer` or `sale`
+    pub kind: Option<String>,
+    /// Include archived products and customers (default false)
+    pub include_archived: Option<bool>,
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "Search",
+    params(GlobalSearchParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Ranked full-text search across products, customers and sales; misspelt words match the closest indexed words", body = Page<SearchHit>), (status = 422, description = "Invalid query, kind, page or limit", body = ApiError))
+)]
+pub async fn search(
+    State(state): State<AppState>,
+    Query(params): Query<GlobalSearchParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let query = fts::search_query(&state.db, &["products_fts", "customers_fts", "sales_fts"], &params.q)
+        .await?
+        .ok_or_else(|| AppError::invalid_field("q", "must contain at least one word"))?;
+    let kind = match params.kind.as_deref() {
+        None | Some("") => None,
+        Some(kind) => Some(SearchKind::from_str(kind).map_err(|_| {
+            AppError::invalid_field("kind", "must be one of product, customer or sale")
+        })?),
+    };
+
+    let filter = repo::search::SearchFilter {
+        query,
+        kind,
+        include_archived: params.include_archived.unwrap_or(false),
+    };
+    if export.is_csv() {
+        return csv_stream("search", repo::search::stream(&state.db, &filter));
+    }
+
+    let (hits, total) = repo::search::list(&state.db, &filter, limit, offset).await?;
+    export.respond("search", Page::new(hits, total, page, limit))
 }
 
 #[utoipa::path(
     get,
     path = "/api/products",
     tag = "Products",
+    params(SearchParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "List all products with optional search and pagination", body = [Product]))
+    responses((status = 200, description = "List all products with optional search and pagination", body = Page<Product>), (status = 422, description = "Invalid page or limit", body = ApiError))
 )]
 pub async fn list_products(
     State(state): State<AppState>,
     Query(params): Query<SearchParams>,
-) -> Result<Json<Vec<Product>>, StatusCode> {
-    let rows = if let Some(search) = params.search {
-        let pattern = format!("%{}%", search);
-        sqlx::query(
-            "SELECT id, name, description, price_cents, stock, product_type 
-             FROM products 
-             WHERE name LIKE ? 
-                OR description LIKE ? 
-                OR EXISTS (
-                    SELECT 1 FROM product_details 
-                    WHERE product_details.product_id = products.id 
-                    AND (detail_name LIKE ? OR detail_value LIKE ?)
-                )"
-        )
-        .bind(&pattern)
-        .bind(&pattern)
-        .bind(&pattern)
-        .bind(&pattern)
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?
-    } else {
-        sqlx::query("SELECT id, name, description, price_cents, stock, product_type FROM products")
-            .fetch_all(&state.db)
-            .await
-            .map_err(map_db_err)?
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let filter = repo::products::ProductFilter {
+        search: match params.search.as_deref() {
+            Some(search) => fts::search_query(&state.db, &["products_fts"], search).await?,
+            None => None,
+        },
+        include_archived: params.include_archived.unwrap_or(false),
     };
-
-    let mut products = Vec::with_capacity(rows.len());
-    for row in rows {
-        let mut product = product_from_row(&row)?;
-        let details_rows = sqlx::query(
-            "SELECT product_id, detail_name, detail_value FROM product_details WHERE product_id = ?",
-        )
-        .bind(product.id.to_string())
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
-
-        let details: Vec<ProductDetails> = details_rows
-            .into_iter()
-            .map(|row| ProductDetails {
-                product_id: parse_uuid(row.get("product_id")).unwrap_or_default(),
-                detail_name: row.get("detail_name"),
-                detail_value: row.get("detail_value"),
-            })
-            .collect();
-        
-        product.details = details;
-        products.push(product);
+    if export.is_csv() {
+        return csv_stream("products", repo::products::stream(&state.db, &filter));
     }
-    Ok(Json(products))
+    let (products, total) = repo::products::list(&state.db, &filter, limit, offset).await?;
+    export.respond("products", Page::new(products, total, page, limit))
 }
-
 #[utoipa::path(
     post,
     path = "/api/products",
     tag = "Products",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
     request_body = ProductInput,
     security(("bearer_auth" = [])),
-    responses((status = 201, description = "Create a new product with details", body = Product))
+    responses((status = 201, description = "Create a new product with details", body = Product), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn create_product(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Json(input): Json<ProductInput>,
-) -> Result<(StatusCode, Json<Product>), StatusCode> {
+) -> Result<(StatusCode, Json<Product>), AppError> {
+    input.validate()?;
     let product_id = Uuid::new_v4();
     let product = Product {
         id: product_id,
         name: input.name,
         description: input.description,
         price_cents: input.price_cents,
+        cost_price_cents: input.cost_price_cents,
         stock: input.stock,
         product_type: input.product_type,
-
+        reorder_point: input.reorder_point,
+        reorder_quantity: input.reorder_quantity,
         details: input.details.iter().map(|d| ProductDetails {
             product_id,
             detail_name: d.detail_name.clone(),
             detail_value: d.detail_value.clone(),
         }).collect(),
+        archived_at: None,
     };
 
     let mut tx = state.db.begin().await.map_err(map_db_err)?;
-
-    sqlx::query(
-        "INSERT INTO products (id, name, description, price_cents, stock, product_type) VALUES (?, ?, ?, ?, ?, ?)",
-    )
-    .bind(product.id.to_string())
-    .bind(&product.name)
-    .bind(&product.description)
-    .bind(product.price_cents)
-    .bind(product.stock)
-    .bind(product.product_type.as_str())
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
-
-    for detail in input.details {
-        sqlx::query(
-            "INSERT INTO product_details (id, product_id, detail_name, detail_value) VALUES (?, ?, ?, ?)",
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(product_id.to_string())
-        .bind(&detail.detail_name)
-        .bind(&detail.detail_value)
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
-    }
+    repo::products::insert(&mut tx, &product).await?;
+    record_price(&mut tx, product_id, product.price_cents, None, &claims).await?;
+    webhooks::enqueue(&mut tx, WebhookEventType::ProductCreated, &product).await?;
 
     tx.commit().await.map_err(map_db_err)?;
 
@@ -176,40 +245,13 @@ pub async fn create_product(
     tag = "Products",
     params(("id" = String, Path, description = "Product id")),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve a specific product by its unique ID", body = Product), (status = 404))
+    responses((status = 200, description = "Retrieve a specific product by its unique ID", body = Product), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn get_product(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<Json<Product>, StatusCode> {
-    let row = sqlx::query(
-        "SELECT id, name, description, price_cents, stock, product_type FROM products WHERE id = ?",
-    )
-    .bind(id.to_string())
-    .fetch_optional(&state.db)
-    .await
-    .map_err(map_db_err)?
-    .ok_or(StatusCode::NOT_FOUND)?;
-
-    let mut product = product_from_row(&row)?;
-    let details_rows = sqlx::query(
-        "SELECT product_id, detail_name, detail_value FROM product_details WHERE product_id = ?",
-    )
-    .bind(product.id.to_string())
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
-
-    product.details = details_rows
-        .into_iter()
-        .map(|row| ProductDetails {
-            product_id: parse_uuid(row.get("product_id")).unwrap_or_default(),
-            detail_name: row.get("detail_name"),
-            detail_value: row.get("detail_value"),
-        })
-        .collect();
-
-    Ok(Json(product))
+) -> Result<Json<Product>, AppError> {
+    Ok(Json(repo::products::fetch(&state.db, id).await?))
 }
 
 #[utoipa::path(
@@ -219,65 +261,42 @@ pub async fn get_product(
     params(("id" = String, Path, description = "Product id")),
     request_body = ProductInput,
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Update an existing product's information", body = Product), (status = 404))
+    responses((status = 200, description = "Update an existing product's information", body = Product), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn update_product(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Path(id): Path<Uuid>,
     Json(input): Json<ProductInput>,
-) -> Result<Json<Product>, StatusCode> {
-    let product = Product {
+) -> Result<Json<Product>, AppError> {
+    input.validate()?;
+    let mut product = Product {
         id,
         name: input.name,
         description: input.description,
         price_cents: input.price_cents,
+        cost_price_cents: input.cost_price_cents,
         stock: input.stock,
         product_type: input.product_type,
+        reorder_point: input.reorder_point,
+        reorder_quantity: input.reorder_quantity,
         details: input.details.iter().map(|d| ProductDetails {
             product_id: id,
             detail_name: d.detail_name.clone(),
             detail_value: d.detail_value.clone(),
         }).collect(),
+        archived_at: None,
     };
 
     let mut tx = state.db.begin().await.map_err(map_db_err)?;
 
-    let result = sqlx::query(
-        "UPDATE products SET name = ?, description = ?, price_cents = ?, stock = ?, product_type = ? WHERE id = ?",
-    )
-    .bind(&product.name)
-    .bind(&product.description)
-    .bind(product.price_cents)
-    .bind(product.stock)
-    .bind(product.product_type.as_str())
-    .bind(product.id.to_string())
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
-
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    let (previous_price, archived_at) = repo::products::update(&mut tx, &product).await?;
+    product.archived_at = archived_at;
 
-    // Replace details
-    sqlx::query("DELETE FROM product_details WHERE product_id = ?")
-        .bind(product.id.to_string())
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
-
-    for detail in input.details {
-        sqlx::query(
-            "INSERT INTO product_details (id, product_id, detail_name, detail_value) VALUES (?, ?, ?, ?)",
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(product.id.to_string())
-        .bind(&detail.detail_name)
-        .bind(&detail.detail_value)
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
+    if product.price_cents != previous_price {
+        record_price(&mut tx, product.id, product.price_cents, None, &claims).await?;
     }
+    webhooks::enqueue(&mut tx, WebhookEventType::ProductUpdated, &product).await?;
 
     tx.commit().await.map_err(map_db_err)?;
 
@@ -290,104 +309,264 @@ pub async fn update_product(
     tag = "Products",
     params(("id" = String, Path, description = "Product id")),
     security(("bearer_auth" = [])),
-    responses((status = 204, description = "Permanently remove a product from the system"), (status = 404))
+    responses((status = 204, description = "Archive a product; it is hidden from lists but stays resolvable from sales"), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn delete_product(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx::query("DELETE FROM products WHERE id = ?")
-        .bind(id.to_string())
-        .execute(&state.db)
-        .await
-        .map_err(map_db_err)?;
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    // Archiving an archived product again changes nothing
+    let Some(product) = repo::products::archive(&mut tx, id, Utc::now()).await? else {
+        return match repo::products::archived(&mut tx, id).await? {
+            Some(_) => Ok(StatusCode::NO_CONTENT),
+            None => Err(AppError::not_found("Product")),
+        };
+    };
+    webhooks::enqueue(&mut tx, WebhookEventType::ProductArchived, &product).await?;
+    tx.commit().await.map_err(map_db_err)?;
 
-    if result.rows_affected() == 0 {
-        Ok(StatusCode::NOT_FOUND)
-    } else {
-        Ok(StatusCode::NO_CONTENT)
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/products/{id}/restore",
+    tag = "Products",
+    params(("id" = String, Path, description = "Product id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Restore an archived product", body = Product), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn restore_product(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Product>, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    // Restoring an active product changes nothing
+    let Some(product) = repo::products::restore(&mut tx, id).await? else {
+        drop(tx);
+        return get_product(State(state), Path(id)).await;
+    };
+    webhooks::enqueue(&mut tx, WebhookEventType::ProductRestored, &product).await?;
+    tx.commit().await.map_err(map_db_err)?;
+
+    Ok(Json(product))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PriceHistoryParams {
+    /// Report the price in effect at this instant (default now)
+    pub at: Option<DateTime<Utc>>,
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/products/{id}/price_history",
+    tag = "Products",
+    params(("id" = String, Path, description = "Product id"), PriceHistoryParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List past, current and scheduled prices of a product, latest first", body = ProductPriceHistory), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn get_product_price_history(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PriceHistoryParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    repo::products::ensure_exists(&state.db, id).await?;
+    let (prices, total) = repo::product_prices::list(&state.db, id, limit, offset).await?;
+    let at = params.at.unwrap_or_else(Utc::now);
+    let price_cents_at = repo::product_prices::recorded_at(&state.db, id, at).await?;
+
+    export.respond("price-history", ProductPriceHistory {
+        page: Page::new(prices, total, page, limit),
+        price_cents_at,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/products/{id}/prices",
+    tag = "Products",
+    params(
+        ("id" = String, Path, description = "Product id"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")
+    ),
+    request_body = ProductPriceInput,
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Change a product's price now or schedule a change for later", body = ProductPrice), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn create_product_price(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<ProductPriceInput>,
+) -> Result<(StatusCode, Json<ProductPrice>), AppError> {
+    input.validate()?;
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    repo::products::ensure_exists(&mut tx, id).await?;
+
+    let price = record_price(&mut tx, id, input.price_cents, input.effective_from, &claims).await?;
+    if !price.is_scheduled() {
+        repo::product_prices::apply_due(&mut tx, Utc::now()).await?;
+    }
+
+    tx.commit().await.map_err(map_db_err)?;
+    Ok((StatusCode::CREATED, Json(price)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/products/{id}/prices/{price_id}",
+    tag = "Products",
+    params(("id" = String, Path, description = "Product id"), ("price_id" = String, Path, description = "Price id")),
+    security(("bearer_auth" = [])),
+    responses((status = 204, description = "Cancel a scheduled price change"), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "Price is already in effect", body = ApiError))
+)]
+pub async fn cancel_product_price(
+    State(state): State<AppState>,
+    Path((id, price_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    repo::product_prices::cancel(&state.db, id, price_id, Utc::now()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Appends a price to a product's history, effective now unless a later
+/// `effective_from` schedules it. Does not touch `products.price_cents`.
+async fn record_price(
+    tx: &mut Tx,
+    product_id: Uuid,
+    price_cents: i64,
+    effective_from: Option<DateTime<Utc>>,
+    claims: &Claims,
+) -> Result<ProductPrice, AppError> {
+    let now = Utc::now();
+    let changed_by = Uuid::parse_str(&claims.sub).ok();
+    repo::product_prices::insert(tx, product_id, price_cents, effective_from.unwrap_or(now), changed_by, now).await
+}
+
+/// New sales may only reference active records; archived ones are kept for
+/// history. `archived` is the record's state as its repository reports it.
+fn ensure_not_archived(archived: Option<bool>, field: &str) -> Result<(), AppError> {
+    if archived == Some(true) {
+        return Err(AppError::invalid_field(field, "refers to an archived record"));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/products/low_stock",
+    tag = "Inventory",
+    params(PageParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List physical goods at or below their reorder point", body = Page<Product>), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_low_stock_products(
+    State(state): State<AppState>,
+    Query(params): Query<PageParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    if export.is_csv() {
+        return csv_stream("low-stock-products", repo::products::stream_low_stock(&state.db));
+    }
+    let (products, total) = repo::products::low_stock(&state.db, limit, offset).await?;
+    export.respond("low-stock-products", Page::new(products, total, page, limit))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StockAlertParams {
+    /// Include alerts that have already been acknowledged
+    pub include_acknowledged: Option<bool>,
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stock_alerts",
+    tag = "Inventory",
+    params(StockAlertParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List low-stock alerts raised by sales, newest first", body = Page<StockAlert>), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_stock_alerts(
+    State(state): State<AppState>,
+    Query(params): Query<StockAlertParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let include_acknowledged = params.include_acknowledged.unwrap_or(false);
+    if export.is_csv() {
+        return csv_stream("stock-alerts", repo::stock_alerts::stream(&state.db, include_acknowledged));
     }
+    let (alerts, total) = repo::stock_alerts::list(&state.db, include_acknowledged, limit, offset).await?;
+    export.respond("stock-alerts", Page::new(alerts, total, page, limit))
+}
+#[utoipa::path(
+    post,
+    path = "/api/stock_alerts/{id}/acknowledge",
+    tag = "Inventory",
+    params(("id" = String, Path, description = "Stock alert id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Mark a low-stock alert as handled", body = StockAlert), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn acknowledge_stock_alert(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StockAlert>, AppError> {
+    Ok(Json(repo::stock_alerts::acknowledge(&state.db, id).await?))
 }
 
 #[utoipa::path(
     get,
     path = "/api/customers",
     tag = "Customers",
+    params(SearchParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "List all customers with optional search and pagination", body = [Customer]))
+    responses((status = 200, description = "List all customers with optional search and pagination", body = Page<Customer>), (status = 422, description = "Invalid page or limit", body = ApiError))
 )]
 pub async fn list_customers(
     State(state): State<AppState>,
     Query(params): Query<SearchParams>,
-) -> Result<Json<Vec<Customer>>, StatusCode> {
-    let rows = if let Some(search) = params.search {
-        let pattern = format!("%{}%", search);
-        sqlx::query(
-            "SELECT id, first_name, last_name, middle_name, mobile_number, date_of_birth, email \
-             FROM customers \
-             WHERE first_name LIKE ? \
-                OR last_name LIKE ? \
-                OR middle_name LIKE ? \
-                OR EXISTS (
-                    SELECT 1 FROM customer_details \
-                    WHERE customer_details.customer_id = customers.id \
-                    AND detail_value LIKE ? \
-                )"
-        )
-        .bind(&pattern)
-        .bind(&pattern)
-        .bind(&pattern)
-        .bind(&pattern)
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?
-    } else {
-        sqlx::query(
-            "SELECT id, first_name, last_name, middle_name, mobile_number, date_of_birth, email FROM customers",
-        )
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let filter = repo::customers::CustomerFilter {
+        search: match params.search.as_deref() {
+            Some(search) => fts::search_query(&state.db, &["customers_fts"], search).await?,
+            None => None,
+        },
+        include_archived: params.include_archived.unwrap_or(false),
     };
-
-    let mut customers = Vec::with_capacity(rows.len());
-    for row in rows {
-        let mut customer = customer_from_row(&row)?;
-        let details_rows = sqlx::query(
-            "SELECT customer_id, detail_name, detail_value FROM customer_details WHERE customer_id = ?",
-        )
-        .bind(customer.id.to_string())
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
-
-        let details: Vec<CustomerDetails> = details_rows
-            .into_iter()
-            .map(|row| CustomerDetails {
-                customer_id: parse_uuid(row.get("customer_id")).unwrap_or_default(),
-                detail_name: row.get("detail_name"),
-                detail_value: row.get("detail_value"),
-            })
-            .collect();
-        
-        customer.details = details;
-        customers.push(customer);
+    if export.is_csv() {
+        return csv_stream("customers", repo::customers::stream(&state.db, &filter));
     }
-    Ok(Json(customers))
+    let (customers, total) = repo::customers::list(&state.db, &filter, limit, offset).await?;
+    export.respond("customers", Page::new(customers, total, page, limit))
 }
-
 #[utoipa::path(
     post,
     path = "/api/customers",
     tag = "Customers",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
     request_body = CustomerInput,
     security(("bearer_auth" = [])),
-    responses((status = 201, description = "Register a new customer", body = Customer), (status = 400))
+    responses((status = 201, description = "Register a new customer", body = Customer), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn create_customer(
     State(state): State<AppState>,
     Json(input): Json<CustomerInput>,
-) -> Result<(StatusCode, Json<Customer>), StatusCode> {
+) -> Result<(StatusCode, Json<Customer>), AppError> {
+    input.validate()?;
     let customer_id = Uuid::new_v4();
     let customer = Customer {
         id: customer_id,
@@ -402,36 +581,12 @@ pub async fn create_customer(
             detail_name: d.detail_name.clone(),
             detail_value: d.detail_value.clone(),
         }).collect(),
+        archived_at: None,
     };
 
     let mut tx = state.db.begin().await.map_err(map_db_err)?;
-
-    sqlx::query(
-        "INSERT INTO customers (id, first_name, last_name, middle_name, mobile_number, date_of_birth, email) VALUES (?, ?, ?, ?, ?, ?, ?)",
-    )
-    .bind(customer.id.to_string())
-    .bind(&customer.first_name)
-    .bind(&customer.last_name)
-    .bind(&customer.middle_name)
-    .bind(&customer.mobile_number)
-    .bind(&customer.date_of_birth)
-    .bind(&customer.email)
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
-
-    for detail in input.details {
-        sqlx::query(
-            "INSERT INTO customer_details (id, customer_id, detail_name, detail_value) VALUES (?, ?, ?, ?)",
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(customer_id.to_string())
-        .bind(&detail.detail_name)
-        .bind(&detail.detail_value)
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
-    }
+    repo::customers::insert(&mut tx, &customer).await?;
+    webhooks::enqueue(&mut tx, WebhookEventType::CustomerCreated, &customer).await?;
 
     tx.commit().await.map_err(map_db_err)?;
 
@@ -444,40 +599,13 @@ pub async fn create_customer(
     tag = "Customers",
     params(("id" = String, Path, description = "Customer id")),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve a specific customer's profile", body = Customer), (status = 404))
+    responses((status = 200, description = "Retrieve a specific customer's profile", body = Customer), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn get_customer(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<Json<Customer>, StatusCode> {
-    let row = sqlx::query(
-        "SELECT id, first_name, last_name, middle_name, mobile_number, date_of_birth, email FROM customers WHERE id = ?",
-    )
-    .bind(id.to_string())
-    .fetch_optional(&state.db)
-    .await
-    .map_err(map_db_err)?
-    .ok_or(StatusCode::NOT_FOUND)?;
-
-    let mut customer = customer_from_row(&row)?;
-    let details_rows = sqlx::query(
-        "SELECT customer_id, detail_name, detail_value FROM customer_details WHERE customer_id = ?",
-    )
-    .bind(customer.id.to_string())
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
-
-    customer.details = details_rows
-        .into_iter()
-        .map(|row| CustomerDetails {
-            customer_id: parse_uuid(row.get("customer_id")).unwrap_or_default(),
-            detail_name: row.get("detail_name"),
-            detail_value: row.get("detail_value"),
-        })
-        .collect();
-
-    Ok(Json(customer))
+) -> Result<Json<Customer>, AppError> {
+    Ok(Json(repo::customers::fetch(&state.db, id).await?))
 }
 
 #[utoipa::path(
@@ -487,16 +615,17 @@ pub async fn get_customer(
     params(("id" = String, Path, description = "Customer id")),
     request_body = CustomerInput,
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Update a customer's profile information", body = Customer), (status = 400), (status = 404))
+    responses((status = 200, description = "Update a customer's profile information", body = Customer), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn update_customer(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
     Json(input): Json<CustomerInput>,
-) -> Result<Json<Customer>, StatusCode> {
+) -> Result<Json<Customer>, AppError> {
+    input.validate()?;
 
 
-    let customer = Customer {
+    let mut customer = Customer {
         id,
         first_name: input.first_name,
         last_name: input.last_name,
@@ -509,47 +638,13 @@ pub async fn update_customer(
             detail_name: d.detail_name.clone(),
             detail_value: d.detail_value.clone(),
         }).collect(),
+        archived_at: None,
     };
 
     let mut tx = state.db.begin().await.map_err(map_db_err)?;
 
-    let result = sqlx::query(
-        "UPDATE customers SET first_name = ?, last_name = ?, middle_name = ?, mobile_number = ?, date_of_birth = ?, email = ? WHERE id = ?",
-    )
-    .bind(&customer.first_name)
-    .bind(&customer.last_name)
-    .bind(&customer.middle_name)
-    .bind(&customer.mobile_number)
-    .bind(&customer.date_of_birth)
-    .bind(&customer.email)
-    .bind(customer.id.to_string())
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
-
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
-
-    // Replace details
-    sqlx::query("DELETE FROM customer_details WHERE customer_id = ?")
-        .bind(customer.id.to_string())
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
-
-    for detail in input.details {
-        sqlx::query(
-            "INSERT INTO customer_details (id, customer_id, detail_name, detail_value) VALUES (?, ?, ?, ?)",
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(customer.id.to_string())
-        .bind(&detail.detail_name)
-        .bind(&detail.detail_value)
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
-    }
+    customer.archived_at = repo::customers::update(&mut tx, &customer).await?;
+    webhooks::enqueue(&mut tx, WebhookEventType::CustomerUpdated, &customer).await?;
 
     tx.commit().await.map_err(map_db_err)?;
 
@@ -562,105 +657,300 @@ pub async fn update_customer(
     tag = "Customers",
     params(("id" = String, Path, description = "Customer id")),
     security(("bearer_auth" = [])),
-    responses((status = 204, description = "Permanently remove a customer"), (status = 404))
+    responses((status = 204, description = "Archive a customer; they are hidden from lists but stay resolvable from sales"), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn delete_customer(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx::query("DELETE FROM customers WHERE id = ?")
-        .bind(id.to_string())
-        .execute(&state.db)
-        .await
-        .map_err(map_db_err)?;
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    // Archiving an archived customer again changes nothing
+    let Some(customer) = repo::customers::archive(&mut tx, id, Utc::now()).await? else {
+        return match repo::customers::archived(&mut tx, id).await? {
+            Some(_) => Ok(StatusCode::NO_CONTENT),
+            None => Err(AppError::not_found("Customer")),
+        };
+    };
+    webhooks::enqueue(&mut tx, WebhookEventType::CustomerArchived, &customer).await?;
+    tx.commit().await.map_err(map_db_err)?;
 
-    if result.rows_affected() == 0 {
-        Ok(StatusCode::NOT_FOUND)
-    } else {
-        Ok(StatusCode::NO_CONTENT)
-    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/customers/{id}/restore",
+    tag = "Customers",
+    params(("id" = String, Path, description = "Customer id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Restore an archived customer", body = Customer), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn restore_customer(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Customer>, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    // Restoring an active customer changes nothing
+    let Some(customer) = repo::customers::restore(&mut tx, id).await? else {
+        drop(tx);
+        return get_customer(State(state), Path(id)).await;
+    };
+    webhooks::enqueue(&mut tx, WebhookEventType::CustomerRestored, &customer).await?;
+    tx.commit().await.map_err(map_db_err)?;
+
+    Ok(Json(customer))
 }
 
 #[utoipa::path(
     get,
-    path = "/api/sales",
-    tag = "Sales",
+    path = "/api/customers/{id}/stats",
+    tag = "Customers",
+    params(("id" = String, Path, description = "Customer id"), ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "List individual sale items with filtering by date and pagination", body = SalesItemsListResponse))
+    responses((status = 200, description = "Purchase history, lifetime value and RFM scores of a customer", body = CustomerStats), (status = 404, description = "Not found", body = ApiError))
 )]
-pub async fn list_sales(
+pub async fn get_customer_stats(
     State(state): State<AppState>,
-    Query(params): Query<SalesSearchParams>,
-) -> Result<Json<SalesItemsListResponse>, StatusCode> {
-    let mut query = "SELECT id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note FROM sale_items".to_string();
-    let mut args = Vec::new();
+    Path(id): Path<Uuid>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (mut stats, _) = repo::customers::purchase_history(&state.db, Some(id), Utc::now())
+        .await?
+        .pop()
+        .ok_or_else(|| AppError::not_found("Customer"))?;
+    if stats.visit_count > 0 {
+        let rfm = customer_rfm(&state.db, &stats).await?;
+        stats.segment = Some(rfm.segment());
+        stats.rfm = Some(rfm);
+    }
+    export.respond("customer-stats", stats)
+}
 
-    let (start_date, end_date) = get_default_dates(params.start_date.clone(), params.end_date.clone());
+#[derive(Deserialize, IntoParams)]
+pub struct CustomerSegmentParams {
+    /// Only list customers in this segment
+    pub segment: Option<CustomerSegment>,
+    /// Include archived customers (default false)
+    pub include_archived: Option<bool>,
+}
 
-    query.push_str(" WHERE date(date_of_sale) >= date(?) AND date(date_of_sale) <= date(?)");
-    args.push(start_date);
-    args.push(end_date);
-    
-    // Sort by date descending
-    query.push_str(" ORDER BY date_of_sale DESC");
+#[utoipa::path(
+    get,
+    path = "/api/customers/segments",
+    tag = "Reports",
+    params(CustomerSegmentParams, PageParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Customers grouped into RFM segments", body = CustomerSegmentReport), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_customer_segments(
+    State(state): State<AppState>,
+    Query(params): Query<CustomerSegmentParams>,
+    Query(paging): Query<PageParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, paging.page, paging.limit)?;
+    let include_archived = params.include_archived.unwrap_or(false);
+    let mut customers: Vec<CustomerStats> = customer_stats(&state.db)
+        .await?
+        .into_iter()
+        .filter(|(_, archived)| include_archived || !archived)
+        .map(|(stats, _)| stats)
+        .filter(|stats| stats.segment.is_some())
+        .collect();
 
-    // Pagination
-    let limit = params.limit.unwrap_or(20);
-    // page defaults to 1
-    let page = params.page.unwrap_or(1);
-    let offset = (page - 1) * limit;
+    let segments = CustomerSegment::ALL
+        .into_iter()
+        .map(|segment| {
+            let members = customers.iter().filter(|stats| stats.segment == Some(segment));
+            SegmentSummary {
+                segment,
+                customers: members.clone().count() as i64,
+                total_spend_cents: members.map(|stats| stats.total_spend_cents).sum(),
+            }
+        })
+        .collect();
 
-    query.push_str(" LIMIT ? OFFSET ?");
-    
-    let mut sql_query = sqlx::query(&query);
-    for arg in &args {
-        sql_query = sql_query.bind(arg);
+    if let Some(segment) = params.segment {
+        customers.retain(|stats| stats.segment == Some(segment));
     }
-    sql_query = sql_query.bind(limit).bind(offset);
+    customers.sort_by(|a, b| {
+        b.total_spend_cents
+            .cmp(&a.total_spend_cents)
+            .then_with(|| a.customer_name.cmp(&b.customer_name))
+    });
+    let total = customers.len() as i64;
+    let items = customers
+        .into_iter()
+        .skip(offset as usize)
+        .take(usize::try_from(limit).unwrap_or(usize::MAX))
+        .collect();
 
-    let rows = sql_query
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
+    export.respond("customer-segments", CustomerSegmentReport {
+        segments,
+        customers: Page::new(items, total, page, limit),
+    })
+}
 
-    let mut sales = Vec::with_capacity(rows.len());
-    for row in rows {
-        sales.push(sale_item_from_row(&row)?);
-    }
-    
-    // Calculate total for the period (DB side query usually better, but for small datasets iterating is fine too. User asked for DB query)
-    // Let's run a separate COUNT/SUM query as requested.
-    let sum_query = "SELECT SUM(total_cents) as total FROM sale_items WHERE date(date_of_sale) >= date(?) AND date(date_of_sale) <= date(?)".to_string();
-    
-    let mut sql_sum_query = sqlx::query(&sum_query);
-    for arg in &args {
-        sql_sum_query = sql_sum_query.bind(arg);
+/// Stats for every customer, with whether they are archived. RFM scores rank
+/// each buying customer against all the others, so they are worked out
+/// together here; [`customer_rfm`] ranks a single customer in SQL.
+async fn customer_stats(db: &Db) -> Result<Vec<(CustomerStats, bool)>, AppError> {
+    let mut customers = repo::customers::purchase_history(db, None, Utc::now()).await?;
+
+    let buyers: Vec<&CustomerStats> = customers.iter().map(|(stats, _)| stats).filter(|stats| stats.visit_count > 0).collect();
+    let sorted = |value: fn(&CustomerStats) -> i64| {
+        let mut values: Vec<i64> = buyers.iter().map(|stats| value(stats)).collect();
+        values.sort_unstable();
+        values
+    };
+    let recency_of = |stats: &CustomerStats| stats.last_purchase.map(|last| last.timestamp()).unwrap_or(0);
+    let recencies = sorted(recency_of);
+    let frequencies = sorted(|stats| stats.visit_count);
+    let spends = sorted(|stats| stats.total_spend_cents);
+
+    for (stats, _) in &mut customers {
+        if stats.visit_count == 0 {
+            continue;
+        }
+        let rfm = RfmScore {
+            recency: quintile_score(recency_of(stats), &recencies),
+            frequency: quintile_score(stats.visit_count, &frequencies),
+            monetary: quintile_score(stats.total_spend_cents, &spends),
+        };
+        stats.segment = Some(rfm.segment());
+        stats.rfm = Some(rfm);
     }
-    
-    let row = sql_sum_query.fetch_one(&state.db).await.map_err(map_db_err)?;
-    let total_sales_period_cents: i64 = row.try_get("total").unwrap_or(0);
+    Ok(customers)
+}
 
-    Ok(Json(SalesItemsListResponse {
-        sales,
-        total_sales_period_cents,
-    }))
+/// RFM scores of one buying customer against every buyer, as
+/// [`customer_stats`] scores them.
+async fn customer_rfm(db: &Db, stats: &CustomerStats) -> Result<RfmScore, AppError> {
+    let rank = repo::customers::buyer_rank(db, stats).await?;
+    let score = |(below, equal): (i64, i64)| quintile(below, equal, rank.buyers);
+    Ok(RfmScore {
+        recency: score(rank.recency),
+        frequency: score(rank.frequency),
+        monetary: score(rank.monetary),
+    })
+}
+
+/// Scores `value` from 1 to 5 by the fifth of the sorted `population` it
+/// falls in, higher values scoring higher. Equal values share a score.
+fn quintile_score(value: i64, population: &[i64]) -> u8 {
+    let below = population.partition_point(|v| *v < value);
+    let equal = population[below..].partition_point(|v| *v == value);
+    quintile(below as i64, equal as i64, population.len() as i64)
+}
+
+/// Scores from 1 to 5 a value with `below` values under it and `equal`,
+/// itself included, level with it among `total`.
+fn quintile(below: i64, equal: i64, total: i64) -> u8 {
+    let percentile = (below as f64 + equal as f64 / 2.0) / total as f64;
+    ((percentile * 5.0) as u8 + 1).min(5)
 }
 
 #[utoipa::path(
-    post,
+    get,
+    path = "/api/sales",
+    tag = "Sales",
+    params(SalesSearchParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List individual sale items with filtering by date and pagination", body = SalesItemsListResponse), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_sales(
+    State(state): State<AppState>,
+    Query(params): Query<SalesSearchParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date.clone(), params.end_date.clone())?;
+    let (from, to) = state.timezone.day_bounds(start_date, end_date);
+    if export.is_csv() {
+        return csv_stream("sale-items", repo::sale_items::stream(&state.db, from, to));
+    }
+    let (sales, total) = repo::sale_items::list(&state.db, from, to, limit, offset).await?;
+    // Summed over the whole period, not just the current page.
+    let total_sales_period_cents = repo::sale_items::total_cents(&state.db, from, to).await?;
+
+    export.respond("sale-items", SalesItemsListResponse {
+        page: Page::new(sales, total, page, limit),
+        total_sales_period_cents,
+    })
+}
+#[utoipa::path(
+    post,
     path = "/api/sales",
     tag = "Sales",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
     request_body = SaleItemInput,
     security(("bearer_auth" = [])),
-    responses((status = 201, description = "Record a single sale item (for legacy or single-item sales)", body = SaleItem))
+    responses((status = 201, description = "Record a single sale item (for legacy or single-item sales). It joins the sale `sale_id` names, or else becomes a one-item sale by the signed-in staff member, in their open shift unless it is a `web` sale", body = SaleItem), (status = 409, description = "No open shift to record the item in", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn create_sale(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Json(input): Json<SaleItemInput>,
-) -> Result<(StatusCode, Json<SaleItem>), StatusCode> {
+) -> Result<(StatusCode, Json<SaleItem>), AppError> {
+    input.validate()?;
+    ensure_not_archived(repo::products::archived(&state.db, input.product_id).await?, "product_id")?;
+    if let Some(customer_id) = input.customer_id {
+        ensure_not_archived(repo::customers::archived(&state.db, customer_id).await?, "customer_id")?;
+    }
+
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    // Items only reach a shift's takings through their sale, so a loose item
+    // becomes a one-item sale by the signed-in staff member, made like any
+    // other sale
+    let Some(sale_id) = input.sale_id else {
+        let sales_channel = input.sales_channel.clone().unwrap_or(SalesChannel::Mobile);
+        let shift = repo::shifts::open_for_staff(&mut tx, &claims.sub).await?;
+        if shift.is_none() && !matches!(sales_channel, SalesChannel::Web) {
+            return Err(AppError::Conflict("Open a shift before recording sales".to_string(), vec![]));
+        }
+        let sale_id = Uuid::new_v4();
+        let sale = sale_from_input(
+            sale_id,
+            SaleInput {
+                customer_id: input.customer_id,
+                date_and_time: input.date_of_sale,
+                total_cents: input.total_cents,
+                discount: input.discount,
+                total_resolved: input.total_resolved,
+                sales_channel,
+                staff_responsible: parse_uuid(claims.sub)?,
+                company_branch: shift.as_ref().map(|shift| shift.company_branch.clone()).unwrap_or_default(),
+                car_number: String::new(),
+                receipt_number: String::new(),
+                payment_method: PaymentMethod::default(),
+                shift_id: shift.map(|shift| shift.id),
+                sale_items: vec![input],
+            },
+        );
+        let (sale, stock_alerts) = insert_sale(&mut tx, sale).await?;
+        tx.commit().await.map_err(map_db_err)?;
+        log_stock_alerts(&stock_alerts);
+        live::publish_sales(&state, vec![sale.clone()]);
+
+        let item = sale.sale_items.into_iter().next().ok_or_else(|| AppError::internal("the sale was stored without its item"))?;
+        return Ok((StatusCode::CREATED, Json(item)));
+    };
+
+    // Adding to a recorded sale edits it, like `update_sale`
+    let recorded = repo::sales::find(&mut tx, sale_id)
+        .await?
+        .ok_or_else(|| AppError::invalid_field("sale_id", "is not a sale"))?;
+    let shift = match recorded.shift_id {
+        Some(shift_id) => repo::shifts::find_open(&mut tx, shift_id).await?,
+        None => None,
+    };
+    if !matches!(recorded.sales_channel, SalesChannel::Web) && shift.is_none() {
+        return Err(AppError::Conflict("The sale's shift is not open".to_string(), vec![]));
+    }
+
     let sale = SaleItem {
         id: Uuid::new_v4(),
-        sale_id: input.sale_id,
+        sale_id: Some(sale_id),
         product_id: input.product_id,
         customer_id: input.customer_id,
         date_of_sale: input.date_of_sale,
@@ -670,36 +960,16 @@ pub async fn create_sale(
         total_resolved: input.total_resolved,
         note: input.note,
         product_name: None,
-        price_per_item: None,
+        price_per_item: Some(repo::product_prices::price_at(&mut tx, input.product_id, Utc::now()).await?),
+        cost_per_item: None,
     };
+    repo::sale_items::insert(&mut tx, &sale).await?;
+    let stock_alert = deduct_stock(&mut tx, &sale, sale.quantity).await?;
+    let sale = repo::sale_items::fetch(&mut tx, sale.id).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    log_stock_alerts(stock_alert.as_slice());
 
-    sqlx::query(
-        "INSERT INTO sale_items (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note, product_name, price_per_item) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT name FROM products WHERE id = ?), (SELECT price_cents FROM products WHERE id = ?))",
-    )
-    .bind(sale.id.to_string())
-    .bind(sale.sale_id.map(|id| id.to_string()))
-    .bind(sale.product_id.to_string())
-    .bind(sale.customer_id.map(|id| id.to_string()))
-    .bind(&sale.date_of_sale)
-    .bind(sale.quantity)
-    .bind(sale.discount)
-    .bind(sale.total_cents)
-    .bind(sale.total_resolved)
-    .bind(&sale.note)
-    .bind(sale.product_id.to_string())
-    .bind(sale.product_id.to_string())
-    .execute(&state.db)
-    .await
-    .map_err(map_db_err)?;
-
-    // Fetch the created item to get product_name and price_per_item and ensure consistency
-    let row = sqlx::query("SELECT sale_items.id, sale_items.sale_id, sale_items.product_id, sale_items.customer_id, sale_items.date_of_sale, sale_items.quantity, sale_items.discount, sale_items.total_cents, sale_items.total_resolved, sale_items.note, COALESCE(sale_items.product_name, products.name) as product_name, COALESCE(sale_items.price_per_item, products.price_cents) as price_per_item FROM sale_items LEFT JOIN products ON sale_items.product_id = products.id WHERE sale_items.id = ?")
-        .bind(sale.id.to_string())
-        .fetch_one(&state.db)
-        .await
-        .map_err(map_db_err)?;
-        
-    let sale = sale_item_from_row(&row)?;
+    live::publish_sales(&state, vec![]);
 
     Ok((StatusCode::CREATED, Json(sale)))
 }
@@ -708,20 +978,61 @@ pub async fn create_sale(
     post,
     path = "/api/sales_transactions",
     tag = "Sales",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
     request_body = SaleInput,
     security(("bearer_auth" = [])),
-    responses((status = 201, description = "Create a new sales transaction containing multiple items", body = Sale))
+    responses((status = 201, description = "Create a new sales transaction containing multiple items, in the shift `shift_id` names or else the signed-in staff member's open shift. Only `web` sales may be made outside a shift", body = Sale), (status = 409, description = "An in-store sale with no open shift", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn create_sales_transaction(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Json(input): Json<shared::models::SaleInput>,
-) -> Result<(StatusCode, Json<shared::models::Sale>), StatusCode> {
-    let sale_id = Uuid::new_v4();
-    let mut sale = shared::models::Sale {
+) -> Result<(StatusCode, Json<shared::models::Sale>), AppError> {
+    input.validate()?;
+    let mut sale = sale_from_input(Uuid::new_v4(), input);
+
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+
+    let staff_archived = repo::staff::archived(&mut tx, sale.staff_responsible).await?;
+    if staff_archived.is_none() {
+        return Err(AppError::invalid_field("staff_responsible", "is not a staff member"));
+    }
+    ensure_not_archived(staff_archived, "staff_responsible")?;
+    if let Some(customer_id) = sale.customer_id {
+        ensure_not_archived(repo::customers::archived(&mut tx, customer_id).await?, "customer_id")?;
+    }
+    for (index, item) in sale.sale_items.iter().enumerate() {
+        let field = format!("sale_items[{}].product_id", index);
+        ensure_not_archived(repo::products::archived(&mut tx, item.product_id).await?, &field)?;
+    }
+
+    // Sales go into the shift named, or else the one the seller has open.
+    // Web orders are taken without a till, so only they may have neither.
+    sale.shift_id = match sale.shift_id {
+        Some(shift_id) => match repo::shifts::find_open(&mut tx, shift_id).await? {
+            Some(shift) => Some(shift.id),
+            None => return Err(AppError::invalid_field("shift_id", "is not an open shift")),
+        },
+        None => repo::shifts::open_for_staff(&mut tx, &claims.sub).await?.map(|shift| shift.id),
+    };
+    if sale.shift_id.is_none() && !matches!(sale.sales_channel, SalesChannel::Web) {
+        return Err(AppError::Conflict("Open a shift before ringing up in-store sales".to_string(), vec![]));
+    }
+
+    let (sale, stock_alerts) = insert_sale(&mut tx, sale).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    log_stock_alerts(&stock_alerts);
+    live::publish_sales(&state, vec![sale.clone()]);
+
+    Ok((StatusCode::CREATED, Json(sale)))
+}
+
+fn sale_from_input(sale_id: Uuid, input: SaleInput) -> Sale {
+    Sale {
         id: sale_id,
         customer_id: input.customer_id,
         date_and_time: input.date_and_time,
-        sale_items: input.sale_items.iter().map(|item_input| shared::models::SaleItem {
+        sale_items: input.sale_items.into_iter().map(|item_input| SaleItem {
             id: Uuid::new_v4(),
             sale_id: Some(sale_id),
             product_id: item_input.product_id,
@@ -731,9 +1042,10 @@ pub async fn create_sales_transaction(
             discount: item_input.discount,
             total_cents: item_input.total_cents,
             total_resolved: item_input.total_resolved,
-            note: item_input.note.clone(),
+            note: item_input.note,
             product_name: None,
             price_per_item: None,
+            cost_per_item: None,
         }).collect(),
         total_cents: input.total_cents,
         discount: input.discount,
@@ -743,179 +1055,350 @@ pub async fn create_sales_transaction(
         company_branch: input.company_branch,
         car_number: input.car_number,
         receipt_number: input.receipt_number,
-    };
-
-    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+        payment_method: input.payment_method,
+        shift_id: input.shift_id,
+    }
+}
 
-    sqlx::query(
-        "INSERT INTO sales (id, customer_id, date_and_time, total_cents, discount, total_resolved, sales_channel, staff_responsible, company_branch, car_number, receipt_number) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-    )
-    .bind(sale.id.to_string())
-    .bind(sale.customer_id.map(|id| id.to_string()))
-    .bind(&sale.date_and_time)
-    .bind(sale.total_cents)
-    .bind(sale.discount)
-    .bind(sale.total_resolved)
-    .bind(sale.sales_channel.to_string())
-    .bind(sale.staff_responsible.to_string())
-    .bind(&sale.company_branch)
-    .bind(&sale.car_number)
-    .bind(&sale.receipt_number)
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
+/// Stores a sale with its items and takes them out of stock. Items snapshot
+/// the product's current name and the price in effect now, including a
+/// scheduled price that has come due but not been applied yet, unless
+/// `product_name` or `price_per_item` are already set.
+async fn insert_sale(
+    tx: &mut Tx,
+    mut sale: Sale,
+) -> Result<(Sale, Vec<StockAlert>), AppError> {
+    let mut stock_alerts = Vec::new();
+    let now = Utc::now();
+    for item in &mut sale.sale_items {
+        if item.price_per_item.is_none() {
+            item.price_per_item = Some(repo::product_prices::price_at(&mut *tx, item.product_id, now).await?);
+        }
+    }
 
+    repo::sales::insert(&mut *tx, &sale).await?;
     for item in &sale.sale_items {
-        sqlx::query(
-            "INSERT INTO sale_items (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note, product_name, price_per_item) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT name FROM products WHERE id = ?), (SELECT price_cents FROM products WHERE id = ?))",
-        )
-        .bind(item.id.to_string())
-        .bind(item.sale_id.map(|id| id.to_string()))
-        .bind(item.product_id.to_string())
-        .bind(item.customer_id.map(|id| id.to_string()))
-        .bind(&item.date_of_sale)
-        .bind(item.quantity)
-        .bind(item.discount)
-        .bind(item.total_cents)
-        .bind(item.total_resolved)
-        .bind(&item.note)
-        .bind(item.product_id.to_string())
-        .bind(item.product_id.to_string())
-        .execute(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
+        repo::sale_items::insert(&mut *tx, item).await?;
+        if let Some(alert) = deduct_stock(tx, item, item.quantity).await? {
+            stock_alerts.push(alert);
+        }
     }
 
-    // Re-fetch items to get product_names and price_per_item
-    let items_rows = sqlx::query(
-        "SELECT sale_items.id, sale_items.sale_id, sale_items.product_id, sale_items.customer_id, sale_items.date_of_sale, sale_items.quantity, sale_items.discount, sale_items.total_cents, sale_items.total_resolved, sale_items.note, COALESCE(sale_items.product_name, products.name) as product_name, COALESCE(sale_items.price_per_item, products.price_cents) as price_per_item 
-         FROM sale_items 
-         LEFT JOIN products ON sale_items.product_id = products.id 
-         WHERE sale_id = ?"
-    )
-    .bind(sale.id.to_string())
-    .fetch_all(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
+    // Read the items back for the names and prices they were snapshotted at
+    sale.sale_items = repo::sale_items::for_sale(&mut *tx, sale.id).await?;
+    webhooks::enqueue(&mut *tx, WebhookEventType::SaleCreated, &sale).await?;
+    Ok((sale, stock_alerts))
+}
 
-    let items = items_rows.into_iter().map(|r| sale_item_from_row(&r)).collect::<Result<Vec<_>, _>>()?;
-    sale.sale_items = items;
+fn log_stock_alerts(stock_alerts: &[StockAlert]) {
+    for alert in stock_alerts {
+        tracing::warn!(
+            "Low stock: {} has {} left (reorder point {}, reorder {})",
+            alert.product_name, alert.stock, alert.reorder_point, alert.reorder_quantity
+        );
+    }
+}
 
-    tx.commit().await.map_err(map_db_err)?;
+/// Takes `quantity` of a sold item out of stock and raises a `StockAlert`
+/// when the sale is the one that pushes a physical good down to its reorder
+/// point.
+async fn deduct_stock(
+    tx: &mut Tx,
+    item: &SaleItem,
+    quantity: i64,
+) -> Result<Option<StockAlert>, AppError> {
+    let Some(level) = repo::products::remove_stock(&mut *tx, item.product_id, quantity).await? else {
+        return Ok(None);
+    };
 
-    Ok((StatusCode::CREATED, Json(sale)))
-}
+    let previous_stock = level.stock + quantity;
+    if level.reorder_point <= 0 || level.stock > level.reorder_point || previous_stock <= level.reorder_point {
+        return Ok(None);
+    }
 
+    let alert = StockAlert {
+        id: Uuid::new_v4(),
+        product_id: item.product_id,
+        product_name: level.name,
+        stock: level.stock,
+        reorder_point: level.reorder_point,
+        reorder_quantity: level.reorder_quantity,
+        sale_id: item.sale_id,
+        created_at: Utc::now(),
+        acknowledged: false,
+    };
+    repo::stock_alerts::insert(&mut *tx, &alert).await?;
+    webhooks::enqueue(&mut *tx, WebhookEventType::StockLow, &alert).await?;
+
+    Ok(Some(alert))
+}
 
 #[utoipa::path(
     get,
     path = "/api/sales_transactions",
     tag = "Sales",
+    params(SalesSearchParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "List sales transactions with filtering", body = [Sale]))
+    responses((status = 200, description = "List sales transactions with filtering", body = Page<Sale>), (status = 422, description = "Invalid page or limit", body = ApiError))
 )]
 pub async fn list_sales_transactions(
     State(state): State<AppState>,
     Query(params): Query<SalesSearchParams>,
-) -> Result<Json<Vec<Sale>>, StatusCode> {
-    let mut query = "SELECT sales.* FROM sales LEFT JOIN customers ON sales.customer_id = customers.id".to_string();
-    let mut conditions = Vec::new();
-    let mut args = Vec::new();
-
-    if let Some(q) = &params.query {
-        if !q.is_empty() {
-            let pattern = format!("%{}%", q);
-            conditions.push("(customers.first_name LIKE ? OR customers.last_name LIKE ? OR sales.receipt_number LIKE ?)");
-            args.push(pattern.clone());
-            args.push(pattern.clone());
-            args.push(pattern.clone());
-        }
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let filter = repo::sales::SaleFilter {
+        search: match params.query.as_deref() {
+            Some(query) => fts::search_query(&state.db, &["sales_fts", "customers_fts"], query).await?,
+            None => None,
+        },
+        start: params
+            .start_date
+            .as_deref()
+            .filter(|start| !start.is_empty())
+            .map(|start| parse_instant_param("start_date", start))
+            .transpose()?,
+        end: params
+            .end_date
+            .as_deref()
+            .filter(|end| !end.is_empty())
+            .map(|end| parse_instant_param("end_date", end))
+            .transpose()?,
+    };
+    if export.is_csv() {
+        return csv_stream("sales-transactions", repo::sales::stream(&state.db, &filter));
     }
+    let (sales, total) = repo::sales::list(&state.db, &filter, limit, offset).await?;
+    export.respond("sales-transactions", Page::new(sales, total, page, limit))
+}
+#[utoipa::path(
+    get,
+    path = "/api/sales_transactions/{id}",
+    tag = "Sales",
+    params(("id" = String, Path, description = "Sale UUID")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Retrieve a full sales transaction", body = Sale), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn get_sales_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Sale>, AppError> {
+    Ok(Json(repo::sales::fetch(&state.db, id).await?))
+}
 
-    if let Some(start) = &params.start_date {
-        if !start.is_empty() {
-             conditions.push("sales.date_and_time >= ?");
-             args.push(start.clone());
-        }
-    }
-    
-    if let Some(end) = &params.end_date {
-        if !end.is_empty() {
-             conditions.push("sales.date_and_time <= ?");
-             args.push(end.clone());
-        }
+#[derive(Deserialize, IntoParams)]
+pub struct SyncParams {
+    /// `version` from the previous sync; omit for a full snapshot
+    pub since: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sync/changes",
+    tag = "Sync",
+    params(SyncParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Download the products and customers changed since a version, or a full snapshot of the active ones", body = SyncChanges))
+)]
+pub async fn get_sync_changes(
+    State(state): State<AppState>,
+    Query(params): Query<SyncParams>,
+) -> Result<Json<SyncChanges>, AppError> {
+    // Read the version before the rows: anything written in between gets a
+    // later version and is simply sent again on the next sync.
+    let version = repo::sync::version(&state.db).await?;
+
+    // A version this server never issued means the device synced against
+    // another database, so it starts over from a snapshot.
+    let since = params.since.filter(|since| (1..=version).contains(since));
+
+    let products = repo::products::changed_since(&state.db, since).await?;
+    let customers = repo::customers::changed_since(&state.db, since).await?;
+
+    let (deleted_product_ids, deleted_customer_ids) = match since {
+        Some(since) => (
+            repo::sync::deleted_since(&state.db, "product", "products", since).await?,
+            repo::sync::deleted_since(&state.db, "customer", "customers", since).await?,
+        ),
+        None => (vec![], vec![]),
+    };
+
+    Ok(Json(SyncChanges {
+        version,
+        full: since.is_none(),
+        products,
+        customers,
+        deleted_product_ids,
+        deleted_customer_ids,
+    }))
+}
+
+/// Ids of `entity` records changed after `since` that are no longer in
+/// `table`. `table` must be a trusted table name.
+#[utoipa::path(
+    post,
+    path = "/api/sync/sales",
+    tag = "Sync",
+    request_body = SyncUpload,
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Upload sales recorded offline; each one is accepted, recognised as a duplicate or rejected on its own", body = SyncUploadResult), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn upload_sync_sales(
+    State(state): State<AppState>,
+    Json(input): Json<SyncUpload>,
+) -> Result<Json<SyncUploadResult>, AppError> {
+    input.validate()?;
+
+    // Sales are stored oldest first, whatever order they were sent in, so a
+    // batch is resolved the same way however the device split it up.
+    let mut uploads: Vec<(usize, SyncSaleInput)> = input.sales.into_iter().enumerate().collect();
+    uploads.sort_by_key(|(_, upload)| (upload.sale.date_and_time, upload.client_id));
+
+    let mut results = Vec::with_capacity(uploads.len());
+    let mut accepted = Vec::new();
+    for (index, upload) in uploads {
+        let client_id = upload.client_id;
+        let mut tx = state.db.begin().await.map_err(map_db_err)?;
+        let result = match sync_sale(&mut tx, upload).await {
+            Ok((result, inserted)) => {
+                tx.commit().await.map_err(map_db_err)?;
+                if let Some((sale, stock_alerts)) = inserted {
+                    log_stock_alerts(&stock_alerts);
+                    accepted.push(sale);
+                }
+                result
+            }
+            Err(AppError::Internal(cause)) => return Err(AppError::Internal(cause)),
+            Err(err) => SyncSaleResult {
+                client_id,
+                status: SyncSaleStatus::Rejected,
+                conflicts: vec![],
+                error: Some(err.into_body()),
+            },
+        };
+        results.push((index, result));
     }
 
-    if !conditions.is_empty() {
-        query.push_str(" WHERE ");
-        query.push_str(&conditions.join(" AND "));
+    if !accepted.is_empty() {
+        live::publish_sales(&state, accepted);
     }
-    
-    query.push_str(" ORDER BY sales.date_and_time DESC");
 
-    // Pagination
-    let limit = params.limit.unwrap_or(20);
-    let page = params.page.unwrap_or(1);
-    let offset = (page - 1) * limit;
+    results.sort_by_key(|(index, _)| *index);
+    Ok(Json(SyncUploadResult {
+        results: results.into_iter().map(|(_, result)| result).collect(),
+    }))
+}
 
-    query.push_str(" LIMIT ? OFFSET ?");
-    
-    let mut sql_query = sqlx::query(&query);
-    for arg in &args {
-        sql_query = sql_query.bind(arg);
+/// Records one offline sale, resolving conflicts with the server's records:
+/// archived products and customers are still sold to, missing customers are
+/// dropped from the sale, and a missing product or a staff member who is
+/// missing or archived rejects the whole sale. Items
+/// keep the amounts the device charged but snapshot the price the server had
+/// in effect at the time of sale. Returns the stored sale unless it was a
+/// duplicate or rejected.
+async fn sync_sale(
+    tx: &mut Tx,
+    upload: SyncSaleInput,
+) -> Result<(SyncSaleResult, Option<(Sale, Vec<StockAlert>)>), AppError> {
+    let SyncSaleInput { client_id, sale: input } = upload;
+    let mut result = SyncSaleResult {
+        client_id,
+        status: SyncSaleStatus::Accepted,
+        conflicts: vec![],
+        error: None,
+    };
+
+    if repo::sales::exists(&mut *tx, client_id).await? {
+        result.status = SyncSaleStatus::Duplicate;
+        return Ok((result, None));
     }
-    sql_query = sql_query.bind(limit).bind(offset);
 
-    let rows = sql_query
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
+    input.validate()?;
+    let mut sale = sale_from_input(client_id, input);
 
-    let mut sales = Vec::with_capacity(rows.len());
-    for row in rows {
-        sales.push(sale_from_row(&row)?);
+    if let Some(shift_id) = sale.shift_id
+        && repo::shifts::find_open(&mut *tx, shift_id).await?.is_none()
+    {
+        sale.shift_id = None;
+        result.conflicts.push(SyncConflict::record(SyncConflictKind::ShiftClosed, None, shift_id));
     }
 
-    Ok(Json(sales))
-}
+    let staff_conflict = match repo::staff::archived(&mut *tx, sale.staff_responsible).await? {
+        None => Some(SyncConflictKind::StaffMissing),
+        Some(true) => Some(SyncConflictKind::StaffArchived),
+        Some(false) => None,
+    };
+    if let Some(kind) = staff_conflict {
+        result.status = SyncSaleStatus::Rejected;
+        result.conflicts.push(SyncConflict::record(kind, None, sale.staff_responsible));
+    }
 
-#[utoipa::path(
-    get,
-    path = "/api/sales_transactions/{id}",
-    tag = "Sales",
-    params(("id" = String, Path, description = "Sale UUID")),
-    security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve a full sales transaction", body = Sale), (status = 404))
-)]
-pub async fn get_sales_transaction(
-    State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<Sale>, StatusCode> {
-    let row = sqlx::query("SELECT * FROM sales WHERE id = ?")
-        .bind(id.to_string())
-        .fetch_optional(&state.db)
-        .await
-        .map_err(map_db_err)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(customer_id) = sale.customer_id
+        && let Some(kind) = sync_customer_conflict(&mut *tx, customer_id).await?
+    {
+        if kind == SyncConflictKind::CustomerMissing {
+            sale.customer_id = None;
+        }
+        result.conflicts.push(SyncConflict::record(kind, None, customer_id));
+    }
+
+    for (index, item) in sale.sale_items.iter_mut().enumerate() {
+        if let Some(customer_id) = item.customer_id
+            && let Some(kind) = sync_customer_conflict(&mut *tx, customer_id).await?
+        {
+            if kind == SyncConflictKind::CustomerMissing {
+                item.customer_id = None;
+            }
+            result.conflicts.push(SyncConflict::record(kind, Some(index), customer_id));
+        }
 
-    let mut sale = sale_from_row(&row)?;
+        match repo::products::archived(&mut *tx, item.product_id).await? {
+            None => {
+                result.status = SyncSaleStatus::Rejected;
+                result.conflicts.push(SyncConflict::record(
+                    SyncConflictKind::ProductMissing,
+                    Some(index),
+                    item.product_id,
+                ));
+                continue;
+            }
+            Some(true) => result.conflicts.push(SyncConflict::record(
+                SyncConflictKind::ProductArchived,
+                Some(index),
+                item.product_id,
+            )),
+            Some(false) => {}
+        }
 
-    // Fetch items
-    let items_rows = sqlx::query(
-        "SELECT sale_items.id, sale_items.sale_id, sale_items.product_id, sale_items.customer_id, sale_items.date_of_sale, sale_items.quantity, sale_items.discount, sale_items.total_cents, sale_items.total_resolved, sale_items.note, COALESCE(sale_items.product_name, products.name) as product_name, COALESCE(sale_items.price_per_item, products.price_cents) as price_per_item 
-         FROM sale_items 
-         LEFT JOIN products ON sale_items.product_id = products.id 
-         WHERE sale_id = ?"
-    )
-    .bind(sale.id.to_string())
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
+        let price_cents = repo::product_prices::price_at(&mut *tx, item.product_id, item.date_of_sale).await?;
+        item.price_per_item = Some(price_cents);
+        if item.total_cents != price_cents * item.quantity {
+            result.conflicts.push(SyncConflict {
+                kind: SyncConflictKind::PriceChanged,
+                item_index: Some(index),
+                record_id: item.product_id,
+                client_price_cents: Some(item.total_cents / item.quantity),
+                server_price_cents: Some(price_cents),
+            });
+        }
+    }
 
-    let items = items_rows.into_iter().map(|r| sale_item_from_row(&r)).collect::<Result<Vec<_>, _>>()?;
-    sale.sale_items = items;
+    if result.status == SyncSaleStatus::Rejected {
+        return Ok((result, None));
+    }
+    let inserted = insert_sale(tx, sale).await?;
+    Ok((result, Some(inserted)))
+}
 
-    Ok(Json(sale))
+async fn sync_customer_conflict<'c>(
+    conn: impl Into<Conn<'c>>,
+    customer_id: Uuid,
+) -> Result<Option<SyncConflictKind>, AppError> {
+    Ok(match repo::customers::archived(conn, customer_id).await? {
+        None => Some(SyncConflictKind::CustomerMissing),
+        Some(true) => Some(SyncConflictKind::CustomerArchived),
+        Some(false) => None,
+    })
 }
 
 #[utoipa::path(
@@ -924,321 +1407,812 @@ pub async fn get_sales_transaction(
     tag = "Sales",
     params(("id" = String, Path, description = "Sale id")),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve a specific sale item", body = SaleItem), (status = 404))
+    responses((status = 200, description = "Retrieve a specific sale item", body = SaleItem), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn get_sale(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<Json<SaleItem>, StatusCode> {
-    let row = sqlx::query(
-        "SELECT id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note FROM sale_items WHERE id = ?",
-    )
-    .bind(id.to_string())
-    .fetch_optional(&state.db)
-    .await
-    .map_err(map_db_err)?
-    .ok_or(StatusCode::NOT_FOUND)?;
-
-    Ok(Json(sale_item_from_row(&row)?))
+) -> Result<Json<SaleItem>, AppError> {
+    Ok(Json(repo::sale_items::fetch(&state.db, id).await?))
 }
 
 #[utoipa::path(
     get,
     path = "/api/sales/stats/today",
     tag = "Reports",
+    params(ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Get sales statistics for the current day", body = SalesStats))
+    responses((status = 200, description = "Get sales statistics for the current day in the business timezone", body = SalesStats))
 )]
 pub async fn get_today_sales(
     State(state): State<AppState>,
-) -> Result<Json<shared::models::SalesStats>, StatusCode> {
-    let row = sqlx::query(
-        "SELECT SUM(total_resolved) as total, COUNT(*) as count FROM sale_items WHERE date(date_of_sale) = date('now')",
-    )
-    .fetch_one(&state.db)
-    .await
-    .map_err(map_db_err)?;
+    export: Export,
+) -> Result<Response, AppError> {
+    export.respond("sales-today", today_sales(&state).await?)
+}
 
-    let total: Option<i64> = row.try_get("total").unwrap_or(Some(0));
-    let count: i64 = row.try_get("count").unwrap_or(0);
+/// Today's sales in the business timezone, also pushed to live dashboards.
+pub(crate) async fn today_sales(state: &AppState) -> Result<SalesStats, AppError> {
+    let today = state.timezone.today();
+    let (total_sales_cents, count) = repo::reports::totals(&state.db, state.timezone, today, today).await?;
 
-    Ok(Json(shared::models::SalesStats {
-        total_sales_cents: total.unwrap_or(0),
+    Ok(SalesStats {
+        total_sales_cents,
         count,
-    }))
+        ..Default::default()
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/calendar",
+    tag = "Reports",
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The business timezone and today's date in it, which default report ranges are based on", body = BusinessCalendar))
+)]
+pub async fn get_business_calendar(State(state): State<AppState>) -> Json<BusinessCalendar> {
+    Json(BusinessCalendar {
+        timezone: state.timezone.name().to_string(),
+        today: state.timezone.today(),
+    })
 }
 
 #[utoipa::path(
     get,
     path = "/api/sales/stats/week",
     tag = "Reports",
+    params(ExportParams),
     security(("bearer_auth" = [])),
     responses((status = 200, description = "Get daily sales statistics for the current week (Mon-Sun)", body = [DailySales]))
 )]
 pub async fn get_weekly_sales_stats(
     State(state): State<AppState>,
-) -> Result<Json<Vec<shared::models::DailySales>>, StatusCode> {
-    // Logic: Find the most recent Monday (or today if it's Monday) and query from there.
-    // In SQLite, DATE('now', 'weekday 1') returns the *next* Monday if today is not Monday, 
-    // or today if it is Monday... wait, 'weekday 1' is Sunday in some systems?
-    // SQLite: 0=Sunday, 1=Monday, ..., 6=Saturday.
-    // Date('now', '-6 days', 'weekday 1') allows us to go back to previous monday.
-    
-    // Let's rely on rust-chrono for date calc to be safe and consistent.
-    let now = Utc::now().date_naive();
-    let days_since_monday = now.weekday().num_days_from_monday();
-    let start_date = now - chrono::Duration::days(days_since_monday as i64); // This Monday
-    
-    let rows = sqlx::query(
-        "SELECT date(date_of_sale) as day, SUM(total_resolved) as total, COUNT(*) as count 
-         FROM sale_items 
-         WHERE date(date_of_sale) >= date(?) 
-         GROUP BY day 
-         ORDER BY day ASC",
-    )
-    .bind(start_date.to_string())
-    .fetch_all(&state.db)
-    .await
-    .map_err(map_db_err)?;
-
-    let mut daily_sales = Vec::new();
-    
-    // We want to fill in gaps if no sales on a specific day?
-    // User requirement: "line chart of sales over the last n days of the week, today inclusive. Assume the week starts on Mondays."
-    // So if today is Wed, we want Mon, Tue, Wed.
-    
-
-    let today = now;
-    
-    // Create map for easy lookup
-    use std::collections::HashMap;
-    let mut sales_map: HashMap<String, (i64, i64)> = HashMap::new(); // (total, count)
-    for row in rows {
-        let day: String = row.try_get("day").unwrap_or_default();
-        let total: i64 = row.try_get("total").unwrap_or(0);
-        let count: i64 = row.try_get("count").unwrap_or(0);
-        sales_map.insert(day, (total, count));
-    }
-    
-    // Fill the last 7 days (or N days)
-    // Actually the requirement is just last week dynamics.
-    // Let's iterate from start_date to today.
-    
-    let mut current_iter = start_date;
-    while current_iter <= today {
-        let day_str = current_iter.to_string();
-        let (total, count) = sales_map.get(&day_str).unwrap_or(&(0, 0));
-        daily_sales.push(shared::models::DailySales {
-            date: day_str,
-            total_sales_cents: *total,
-            count: *count,
-        });
-        current_iter = current_iter + chrono::Duration::days(1);
-    }
+    export: Export,
+) -> Result<Response, AppError> {
+    export.respond("sales-week", week_sales(&state).await?)
+}
 
-    Ok(Json(daily_sales))
+/// This week's sales by day, from Monday up to today.
+pub(crate) async fn week_sales(state: &AppState) -> Result<Vec<DailySales>, AppError> {
+    let today = state.timezone.today();
+    let monday = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+    daily_sales(&state.db, state.timezone, monday, today).await
 }
 
 #[derive(Deserialize, IntoParams)]
-pub struct StatsRangeParams {
-    pub start_date: Option<String>,
-    pub end_date: Option<String>,
+pub struct ComparisonParams {
+    /// Period to compare the report against; no comparison when omitted
+    pub compare: Option<ComparisonPeriod>,
+    /// Start of a `custom` comparison period, YYYY-MM-DD
+    pub compare_start_date: Option<String>,
+    /// End of a `custom` comparison period, YYYY-MM-DD
+    pub compare_end_date: Option<String>,
+}
+
+impl ComparisonParams {
+    /// Dates to compare the report range against, if a comparison was asked for.
+    fn range(&self, start: NaiveDate, end: NaiveDate) -> Result<Option<(NaiveDate, NaiveDate)>, AppError> {
+        let Some(period) = self.compare else {
+            return Ok(None);
+        };
+        let out_of_range = |field| AppError::invalid_field(field, "is out of range");
+
+        let range = match period {
+            ComparisonPeriod::PreviousPeriod => {
+                let previous_end = start.pred_opt().ok_or_else(|| out_of_range("start_date"))?;
+                (previous_end - (end - start), previous_end)
+            }
+            ComparisonPeriod::LastYear => (
+                start.checked_sub_months(Months::new(12)).ok_or_else(|| out_of_range("start_date"))?,
+                end.checked_sub_months(Months::new(12)).ok_or_else(|| out_of_range("end_date"))?,
+            ),
+            ComparisonPeriod::Custom => {
+                let required = |field: &str, value: &Option<String>| {
+                    value
+                        .as_deref()
+                        .filter(|value| !value.is_empty())
+                        .ok_or_else(|| AppError::invalid_field(field, "is required for a custom comparison"))
+                        .and_then(|value| parse_date_param(field, value))
+                };
+                let range = (
+                    required("compare_start_date", &self.compare_start_date)?,
+                    required("compare_end_date", &self.compare_end_date)?,
+                );
+                if range.1 < range.0 {
+                    return Err(AppError::invalid_field("compare_end_date", "must not be before compare_start_date"));
+                }
+                if (range.1 - range.0).num_days() > MAX_DAILY_SALES_DAYS {
+                    return Err(AppError::invalid_field(
+                        "compare_end_date",
+                        format!("must be at most {} days after compare_start_date", MAX_DAILY_SALES_DAYS),
+                    ));
+                }
+                range
+            }
+        };
+        Ok(Some(range))
+    }
 }
 
 #[utoipa::path(
     get,
-    path = "/api/sales/stats/top_products",
+    path = "/api/sales/stats/totals",
     tag = "Reports",
-    params(
-        ("start_date" = Option<String>, Query, description = "Start date YYYY-MM-DD"),
-        ("end_date" = Option<String>, Query, description = "End date YYYY-MM-DD")
-    ),
+    params(StatsRangeParams, ComparisonParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Get a list of top-selling products within a date range", body = [TopProduct]))
+    responses((status = 200, description = "Get sales totals within a date range, optionally against a comparison period", body = SalesStats), (status = 422, description = "Invalid input", body = ApiError))
 )]
-pub async fn get_top_products(
+pub async fn get_sales_totals(
     State(state): State<AppState>,
     Query(params): Query<StatsRangeParams>,
-) -> Result<Json<Vec<TopProduct>>, StatusCode> {
-    let (start_date, end_date) = get_default_dates(params.start_date, params.end_date);
-
-    let mut query = "
-        SELECT p.name as product_name, SUM(s.total_resolved) as total 
-        FROM sale_items s
-        JOIN products p ON s.product_id = p.id
-        WHERE date(s.date_of_sale) >= date(?) AND date(s.date_of_sale) <= date(?)
-    ".to_string();
-    
-    let mut args = Vec::new();
-    args.push(start_date);
-    args.push(end_date);
-    
-    query.push_str(" GROUP BY p.name ORDER BY total DESC LIMIT 20");
-
-    let mut sql_query = sqlx::query(&query);
-    for arg in &args {
-        sql_query = sql_query.bind(arg);
-    }
-
-    let rows = sql_query
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
+    Query(comparison): Query<ComparisonParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let (total_sales_cents, count) = repo::reports::totals(&state.db, state.timezone, start_date, end_date).await?;
+    let mut stats = SalesStats {
+        total_sales_cents,
+        count,
+        ..Default::default()
+    };
 
-    let mut results = Vec::new();
-    for row in rows {
-        let product_name: String = row.try_get("product_name").unwrap_or_default();
-        let total: i64 = row.try_get("total").unwrap_or(0);
-        results.push(TopProduct {
-            product_name,
-            total_sales_cents: total,
-        });
+    if let Some((compare_start, compare_end)) = comparison.range(start_date, end_date)? {
+        let (previous_total, previous_count) =
+            repo::reports::totals(&state.db, state.timezone, compare_start, compare_end).await?;
+        stats.total_change = Some(Change::between(total_sales_cents, previous_total));
+        stats.count_change = Some(Change::between(count, previous_count));
     }
-
-    Ok(Json(results))
+    export.respond("sales-totals", stats)
 }
 
 #[utoipa::path(
     get,
-    path = "/api/sales/stats/by_product",
+    path = "/api/sales/stats/daily",
     tag = "Reports",
-    params(StatsRangeParams),
+    params(StatsRangeParams, ComparisonParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Get sales summary grouped by product within a date range", body = [ProductSalesSummary]))
+    responses((status = 200, description = "Get sales for every day of a date range, optionally against a comparison period of the same length matched up day by day", body = [DailySales]), (status = 422, description = "Invalid input", body = ApiError))
 )]
-pub async fn get_sales_by_product(
+pub async fn get_daily_sales(
     State(state): State<AppState>,
     Query(params): Query<StatsRangeParams>,
-) -> Result<Json<Vec<ProductSalesSummary>>, StatusCode> {
-    let (start_date, end_date) = get_default_dates(params.start_date, params.end_date);
-
-    let query = "
-        SELECT p.name as product_name, SUM(s.quantity) as total_quantity, SUM(s.total_resolved) as total_amount
-        FROM sale_items s
-        JOIN products p ON s.product_id = p.id
-        WHERE date(s.date_of_sale) >= date(?) AND date(s.date_of_sale) <= date(?)
-        GROUP BY p.name
-        ORDER BY total_amount DESC
-    ";
-
-    let rows = sqlx::query(query)
-        .bind(start_date)
-        .bind(end_date)
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
-
-    let mut results = Vec::new();
-    for row in rows {
-        results.push(ProductSalesSummary {
-            product_name: row.try_get("product_name").unwrap_or_default(),
-            total_quantity: row.try_get("total_quantity").unwrap_or(0),
-            total_amount_cents: row.try_get("total_amount").unwrap_or(0),
-        });
+    Query(comparison): Query<ComparisonParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start, end) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    if (end - start).num_days() > MAX_DAILY_SALES_DAYS {
+        return Err(AppError::invalid_field(
+            "end_date",
+            format!("must be at most {} days after start_date", MAX_DAILY_SALES_DAYS),
+        ));
     }
-
-    Ok(Json(results))
+    let mut days = daily_sales(&state.db, state.timezone, start, end).await?;
+
+    if let Some((compare_start, compare_end)) = comparison.range(start, end)? {
+        // Days are matched one to one: a custom period must be as long as the
+        // report, and last year's, a day shorter or longer across 29
+        // February, is made to fit
+        let length = end - start;
+        if comparison.compare == Some(ComparisonPeriod::Custom) && compare_end - compare_start != length {
+            return Err(AppError::invalid_field(
+                "compare_end_date",
+                format!("must make the comparison period {} days long, like the report", length.num_days() + 1),
+            ));
+        }
+        let previous = daily_sales(&state.db, state.timezone, compare_start, compare_start + length).await?;
+        for (day, previous) in days.iter_mut().zip(previous) {
+            day.total_change = Some(Change::between(day.total_sales_cents, previous.total_sales_cents));
+            day.count_change = Some(Change::between(day.count, previous.count));
+            day.compared_date = Some(previous.date);
+        }
+    }
+    export.respond("daily-sales", days)
 }
 
-fn get_default_dates(start: Option<String>, end: Option<String>) -> (String, String) {
-    let now = Utc::now();
-    let today = now.format("%Y-%m-%d").to_string();
-    let first_day = format!("{}-{:02}-01", now.year(), now.month());
-
-    let start_date = start.filter(|s| !s.is_empty()).unwrap_or(first_day);
-    let end_date = end.filter(|s| !s.is_empty()).unwrap_or(today);
-    
-    (start_date, end_date)
+/// Longest range `/api/sales/stats/daily` and `/api/sales/stats/series` cover, about ten years.
+const MAX_DAILY_SALES_DAYS: i64 = 3660;
+
+/// Sales for every day from `start` to `end`, including days without any.
+pub(crate) async fn daily_sales(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<DailySales>, AppError> {
+    let series = sales_series(db, tz, start, end, TimeBucket::Day, WeekStart::default(), None).await?;
+    Ok(series
+        .into_iter()
+        .next()
+        .map(|series| series.points)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|point| DailySales {
+            date: point.start,
+            total_sales_cents: point.total_sales_cents,
+            count: point.count,
+            ..Default::default()
+        })
+        .collect())
 }
 
-
+#[derive(Deserialize, IntoParams)]
+pub struct SeriesParams {
+    /// Start date YYYY-MM-DD, defaults to the first of the month
+    pub start_date: Option<String>,
+    /// End date YYYY-MM-DD, defaults to today
+    pub end_date: Option<String>,
+    /// Defaults to day
+    pub bucket: Option<TimeBucket>,
+    /// First day of `week` buckets, defaults to Monday
+    pub week_start: Option<WeekStart>,
+    /// One series per value of this; a single overall series when omitted
+    pub breakdown: Option<SeriesBreakdown>,
+}
 
 #[utoipa::path(
-    put,
-    path = "/api/sales/{id}",
-    tag = "Sales",
-    params(("id" = String, Path, description = "Sale id")),
-    request_body = SaleItemInput,
+    get,
+    path = "/api/sales/stats/series",
+    tag = "Reports",
+    params(SeriesParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Update a specific sale item", body = SaleItem), (status = 404))
+    responses((status = 200, description = "Sales over a date range by hour, day, week or month in the business timezone, with a point for every bucket, optionally broken down by branch, channel, staff or product type", body = TimeSeries), (status = 422, description = "Invalid input", body = ApiError))
 )]
-pub async fn update_sale(
+pub async fn get_sales_series(
     State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-    Json(input): Json<SaleItemInput>,
-) -> Result<Json<SaleItem>, StatusCode> {
-    let sale = SaleItem {
-        id,
-        sale_id: input.sale_id,
-        product_id: input.product_id,
-        customer_id: input.customer_id,
-        date_of_sale: input.date_of_sale,
-        quantity: input.quantity,
-        discount: input.discount,
-        total_cents: input.total_cents,
-        total_resolved: input.total_resolved,
-        note: input.note,
-        product_name: None,
-        price_per_item: None,
+    Query(params): Query<SeriesParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    if (end_date - start_date).num_days() > MAX_DAILY_SALES_DAYS {
+        return Err(AppError::invalid_field(
+            "end_date",
+            format!("must be at most {} days after start_date", MAX_DAILY_SALES_DAYS),
+        ));
+    }
+    let bucket = params.bucket.unwrap_or_default();
+    let week_start = params.week_start.unwrap_or_default();
+    let series = sales_series(&state.db, state.timezone, start_date, end_date, bucket, week_start, params.breakdown).await?;
+    export.respond(
+        "sales-series",
+        TimeSeries {
+            start_date,
+            end_date,
+            bucket,
+            week_start,
+            breakdown: params.breakdown,
+            series,
+        },
+    )
+}
+
+/// Most buckets a time series is filled in for.
+const MAX_SERIES_POINTS: usize = 3660;
+
+/// The local start of every bucket covering `start..=end`, formatted the way
+/// [`sales_series`] groups by them.
+fn bucket_starts(tz: BusinessTz, start: NaiveDate, end: NaiveDate, bucket: TimeBucket, week_start: WeekStart) -> Vec<String> {
+    let first = match bucket {
+        TimeBucket::Hour => {
+            return tz
+                .local_hours(start, end)
+                .iter()
+                .map(|hour| hour.format("%Y-%m-%dT%H:00").to_string())
+                .collect();
+        }
+        TimeBucket::Day => start,
+        TimeBucket::Week => {
+            let days_in = (7 + start.weekday().num_days_from_monday() - week_start.weekday().num_days_from_monday()) % 7;
+            start - chrono::Duration::days(days_in as i64)
+        }
+        TimeBucket::Month => start.with_day(1).unwrap_or(start),
     };
+    let mut starts = Vec::new();
+    let mut day = Some(first);
+    while let Some(current) = day.filter(|day| *day <= end) {
+        starts.push(current.to_string());
+        day = match bucket {
+            TimeBucket::Week => current.checked_add_days(chrono::Days::new(7)),
+            TimeBucket::Month => current.checked_add_months(Months::new(1)),
+            _ => current.succ_opt(),
+        };
+    }
+    starts
+}
 
-    let result = sqlx::query(
-        "UPDATE sale_items SET product_id = ?, customer_id = ?, date_of_sale = ?, quantity = ?, discount = ?, total_cents = ?, total_resolved = ?, note = ?, product_name = (SELECT name FROM products WHERE id = ?), price_per_item = (SELECT price_cents FROM products WHERE id = ?) WHERE id = ?",
-    )
-    .bind(sale.product_id.to_string())
-    .bind(sale.customer_id.map(|id| id.to_string()))
-    .bind(&sale.date_of_sale)
-    .bind(sale.quantity)
-    .bind(sale.discount)
-    .bind(sale.total_cents)
-    .bind(sale.total_resolved)
-    .bind(&sale.note)
-    .bind(sale.product_id.to_string())
-    .bind(sale.product_id.to_string())
-    .bind(sale.id.to_string())
-    .execute(&state.db)
-    .await
-    .map_err(map_db_err)?;
-
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+/// Sales from `start` to `end` by `bucket`, with a point for every bucket
+/// including empty ones. Without a breakdown there is always exactly one
+/// series; with one, a series per value that had sales, highest total first.
+pub(crate) async fn sales_series(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+    bucket: TimeBucket,
+    week_start: WeekStart,
+    breakdown: Option<SeriesBreakdown>,
+) -> Result<Vec<SalesSeries>, AppError> {
+    let starts = bucket_starts(tz, start, end, bucket, week_start);
+    if starts.len() > MAX_SERIES_POINTS {
+        return Err(AppError::invalid_field(
+            "end_date",
+            format!("must be at most {} buckets after start_date", MAX_SERIES_POINTS),
+        ));
     }
 
-    // Fetch the updated item
-    let row = sqlx::query("SELECT sale_items.id, sale_items.sale_id, sale_items.product_id, sale_items.customer_id, sale_items.date_of_sale, sale_items.quantity, sale_items.discount, sale_items.total_cents, sale_items.total_resolved, sale_items.note, COALESCE(sale_items.product_name, products.name) as product_name, COALESCE(sale_items.price_per_item, products.price_cents) as price_per_item FROM sale_items LEFT JOIN products ON sale_items.product_id = products.id WHERE sale_items.id = ?")
-        .bind(sale.id.to_string())
-        .fetch_one(&state.db)
-        .await
-        .map_err(map_db_err)?;
-    
-    let sale = sale_item_from_row(&row)?;
+    let rows = repo::reports::series(db, tz, start, end, bucket, week_start, breakdown).await?;
 
-    Ok(Json(sale))
+    // Label and (total, count) by bucket start, for each series key
+    type Buckets = std::collections::HashMap<String, (i64, i64)>;
+    let mut by_series: std::collections::HashMap<Option<String>, (Option<String>, Buckets)> =
+        std::collections::HashMap::new();
+    if breakdown.is_none() {
+        by_series.insert(None, (None, Default::default()));
+    }
+    for row in rows {
+        let entry = by_series.entry(row.key).or_default();
+        entry.0 = entry.0.take().or(row.label);
+        entry.1.insert(row.bucket, (row.total, row.count));
+    }
+
+    let mut series: Vec<SalesSeries> = by_series
+        .into_iter()
+        .map(|(key, (label, by_bucket))| {
+            let points: Vec<SeriesPoint> = starts
+                .iter()
+                .map(|start| {
+                    let (total_sales_cents, count) = by_bucket.get(start).copied().unwrap_or((0, 0));
+                    SeriesPoint {
+                        start: start.clone(),
+                        total_sales_cents,
+                        count,
+                    }
+                })
+                .collect();
+            let label = match (&key, breakdown) {
+                (_, None) => "All sales".to_string(),
+                (None, Some(_)) => "Not recorded".to_string(),
+                (Some(key), Some(_)) => label.unwrap_or_else(|| key.clone()),
+            };
+            SalesSeries {
+                key,
+                label,
+                total_sales_cents: points.iter().map(|point| point.total_sales_cents).sum(),
+                count: points.iter().map(|point| point.count).sum(),
+                points,
+            }
+        })
+        .collect();
+    series.sort_by(|a, b| b.total_sales_cents.cmp(&a.total_sales_cents).then_with(|| a.label.cmp(&b.label)));
+    Ok(series)
 }
 
-#[utoipa::path(
-    delete,
+#[derive(Deserialize, IntoParams)]
+pub struct LiveParams {
+    /// Token from `/api/auth/login`; `EventSource` cannot send an Authorization header
+    pub token: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/live",
+    tag = "Reports",
+    params(LiveParams),
+    security(()),
+    responses((status = 200, description = "Server-Sent Events stream of `stats` events (DashboardStats) on connect and whenever sales change, and `sale` events (Sale) for every new sales transaction; ends when the token expires", content_type = "text/event-stream", body = DashboardStats), (status = 401, description = "Invalid token", body = ApiError))
+)]
+pub async fn live_events(
+    State(state): State<AppState>,
+    Query(params): Query<LiveParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let claims = crate::auth::authenticate(&state, &params.token).await?;
+    // Subscribe before reading the stats so no change slips in between
+    let updates = BroadcastStream::new(state.live.subscribe());
+    let stats = live::dashboard_stats(&state).await?;
+
+    // The stream ends when the token expires; reconnecting needs a fresh one
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(AppError::internal)?.as_secs();
+    let lifetime = std::time::Duration::from_secs((claims.exp as u64).saturating_sub(now));
+    let expiry = tokio::time::interval_at(tokio::time::Instant::now() + lifetime, lifetime.max(std::time::Duration::from_secs(1)));
+    let expired = IntervalStream::new(expiry).map(|_| None);
+
+    let stream = tokio_stream::once(LiveUpdate::Stats(stats))
+        .chain(updates.filter_map(Result::ok))
+        .map(Some)
+        .merge(expired)
+        .map_while(|update| update.map(|update| Ok(update.into_event())));
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StatsRangeParams {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/top_products",
+    tag = "Reports",
+    params(
+        ("start_date" = Option<String>, Query, description = "Start date YYYY-MM-DD"),
+        ("end_date" = Option<String>, Query, description = "End date YYYY-MM-DD")
+    , ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Get a list of top-selling products within a date range", body = [TopProduct]))
+)]
+pub async fn get_top_products(
+    State(state): State<AppState>,
+    Query(params): Query<StatsRangeParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+
+    let results = repo::reports::top_products(&state.db, state.timezone, start_date, end_date).await?;
+    export.respond("top-products", results)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/by_product",
+    tag = "Reports",
+    params(StatsRangeParams, ComparisonParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Get sales summary grouped by product within a date range. With a comparison period, products sold only in that period are listed too.", body = [ProductSalesSummary]), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_sales_by_product(
+    State(state): State<AppState>,
+    Query(params): Query<StatsRangeParams>,
+    Query(comparison): Query<ComparisonParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let sales = repo::reports::product_sales(&state.db, state.timezone, start_date, end_date).await?;
+
+    let Some((compare_start, compare_end)) = comparison.range(start_date, end_date)? else {
+        let summaries: Vec<_> = sales.into_iter().map(|(_, summary)| summary).collect();
+        return export.respond("sales-by-product", summaries);
+    };
+    // Matched by product, so a renamed product is still compared with itself
+    let mut previous: std::collections::HashMap<Uuid, ProductSalesSummary> =
+        repo::reports::product_sales(&state.db, state.timezone, compare_start, compare_end)
+            .await?
+            .into_iter()
+            .collect();
+    let mut summaries = Vec::with_capacity(sales.len());
+    for (product_id, mut summary) in sales {
+        let (quantity, amount) = previous
+            .remove(&product_id)
+            .map(|previous| (previous.total_quantity, previous.total_amount_cents))
+            .unwrap_or((0, 0));
+        summary.quantity_change = Some(Change::between(summary.total_quantity, quantity));
+        summary.amount_change = Some(Change::between(summary.total_amount_cents, amount));
+        summaries.push(summary);
+    }
+    let mut dropped: Vec<ProductSalesSummary> = previous.into_values().collect();
+    dropped.sort_by(|a, b| b.total_amount_cents.cmp(&a.total_amount_cents).then_with(|| a.product_name.cmp(&b.product_name)));
+    summaries.extend(dropped.into_iter().map(|previous| ProductSalesSummary {
+        quantity_change: Some(Change::between(0, previous.total_quantity)),
+        amount_change: Some(Change::between(0, previous.total_amount_cents)),
+        product_name: previous.product_name,
+        total_quantity: 0,
+        total_amount_cents: 0,
+        total_cost_cents: 0,
+        gross_profit_cents: 0,
+        margin_percent: None,
+    }));
+    export.respond("sales-by-product", summaries)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct MarginParams {
+    /// Start date YYYY-MM-DD, defaults to the first of the month
+    pub start_date: Option<String>,
+    /// End date YYYY-MM-DD, defaults to today
+    pub end_date: Option<String>,
+    /// Defaults to product
+    pub group_by: Option<MarginGrouping>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/margin",
+    tag = "Reports",
+    params(MarginParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Revenue, cost, gross profit and margin per product, category or period within a date range. Items sold before cost prices were tracked count at the product's current cost.", body = MarginReport), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_margin_report(
+    State(state): State<AppState>,
+    Query(params): Query<MarginParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let group_by = params.group_by.unwrap_or_default();
+    let lines = repo::reports::margin(&state.db, state.timezone, start_date, end_date, group_by).await?;
+    let total = MarginLine::new(
+        "Total".to_string(),
+        lines.iter().map(|line| line.quantity).sum(),
+        lines.iter().map(|line| line.revenue_cents).sum(),
+        lines.iter().map(|line| line.cost_cents).sum(),
+    );
+
+    export.respond("margin-report", MarginReport { group_by, lines, total })
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct HeatmapParams {
+    /// Start date YYYY-MM-DD, defaults to the first of the month
+    pub start_date: Option<String>,
+    /// End date YYYY-MM-DD, defaults to today
+    pub end_date: Option<String>,
+    pub company_branch: Option<String>,
+    pub sales_channel: Option<SalesChannel>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/heatmap",
+    tag = "Reports",
+    params(HeatmapParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Sales by local hour of day and weekday within a date range: all 168 cells, Monday 00:00 first", body = [HeatmapCell]), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_sales_heatmap(
+    State(state): State<AppState>,
+    Query(params): Query<HeatmapParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let company_branch = params.company_branch.filter(|branch| !branch.is_empty());
+    let rows = repo::reports::heatmap(
+        &state.db,
+        state.timezone,
+        start_date,
+        end_date,
+        company_branch.as_deref(),
+        params.sales_channel,
+    )
+    .await?;
+
+    let mut cells: Vec<HeatmapCell> = (0..7)
+        .flat_map(|weekday| {
+            (0..24).map(move |hour| HeatmapCell {
+                weekday,
+                hour,
+                total_sales_cents: 0,
+                count: 0,
+            })
+        })
+        .collect();
+    for row in rows {
+        if let Some(cell) = cells.get_mut((row.weekday * 24 + row.hour) as usize) {
+            *cell = row;
+        }
+    }
+
+    export.respond("sales-heatmap", cells)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct StaffLeaderboardParams {
+    /// Start date YYYY-MM-DD, defaults to the first of the month
+    pub start_date: Option<String>,
+    /// End date YYYY-MM-DD, defaults to today
+    pub end_date: Option<String>,
+    /// Defaults to revenue
+    pub sort_by: Option<StaffRanking>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/by_staff",
+    tag = "Reports",
+    params(StaffLeaderboardParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Rank staff by their sales within a date range", body = [StaffPerformance]), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_staff_leaderboard(
+    State(state): State<AppState>,
+    Query(params): Query<StaffLeaderboardParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let ranking = params.sort_by.unwrap_or_default();
+    export.respond(
+        "staff-leaderboard",
+        repo::reports::staff_leaderboard(&state.db, state.timezone, start_date, end_date, ranking, None).await?,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/sales/stats/by_staff/{id}",
+    tag = "Reports",
+    params(("id" = String, Path, description = "Staff UUID"), StaffLeaderboardParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "One staff member's leaderboard entry with their daily sales and products sold", body = StaffPerformanceDetail), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn get_staff_performance(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<StaffLeaderboardParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (first_day, last_day) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let ranking = params.sort_by.unwrap_or_default();
+
+    let performance = repo::reports::staff_leaderboard(&state.db, state.timezone, first_day, last_day, ranking, Some(id))
+        .await?
+        .pop()
+        .ok_or_else(|| AppError::not_found("Staff"))?;
+
+    let by_day: std::collections::HashMap<String, (i64, i64)> =
+        repo::reports::staff_daily(&state.db, state.timezone, id, first_day, last_day)
+            .await?
+            .into_iter()
+            .map(|(day, total, count)| (day, (total, count)))
+            .collect();
+    let daily = first_day
+        .iter_days()
+        .take_while(|day| *day <= last_day)
+        .map(|day| {
+            let date = day.to_string();
+            let (total_sales_cents, count) = by_day.get(&date).copied().unwrap_or((0, 0));
+            DailySales {
+                date,
+                total_sales_cents,
+                count,
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    let products = repo::reports::staff_product_sales(&state.db, state.timezone, id, first_day, last_day).await?;
+
+    export.respond("staff-performance", StaffPerformanceDetail { performance, daily, products })
+}
+
+fn parse_date_param(field: &str, value: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::invalid_field(field, "must be a date in YYYY-MM-DD format"))
+}
+
+/// An instant given as RFC 3339, or as a UTC date or date and time.
+fn parse_instant_param(field: &str, value: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|instant| instant.with_timezone(&Utc))
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").map(|local| local.and_utc()))
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|day| day.and_time(chrono::NaiveTime::MIN).and_utc()))
+        .map_err(|_| AppError::invalid_field(field, "must be a date (YYYY-MM-DD) or an RFC 3339 timestamp"))
+}
+
+/// The requested report range, from the first of the month to today in the
+/// business timezone by default.
+fn get_default_dates(
+    tz: BusinessTz,
+    start: Option<String>,
+    end: Option<String>,
+) -> Result<(NaiveDate, NaiveDate), AppError> {
+    let today = tz.today();
+    let start_date = match start.filter(|s| !s.is_empty()) {
+        Some(start) => parse_date_param("start_date", &start)?,
+        None => today.with_day(1).unwrap_or(today),
+    };
+    let end_date = match end.filter(|s| !s.is_empty()) {
+        Some(end) => parse_date_param("end_date", &end)?,
+        None => today,
+    };
+    Ok((start_date, end_date))
+}
+
+
+
+#[utoipa::path(
+    put,
     path = "/api/sales/{id}",
     tag = "Sales",
     params(("id" = String, Path, description = "Sale id")),
+    request_body = SaleItemInput,
     security(("bearer_auth" = [])),
-    responses((status = 204, description = "Delete a specific sale item"), (status = 404))
+    responses((status = 200, description = "Update a specific sale item; it keeps the product name and prices it was sold at unless the product changes", body = SaleItem), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
-pub async fn delete_sale(
+pub async fn update_sale(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-) -> Result<StatusCode, StatusCode> {
-    let result = sqlx::query("DELETE FROM sale_items WHERE id = ?")
-        .bind(id.to_string())
-        .execute(&state.db)
-        .await
-        .map_err(map_db_err)?;
+    Json(input): Json<SaleItemInput>,
+) -> Result<Json<SaleItem>, AppError> {
+    input.validate()?;
+    let sale = SaleItem {
+        id,
+        sale_id: input.sale_id,
+        product_id: input.product_id,
+        customer_id: input.customer_id,
+        date_of_sale: input.date_of_sale,
+        quantity: input.quantity,
+        discount: input.discount,
+        total_cents: input.total_cents,
+        total_resolved: input.total_resolved,
+        note: input.note,
+        product_name: None,
+        price_per_item: None,
+        cost_per_item: None,
+    };
 
-    if result.rows_affected() == 0 {
-        Ok(StatusCode::NOT_FOUND)
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    let previous = repo::sale_items::fetch(&mut tx, sale.id).await?;
+    let (previous_product_id, previous_quantity) = (previous.product_id, previous.quantity);
+    repo::sale_items::update(&mut tx, &sale).await?;
+
+    // The item keeps the name and prices it was sold at unless it now sells
+    // another product, which is snapshotted as of now
+    if previous_product_id != sale.product_id {
+        let price_cents = repo::product_prices::price_at(&mut tx, sale.product_id, Utc::now()).await?;
+        repo::sale_items::snapshot_product(&mut tx, sale.id, sale.product_id, price_cents).await?;
+    }
+
+    // Stock follows the item: the old product gets back what the new
+    // quantity no longer takes
+    let mut stock_alerts = Vec::new();
+    if previous_product_id == sale.product_id {
+        let more_sold = sale.quantity - previous_quantity;
+        if more_sold > 0 {
+            stock_alerts.extend(deduct_stock(&mut tx, &sale, more_sold).await?);
+        } else if more_sold < 0 {
+            repo::products::return_stock(&mut tx, sale.product_id, -more_sold).await?;
+        }
     } else {
-        Ok(StatusCode::NO_CONTENT)
+        repo::products::return_stock(&mut tx, previous_product_id, previous_quantity).await?;
+        stock_alerts.extend(deduct_stock(&mut tx, &sale, sale.quantity).await?);
     }
+
+    let sale = repo::sale_items::fetch(&mut tx, sale.id).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    log_stock_alerts(&stock_alerts);
+
+    live::publish_sales(&state, vec![]);
+
+    Ok(Json(sale))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/sales/{id}",
+    tag = "Sales",
+    params(("id" = String, Path, description = "Sale id")),
+    security(("bearer_auth" = [])),
+    responses((status = 204, description = "Void (refund) a specific sale item; it is kept for refund reporting and its quantity goes back into stock"), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn delete_sale(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    let item = repo::sale_items::fetch(&mut tx, id).await?;
+
+    // The money goes back out of the drawer of whoever voids the item, or
+    // else of the sale's own shift while that is still open
+    let shift_id: Option<Uuid> = match repo::shifts::open_for_staff(&mut tx, &claims.sub).await? {
+        Some(shift) => Some(shift.id),
+        None => {
+            let sale_shift = match item.sale_id {
+                Some(sale_id) => repo::sales::find(&mut tx, sale_id).await?.and_then(|sale| sale.shift_id),
+                None => None,
+            };
+            match sale_shift {
+                Some(sale_shift) => repo::shifts::find_open(&mut tx, sale_shift).await?.map(|shift| shift.id),
+                None => None,
+            }
+        }
+    };
+
+    repo::sale_items::void(&mut tx, &item, parse_uuid(claims.sub.clone())?, Utc::now(), shift_id).await?;
+    repo::products::return_stock(&mut tx, item.product_id, item.quantity).await?;
+    webhooks::enqueue(&mut tx, WebhookEventType::SaleVoided, &item).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    live::publish_sales(&state, vec![]);
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 
@@ -1269,32 +2243,24 @@ pub struct AuthResponse {
     tag = "Auth",
     request_body = AuthRequest,
     security(()),
-    responses((status = 200, description = "Authenticate a staff member and receive a JWT token", body = AuthResponse), (status = 401))
+    responses((status = 200, description = "Authenticate a staff member and receive a JWT token", body = AuthResponse), (status = 401, description = "Invalid credentials", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn login(
     State(state): State<AppState>,
     Json(input): Json<AuthRequest>,
-) -> Result<Json<AuthResponse>, StatusCode> {
-    let row = sqlx::query(
-        "SELECT id, password_hash FROM staff WHERE username = ?",
-    )
-    .bind(&input.username)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(map_db_err)?
-    .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    let staff_uuid: String = row.get("id");
-    let password_hash: String = row.get("password_hash");
+) -> Result<Json<AuthResponse>, AppError> {
+    let (staff_uuid, password_hash) = repo::staff::credentials(&state.db, &input.username)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
 
     if !verify_password(&input.password, &state.password_pepper, &password_hash)? {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(AppError::Unauthorized);
     }
 
-    let expires_in = 3600;
+    let expires_in = state.token_ttl_secs;
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(AppError::internal)?;
     let claims = Claims {
         sub: staff_uuid,
 
@@ -1302,7 +2268,7 @@ pub async fn login(
     };
     let key = EncodingKey::from_secret(state.jwt_secret.as_bytes());
     let token = jsonwebtoken::encode(&Header::default(), &claims, &key)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(AppError::internal)?;
 
     Ok(Json(AuthResponse {
         token,
@@ -1321,46 +2287,52 @@ pub async fn login(
 pub async fn get_profile(
     State(state): State<AppState>,
     Extension(claims): Extension<Claims>,
-) -> Result<Json<Staff>, StatusCode> {
-    let staff_uuid = claims.sub;
-    let staff = sqlx::query("SELECT * FROM staff WHERE id = ?")
-        .bind(staff_uuid)
-        .map(|row: SqliteRow| staff_from_row(&row))
-        .fetch_one(&state.db)
-        .await
-        .map_err(map_db_err)?;
-    Ok(Json(staff))
+) -> Result<Json<Staff>, AppError> {
+    let id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
+    Ok(Json(repo::staff::fetch(&state.db, id).await?))
 }
 
 #[utoipa::path(
     get,
     path = "/api/staff",
     tag = "Staff",
+    params(PageParams, ArchivedParams, ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "List all staff members", body = [Staff]))
+    responses((status = 200, description = "List all staff members", body = Page<Staff>), (status = 422, description = "Invalid page or limit", body = ApiError))
 )]
-pub async fn list_staff(State(state): State<AppState>) -> Result<Json<Vec<Staff>>, StatusCode> {
-    let staff = sqlx::query("SELECT * FROM staff")
-        .map(|row: SqliteRow| staff_from_row(&row))
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
-    Ok(Json(staff))
+pub async fn list_staff(
+    State(state): State<AppState>,
+    Query(params): Query<PageParams>,
+    Query(archived): Query<ArchivedParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let include_archived = archived.include_archived.unwrap_or(false);
+    if export.is_csv() {
+        return csv_stream("staff", repo::staff::stream(&state.db, include_archived));
+    }
+    let (staff, total) = repo::staff::list(&state.db, include_archived, limit, offset).await?;
+    export.respond("staff", Page::new(staff, total, page, limit))
 }
-
 #[utoipa::path(
     post,
     path = "/api/staff",
     tag = "Staff",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
     request_body = StaffInput,
     security(("bearer_auth" = [])),
-    responses((status = 201, description = "Register a new staff member", body = Staff))
+    responses((status = 201, description = "Register a new staff member; admins only", body = Staff), (status = 403, description = "Not an admin", body = ApiError), (status = 409, description = "Staff id already in use", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn create_staff(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Json(input): Json<StaffInput>,
-) -> Result<(StatusCode, Json<Staff>), StatusCode> {
-    let password = input.password.ok_or(StatusCode::BAD_REQUEST)?;
+) -> Result<(StatusCode, Json<Staff>), AppError> {
+    require_admin(&state, &claims, None).await?;
+    input.validate()?;
+    let password = input
+        .password
+        .ok_or_else(|| AppError::invalid_field("password", "is required"))?;
     let password_hash = hash_password(&password, &state.password_pepper)?;
     
     let staff = Staff {
@@ -1372,22 +2344,11 @@ pub async fn create_staff(
         photo_link: input.photo_link,
         username: input.username,
         password_hash,
+        archived_at: None,
+        role: StaffRole::Staff,
     };
 
-    sqlx::query(
-        "INSERT INTO staff (id, staff_id, first_name, last_name, mobile_number, photo_link, username, password_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-    )
-    .bind(staff.id.to_string())
-    .bind(&staff.staff_id)
-    .bind(&staff.first_name)
-    .bind(&staff.last_name)
-    .bind(&staff.mobile_number)
-    .bind(&staff.photo_link)
-    .bind(&staff.username)
-    .bind(&staff.password_hash)
-    .execute(&state.db)
-    .await
-    .map_err(map_db_err)?;
+    repo::staff::insert(&state.db, &staff).await?;
 
     Ok((StatusCode::CREATED, Json(staff)))
 }
@@ -1398,20 +2359,15 @@ pub async fn create_staff(
     tag = "Staff",
     params(("id" = String, Path, description = "Staff UUID")),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve a specific staff member's details", body = Staff), (status = 404))
+    responses((status = 200, description = "Retrieve a specific staff member's details", body = Staff), (status = 404, description = "Not found", body = ApiError))
 )]
 pub async fn get_staff(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<Json<Staff>, StatusCode> {
-    let staff_uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let staff = sqlx::query("SELECT * FROM staff WHERE id = ?")
-        .bind(staff_uuid.to_string())
-        .map(|row: SqliteRow| staff_from_row(&row))
-        .fetch_one(&state.db)
-        .await
-        .map_err(map_db_err)?;
-    Ok(Json(staff))
+) -> Result<Json<Staff>, AppError> {
+    let staff_uuid = Uuid::parse_str(&id)
+        .map_err(|_| AppError::BadRequest("Invalid staff id".to_string()))?;
+    Ok(Json(repo::staff::fetch(&state.db, staff_uuid).await?))
 }
 
 #[utoipa::path(
@@ -1421,38 +2377,29 @@ pub async fn get_staff(
     params(
         ("id" = String, Path, description = "Staff UUID"),
         ("start_date" = Option<String>, Query, description = "Start date YYYY-MM-DD"),
-        ("end_date" = Option<String>, Query, description = "End date YYYY-MM-DD")
-    ),
+        ("end_date" = Option<String>, Query, description = "End date YYYY-MM-DD"),
+        PageParams
+    , ExportParams),
     security(("bearer_auth" = [])),
-    responses((status = 200, description = "Retrieve sales transactions handled by a specific staff member", body = [Sale]))
+    responses((status = 200, description = "Retrieve sales transactions handled by a specific staff member", body = Page<Sale>), (status = 422, description = "Invalid page or limit", body = ApiError))
 )]
 pub async fn get_staff_transactions(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
     Query(params): Query<StatsRangeParams>,
-) -> Result<Json<Vec<Sale>>, StatusCode> {
-    let (start_date, end_date) = get_default_dates(params.start_date, params.end_date);
-
-    let mut query = "SELECT * FROM sales WHERE staff_responsible = ? AND date(date_and_time) >= date(?) AND date(date_and_time) <= date(?)".to_string();
-    
-    query.push_str(" ORDER BY date_and_time DESC");
-
-    let rows = sqlx::query(&query)
-        .bind(id.to_string())
-        .bind(start_date)
-        .bind(end_date)
-        .fetch_all(&state.db)
-        .await
-        .map_err(map_db_err)?;
-
-    let mut sales = Vec::with_capacity(rows.len());
-    for row in rows {
-        sales.push(sale_from_row(&row)?);
+    Query(paging): Query<PageParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, paging.page, paging.limit)?;
+    let (start_date, end_date) = get_default_dates(state.timezone, params.start_date, params.end_date)?;
+    let (from, to) = state.timezone.day_bounds(start_date, end_date);
+
+    if export.is_csv() {
+        return csv_stream("staff-transactions", repo::sales::stream_by_staff(&state.db, id, from, to));
     }
-
-    Ok(Json(sales))
+    let (sales, total) = repo::sales::list_by_staff(&state.db, id, from, to, limit, offset).await?;
+    export.respond("staff-transactions", Page::new(sales, total, page, limit))
 }
-
 #[utoipa::path(
     put,
     path = "/api/staff/{id}",
@@ -1460,23 +2407,22 @@ pub async fn get_staff_transactions(
     params(("id" = String, Path, description = "Staff UUID")),
     security(("bearer_auth" = [])),
     request_body = StaffInput,
-    responses((status = 200, description = "Update a staff member's information", body = Staff), (status = 404))
+    responses((status = 200, description = "Update a staff member's information; admins may update anyone, others only themselves", body = Staff), (status = 403, description = "Not an admin or the staff member", body = ApiError), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "Staff id already in use", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
 )]
 pub async fn update_staff(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Path(id): Path<String>,
     Json(input): Json<StaffInput>,
-) -> Result<Json<Staff>, StatusCode> {
+) -> Result<Json<Staff>, AppError> {
+    let staff_uuid = Uuid::parse_str(&id)
+        .map_err(|_| AppError::BadRequest("Invalid staff id".to_string()))?;
+    require_admin(&state, &claims, Some(staff_uuid)).await?;
+    input.validate()?;
     let mut tx = state.db.begin().await.map_err(map_db_err)?;
-    let staff_uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
 
     // Retrieve existing staff to keep password if not updated
-    let existing_staff = sqlx::query("SELECT * FROM staff WHERE id = ?")
-        .bind(staff_uuid.to_string())
-        .map(|row: SqliteRow| staff_from_row(&row))
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(map_db_err)?;
+    let existing_staff = repo::staff::fetch(&mut tx, staff_uuid).await?;
 
     // Handle password update
     let password_hash = if let Some(pwd) = input.password {
@@ -1498,23 +2444,11 @@ pub async fn update_staff(
         photo_link: input.photo_link,
         username: input.username,
         password_hash,
+        archived_at: existing_staff.archived_at,
+        role: existing_staff.role,
     };
 
-    sqlx::query(
-        "UPDATE staff SET staff_id = ?, first_name = ?, last_name = ?, mobile_number = ?, photo_link = ?, username = ?, password_hash = ? WHERE id = ?",
-    )
-    .bind(&updated_staff.staff_id)
-    .bind(&updated_staff.first_name)
-    .bind(&updated_staff.last_name)
-    .bind(&updated_staff.mobile_number)
-    .bind(&updated_staff.photo_link)
-    .bind(&updated_staff.username)
-    .bind(&updated_staff.password_hash)
-    .bind(staff_uuid.to_string())
-    .execute(&mut *tx)
-    .await
-    .map_err(map_db_err)?;
-
+    repo::staff::update(&mut tx, &updated_staff).await?;
     tx.commit().await.map_err(map_db_err)?;
 
     Ok(Json(updated_staff))
@@ -1526,149 +2460,727 @@ pub async fn update_staff(
     tag = "Staff",
     params(("id" = String, Path, description = "Staff UUID")),
     security(("bearer_auth" = [])),
-    responses((status = 204, description = "Permanently remove a staff member"), (status = 404))
+    responses((status = 204, description = "Archive a staff member; they can no longer log in but stay resolvable from sales. Admins may archive anyone, others only themselves"), (status = 403, description = "Not an admin or the staff member", body = ApiError), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "The last admin cannot be archived", body = ApiError))
 )]
 pub async fn delete_staff(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    let staff_uuid = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let result = sqlx::query("DELETE FROM staff WHERE id = ?")
-        .bind(staff_uuid.to_string())
-        .execute(&state.db)
-        .await
-        .map_err(map_db_err)?;
+) -> Result<StatusCode, AppError> {
+    let staff_uuid = Uuid::parse_str(&id)
+        .map_err(|_| AppError::BadRequest("Invalid staff id".to_string()))?;
+    require_admin(&state, &claims, Some(staff_uuid)).await?;
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    keep_an_admin(&mut tx, staff_uuid).await?;
+    repo::staff::archive(&mut tx, staff_uuid, Utc::now()).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    if result.rows_affected() == 0 {
-        Ok(StatusCode::NOT_FOUND)
-    } else {
-        Ok(StatusCode::NO_CONTENT)
+#[utoipa::path(
+    post,
+    path = "/api/staff/{id}/restore",
+    tag = "Staff",
+    params(("id" = String, Path, description = "Staff UUID")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Restore an archived staff member; admins only", body = Staff), (status = 403, description = "Not an admin", body = ApiError), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn restore_staff(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Staff>, AppError> {
+    require_admin(&state, &claims, None).await?;
+    Ok(Json(repo::staff::restore(&state.db, id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/staff/{id}/role",
+    tag = "Admin",
+    params(("id" = String, Path, description = "Staff UUID")),
+    request_body = StaffRoleInput,
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Grant or revoke the admin role", body = Staff), (status = 403, description = "Not an admin", body = ApiError), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "The last admin cannot be demoted", body = ApiError))
+)]
+pub async fn set_staff_role(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<StaffRoleInput>,
+) -> Result<Json<Staff>, AppError> {
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    if input.role != StaffRole::Admin {
+        keep_an_admin(&mut tx, id).await?;
     }
+    let staff = repo::staff::set_role(&mut tx, id, input.role).await?;
+    tx.commit().await.map_err(map_db_err)?;
+    Ok(Json(staff))
 }
 
-fn product_from_row(row: &SqliteRow) -> Result<Product, StatusCode> {
-    let product_type: String = row.get("product_type");
-    let product_type = ProductType::from_str(&product_type)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// Refuses callers who are not admins, or, with `own` set, not that staff
+/// member either.
+async fn require_admin(state: &AppState, claims: &Claims, own: Option<Uuid>) -> Result<(), AppError> {
+    if own.is_some_and(|id| claims.sub == id.to_string()) || auth::is_admin(&state.db, claims).await? {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
 
-    Ok(Product {
-        id: parse_uuid(row.get("id"))?,
-        name: row.get("name"),
-        description: row.get("description"),
-        price_cents: row.get("price_cents"),
-        stock: row.get("stock"),
-        product_type,
-        details: vec![],
-    })
+/// Refuses to archive or demote staff member `id` if they are the only
+/// active admin left. The admins stay locked until `tx` ends, so two
+/// admins demoting each other cannot both count the other as remaining.
+async fn keep_an_admin(tx: &mut Tx, id: Uuid) -> Result<(), AppError> {
+    if repo::staff::lock_active_admins(tx).await? == [id] {
+        return Err(AppError::Conflict("The last admin must stay an active admin".to_string(), vec![]));
+    }
+    Ok(())
 }
 
-fn customer_from_row(row: &SqliteRow) -> Result<Customer, StatusCode> {
-    Ok(Customer {
-        id: parse_uuid(row.get("id"))?,
-        first_name: row.get("first_name"),
-        last_name: row.get("last_name"),
-        middle_name: row.get("middle_name"),
-        mobile_number: row.get("mobile_number"),
-        date_of_birth: row.get("date_of_birth"),
-        email: row.get("email"),
-        details: vec![],
-    })
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    tag = "Webhooks",
+    params(PageParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List webhook subscriptions", body = Page<Webhook>), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    Query(params): Query<PageParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    if export.is_csv() {
+        return csv_stream("webhooks", repo::webhooks::stream(&state.db));
+    }
+    let (webhooks, total) = repo::webhooks::list(&state.db, limit, offset).await?;
+    export.respond("webhooks", Page::new(webhooks, total, page, limit))
 }
 
-fn sale_item_from_row(row: &SqliteRow) -> Result<SaleItem, StatusCode> {
-    let sale_id_str: Option<String> = row.get("sale_id");
-    let sale_id = match sale_id_str {
-        Some(s) => Some(parse_uuid(s)?),
-        None => None,
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    tag = "Webhooks",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
+    request_body = WebhookInput,
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Subscribe a URL to events; the response is the only one that includes the signing secret", body = Webhook), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Json(input): Json<WebhookInput>,
+) -> Result<(StatusCode, Json<Webhook>), AppError> {
+    input.validate()?;
+    webhooks::resolve_target(&input.url, false)
+        .await
+        .map_err(|err| AppError::invalid_field("url", err))?;
+    let webhook = Webhook {
+        id: Uuid::new_v4(),
+        url: input.url,
+        description: input.description,
+        event_types: input.event_types,
+        active: input.active,
+        created_at: Utc::now(),
+        secret: Some(webhooks::generate_secret()),
     };
-    
-    let customer_id_str: Option<String> = row.get("customer_id");
-    let customer_id = match customer_id_str {
-        Some(s) => Some(parse_uuid(s)?),
-        None => None,
+
+    repo::webhooks::insert(&state.db, &webhook).await?;
+
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}",
+    tag = "Webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Retrieve a webhook subscription", body = Webhook), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn get_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Webhook>, AppError> {
+    Ok(Json(repo::webhooks::fetch(&state.db, id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/webhooks/{id}",
+    tag = "Webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    request_body = WebhookInput,
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Change a webhook's URL, events or active flag; the secret is kept", body = Webhook), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<WebhookInput>,
+) -> Result<Json<Webhook>, AppError> {
+    input.validate()?;
+    webhooks::resolve_target(&input.url, false)
+        .await
+        .map_err(|err| AppError::invalid_field("url", err))?;
+    Ok(Json(repo::webhooks::update(&state.db, id, &input).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/webhooks/{id}",
+    tag = "Webhooks",
+    params(("id" = String, Path, description = "Webhook id")),
+    security(("bearer_auth" = [])),
+    responses((status = 204, description = "Delete a webhook subscription and its delivery log"), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    repo::webhooks::delete(&state.db, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct WebhookDeliveryParams {
+    /// Only deliveries with this status: `pending`, `succeeded` or `failed`
+    pub status: Option<String>,
+    /// 1-based page number (default 1)
+    pub page: Option<i64>,
+    /// Page size, 1..=100 (default 20)
+    pub limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}/deliveries",
+    tag = "Webhooks",
+    params(("id" = String, Path, description = "Webhook id"), WebhookDeliveryParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Delivery log of a webhook, latest first", body = Page<WebhookDelivery>), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid status, page or limit", body = ApiError))
+)]
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<WebhookDeliveryParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let status = params
+        .status
+        .as_deref()
+        .map(|status| {
+            WebhookDeliveryStatus::from_str(status)
+                .map_err(|_| AppError::invalid_field("status", "must be pending, succeeded or failed"))
+        })
+        .transpose()?;
+
+    repo::webhooks::fetch(&state.db, id).await?;
+    if export.is_csv() {
+        return csv_stream("webhook-deliveries", repo::webhooks::stream_deliveries(&state.db, id, status));
+    }
+    let (deliveries, total) = repo::webhooks::list_deliveries(&state.db, id, status, limit, offset).await?;
+    export.respond("webhook-deliveries", Page::new(deliveries, total, page, limit))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/webhook_deliveries/{id}/retry",
+    tag = "Webhooks",
+    params(("id" = String, Path, description = "Delivery id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Queue a delivery to be sent again on the next dispatch, with a fresh set of attempts", body = WebhookDelivery), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn retry_webhook_delivery(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDelivery>, AppError> {
+    Ok(Json(repo::webhooks::retry_delivery(&state.db, id, Utc::now()).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/report_subscriptions",
+    tag = "Email Reports",
+    params(PageParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List scheduled email reports", body = Page<ReportSubscription>), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_report_subscriptions(
+    State(state): State<AppState>,
+    Query(params): Query<PageParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    if export.is_csv() {
+        return csv_stream("report-subscriptions", repo::report_subscriptions::stream(&state.db));
+    }
+    let (subscriptions, total) = repo::report_subscriptions::list(&state.db, limit, offset).await?;
+    export.respond("report-subscriptions", Page::new(subscriptions, total, page, limit))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/report_subscriptions",
+    tag = "Email Reports",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries with the same key replay the first response")),
+    request_body = ReportSubscriptionInput,
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Schedule a report to be emailed to the recipients", body = ReportSubscription), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn create_report_subscription(
+    State(state): State<AppState>,
+    Json(input): Json<ReportSubscriptionInput>,
+) -> Result<(StatusCode, Json<ReportSubscription>), AppError> {
+    input.validate()?;
+    let now = Utc::now();
+    let subscription = ReportSubscription {
+        id: Uuid::new_v4(),
+        name: input.name,
+        report_type: input.report_type,
+        parameters: input.parameters,
+        frequency: input.frequency,
+        hour: input.hour,
+        recipients: input.recipients,
+        active: input.active,
+        next_run_at: email_reports::next_run(state.timezone, input.frequency, input.hour, now),
+        last_sent_at: None,
+        last_error: None,
+        created_at: now,
     };
 
-    Ok(SaleItem {
-        id: parse_uuid(row.get("id"))?,
-        sale_id,
-        product_id: parse_uuid(row.get("product_id"))?,
-        customer_id,
-        date_of_sale: row.get("date_of_sale"),
-        quantity: row.get("quantity"),
-        discount: row.get("discount"),
-        total_cents: row.get("total_cents"),
-        total_resolved: row.get("total_resolved"),
-        note: row.get("note"),
-        product_name: row.try_get("product_name").ok(),
-        price_per_item: row.try_get("price_per_item").ok(),
-    })
+    repo::report_subscriptions::insert(&state.db, &subscription).await?;
+
+    Ok((StatusCode::CREATED, Json(subscription)))
 }
 
-fn sale_from_row(row: &SqliteRow) -> Result<Sale, StatusCode> {
-    let sales_channel_str: String = row.get("sales_channel");
-    let sales_channel = shared::models::SalesChannel::from_str(&sales_channel_str).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+#[utoipa::path(
+    get,
+    path = "/api/report_subscriptions/{id}",
+    tag = "Email Reports",
+    params(("id" = String, Path, description = "Report subscription id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Retrieve a scheduled email report", body = ReportSubscription), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn get_report_subscription(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReportSubscription>, AppError> {
+    Ok(Json(repo::report_subscriptions::fetch(&state.db, id).await?))
+}
 
-    let customer_id_str: Option<String> = row.get("customer_id");
-    let customer_id = match customer_id_str {
-        Some(s) => Some(parse_uuid(s)?),
-        None => None,
+#[utoipa::path(
+    put,
+    path = "/api/report_subscriptions/{id}",
+    tag = "Email Reports",
+    params(("id" = String, Path, description = "Report subscription id")),
+    request_body = ReportSubscriptionInput,
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Change a scheduled email report; the next run is recalculated from the new schedule", body = ReportSubscription), (status = 404, description = "Not found", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn update_report_subscription(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<ReportSubscriptionInput>,
+) -> Result<Json<ReportSubscription>, AppError> {
+    input.validate()?;
+    let next_run_at = email_reports::next_run(state.timezone, input.frequency, input.hour, Utc::now());
+    Ok(Json(repo::report_subscriptions::update(&state.db, id, &input, next_run_at).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/report_subscriptions/{id}",
+    tag = "Email Reports",
+    params(("id" = String, Path, description = "Report subscription id")),
+    security(("bearer_auth" = [])),
+    responses((status = 204, description = "Stop and delete a scheduled email report"), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn delete_report_subscription(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    repo::report_subscriptions::delete(&state.db, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/report_subscriptions/{id}/send",
+    tag = "Email Reports",
+    params(("id" = String, Path, description = "Report subscription id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Email the report now without changing its schedule; a failed send is reported in `last_error`", body = ReportSubscription), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn send_report_subscription(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReportSubscription>, AppError> {
+    let subscription = repo::report_subscriptions::fetch(&state.db, id).await?;
+    email_reports::send(&state.db, &state.mailer, state.timezone, &subscription, false).await?;
+    Ok(Json(repo::report_subscriptions::fetch(&state.db, id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/report_subscriptions/{id}/preview",
+    tag = "Email Reports",
+    params(("id" = String, Path, description = "Report subscription id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The email body the report would be sent with now", content_type = "text/html", body = String), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn preview_report_subscription(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Html<String>, AppError> {
+    let subscription = repo::report_subscriptions::fetch(&state.db, id).await?;
+    let report = email_reports::render(&state.db, state.timezone, &subscription, Utc::now()).await?;
+    Ok(Html(report.html))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ShiftFilterParams {
+    /// Only open (`true`) or closed (`false`) shifts
+    pub open: Option<bool>,
+    pub register: Option<String>,
+    pub company_branch: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shifts",
+    tag = "Shifts",
+    params(PageParams, ShiftFilterParams, ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "List till shifts, most recently opened first", body = Page<Shift>), (status = 422, description = "Invalid page or limit", body = ApiError))
+)]
+pub async fn list_shifts(
+    State(state): State<AppState>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<ShiftFilterParams>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let (page, limit, offset) = export_window(&export, params.page, params.limit)?;
+    let filter = repo::shifts::ShiftFilter {
+        open: filter.open,
+        register: filter.register.filter(|register| !register.is_empty()),
+        company_branch: filter.company_branch.filter(|branch| !branch.is_empty()),
     };
+    if export.is_csv() {
+        return csv_stream("shifts", repo::shifts::stream(&state.db, &filter));
+    }
+    let (shifts, total) = repo::shifts::list(&state.db, &filter, limit, offset).await?;
+    export.respond("shifts", Page::new(shifts, total, page, limit))
+}
 
-    Ok(Sale {
-        id: parse_uuid(row.get("id"))?,
-        customer_id,
-        date_and_time: row.get("date_and_time"),
-        sale_items: vec![],
-        total_cents: row.get("total_cents"),
-        discount: row.get("discount"),
-        total_resolved: row.get("total_resolved"),
-        sales_channel,
-        staff_responsible: parse_uuid(row.get("staff_responsible"))?,
-        company_branch: row.get("company_branch"),
-        car_number: row.get("car_number"),
-        receipt_number: row.get("receipt_number"),
-    })
+#[utoipa::path(
+    post,
+    path = "/api/shifts",
+    tag = "Shifts",
+    request_body = ShiftInput,
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Open a shift on a till with an opening float; sales the signed-in staff member rings up go into it until it closes", body = Shift), (status = 409, description = "The till or the staff member already has an open shift", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn create_shift(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(input): Json<ShiftInput>,
+) -> Result<(StatusCode, Json<Shift>), AppError> {
+    input.validate()?;
+    let shift = Shift {
+        id: Uuid::new_v4(),
+        register: input.register.trim().to_string(),
+        company_branch: input.company_branch.trim().to_string(),
+        opened_by: parse_uuid(claims.sub.clone())?,
+        opened_at: Utc::now(),
+        opening_float_cents: input.opening_float_cents,
+        closed_by: None,
+        closed_at: None,
+        counted_cash_cents: None,
+        note: None,
+    };
+
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    if repo::shifts::open_for_staff(&mut tx, &claims.sub).await?.is_some() {
+        return Err(AppError::Conflict("You already have an open shift".to_string(), vec![]));
+    }
+    if repo::shifts::register_is_open(&mut tx, &shift.company_branch, &shift.register).await? {
+        return Err(AppError::Conflict(
+            "The till already has an open shift".to_string(),
+            vec![FieldError {
+                field: "register".to_string(),
+                message: "already has an open shift".to_string(),
+            }],
+        ));
+    }
+
+    repo::shifts::insert(&mut tx, &shift).await?;
+    tx.commit().await.map_err(map_db_err)?;
+
+    Ok((StatusCode::CREATED, Json(shift)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shifts/current",
+    tag = "Shifts",
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The signed-in staff member's open shift", body = Shift), (status = 404, description = "No open shift", body = ApiError))
+)]
+pub async fn get_current_shift(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Shift>, AppError> {
+    repo::shifts::open_for_staff(&state.db, &claims.sub)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found("Open shift"))
 }
 
-fn staff_from_row(row: &SqliteRow) -> Staff {
-    Staff {
-        id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
-        staff_id: row.get("staff_id"),
-        first_name: row.get("first_name"),
-        last_name: row.get("last_name"),
-        mobile_number: row.get("mobile_number"),
-        photo_link: row.get("photo_link"),
-        username: row.get("username"),
-        password_hash: row.get("password_hash"),
+#[utoipa::path(
+    get,
+    path = "/api/shifts/{id}",
+    tag = "Shifts",
+    params(("id" = String, Path, description = "Shift id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Retrieve a shift", body = Shift), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn get_shift(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Shift>, AppError> {
+    Ok(Json(repo::shifts::fetch(&state.db, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shifts/{id}/cash_movements",
+    tag = "Shifts",
+    params(("id" = String, Path, description = "Shift id")),
+    request_body = CashMovementInput,
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Record cash put into or taken out of the drawer of an open shift", body = CashMovement), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "The shift is closed", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn create_cash_movement(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<CashMovementInput>,
+) -> Result<(StatusCode, Json<CashMovement>), AppError> {
+    input.validate()?;
+    let movement = CashMovement {
+        id: Uuid::new_v4(),
+        shift_id: id,
+        kind: input.kind,
+        amount_cents: input.amount_cents,
+        reason: input.reason.trim().to_string(),
+        recorded_by: parse_uuid(claims.sub)?,
+        recorded_at: Utc::now(),
+    };
+
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    if repo::shifts::find_open(&mut tx, id).await?.is_none() {
+        repo::shifts::fetch(&mut tx, id).await?;
+        return Err(AppError::Conflict("The shift is closed".to_string(), vec![]));
     }
+    repo::shifts::insert_cash_movement(&mut tx, &movement).await?;
+    tx.commit().await.map_err(map_db_err)?;
+
+    Ok((StatusCode::CREATED, Json(movement)))
 }
 
-fn parse_uuid(value: String) -> Result<Uuid, StatusCode> {
-    Uuid::parse_str(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+#[utoipa::path(
+    post,
+    path = "/api/shifts/{id}/close",
+    tag = "Shifts",
+    params(("id" = String, Path, description = "Shift id")),
+    request_body = ShiftCloseInput,
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Close a shift with the cash counted in the drawer and return its Z report, which is stored and never changes", body = ShiftReport), (status = 404, description = "Not found", body = ApiError), (status = 409, description = "The shift is already closed", body = ApiError), (status = 422, description = "Invalid input", body = ApiError))
+)]
+pub async fn close_shift(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<ShiftCloseInput>,
+) -> Result<Json<ShiftReport>, AppError> {
+    input.validate()?;
+    let closed_by = parse_uuid(claims.sub)?;
+    let closed_at = Utc::now();
+    let note = input.note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+
+    // Sales and cash movements lock the shift open while they write into
+    // it, so the report sees all of them or the shift is closed to them
+    let mut tx = state.db.begin().await.map_err(map_db_err)?;
+    let closed = repo::shifts::close(&mut tx, id, closed_by, closed_at, input.counted_cash_cents, note.as_deref()).await?;
+    let Some(shift) = closed else {
+        repo::shifts::fetch(&mut tx, id).await?;
+        return Err(AppError::Conflict("The shift is already closed".to_string(), vec![]));
+    };
+
+    let report = repo::shifts::report(&mut tx, shift, ShiftReportKind::Z, closed_at).await?;
+    repo::shifts::store_z_report(&mut tx, &report).await?;
+    tx.commit().await.map_err(map_db_err)?;
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/shifts/{id}/report",
+    tag = "Shifts",
+    params(("id" = String, Path, description = "Shift id"), ExportParams),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The X report of an open shift, as it stands now, or the stored Z report of a closed one", body = ShiftReport), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn get_shift_report(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    export: Export,
+) -> Result<Response, AppError> {
+    let report = repo::shifts::load_report(&state.db, id).await?;
+    let name = format!("{}-report-{}", shift_report_letter(report.kind), report.shift.opened_at.format("%Y%m%d"));
+    export.respond(&name, report)
 }
 
-fn map_db_err(err: sqlx::Error) -> StatusCode {
-    match err {
-        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
-        _ => StatusCode::INTERNAL_SERVER_ERROR,
+#[utoipa::path(
+    get,
+    path = "/api/shifts/{id}/report/print",
+    tag = "Shifts",
+    params(("id" = String, Path, description = "Shift id")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The shift's X or Z report as a printable page", content_type = "text/html", body = String), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn print_shift_report(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Html<String>, AppError> {
+    let report = repo::shifts::load_report(&state.db, id).await?;
+    let shift = &report.shift;
+    let place = if shift.company_branch.is_empty() {
+        shift.register.clone()
+    } else {
+        format!("{}, {}", shift.register, shift.company_branch)
+    };
+    let heading = format!("{} report: {}", shift_report_letter(report.kind), place);
+    let local = |instant: DateTime<Utc>| state.timezone.local_time(instant).format("%Y-%m-%d %H:%M").to_string();
+    let summary = match shift.closed_at {
+        Some(closed_at) => format!("Shift opened {} and closed {} ({}).", local(shift.opened_at), local(closed_at), state.timezone.name()),
+        None => format!(
+            "Shift opened {}, still open at {} ({}).",
+            local(shift.opened_at),
+            local(report.generated_at),
+            state.timezone.name()
+        ),
+    };
+    Ok(Html(email_reports::html_document(&heading, Some(&summary), &report.sheets())))
+}
+
+fn shift_report_letter(kind: ShiftReportKind) -> &'static str {
+    match kind {
+        ShiftReportKind::X => "X",
+        ShiftReportKind::Z => "Z",
     }
 }
 
-fn hash_password(password: &str, pepper: &str) -> Result<String, StatusCode> {
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups",
+    tag = "Admin",
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Backups in the backup directory, newest first", body = Vec<Backup>), (status = 403, description = "Not an admin", body = ApiError))
+)]
+pub async fn list_backups(State(state): State<AppState>) -> Result<Json<Vec<Backup>>, AppError> {
+    Ok(Json(backup::list(&state.backup_dir).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups",
+    tag = "Admin",
+    security(("bearer_auth" = [])),
+    responses((status = 201, description = "Back up the SQLite database while it stays in use", body = Backup), (status = 400, description = "The database is PostgreSQL", body = ApiError), (status = 403, description = "Not an admin", body = ApiError))
+)]
+pub async fn create_backup(State(state): State<AppState>) -> Result<(StatusCode, Json<Backup>), AppError> {
+    let backup = backup::create(&state.db, &state.backup_dir).await?;
+    tracing::info!("Backed up the database to {}", backup.file_name);
+    Ok((StatusCode::CREATED, Json(backup)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups/{file_name}",
+    tag = "Admin",
+    params(("file_name" = String, Path, description = "Backup file name")),
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "The backup as a SQLite database file", content_type = "application/vnd.sqlite3", body = Vec<u8>), (status = 403, description = "Not an admin", body = ApiError), (status = 404, description = "Not found", body = ApiError))
+)]
+pub async fn download_backup(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<Response, AppError> {
+    let path = backup::path(&state.backup_dir, &file_name).ok_or_else(|| AppError::not_found("Backup"))?;
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(AppError::not_found("Backup")),
+        Err(err) => return Err(AppError::internal(err)),
+    };
+    let length = file.metadata().await.map_err(AppError::internal)?.len();
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name))
+        .map_err(AppError::internal)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/vnd.sqlite3")),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::CONTENT_LENGTH, HeaderValue::from(length)),
+        ],
+        // Backups can be large, so they are sent as they are read
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/integrity",
+    tag = "Admin",
+    security(("bearer_auth" = [])),
+    responses((status = 200, description = "Check the database file and look for orphaned rows", body = IntegrityReport), (status = 403, description = "Not an admin", body = ApiError))
+)]
+pub async fn get_integrity_report(State(state): State<AppState>) -> Result<Json<IntegrityReport>, AppError> {
+    let storage_errors = integrity::storage_errors(&state.db).await.map_err(map_db_err)?;
+    let report = integrity::check(&state.db).await.map_err(map_db_err)?;
+    Ok(Json(IntegrityReport {
+        ok: storage_errors.is_empty() && report.is_clean(),
+        storage_errors,
+        orphans: report
+            .findings
+            .iter()
+            .map(|finding| OrphanCount { reference: finding.describe(), orphans: finding.orphans })
+            .collect(),
+    }))
+}
+
+fn map_db_err(err: sqlx::Error) -> AppError {
+    AppError::from(err)
+}
+
+fn hash_password(password: &str, pepper: &str) -> Result<String, AppError> {
     let salted = format!("{password}{pepper}");
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     let hash = argon2
         .hash_password(salted.as_bytes(), &salt)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(AppError::internal)?
         .to_string();
     Ok(hash)
 }
 
-fn verify_password(password: &str, pepper: &str, stored: &str) -> Result<bool, StatusCode> {
+fn verify_password(password: &str, pepper: &str, stored: &str) -> Result<bool, AppError> {
     let salted = format!("{password}{pepper}");
     let parsed =
-        PasswordHash::new(stored).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        PasswordHash::new(stored).map_err(AppError::internal)?;
     let argon2 = Argon2::default();
     Ok(argon2
         .verify_password(salted.as_bytes(), &parsed)
@@ -1684,27 +3196,33 @@ fn verify_password(password: &str, pepper: &str, stored: &str) -> Result<bool, S
     request_body(content = FileUpload, content_type = "multipart/form-data"),
     responses(
         (status = 200, description = "File uploaded", body = UploadResponse),
-        (status = 400, description = "Bad Request")
+        (status = 400, description = "Malformed upload", body = ApiError),
+        (status = 422, description = "Missing file", body = ApiError)
     )
 )]
-pub async fn upload_file(mut multipart: axum::extract::Multipart) -> Result<Json<UploadResponse>, StatusCode> {
-    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+pub async fn upload_file(
+    State(state): State<AppState>,
+    mut multipart: axum::extract::Multipart,
+) -> Result<Json<UploadResponse>, AppError> {
+    while let Some(field) = multipart.next_field().await.map_err(|err| AppError::BadRequest(err.body_text()))? {
         let file_name = field.file_name().unwrap_or("file").to_string();
         
         if let Some(ext) = std::path::Path::new(&file_name).extension().and_then(|s| s.to_str()) {
-             let data = field.bytes().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+             let data = field.bytes().await.map_err(AppError::internal)?;
              
              let new_filename = format!("{}.{}", Uuid::new_v4(), ext);
-             let upload_dir = "uploads";
-             tokio::fs::create_dir_all(upload_dir).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+             tokio::fs::create_dir_all(&state.upload_dir).await.map_err(AppError::internal)?;
              
-             let filepath = std::path::Path::new(upload_dir).join(&new_filename);
-             tokio::fs::write(&filepath, data).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+             let filepath = state.upload_dir.join(&new_filename);
+             tokio::fs::write(&filepath, data).await.map_err(AppError::internal)?;
              
              return Ok(Json(UploadResponse {
                  url: format!("/uploads/{}", new_filename),
              }));
         }
     }
-    Err(StatusCode::BAD_REQUEST)
+    Err(AppError::invalid_field("file", "a file with an extension is required"))
 }
+
+#[cfg(test)]
+mod tests;
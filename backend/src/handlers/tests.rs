@@ -0,0 +1,901 @@
+//! Handler tests, run against SQLite and, with `TEST_POSTGRES_URL`, on
+//! PostgreSQL. See `crate::testing`.
+
+use crate::testing::{
+    self, ADMIN_ID, create_customer, create_product, create_staff, open_shift, sale_input, stock_of,
+};
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use chrono::Utc;
+use serde_json::{Value, json};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn read_endpoints_run_on_every_backend() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let customer = create_customer(&app, "Ada").await;
+        open_shift(&app).await;
+        let mut sale = sale_input(&[(&tea, 2)]);
+        sale["customer_id"] = customer["id"].clone();
+        app.create("/api/sales_transactions", sale).await;
+
+        let today = Utc::now().date_naive();
+        let customer_id = customer["id"].as_str().unwrap();
+        for uri in [
+            "/api/search?q=tea".to_string(),
+            "/api/products?search=tea".to_string(),
+            "/api/products/low_stock".to_string(),
+            "/api/stock_alerts".to_string(),
+            "/api/customers?search=ada".to_string(),
+            format!("/api/customers/{}/stats", customer_id),
+            "/api/customers/segments".to_string(),
+            "/api/sales?query=tea".to_string(),
+            "/api/sales_transactions?query=ada".to_string(),
+            "/api/sync/changes".to_string(),
+            "/api/calendar".to_string(),
+            "/api/sales/stats/today".to_string(),
+            "/api/sales/stats/week".to_string(),
+            "/api/sales/stats/totals".to_string(),
+            "/api/sales/stats/daily".to_string(),
+            "/api/sales/stats/series?bucket=hour&breakdown=product_type".to_string(),
+            "/api/sales_stats/top_products".to_string(),
+            "/api/sales/stats/by_product?compare=previous_period".to_string(),
+            "/api/sales/stats/margin?group_by=product".to_string(),
+            "/api/sales/stats/heatmap".to_string(),
+            "/api/sales/stats/by_staff".to_string(),
+            format!("/api/sales/stats/by_staff/{}", ADMIN_ID),
+            format!("/api/staff/{}/transactions", ADMIN_ID),
+            "/api/staff".to_string(),
+            "/api/shifts".to_string(),
+            "/api/shifts/current".to_string(),
+            "/api/webhooks".to_string(),
+            "/api/report_subscriptions".to_string(),
+            "/api/admin/integrity".to_string(),
+            format!("/api/sales/stats/daily?start_date={}&end_date={}&format=csv", today, today),
+        ] {
+            let (status, body) = app.get(&uri).await;
+            assert_eq!(status, StatusCode::OK, "GET {} on {:?}: {}", uri, app.backend(), body);
+        }
+
+        let (_, totals) = app.get("/api/sales/stats/totals").await;
+        assert_eq!(totals["count"], 1, "{:?}: {}", app.backend(), totals);
+        let (_, hits) = app.get("/api/products?search=tea").await;
+        assert_eq!(hits["total"], 1, "{:?}: {}", app.backend(), hits);
+        assert_eq!(stock_of(&app, &tea).await, 8, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn write_endpoints_run_on_every_backend() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let tea_id = tea["id"].as_str().unwrap();
+        let mut update = tea.clone();
+        update["name"] = json!("Jasmine Tea");
+        let (status, updated) = app.put(&format!("/api/products/{}", tea_id), update).await;
+        assert_eq!((status, &updated["name"]), (StatusCode::OK, &json!("Jasmine Tea")), "{:?}", app.backend());
+        app.create(
+            &format!("/api/products/{}/prices", tea_id),
+            json!({ "price_cents": 350, "effective_from": Utc::now() + chrono::Duration::days(1) }),
+        )
+        .await;
+
+        let customer = create_customer(&app, "Ada").await;
+        let customer_uri = format!("/api/customers/{}", customer["id"].as_str().unwrap());
+        assert_eq!(app.delete(&customer_uri).await.0, StatusCode::NO_CONTENT, "{:?}", app.backend());
+        let restore_uri = format!("{}/restore", customer_uri);
+        assert_eq!(app.post(&restore_uri, json!({})).await.0, StatusCode::OK, "{:?}", app.backend());
+
+        let staff = create_staff(&app, "grace").await;
+        let (status, _) = app.delete(&format!("/api/staff/{}", staff["id"].as_str().unwrap())).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{:?}", app.backend());
+
+        let shift = open_shift(&app).await;
+        let (_, sale) = app.post("/api/sales_transactions", sale_input(&[(&tea, 2)])).await;
+        let item_uri = format!("/api/sales/{}", sale["sale_items"][0]["id"].as_str().unwrap());
+        let (status, _) = app.delete(&item_uri).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{:?}", app.backend());
+        let close_uri = format!("/api/shifts/{}/close", shift["id"].as_str().unwrap());
+        let (status, closed) = app.post(&close_uri, json!({ "counted_cash_cents": 0, "note": null })).await;
+        assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), closed);
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn every_sale_item_write_adjusts_stock() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let coffee = create_product(&app, "Coffee", 400, 10).await;
+        open_shift(&app).await;
+
+        let (status, sale) = app.post("/api/sales_transactions", sale_input(&[(&tea, 2)])).await;
+        assert_eq!(status, StatusCode::CREATED, "{:?}: {}", app.backend(), sale);
+        assert_eq!(stock_of(&app, &tea).await, 8, "{:?}", app.backend());
+
+        let mut item = sale_input(&[(&tea, 3)])["sale_items"][0].clone();
+        item["sale_id"] = sale["id"].clone();
+        let item = app.create("/api/sales", item).await;
+        assert_eq!(stock_of(&app, &tea).await, 5, "{:?}", app.backend());
+
+        let item_uri = format!("/api/sales/{}", item["id"].as_str().unwrap());
+        let mut update = item.clone();
+        update["quantity"] = json!(1);
+        let (status, _) = app.put(&item_uri, update.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", app.backend());
+        assert_eq!(stock_of(&app, &tea).await, 7, "{:?}", app.backend());
+
+        update["product_id"] = coffee["id"].clone();
+        update["quantity"] = json!(4);
+        app.put(&item_uri, update).await;
+        assert_eq!(stock_of(&app, &tea).await, 8, "{:?}", app.backend());
+        assert_eq!(stock_of(&app, &coffee).await, 6, "{:?}", app.backend());
+
+        let (status, _) = app.delete(&item_uri).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{:?}", app.backend());
+        assert_eq!(stock_of(&app, &coffee).await, 10, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn selling_down_to_the_reorder_point_raises_one_alert() {
+    for app in testing::apps().await {
+        let mut input = json!({
+            "name": "Green Tea",
+            "description": "",
+            "price_cents": 300,
+            "stock": 5,
+            "product_type": "physical_good",
+            "reorder_point": 3,
+            "reorder_quantity": 12,
+            "details": [],
+        });
+        let tea = app.create("/api/products", input.clone()).await;
+        input["name"] = json!("Haircut");
+        input["product_type"] = json!("service");
+        let haircut = app.create("/api/products", input).await;
+        open_shift(&app).await;
+
+        app.create("/api/sales_transactions", sale_input(&[(&tea, 1), (&haircut, 4)])).await;
+        let (_, alerts) = app.get("/api/stock_alerts").await;
+        assert_eq!(alerts["total"], 0, "{:?}: {}", app.backend(), alerts);
+
+        app.create("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        app.create("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        let (_, alerts) = app.get("/api/stock_alerts").await;
+        assert_eq!(alerts["total"], 1, "{:?}: {}", app.backend(), alerts);
+        let alert = &alerts["items"][0];
+        assert_eq!((&alert["stock"], &alert["reorder_quantity"]), (&json!(3), &json!(12)), "{:?}", app.backend());
+
+        let (_, low) = app.get("/api/products/low_stock").await;
+        assert_eq!(low["items"][0]["id"], tea["id"], "{:?}: {}", app.backend(), low);
+        assert_eq!(stock_of(&app, &haircut).await, 5, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn lists_page_within_bounds() {
+    for app in testing::apps().await {
+        for name in ["Apple", "Banana", "Cherry", "Date", "Elderberry"] {
+            create_product(&app, name, 100, 50).await;
+        }
+
+        let (_, page) = app.get("/api/products?page=2&limit=2").await;
+        assert_eq!(
+            (&page["total"], &page["page"], &page["limit"], &page["next_page"]),
+            (&json!(5), &json!(2), &json!(2), &json!(3)),
+            "{:?}: {}",
+            app.backend(),
+            page
+        );
+        let names: Vec<&str> = page["items"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["Cherry", "Date"], "{:?}", app.backend());
+        let (_, last) = app.get("/api/products?page=3&limit=2").await;
+        assert_eq!(last["next_page"], Value::Null, "{:?}", app.backend());
+
+        for query in ["limit=0", "limit=101", "limit=-1", "page=0", "page=-3"] {
+            let (status, body) = app.get(&format!("/api/customers?{}", query)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{} on {:?}: {}", query, app.backend(), body);
+        }
+        let (status, _) = app.get("/api/products?page=9223372036854775807&limit=100").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}", app.backend());
+
+        open_shift(&app).await;
+        let cherry = &page["items"][0];
+        app.create("/api/sales_transactions", sale_input(&[(cherry, 1)])).await;
+        let (_, items) = app.get("/api/sales?limit=1").await;
+        assert_eq!(items["items"].as_array().unwrap().len(), 1, "{:?}", app.backend());
+        assert!(items.get("sales").is_none(), "{:?}: {}", app.backend(), items);
+        assert_eq!(items["total_sales_period_cents"], cherry["price_cents"], "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn errors_are_json_with_codes_and_field_details() {
+    for app in testing::apps().await {
+        let staff = json!({
+            "id": null,
+            "first_name": "Grace",
+            "last_name": "Hopper",
+            "mobile_number": "0123456789",
+            "photo_link": "",
+            "staff_id": "staff-0001",
+            "username": "grace",
+            "password": "password123",
+        });
+        let (status, body) = app.post("/api/staff", staff).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}: {}", app.backend(), body);
+        assert_eq!(body["code"], "conflict", "{:?}", app.backend());
+        assert_eq!(body["details"][0]["field"], "staff_id", "{:?}: {}", app.backend(), body);
+
+        let (status, body) = app.get(&format!("/api/products/{}", uuid::Uuid::new_v4())).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("not_found")), "{:?}", app.backend());
+
+        let (status, body) = app.get("/api/products/not-a-uuid").await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("bad_request")), "{:?}", app.backend());
+
+        let (status, body) = app.post("/api/products", json!({ "name": "Tea" })).await;
+        assert_eq!((status, &body["code"]), (StatusCode::UNPROCESSABLE_ENTITY, &json!("validation_failed")), "{:?}", app.backend());
+
+        let response = app
+            .send(axum::http::Request::get("/api/products").body(axum::body::Body::empty()).unwrap())
+            .await;
+        let (status, body) = testing::text(response).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", app.backend());
+        assert!(body.contains(r#""code":"unauthorized""#), "{:?}: {}", app.backend(), body);
+
+        app.finish().await;
+    }
+}
+
+fn names(page: &Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|item| item["name"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn search_forgives_typos() {
+    for app in testing::apps().await {
+        create_product(&app, "Green Tea", 300, 10).await;
+        create_product(&app, "Tent", 9000, 2).await;
+        create_customer(&app, "Teagan").await;
+
+        let (_, exact) = app.get("/api/products?search=tea").await;
+        assert_eq!(names(&exact), ["Green Tea"], "{:?}", app.backend());
+        for typo in ["tae", "tee", "TAE", "gren tae"] {
+            let (status, found) = app.get(&format!("/api/products?search={}", typo.replace(' ', "+"))).await;
+            assert_eq!(status, StatusCode::OK, "{} on {:?}: {}", typo, app.backend(), found);
+            assert_eq!(names(&found), ["Green Tea"], "{} on {:?}", typo, app.backend());
+        }
+        let (_, none) = app.get("/api/products?search=biscuit").await;
+        assert_eq!(none["total"], 0, "{:?}", app.backend());
+
+        let (_, hits) = app.get("/api/search?q=tae").await;
+        let titles: Vec<&str> = hits["items"].as_array().unwrap().iter().map(|hit| hit["title"].as_str().unwrap()).collect();
+        assert_eq!(titles, ["Green Tea"], "{:?}: {}", app.backend(), hits);
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn search_index_follows_edits() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let uri = format!("/api/products/{}", tea["id"].as_str().unwrap());
+
+        let mut update = tea.clone();
+        update["name"] = json!("Sencha");
+        update["details"] = json!([{ "detail_name": "origin", "detail_value": "Shizuoka" }]);
+        app.put(&uri, update).await;
+        for (search, total) in [("green", 0), ("sencha", 1), ("shizuoka", 1)] {
+            let (_, found) = app.get(&format!("/api/products?search={}", search)).await;
+            assert_eq!(found["total"], total, "{} on {:?}", search, app.backend());
+        }
+
+        let customer = create_customer(&app, "Ada").await;
+        let mut update = customer.clone();
+        update["email"] = json!("countess@example.com");
+        app.put(&format!("/api/customers/{}", customer["id"].as_str().unwrap()), update).await;
+        let (_, found) = app.get("/api/customers?search=countess").await;
+        assert_eq!(found["total"], 1, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn sales_charge_a_due_price_before_the_ticker_applies_it() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let tea_id = uuid::Uuid::parse_str(tea["id"].as_str().unwrap()).unwrap();
+        let due = Utc::now();
+        crate::db::query(
+            "INSERT INTO product_prices (id, product_id, price_cents, effective_from, changed_by, created_at) VALUES (?, ?, 350, ?, NULL, ?)",
+        )
+        .bind(uuid::Uuid::new_v4())
+        .bind(tea_id)
+        .bind(due)
+        .bind(due)
+        .execute(app.db())
+        .await
+        .unwrap();
+        open_shift(&app).await;
+
+        let (_, sale) = app.post("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        assert_eq!(sale["sale_items"][0]["price_per_item"], 350, "{:?}: {}", app.backend(), sale);
+        let mut item = sale["sale_items"][0].clone();
+        item["sale_id"] = Value::Null;
+        let (_, single) = app.post("/api/sales", item).await;
+        assert_eq!(single["price_per_item"], 350, "{:?}: {}", app.backend(), single);
+
+        let (_, product) = app.get(&format!("/api/products/{}", tea_id)).await;
+        assert_eq!(product["price_cents"], 300, "{:?}", app.backend());
+        assert_eq!(crate::repo::product_prices::apply_due(app.db(), Utc::now()).await.unwrap(), 1, "{:?}", app.backend());
+        let (_, product) = app.get(&format!("/api/products/{}", tea_id)).await;
+        assert_eq!(product["price_cents"], 350, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn scheduled_prices_can_be_cancelled_until_due() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let prices_uri = format!("/api/products/{}/prices", tea["id"].as_str().unwrap());
+        let scheduled = app
+            .create(&prices_uri, json!({ "price_cents": 350, "effective_from": Utc::now() + chrono::Duration::days(1) }))
+            .await;
+        let now = app.create(&prices_uri, json!({ "price_cents": 320 })).await;
+
+        let (_, product) = app.get(&format!("/api/products/{}", tea["id"].as_str().unwrap())).await;
+        assert_eq!(product["price_cents"], 320, "{:?}", app.backend());
+        let (status, _) = app.delete(&format!("{}/{}", prices_uri, now["id"].as_str().unwrap())).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}", app.backend());
+        let (status, _) = app.delete(&format!("{}/{}", prices_uri, scheduled["id"].as_str().unwrap())).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn archived_staff_lose_access() {
+    for app in testing::apps().await {
+        let grace = create_staff(&app, "grace").await;
+        let grace_id = grace["id"].as_str().unwrap();
+        let token = testing::token_for(grace_id, 3600);
+        let (status, _) = app.call_as(&token, Method::GET, "/api/auth/profile", None).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", app.backend());
+
+        let (status, _) = app.delete(&format!("/api/staff/{}", grace_id)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT, "{:?}", app.backend());
+        let shift = json!({ "register": "Till 2", "company_branch": "", "opening_float_cents": 0 });
+        let (status, _) = app.call_as(&token, Method::POST, "/api/shifts", Some(shift)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", app.backend());
+        let (status, _) = app.call_as(&token, Method::GET, &format!("/api/live?token={}", token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", app.backend());
+        let stranger = testing::token_for(&uuid::Uuid::new_v4().to_string(), 3600);
+        let (status, _) = app.call_as(&stranger, Method::GET, "/api/auth/profile", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", app.backend());
+
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        open_shift(&app).await;
+        let mut sale = sale_input(&[(&tea, 1)]);
+        sale["staff_responsible"] = json!(grace_id);
+        let (status, body) = app.post("/api/sales_transactions", sale).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+        assert_eq!(body["details"][0]["field"], "staff_responsible", "{:?}", app.backend());
+
+        let (status, _) = app.post(&format!("/api/staff/{}/restore", grace_id), json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", app.backend());
+        let (status, _) = app.call_as(&token, Method::GET, "/api/auth/profile", None).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn archived_products_are_hidden_but_kept() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Green Tea", 300, 10).await;
+        let uri = format!("/api/products/{}", tea["id"].as_str().unwrap());
+        open_shift(&app).await;
+        let sale = app.create("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+
+        assert_eq!(app.delete(&uri).await.0, StatusCode::NO_CONTENT, "{:?}", app.backend());
+        let (_, listed) = app.get("/api/products").await;
+        assert_eq!(listed["total"], 0, "{:?}", app.backend());
+        let (_, listed) = app.get("/api/products?include_archived=true").await;
+        assert_eq!(listed["total"], 1, "{:?}", app.backend());
+        let (status, archived) = app.get(&uri).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", app.backend());
+        assert!(archived["archived_at"].is_string(), "{:?}: {}", app.backend(), archived);
+
+        let (status, body) = app.post("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+        let (_, sale) = app.get(&format!("/api/sales_transactions/{}", sale["id"].as_str().unwrap())).await;
+        assert_eq!(sale["sale_items"][0]["product_name"], "Green Tea", "{:?}: {}", app.backend(), sale);
+
+        assert_eq!(app.post(&format!("{}/restore", uri), json!({})).await.0, StatusCode::OK, "{:?}", app.backend());
+        let (_, listed) = app.get("/api/products").await;
+        assert_eq!(listed["total"], 1, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn offline_sales_sync_with_conflicts_resolved() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 250, 10).await;
+        let cake = create_product(&app, "Cake", 400, 10).await;
+        app.delete(&format!("/api/products/{}", cake["id"].as_str().unwrap())).await;
+        let missing = Uuid::new_v4();
+
+        let mut resolved = sale_input(&[(&tea, 1), (&cake, 1)]);
+        resolved["customer_id"] = json!(missing);
+        resolved["sale_items"][0]["total_cents"] = json!(200);
+        let mut rejected = sale_input(&[(&tea, 1)]);
+        rejected["sale_items"][0]["product_id"] = json!(missing);
+        let mut unknown_seller = sale_input(&[(&tea, 1)]);
+        unknown_seller["staff_responsible"] = json!(missing);
+        let grace = create_staff(&app, "grace").await;
+        app.delete(&format!("/api/staff/{}", grace["id"].as_str().unwrap())).await;
+        let mut archived_seller = sale_input(&[(&tea, 1)]);
+        archived_seller["staff_responsible"] = grace["id"].clone();
+        let uploads = json!({ "sales": [
+            { "client_id": Uuid::new_v4(), "sale": resolved },
+            { "client_id": Uuid::new_v4(), "sale": rejected },
+            { "client_id": Uuid::new_v4(), "sale": unknown_seller },
+            { "client_id": Uuid::new_v4(), "sale": archived_seller },
+        ]});
+
+        let (status, first) = app.post("/api/sync/sales", uploads.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), first);
+        let [resolved, rejected] = [&first["results"][0], &first["results"][1]];
+        assert_eq!(resolved["status"], "accepted", "{:?}: {}", app.backend(), first);
+        let kinds: Vec<&str> =
+            resolved["conflicts"].as_array().unwrap().iter().map(|c| c["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["customer_missing", "price_changed", "product_archived"], "{:?}", app.backend());
+        let price = &resolved["conflicts"][1];
+        assert_eq!((&price["client_price_cents"], &price["server_price_cents"]), (&json!(200), &json!(250)));
+        assert_eq!(rejected["status"], "rejected", "{:?}: {}", app.backend(), first);
+        assert_eq!(rejected["conflicts"][0]["kind"], "product_missing", "{:?}", app.backend());
+        for (result, kind, seller) in [
+            (&first["results"][2], "staff_missing", json!(missing)),
+            (&first["results"][3], "staff_archived", grace["id"].clone()),
+        ] {
+            assert_eq!(result["status"], "rejected", "{:?}: {}", app.backend(), result);
+            assert_eq!((&result["conflicts"][0]["kind"], &result["conflicts"][0]["record_id"]), (&json!(kind), &seller));
+            let uri = format!("/api/sales_transactions/{}", result["client_id"].as_str().unwrap());
+            assert_eq!(app.get(&uri).await.0, StatusCode::NOT_FOUND, "{:?}", app.backend());
+        }
+
+        let (_, sale) = app.get(&format!("/api/sales_transactions/{}", resolved["client_id"].as_str().unwrap())).await;
+        assert_eq!(sale["customer_id"], Value::Null, "{:?}: {}", app.backend(), sale);
+        assert_eq!(sale["sale_items"][0]["total_cents"], 200, "{:?}", app.backend());
+        assert_eq!(sale["sale_items"][0]["price_per_item"], 250, "{:?}", app.backend());
+        assert_eq!(stock_of(&app, &tea).await, 9, "{:?}", app.backend());
+
+        let (_, again) = app.post("/api/sync/sales", uploads).await;
+        assert_eq!(again["results"][0]["status"], "duplicate", "{:?}: {}", app.backend(), again);
+        assert_eq!(again["results"][1]["status"], "rejected", "{:?}", app.backend());
+        assert_eq!(stock_of(&app, &tea).await, 9, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn live_stream_ends_when_the_token_expires() {
+    for app in testing::apps().await {
+        let live = |token: &str| {
+            let request = Request::builder().uri(format!("/api/live?token={}", token)).body(Body::empty()).unwrap();
+            app.send(request)
+        };
+        assert_eq!(live("not-a-token").await.status(), StatusCode::UNAUTHORIZED, "{:?}", app.backend());
+
+        let response = live(&testing::token_for(ADMIN_ID, 1)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{:?}", app.backend());
+        let body = tokio::time::timeout(Duration::from_secs(10), testing::text(response))
+            .await
+            .unwrap_or_else(|_| panic!("the stream outlived its token on {:?}", app.backend()))
+            .1;
+        assert!(body.starts_with("event: stats\n"), "{:?}: {}", app.backend(), body);
+
+        app.finish().await;
+    }
+}
+
+/// The heatmap cells for January 2026 that have sales, as (weekday, hour,
+/// count, total).
+async fn heatmap(app: &testing::TestApp, query: &str) -> Vec<(i64, i64, i64, i64)> {
+    let (status, cells) = app.get(&format!("/api/sales/stats/heatmap?start_date=2026-01-01&end_date=2026-01-31{}", query)).await;
+    assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), cells);
+    let cells = cells.as_array().unwrap();
+    assert_eq!(cells.len(), 168, "{:?}", app.backend());
+    cells
+        .iter()
+        .filter(|cell| cell["count"] != 0)
+        .map(|cell| {
+            let field = |name: &str| cell[name].as_i64().unwrap();
+            (field("weekday"), field("hour"), field("count"), field("total_sales_cents"))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn heatmap_buckets_sales_by_local_weekday_and_hour() {
+    for app in testing::apps_in(chrono_tz::America::New_York).await {
+        let tea = create_product(&app, "Tea", 250, 100).await;
+        open_shift(&app).await;
+        // Monday 02:30 UTC is still Sunday evening in New York
+        for (at, quantity, channel) in [
+            ("2026-01-05T02:30:00Z", 1, "mobile"),
+            ("2026-01-05T02:45:00Z", 2, "web"),
+            ("2026-01-05T15:00:00Z", 4, "mobile"),
+        ] {
+            let mut sale = sale_input(&[(&tea, quantity)]);
+            sale["date_and_time"] = json!(at);
+            sale["sale_items"][0]["date_of_sale"] = json!(at);
+            sale["sales_channel"] = json!(channel);
+            app.create("/api/sales_transactions", sale).await;
+        }
+
+        assert_eq!(heatmap(&app, "").await, [(0, 10, 1, 1000), (6, 21, 2, 750)], "{:?}", app.backend());
+        assert_eq!(heatmap(&app, "&sales_channel=mobile").await, [(0, 10, 1, 1000), (6, 21, 1, 250)], "{:?}", app.backend());
+
+        // Exports label the hours as local too
+        let request = Request::builder()
+            .uri("/api/sales/stats/heatmap?start_date=2026-01-01&end_date=2026-01-31&format=csv")
+            .header(header::AUTHORIZATION, format!("Bearer {}", testing::token_for(ADMIN_ID, 60)))
+            .body(Body::empty())
+            .unwrap();
+        let (_, csv) = testing::text(app.send(request).await).await;
+        assert!(csv.starts_with("Weekday,Hour,Total Sales,Transactions\r\n"), "{}", csv);
+        assert!(csv.contains("\r\nSunday,21,7.50,2\r\n"), "{:?}: {}", app.backend(), csv);
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn staff_performance_matches_the_leaderboard() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 250, 100).await;
+        let bea = create_staff(&app, "bea").await;
+        open_shift(&app).await;
+        app.create("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        let mut sale = sale_input(&[(&tea, 2)]);
+        sale["staff_responsible"] = bea["id"].clone();
+        app.create("/api/sales_transactions", sale).await;
+
+        let (_, board) = app.get("/api/sales/stats/by_staff").await;
+        let ranked: Vec<(&Value, &Value)> =
+            board.as_array().unwrap().iter().map(|entry| (&entry["staff_id"], &entry["rank"])).collect();
+        assert_eq!(ranked[..2], [(&bea["id"], &json!(1)), (&json!(ADMIN_ID), &json!(2))], "{:?}: {}", app.backend(), board);
+
+        let performance = |id: &str| format!("/api/sales/stats/by_staff/{}", id);
+        let (status, detail) = app.get(&performance(bea["id"].as_str().unwrap())).await;
+        assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), detail);
+        assert_eq!(detail["performance"], board[0], "{:?}", app.backend());
+        assert_eq!(detail["performance"]["average_basket_cents"], 500, "{:?}", app.backend());
+        assert_eq!(detail["products"][0]["total_quantity"], 2, "{:?}: {}", app.backend(), detail);
+        let (_, detail) = app.get(&performance(ADMIN_ID)).await;
+        assert_eq!(detail["performance"], board[1], "{:?}", app.backend());
+        assert_eq!(app.get(&performance(&Uuid::new_v4().to_string())).await.0, StatusCode::NOT_FOUND, "{:?}", app.backend());
+
+        // Sales must name who rang them up
+        let mut sale = sale_input(&[(&tea, 1)]);
+        sale["staff_responsible"] = json!(Uuid::nil());
+        let (status, body) = app.post("/api/sales_transactions", sale).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+        assert_eq!(body["details"][0]["field"], "staff_responsible", "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn edited_sale_items_keep_their_snapshot_unless_the_product_changes() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 300, 10).await;
+        let coffee = create_product(&app, "Coffee", 400, 10).await;
+        open_shift(&app).await;
+        let sale = app.create("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+
+        let tea_uri = format!("/api/products/{}", tea["id"].as_str().unwrap());
+        let mut renamed = tea.clone();
+        renamed["name"] = json!("Green Tea");
+        renamed["price_cents"] = json!(350);
+        renamed["cost_price_cents"] = json!(200);
+        let (status, body) = app.put(&tea_uri, renamed).await;
+        assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), body);
+
+        let mut item = sale["sale_items"][0].clone();
+        let item_uri = format!("/api/sales/{}", item["id"].as_str().unwrap());
+        item["quantity"] = json!(2);
+        let (status, edited) = app.put(&item_uri, item.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), edited);
+        let snapshot = |item: &Value| (item["product_name"].clone(), item["price_per_item"].clone(), item["cost_per_item"].clone());
+        assert_eq!(snapshot(&edited), (json!("Tea"), json!(300), json!(150)), "{:?}", app.backend());
+        assert_eq!(edited["quantity"], 2, "{:?}", app.backend());
+
+        item["product_id"] = coffee["id"].clone();
+        let (_, edited) = app.put(&item_uri, item).await;
+        assert_eq!(snapshot(&edited), (json!("Coffee"), json!(400), json!(200)), "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn comparisons_match_products_and_days() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 100, 100).await;
+        let other_tea = create_product(&app, "Tea", 100, 100).await;
+        open_shift(&app).await;
+        for (at, product, quantity) in [
+            ("2026-01-10T12:00:00Z", &tea, 1),
+            ("2026-02-10T12:00:00Z", &tea, 3),
+            ("2026-02-11T12:00:00Z", &other_tea, 2),
+        ] {
+            let mut sale = sale_input(&[(product, quantity)]);
+            sale["date_and_time"] = json!(at);
+            sale["sale_items"][0]["date_of_sale"] = json!(at);
+            app.create("/api/sales_transactions", sale).await;
+        }
+
+        let february = "start_date=2026-02-01&end_date=2026-02-28";
+        let (_, products) =
+            app.get(&format!("/api/sales/stats/by_product?{}&compare=custom&compare_start_date=2026-01-01&compare_end_date=2026-01-31", february)).await;
+        let rows: Vec<(&Value, &Value)> =
+            products.as_array().unwrap().iter().map(|row| (&row["total_quantity"], &row["quantity_change"]["previous"])).collect();
+        assert_eq!(rows, [(&json!(3), &json!(1)), (&json!(2), &json!(0))], "{:?}: {}", app.backend(), products);
+
+        for (query, field) in [
+            ("compare=custom&compare_start_date=2026-01-31&compare_end_date=2026-01-01", "compare_end_date"),
+            ("compare=custom&compare_start_date=2000-01-01&compare_end_date=2026-01-01", "compare_end_date"),
+            ("compare=custom&compare_start_date=2026-01-01", "compare_end_date"),
+        ] {
+            let (status, body) = app.get(&format!("/api/sales/stats/totals?{}&{}", february, query)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{} on {:?}: {}", query, app.backend(), body);
+            assert_eq!(body["details"][0]["field"], field, "{:?}", app.backend());
+        }
+
+        // Daily comparisons pair each day with one day of the other period
+        let (status, body) =
+            app.get(&format!("/api/sales/stats/daily?{}&compare=custom&compare_start_date=2026-01-01&compare_end_date=2026-01-31", february)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+        let (_, days) =
+            app.get(&format!("/api/sales/stats/daily?{}&compare=custom&compare_start_date=2026-01-01&compare_end_date=2026-01-28", february)).await;
+        let days = days.as_array().unwrap();
+        assert_eq!(days.len(), 28, "{:?}", app.backend());
+        assert_eq!((&days[9]["compared_date"], &days[9]["count_change"]["previous"]), (&json!("2026-01-10"), &json!(1)));
+        let (_, days) = app.get("/api/sales/stats/daily?start_date=2028-02-01&end_date=2028-02-29&compare=last_year").await;
+        let compared: Vec<&Value> = days.as_array().unwrap().iter().map(|day| &day["compared_date"]).collect();
+        assert_eq!((compared.len(), compared[28]), (29, &json!("2027-03-01")), "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn customer_stats_rank_against_every_buyer() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 100, 100).await;
+        open_shift(&app).await;
+        let mut customers = Vec::new();
+        for (name, purchases) in [
+            ("Ana", &[("2026-03-01T09:30:00.750Z", 4), ("2026-02-01T09:00:00Z", 1), ("2026-01-01T09:00:00Z", 1)][..]),
+            ("Ben", &[("2025-06-01T09:00:00Z", 1)][..]),
+            ("Cal", &[("2026-01-15T09:00:00Z", 2), ("2025-12-01T09:00:00Z", 2)][..]),
+            ("Dee", &[("2026-03-01T09:30:00.250Z", 1)][..]),
+            ("Eve", &[][..]),
+        ] {
+            let customer = create_customer(&app, name).await;
+            for (at, quantity) in purchases {
+                let mut sale = sale_input(&[(&tea, *quantity)]);
+                sale["customer_id"] = customer["id"].clone();
+                sale["date_and_time"] = json!(at);
+                app.create("/api/sales_transactions", sale).await;
+            }
+            customers.push(customer);
+        }
+
+        let (_, report) = app.get("/api/customers/segments").await;
+        let ranked = report["customers"]["items"].as_array().unwrap();
+        assert_eq!(ranked.len(), 4, "{:?}: {}", app.backend(), report);
+        for customer in &customers {
+            let (status, stats) = app.get(&format!("/api/customers/{}/stats", customer["id"].as_str().unwrap())).await;
+            assert_eq!(status, StatusCode::OK, "{:?}: {}", app.backend(), stats);
+            match ranked.iter().find(|entry| entry["customer_id"] == customer["id"]) {
+                Some(entry) => assert_eq!(&stats, entry, "{:?}", app.backend()),
+                None => assert_eq!((&stats["visit_count"], &stats["rfm"]), (&json!(0), &Value::Null), "{:?}", app.backend()),
+            }
+        }
+        let (_, ana) = app.get(&format!("/api/customers/{}/stats", customers[0]["id"].as_str().unwrap())).await;
+        // Recency counts whole seconds, so Ana and Dee bought equally recently
+        assert_eq!(ana["rfm"], json!({ "recency": 4, "frequency": 5, "monetary": 5 }), "{:?}", app.backend());
+        assert_eq!(ana["segment"], "champions", "{:?}", app.backend());
+        let (_, ben) = app.get(&format!("/api/customers/{}/stats", customers[1]["id"].as_str().unwrap())).await;
+        assert_eq!(ben["rfm"], json!({ "recency": 1, "frequency": 2, "monetary": 2 }), "{:?}", app.backend());
+        let unknown = format!("/api/customers/{}/stats", Uuid::new_v4());
+        assert_eq!(app.get(&unknown).await.0, StatusCode::NOT_FOUND, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn in_store_sales_need_an_open_shift() {
+    for app in testing::apps().await {
+        let tea = create_product(&app, "Tea", 250, 100).await;
+        let (status, body) = app.post("/api/sales_transactions", sale_input(&[(&tea, 1)])).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}: {}", app.backend(), body);
+        let loose_item = sale_input(&[(&tea, 1)])["sale_items"][0].clone();
+        assert_eq!(app.post("/api/sales", loose_item.clone()).await.0, StatusCode::CONFLICT, "{:?}", app.backend());
+
+        // Web orders are taken without a till
+        let mut web = sale_input(&[(&tea, 1)]);
+        web["sales_channel"] = json!("web");
+        let web = app.create("/api/sales_transactions", web).await;
+        assert_eq!(web["shift_id"], Value::Null, "{:?}", app.backend());
+        let mut web_item = loose_item.clone();
+        web_item["sale_id"] = web["id"].clone();
+        app.create("/api/sales", web_item).await;
+        let mut web_item = loose_item.clone();
+        web_item["sales_channel"] = json!("web");
+        let web_item = app.create("/api/sales", web_item).await;
+        let (_, web) = app.get(&format!("/api/sales_transactions/{}", web_item["sale_id"].as_str().unwrap())).await;
+        assert_eq!((&web["sales_channel"], &web["shift_id"]), (&json!("web"), &Value::Null), "{:?}", app.backend());
+
+        let shift = open_shift(&app).await;
+        let sale = app.create("/api/sales_transactions", sale_input(&[(&tea, 2)])).await;
+        assert_eq!(sale["shift_id"], shift["id"], "{:?}", app.backend());
+        let mut live = app.state.live.subscribe();
+        let item = app.create("/api/sales", loose_item.clone()).await;
+        assert!(item["sale_id"].is_string() && item["sale_id"] != sale["id"], "{:?}: {}", app.backend(), item);
+
+        // A loose item is a sale like any other: by whoever recorded it, in
+        // their shift, announced live and to webhooks as a whole `Sale`
+        let (_, own) = app.get(&format!("/api/sales_transactions/{}", item["sale_id"].as_str().unwrap())).await;
+        assert_eq!(
+            (&own["staff_responsible"], &own["sales_channel"], &own["shift_id"], &own["sale_items"][0]["id"]),
+            (&json!(ADMIN_ID), &json!("mobile"), &shift["id"], &item["id"]),
+            "{:?}",
+            app.backend()
+        );
+        let announced = tokio::time::timeout(Duration::from_secs(5), live.recv()).await.unwrap().unwrap();
+        assert!(
+            matches!(&announced, crate::live::LiveUpdate::Sale(live_sale) if json!(live_sale.id) == item["sale_id"]),
+            "{:?}",
+            app.backend()
+        );
+        let payloads: Vec<String> = crate::db::query_scalar("SELECT payload FROM webhook_events WHERE event_type = 'sale.created'")
+            .fetch_all(app.db())
+            .await
+            .unwrap();
+        assert_eq!(payloads.len(), 4, "{:?}", app.backend());
+        for payload in payloads {
+            let event: Value = serde_json::from_str(&payload).unwrap();
+            assert!(event["data"]["sale_items"].is_array(), "{:?}: {}", app.backend(), event);
+        }
+        let (_, report) = app.get(&format!("/api/shifts/{}/report", shift["id"].as_str().unwrap())).await;
+        assert_eq!((&report["sales_count"], &report["gross_sales_cents"]), (&json!(2), &json!(750)), "{:?}: {}", app.backend(), report);
+
+        let close_uri = format!("/api/shifts/{}/close", shift["id"].as_str().unwrap());
+        app.post(&close_uri, json!({ "counted_cash_cents": 750, "note": null })).await;
+        let mut late_item = loose_item;
+        late_item["sale_id"] = sale["id"].clone();
+        let (status, body) = app.post("/api/sales", late_item).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}: {}", app.backend(), body);
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn staff_accounts_are_managed_by_admins() {
+    for app in testing::apps().await {
+        let grace = create_staff(&app, "grace").await;
+        let grace_id = grace["id"].as_str().unwrap();
+        let token = testing::token_for(grace_id, 3600);
+        let input = |username: &str| {
+            json!({
+                "id": null,
+                "first_name": username,
+                "last_name": "Tester",
+                "mobile_number": "0123456789",
+                "photo_link": "",
+                "staff_id": format!("staff-{}", username),
+                "username": username,
+                "password": "taken-over",
+            })
+        };
+        let admin = format!("/api/staff/{}", ADMIN_ID);
+        for (method, uri, body) in [
+            (Method::PUT, admin.clone(), Some(input("admin"))),
+            (Method::DELETE, admin.clone(), None),
+            (Method::POST, format!("{}/restore", admin), None),
+            (Method::POST, "/api/staff".to_string(), Some(input("mallory"))),
+            (Method::PUT, format!("/api/admin/staff/{}/role", grace_id), Some(json!({ "role": "admin" }))),
+        ] {
+            let (status, body) = app.call_as(&token, method.clone(), &uri, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {} on {:?}: {}", method, uri, app.backend(), body);
+        }
+        let (status, updated) = app.call_as(&token, Method::PUT, &format!("/api/staff/{}", grace_id), Some(input("grace"))).await;
+        assert_eq!((status, &updated["role"]), (StatusCode::OK, &json!("staff")), "{:?}: {}", app.backend(), updated);
+
+        // The seeded admin is the only one, so can neither leave nor step down
+        let (status, body) = app.delete(&admin).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}: {}", app.backend(), body);
+        let (status, body) = app.put(&format!("/api/admin/staff/{}/role", ADMIN_ID), json!({ "role": "staff" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}: {}", app.backend(), body);
+
+        let (status, promoted) = app.put(&format!("/api/admin/staff/{}/role", grace_id), json!({ "role": "admin" })).await;
+        assert_eq!((status, &promoted["role"]), (StatusCode::OK, &json!("admin")), "{:?}: {}", app.backend(), promoted);
+        let (status, demoted) = app.put(&format!("/api/admin/staff/{}/role", ADMIN_ID), json!({ "role": "staff" })).await;
+        assert_eq!((status, &demoted["role"]), (StatusCode::OK, &json!("staff")), "{:?}: {}", app.backend(), demoted);
+        assert_eq!(app.get("/api/admin/integrity").await.0, StatusCode::FORBIDDEN, "{:?}", app.backend());
+        let (status, _) = app.call_as(&token, Method::GET, "/api/admin/integrity", None).await;
+        assert_ne!(status, StatusCode::FORBIDDEN, "{:?}", app.backend());
+        let (status, _) = app.call_as(&token, Method::DELETE, &format!("/api/staff/{}", grace_id), None).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
+
+#[tokio::test]
+async fn admin_endpoints_need_the_admin_role() {
+    for app in testing::apps().await {
+        let grace = create_staff(&app, "grace").await;
+        let token = testing::token_for(grace["id"].as_str().unwrap(), 3600);
+        for (method, uri) in [
+            (Method::GET, "/api/admin/backups"),
+            (Method::POST, "/api/admin/backups"),
+            (Method::GET, "/api/admin/backups/fastsales-20260101T000000000Z.db"),
+            (Method::GET, "/api/admin/integrity"),
+        ] {
+            let (status, body) = app.call_as(&token, method.clone(), uri, None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {} on {:?}: {}", method, uri, app.backend(), body);
+            assert_eq!(body["code"], "forbidden", "{:?}", app.backend());
+        }
+        assert_eq!(app.call_as("", Method::GET, "/api/admin/integrity", None).await.0, StatusCode::UNAUTHORIZED);
+
+        let (status, backup) = app.post("/api/admin/backups", json!({})).await;
+        if app.backend() == crate::db::Backend::Postgres {
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", backup);
+        } else {
+            assert_eq!(status, StatusCode::CREATED, "{}", backup);
+            let request = Request::builder()
+                .uri(format!("/api/admin/backups/{}", backup["file_name"].as_str().unwrap()))
+                .header(header::AUTHORIZATION, format!("Bearer {}", testing::token_for(ADMIN_ID, 60)))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.send(request).await;
+            let length: usize = response.headers()[header::CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(bytes.len(), length);
+            assert!(bytes.starts_with(b"SQLite format 3\0"));
+        }
+        let (status, _) = app.get("/api/admin/backups/fastsales-20260101T000000000Z.db").await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{:?}", app.backend());
+
+        app.finish().await;
+    }
+}
@@ -0,0 +1,233 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderValue, Method, Request, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::AppState;
+use crate::auth::Claims;
+use crate::db;
+use crate::error::AppError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Set on responses that were replayed from an earlier request.
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// How long a stored response can be replayed.
+const RETENTION_HOURS: i64 = 24;
+
+/// Makes POST requests carrying an `Idempotency-Key` header safe to retry:
+/// the first response is stored and replayed for retries with the same key
+/// and body, and reusing a key for a different request is rejected with 422.
+/// Server errors are not stored so the request can be retried.
+///
+/// Must run inside `auth_middleware`, since keys are scoped per staff member.
+pub async fn idempotency_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    if request.method() != Method::POST {
+        return Ok(next.run(request).await);
+    }
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| {
+            AppError::invalid_field(
+                "Idempotency-Key",
+                format!("must be 1 to {} visible ASCII characters", MAX_KEY_LENGTH),
+            )
+        })?
+        .to_string();
+    let staff_id = request
+        .extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or(AppError::Unauthorized)?;
+
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| AppError::BadRequest("Request body is too large".to_string()))?;
+    let request_hash = request_hash(&parts.method, &parts.uri, &body);
+
+    let now = Utc::now();
+    db::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+        .bind(now - Duration::hours(RETENTION_HOURS))
+        .execute(&state.db)
+        .await?;
+
+    let claimed = db::query(
+        "INSERT INTO idempotency_keys (staff_id, idempotency_key, request_hash, created_at) VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING",
+    )
+    .bind(&staff_id)
+    .bind(&key)
+    .bind(&request_hash)
+    .bind(now)
+    .execute(&state.db)
+    .await?
+        > 0;
+
+    if !claimed {
+        return replay(&state, &staff_id, &key, &request_hash).await;
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            forget(&state, &staff_id, &key).await;
+            return Err(AppError::internal(err));
+        }
+    };
+
+    if parts.status.is_server_error() {
+        forget(&state, &staff_id, &key).await;
+    } else {
+        let content_type = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        let stored = db::query(
+            "UPDATE idempotency_keys SET status_code = ?, content_type = ?, response_body = ? WHERE staff_id = ? AND idempotency_key = ?",
+        )
+        .bind(parts.status.as_u16())
+        .bind(content_type)
+        .bind(body.as_ref())
+        .bind(&staff_id)
+        .bind(&key)
+        .execute(&state.db)
+        .await;
+        // The request itself succeeded; failing it now would invite a
+        // retry that runs it twice.
+        if let Err(err) = stored {
+            tracing::error!("Failed to store idempotent response: {}", err);
+        }
+    }
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+async fn replay(
+    state: &AppState,
+    staff_id: &str,
+    key: &str,
+    request_hash: &str,
+) -> Result<Response, AppError> {
+    let row = db::query(
+        "SELECT request_hash, status_code, content_type, response_body FROM idempotency_keys WHERE staff_id = ? AND idempotency_key = ?",
+    )
+    .bind(staff_id)
+    .bind(key)
+    .fetch_one(&state.db)
+    .await?;
+
+    let stored_hash: String = row.get("request_hash");
+    if stored_hash != request_hash {
+        return Err(AppError::invalid_field(
+            "Idempotency-Key",
+            "was already used for a different request",
+        ));
+    }
+
+    let Some(status) = row.get::<Option<u16>, _>("status_code") else {
+        return Err(AppError::Conflict(
+            "A request with this Idempotency-Key is still being processed".to_string(),
+            vec![],
+        ));
+    };
+    let status = StatusCode::from_u16(status).map_err(AppError::internal)?;
+    let content_type: Option<String> = row.get("content_type");
+    let body: Vec<u8> = row.get::<Option<Vec<u8>>, _>("response_body").unwrap_or_default();
+
+    let mut response = Response::new(Body::from(Bytes::from(body)));
+    *response.status_mut() = status;
+    if let Some(content_type) = content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response
+        .headers_mut()
+        .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    Ok(response)
+}
+
+/// Releases a key whose request did not produce a replayable response.
+async fn forget(state: &AppState, staff_id: &str, key: &str) {
+    let deleted = db::query("DELETE FROM idempotency_keys WHERE staff_id = ? AND idempotency_key = ?")
+        .bind(staff_id)
+        .bind(key)
+        .execute(&state.db)
+        .await;
+    if let Err(err) = deleted {
+        tracing::error!("Failed to release idempotency key: {}", err);
+    }
+}
+
+fn request_hash(method: &Method, uri: &axum::http::Uri, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b"\n");
+    hasher.update(uri.path_and_query().map(|p| p.as_str()).unwrap_or_default());
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, ADMIN_ID, TestApp, create_product, open_shift, sale_input, stock_of};
+    use serde_json::Value;
+
+    async fn post_with_key(app: &TestApp, key: &str, body: &Value) -> (StatusCode, bool, String) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/sales_transactions")
+            .header(header::AUTHORIZATION, format!("Bearer {}", testing::token_for(ADMIN_ID, 3600)))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(IDEMPOTENCY_KEY_HEADER, key)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.send(request).await;
+        let replayed = response.headers().contains_key(REPLAYED_HEADER);
+        let (status, body) = testing::text(response).await;
+        (status, replayed, body)
+    }
+
+    #[tokio::test]
+    async fn retries_replay_the_first_response() {
+        for app in testing::apps().await {
+            let tea = create_product(&app, "Tea", 250, 10).await;
+            open_shift(&app).await;
+            let sale = sale_input(&[(&tea, 2)]);
+
+            let (status, replayed, first) = post_with_key(&app, "retry-1", &sale).await;
+            assert_eq!((status, replayed), (StatusCode::CREATED, false), "{:?}: {}", app.backend(), first);
+            let (status, replayed, retry) = post_with_key(&app, "retry-1", &sale).await;
+            assert_eq!((status, replayed), (StatusCode::CREATED, true), "{:?}: {}", app.backend(), retry);
+            assert_eq!(first, retry, "{:?}", app.backend());
+            assert_eq!(stock_of(&app, &tea).await, 8, "{:?}", app.backend());
+            let (_, listed) = app.get("/api/sales_transactions").await;
+            assert_eq!(listed["total"], 1, "{:?}", app.backend());
+
+            let (status, _, body) = post_with_key(&app, "retry-1", &sale_input(&[(&tea, 3)])).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+            let (status, replayed, _) = post_with_key(&app, "retry-2", &sale).await;
+            assert_eq!((status, replayed), (StatusCode::CREATED, false), "{:?}", app.backend());
+            assert_eq!(stock_of(&app, &tea).await, 6, "{:?}", app.backend());
+            app.finish().await;
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Check for rows that reference a missing parent, with an optional repair.
+//! Runs as `backend check-integrity [--repair]` and automatically before
+//! migrations, where orphans that would make migration `0012` fail stop the
+//! server from starting. PostgreSQL databases have had the foreign keys from
+//! the start, so they always come out clean.
+//!
+//! `check-integrity` and `GET /api/admin/integrity` also run SQLite's own
+//! consistency check of the database file, [`storage_errors`].
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::db::{self, Backend, Db};
+
+/// A reference from `table.column` to `references` that may point at a row
+/// which no longer exists.
+struct Reference {
+    table: &'static str,
+    column: &'static str,
+    references: &'static str,
+    /// What `--repair` does about orphans
+    repair: Repair,
+    /// Whether migration `0012` adds the foreign key, so that orphans make it
+    /// fail; the others have had theirs since the table was created
+    added_by_0012: bool,
+}
+
+enum Repair {
+    /// Clear the dangling id
+    SetNull,
+    /// Drop the orphaned rows; they mean nothing without their parent
+    Delete,
+    /// Recreate the missing products as archived placeholders from the
+    /// name and price snapshots on the sale items
+    RecreateProducts,
+}
+
+const REFERENCES: &[Reference] = &[
+    Reference {
+        table: "sale_items",
+        column: "sale_id",
+        references: "sales",
+        repair: Repair::SetNull,
+        added_by_0012: true,
+    },
+    Reference {
+        table: "sale_items",
+        column: "product_id",
+        references: "products",
+        repair: Repair::RecreateProducts,
+        added_by_0012: true,
+    },
+    Reference {
+        table: "sale_items",
+        column: "customer_id",
+        references: "customers",
+        repair: Repair::SetNull,
+        added_by_0012: true,
+    },
+    Reference {
+        table: "sales",
+        column: "customer_id",
+        references: "customers",
+        repair: Repair::SetNull,
+        added_by_0012: false,
+    },
+    Reference {
+        table: "product_details",
+        column: "product_id",
+        references: "products",
+        repair: Repair::Delete,
+        added_by_0012: false,
+    },
+    Reference {
+        table: "customer_details",
+        column: "customer_id",
+        references: "customers",
+        repair: Repair::Delete,
+        added_by_0012: false,
+    },
+];
+
+impl Reference {
+    fn orphan_filter(&self) -> String {
+        format!(
+            "{column} IS NOT NULL AND {column} NOT IN (SELECT id FROM {references})",
+            column = self.column,
+            references = self.references
+        )
+    }
+
+    fn describe(&self) -> String {
+        format!("{}.{} -> {}.id", self.table, self.column, self.references)
+    }
+}
+
+pub struct Finding {
+    reference: &'static Reference,
+    pub orphans: i64,
+}
+
+impl Finding {
+    pub fn describe(&self) -> String {
+        self.reference.describe()
+    }
+}
+
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.findings.iter().all(|finding| finding.orphans == 0)
+    }
+
+    /// Whether orphans would make migration `0012` fail.
+    pub fn blocks_migration(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.orphans > 0 && finding.reference.added_by_0012)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "  {:<45} {} orphaned",
+                finding.reference.describe(),
+                finding.orphans
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts orphaned rows for every reference whose tables exist, so it can
+/// run against a database at any migration level.
+pub async fn check(db: &Db) -> Result<Report, sqlx::Error> {
+    let mut findings = Vec::new();
+    for reference in REFERENCES {
+        if !table_exists(db, reference.table).await?
+            || !table_exists(db, reference.references).await?
+        {
+            continue;
+        }
+        let orphans: i64 = db::query_scalar(format!(
+            "SELECT COUNT(*) FROM {} WHERE {}",
+            reference.table,
+            reference.orphan_filter()
+        ))
+        .fetch_one(db)
+        .await?;
+        findings.push(Finding { reference, orphans });
+    }
+    Ok(Report { findings })
+}
+
+/// Repairs every orphan in one transaction and returns what was found
+/// beforehand.
+pub async fn repair(db: &Db) -> Result<Report, sqlx::Error> {
+    let report = check(db).await?;
+    let mut tx = db.begin().await?;
+    for finding in report.findings.iter().filter(|finding| finding.orphans > 0) {
+        let reference = finding.reference;
+        match reference.repair {
+            Repair::SetNull => {
+                db::query(format!(
+                    "UPDATE {table} SET {column} = NULL WHERE {filter}",
+                    table = reference.table,
+                    column = reference.column,
+                    filter = reference.orphan_filter()
+                ))
+                .execute(&mut tx)
+                .await?;
+            }
+            Repair::Delete => {
+                db::query(format!(
+                    "DELETE FROM {} WHERE {}",
+                    reference.table,
+                    reference.orphan_filter()
+                ))
+                .execute(&mut tx)
+                .await?;
+            }
+            Repair::RecreateProducts => {
+                let missing: Vec<Uuid> = db::query_scalar(format!(
+                    "SELECT DISTINCT product_id FROM sale_items WHERE {}",
+                    reference.orphan_filter()
+                ))
+                .fetch_all(&mut tx)
+                .await?;
+                db::query(format!(
+                    "INSERT INTO products (id, name, description, price_cents, stock, product_type)
+                     SELECT product_id,
+                            COALESCE(MAX(product_name), 'Deleted product'),
+                            'Recreated by integrity repair for sales of a deleted product',
+                            COALESCE(MAX(price_per_item), 0),
+                            0,
+                            'physical_good'
+                     FROM sale_items WHERE {}
+                     GROUP BY product_id",
+                    reference.orphan_filter()
+                ))
+                .execute(&mut tx)
+                .await?;
+
+                // Databases older than migration 0011 have no archive flag yet
+                let archivable: i64 = db::query_scalar(db.backend().column_exists("products"))
+                    .bind("archived_at")
+                    .fetch_one(&mut tx)
+                    .await?;
+                if archivable > 0 {
+                    for id in &missing {
+                        db::query("UPDATE products SET archived_at = ? WHERE id = ?")
+                            .bind(Utc::now())
+                            .bind(id)
+                            .execute(&mut tx)
+                            .await?;
+                    }
+                }
+            }
+        }
+    }
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// What SQLite's `PRAGMA integrity_check` finds wrong with the database
+/// file, such as corrupt pages or indexes out of step with their tables.
+/// Empty when the file is sound, and always on PostgreSQL.
+pub async fn storage_errors(db: &Db) -> Result<Vec<String>, sqlx::Error> {
+    if db.backend() != Backend::Sqlite {
+        return Ok(Vec::new());
+    }
+    let messages: Vec<String> = db::query_scalar("PRAGMA integrity_check")
+        .fetch_all(db)
+        .await?;
+    Ok(messages
+        .into_iter()
+        .filter(|message| message != "ok")
+        .collect())
+}
+
+/// Entry point for `backend check-integrity [--repair]`; returns the process
+/// exit code.
+pub async fn run_command(db: &Db, repair_orphans: bool) -> i32 {
+    match storage_errors(db).await {
+        Ok(errors) if errors.is_empty() => {}
+        Ok(errors) => {
+            println!("The database file is damaged:");
+            for error in errors {
+                println!("  {}", error);
+            }
+            println!("Restore it from a backup with `backend restore <file>`.");
+            return 1;
+        }
+        Err(err) => {
+            eprintln!("Integrity check failed: {}", err);
+            return 2;
+        }
+    }
+    let result = if repair_orphans {
+        repair(db).await
+    } else {
+        check(db).await
+    };
+    match result {
+        Ok(report) if report.is_clean() => {
+            println!("No orphaned rows found.");
+            print!("{}", report);
+            0
+        }
+        Ok(report) if repair_orphans => {
+            println!("Repaired orphaned rows:");
+            print!("{}", report);
+            0
+        }
+        Ok(report) => {
+            println!("Found orphaned rows:");
+            print!("{}", report);
+            println!("Run `backend check-integrity --repair` to fix them.");
+            1
+        }
+        Err(err) => {
+            eprintln!("Integrity check failed: {}", err);
+            2
+        }
+    }
+}
+
+async fn table_exists(db: &Db, table: &str) -> Result<bool, sqlx::Error> {
+    let count: i64 = db::query_scalar(db.backend().table_exists())
+        .bind(table)
+        .fetch_one(db)
+        .await?;
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, ADMIN_ID};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+
+    fn orphans(report: &Report) -> Vec<(String, i64)> {
+        report
+            .findings
+            .iter()
+            .map(|finding| (finding.describe(), finding.orphans))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn repair_fixes_every_kind_of_orphan() {
+        let db = testing::databases().await.remove(0);
+        let path: String =
+            db::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        // Orphans only get in with foreign keys off, as on databases from
+        // before they were enforced
+        let unchecked = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&path)
+                .foreign_keys(false),
+        )
+        .await
+        .unwrap();
+        let missing = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        for sql in [
+            "INSERT INTO sales (id, customer_id, date_and_time, total_cents, discount, total_resolved, sales_channel, staff_responsible, company_branch, car_number, receipt_number)
+             VALUES ('s1', ?1, ?2, 250, 0, 250, 'mobile', ?3, '', '', 'R-1')",
+            "INSERT INTO sale_items (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, product_name, price_per_item)
+             VALUES ('i1', 's1', ?1, ?1, ?2, 1, 0, 250, 250, 'Ghost Tea', 250)",
+            "INSERT INTO sale_items (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, product_name, price_per_item)
+             VALUES ('i2', ?1, ?1, NULL, ?2, 1, 0, 250, 250, 'Ghost Tea', 250)",
+            "INSERT INTO product_details (id, product_id, detail_name, detail_value) VALUES ('d1', 'gone-' || ?1, 'origin', 'nowhere')",
+            "INSERT INTO customer_details (id, customer_id, detail_name, detail_value) VALUES ('d2', ?1, 'note', 'gone')",
+        ] {
+            sqlx::query(sql).bind(&missing).bind(&now).bind(ADMIN_ID).execute(&unchecked).await.unwrap();
+        }
+        unchecked.close().await;
+
+        let expected = vec![
+            ("sale_items.sale_id -> sales.id".to_string(), 1),
+            ("sale_items.product_id -> products.id".to_string(), 2),
+            ("sale_items.customer_id -> customers.id".to_string(), 1),
+            ("sales.customer_id -> customers.id".to_string(), 1),
+            ("product_details.product_id -> products.id".to_string(), 1),
+            (
+                "customer_details.customer_id -> customers.id".to_string(),
+                1,
+            ),
+        ];
+        let report = check(&db).await.unwrap();
+        assert_eq!(orphans(&report), expected);
+        assert!(report.blocks_migration());
+
+        let repaired = repair(&db).await.unwrap();
+        assert_eq!(orphans(&repaired), expected);
+        let after = check(&db).await.unwrap();
+        assert!(after.is_clean(), "{}", after);
+
+        let (name, price, archived): (String, i64, bool) = db::query_as(
+            "SELECT name, price_cents, archived_at IS NOT NULL FROM products WHERE id = ?",
+        )
+        .bind(&missing)
+        .fetch_optional(&db)
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!((name.as_str(), price, archived), ("Ghost Tea", 250, true));
+        let details: i64 = db::query_scalar("SELECT COUNT(*) FROM product_details")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(details, 0);
+        let sale_id: Option<String> =
+            db::query_scalar("SELECT sale_id FROM sale_items WHERE id = 'i2'")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(sale_id, None);
+
+        testing::drop_database(db).await;
+    }
+
+    #[tokio::test]
+    async fn only_references_added_by_0012_block_migration() {
+        let db = testing::databases().await.remove(0);
+        let mut report = check(&db).await.unwrap();
+        assert!(report.is_clean() && !report.blocks_migration());
+        for finding in &mut report.findings {
+            finding.orphans = i64::from(finding.reference.table != "sale_items");
+        }
+        assert!(!report.is_clean() && !report.blocks_migration());
+        testing::drop_database(db).await;
+    }
+}
@@ -0,0 +1,63 @@
+//! Live updates for dashboards. Handlers publish new sales here and every
+//! client connected to `/api/live` receives them, followed by refreshed
+//! dashboard aggregates, as Server-Sent Events.
+
+use axum::response::sse::Event;
+use shared::models::{DashboardStats, LIVE_EVENT_SALE, LIVE_EVENT_STATS, Sale};
+use tokio::sync::broadcast;
+
+use crate::AppState;
+use crate::error::AppError;
+use crate::handlers::{today_sales, week_sales};
+
+/// Updates buffered per client; a client that falls further behind skips
+/// ahead and catches up with the next stats update.
+const CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Clone)]
+pub enum LiveUpdate {
+    Sale(Sale),
+    Stats(DashboardStats),
+}
+
+impl LiveUpdate {
+    pub fn into_event(self) -> Event {
+        let event = match &self {
+            LiveUpdate::Sale(sale) => Event::default().event(LIVE_EVENT_SALE).json_data(sale),
+            LiveUpdate::Stats(stats) => Event::default().event(LIVE_EVENT_STATS).json_data(stats),
+        };
+        event.unwrap_or_else(|err| Event::default().comment(format!("unserializable update: {}", err)))
+    }
+}
+
+pub fn channel() -> broadcast::Sender<LiveUpdate> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Announces committed sales, then the dashboard aggregates they changed.
+/// Pass no sales when sale items were edited or removed. Runs in the
+/// background so the request that made the change is not held up.
+pub fn publish_sales(state: &AppState, sales: Vec<Sale>) {
+    if state.live.receiver_count() == 0 {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        for sale in sales {
+            let _ = state.live.send(LiveUpdate::Sale(sale));
+        }
+        match dashboard_stats(&state).await {
+            Ok(stats) => {
+                let _ = state.live.send(LiveUpdate::Stats(stats));
+            }
+            Err(err) => tracing::error!("Refreshing live dashboard stats failed: {:?}", err),
+        }
+    });
+}
+
+pub async fn dashboard_stats(state: &AppState) -> Result<DashboardStats, AppError> {
+    Ok(DashboardStats {
+        today: today_sales(state).await?,
+        week: week_sales(state).await?,
+    })
+}
@@ -1,22 +1,42 @@
 use axum::{
     Router,
     middleware::from_fn_with_state,
-    routing::{get, post},
+    routing::{delete, get, post, put},
 };
 use axum::extract::FromRef;
+use axum::http::{HeaderName, HeaderValue, header};
+use clap::Parser;
 use leptos_config::{get_configuration, LeptosOptions};
 use leptos_axum::{generate_route_list, LeptosRoutes};
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use std::env;
-use std::path::Path;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+use tracing_subscriber::EnvFilter;
 use utoipa::{Modify, OpenApi};
 use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
 use utoipa_swagger_ui::SwaggerUi;
 
 mod handlers;
 mod auth;
+mod backup;
+mod config;
+mod db;
+mod error;
+mod idempotency;
+mod integrity;
+mod export;
+mod live;
+mod fts;
+mod webhooks;
+mod email_reports;
+mod timezone;
+mod repo;
+#[cfg(test)]
+mod testing;
 
 use handlers::{
     create_customer, create_product, create_sale, create_staff, delete_customer, delete_product,
@@ -24,29 +44,54 @@ use handlers::{
     list_products, list_sales, list_staff, update_customer, update_product, update_sale,
     update_staff, login, upload_file, get_profile, get_today_sales, get_weekly_sales_stats,
     get_top_products, create_sales_transaction, list_sales_transactions, get_sales_transaction,
-    get_sales_by_product, get_staff_transactions,
+    get_sales_by_product, get_staff_transactions, list_low_stock_products, list_stock_alerts,
+    acknowledge_stock_alert, search, get_product_price_history, create_product_price,
+    cancel_product_price, restore_product, restore_customer, restore_staff,
+    get_sync_changes, upload_sync_sales, list_webhooks, create_webhook, get_webhook, update_webhook,
+    delete_webhook, list_webhook_deliveries, retry_webhook_delivery, live_events,
+    get_sales_heatmap, get_staff_leaderboard, get_staff_performance, get_margin_report,
+    get_sales_totals, get_daily_sales, get_sales_series, get_customer_stats, get_customer_segments,
+    list_report_subscriptions, create_report_subscription, get_report_subscription,
+    update_report_subscription, delete_report_subscription, send_report_subscription,
+    preview_report_subscription, get_business_calendar, list_shifts, create_shift, get_current_shift,
+    get_shift, create_cash_movement, close_shift, get_shift_report, print_shift_report,
+    list_backups, create_backup, download_backup, get_integrity_report, set_staff_role,
 };
-use auth::auth_middleware;
-use sqlx::SqlitePool;
+use auth::{admin_middleware, auth_middleware};
+use idempotency::idempotency_middleware;
+use db::Db;
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        handlers::search,
         handlers::list_products,
         handlers::create_product,
         handlers::get_product,
         handlers::update_product,
         handlers::delete_product,
+        handlers::restore_product,
+        handlers::get_product_price_history,
+        handlers::create_product_price,
+        handlers::cancel_product_price,
+        handlers::list_low_stock_products,
+        handlers::list_stock_alerts,
+        handlers::acknowledge_stock_alert,
         handlers::list_customers,
+        handlers::get_customer_stats,
+        handlers::get_customer_segments,
         handlers::create_customer,
         handlers::get_customer,
         handlers::update_customer,
         handlers::delete_customer,
+        handlers::restore_customer,
         handlers::list_sales,
         handlers::create_sale,
         handlers::create_sales_transaction,
         handlers::list_sales_transactions,
         handlers::get_sales_transaction,
+        handlers::get_sync_changes,
+        handlers::upload_sync_sales,
         handlers::get_sale,
         handlers::update_sale,
         handlers::delete_sale,
@@ -55,11 +100,48 @@ use sqlx::SqlitePool;
         handlers::get_staff,
         handlers::update_staff,
         handlers::delete_staff,
+        handlers::restore_staff,
+        handlers::list_webhooks,
+        handlers::create_webhook,
+        handlers::get_webhook,
+        handlers::update_webhook,
+        handlers::delete_webhook,
+        handlers::list_webhook_deliveries,
+        handlers::retry_webhook_delivery,
+        handlers::list_report_subscriptions,
+        handlers::create_report_subscription,
+        handlers::get_report_subscription,
+        handlers::update_report_subscription,
+        handlers::delete_report_subscription,
+        handlers::send_report_subscription,
+        handlers::preview_report_subscription,
+        handlers::list_shifts,
+        handlers::create_shift,
+        handlers::get_current_shift,
+        handlers::get_shift,
+        handlers::create_cash_movement,
+        handlers::close_shift,
+        handlers::get_shift_report,
+        handlers::print_shift_report,
+        handlers::list_backups,
+        handlers::create_backup,
+        handlers::download_backup,
+        handlers::get_integrity_report,
+        handlers::set_staff_role,
         handlers::login,
+        handlers::get_business_calendar,
         handlers::get_today_sales,
         handlers::get_weekly_sales_stats,
+        handlers::get_sales_totals,
+        handlers::get_daily_sales,
+        handlers::get_sales_series,
+        handlers::live_events,
         handlers::get_top_products,
         handlers::get_sales_by_product,
+        handlers::get_margin_report,
+        handlers::get_sales_heatmap,
+        handlers::get_staff_leaderboard,
+        handlers::get_staff_performance,
         handlers::get_staff_transactions
     ),
     components(schemas(
@@ -68,6 +150,10 @@ use sqlx::SqlitePool;
         shared::models::ProductType,
         shared::models::ProductDetails,
         shared::models::ProductDetailsInput,
+        shared::models::ProductPrice,
+        shared::models::ProductPriceInput,
+        shared::models::ProductPriceHistory,
+        shared::models::StockAlert,
         shared::models::Customer,
         shared::models::CustomerInput,
         shared::models::SaleItem,
@@ -76,21 +162,91 @@ use sqlx::SqlitePool;
         shared::models::SaleInput,
         shared::models::Staff,
         shared::models::StaffInput,
+        shared::models::StaffRole,
+        shared::models::StaffRoleInput,
         shared::models::SalesItemsListResponse,
         shared::models::SalesStats,
         shared::models::DailySales,
+        shared::models::TimeBucket,
+        shared::models::WeekStart,
+        shared::models::SeriesBreakdown,
+        shared::models::SeriesPoint,
+        shared::models::SalesSeries,
+        shared::models::TimeSeries,
+        shared::models::DashboardStats,
+        shared::models::BusinessCalendar,
+        shared::models::ComparisonPeriod,
+        shared::models::Change,
+        shared::models::RfmScore,
+        shared::models::CustomerSegment,
+        shared::models::CustomerStats,
+        shared::models::SegmentSummary,
+        shared::models::CustomerSegmentReport,
+        export::ExportFormat,
         shared::models::TopProduct,
         shared::models::ProductSalesSummary,
+        shared::models::MarginGrouping,
+        shared::models::MarginLine,
+        shared::models::MarginReport,
+        shared::models::HeatmapCell,
+        shared::models::SalesChannel,
+        shared::models::StaffRanking,
+        shared::models::StaffPerformance,
+        shared::models::StaffPerformanceDetail,
+        shared::models::SearchHit,
+        shared::models::SearchKind,
+        shared::models::SyncChanges,
+        shared::models::SyncUpload,
+        shared::models::SyncSaleInput,
+        shared::models::SyncSaleStatus,
+        shared::models::SyncConflictKind,
+        shared::models::SyncConflict,
+        shared::models::SyncSaleResult,
+        shared::models::SyncUploadResult,
+        shared::models::Webhook,
+        shared::models::WebhookInput,
+        shared::models::WebhookEventType,
+        shared::models::WebhookDelivery,
+        shared::models::WebhookDeliveryStatus,
+        shared::models::ReportType,
+        shared::models::ReportFrequency,
+        shared::models::ReportParameters,
+        shared::models::ReportSubscription,
+        shared::models::ReportSubscriptionInput,
+        shared::models::PaymentMethod,
+        shared::models::Shift,
+        shared::models::ShiftInput,
+        shared::models::ShiftCloseInput,
+        shared::models::CashMovementKind,
+        shared::models::CashMovement,
+        shared::models::CashMovementInput,
+        shared::models::ShiftReportKind,
+        shared::models::TenderTotal,
+        shared::models::ChannelTotal,
+        shared::models::ShiftReport,
+        shared::models::Backup,
+        shared::models::OrphanCount,
+        shared::models::IntegrityReport,
+        shared::models::ApiError,
+        shared::models::ErrorCode,
+        shared::models::FieldError,
         handlers::AuthRequest,
         handlers::AuthResponse
     )),
     tags(
+        (name = "Search", description = "Full-text search"),
         (name = "Products", description = "Product CRUD"),
+        (name = "Inventory", description = "Stock levels & reorder alerts"),
         (name = "Customers", description = "Customer CRUD"),
         (name = "Sales", description = "Sales CRUD"),
+        (name = "Sync", description = "Offline sync for mobile devices"),
         (name = "Reports", description = "Sales Reports & Statistics"),
         (name = "Staff Transactions", description = "Transactions by Staff"),
         (name = "Staff", description = "Staff CRUD"),
+        (name = "Webhooks", description = "Event subscriptions & delivery log"),
+        (name = "Email Reports", description = "Reports emailed on a schedule"),
+        (name = "Shifts", description = "Till sessions, cash movements & X/Z reports"),
+        (name = "Admin", description = "Database backups & integrity"),
         (name = "Auth", description = "Authentication")
     ),
     modifiers(&SecurityAddon)
@@ -111,10 +267,16 @@ impl Modify for SecurityAddon {
 
 #[derive(Clone)]
 pub struct AppState {
-    pub db: SqlitePool,
+    pub db: Db,
     pub jwt_secret: String,
     pub password_pepper: String,
+    pub token_ttl_secs: u64,
+    pub upload_dir: PathBuf,
+    pub backup_dir: PathBuf,
     pub leptos_options: LeptosOptions,
+    pub live: tokio::sync::broadcast::Sender<live::LiveUpdate>,
+    pub mailer: email_reports::Mailer,
+    pub timezone: timezone::BusinessTz,
 }
 
 impl FromRef<AppState> for LeptosOptions {
@@ -123,8 +285,87 @@ impl FromRef<AppState> for LeptosOptions {
     }
 }
 
+/// Span for each request. Unlike tower-http's default it leaves out the
+/// query string, which for `/api/live` carries the caller's token.
+fn request_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        version = ?request.version(),
+    )
+}
+
+/// The SQLite database to open, by default `fastsales.db` in the working
+/// directory, or `None` for a PostgreSQL URL.
+fn sqlite_options(config: &config::DatabaseConfig) -> Option<SqliteConnectOptions> {
+    let Some(database_url) = &config.url else {
+        let db_path = std::env::current_dir()
+            .expect("current dir unavailable")
+            .join("fastsales.db");
+        tracing::info!("Using local sqlite db at {}", db_path.display());
+        return Some(SqliteConnectOptions::new().filename(db_path));
+    };
+    if db::is_postgres_url(database_url) {
+        return None;
+    }
+    let mut options = SqliteConnectOptions::from_str(database_url).expect("invalid database url");
+    let filename = options.get_filename();
+    if filename != Path::new(":memory:") && filename.extension().is_none() {
+        let mut with_ext = filename.to_path_buf();
+        with_ext.set_extension("db");
+        options = options.filename(with_ext);
+    }
+    tracing::info!("Using sqlite db at {}", options.get_filename().display());
+    Some(options)
+}
+
+/// Opens the configured database: PostgreSQL for a `postgres://` URL,
+/// otherwise SQLite.
+async fn connect_db(config: &config::DatabaseConfig) -> Db {
+    let db = match sqlite_options(config) {
+        Some(options) => Db::connect_sqlite(options, config.max_connections).await,
+        None => {
+            tracing::info!("Using postgres db");
+            let database_url = config.url.as_deref().expect("only a configured URL can be PostgreSQL");
+            Db::connect(database_url, config.max_connections).await
+        }
+    };
+    db.expect("database connection failed")
+}
+
 #[tokio::main]
 async fn main() {
+    let cli = config::Cli::parse();
+    let config = config::Config::load(&cli).unwrap_or_else(|err| {
+        eprintln!("Configuration error: {}", err);
+        std::process::exit(2);
+    });
+    let log_filter = EnvFilter::try_new(&config.server.log_level).unwrap_or_else(|err| {
+        eprintln!("Configuration error: invalid log level {:?}: {}", config.server.log_level, err);
+        std::process::exit(2);
+    });
+    tracing_subscriber::fmt().with_env_filter(log_filter).init();
+
+    match &cli.command {
+        Some(config::Command::CheckIntegrity { repair }) => {
+            let pool = connect_db(&config.database).await;
+            std::process::exit(integrity::run_command(&pool, *repair).await);
+        }
+        Some(config::Command::Backup) => {
+            let pool = connect_db(&config.database).await;
+            std::process::exit(backup::run_backup_command(&pool, &config.backup.dir).await);
+        }
+        Some(config::Command::Restore { file, check }) => {
+            let options = sqlite_options(&config.database);
+            std::process::exit(backup::run_restore_command(options, file, *check).await);
+        }
+        None => {}
+    }
+    if config.auth.password_pepper.is_empty() && !cfg!(debug_assertions) {
+        tracing::warn!("PASSWORD_PEPPER is not set; password hashes are not peppered");
+    }
+
     let conf = get_configuration(Some("Cargo.toml"))
         .await
         .expect("leptos configuration failed");
@@ -138,74 +379,141 @@ async fn main() {
         let doc = ApiDoc::openapi();
         let json = doc.to_pretty_json().expect("Failed to serialize OpenAPI doc");
         let path = std::path::Path::new("openapi.json");
-        let mut file = std::fs::File::create(&path).expect("Failed to create openapi.json");
+        let mut file = std::fs::File::create(path).expect("Failed to create openapi.json");
         file.write_all(json.as_bytes()).expect("Failed to write openapi.json");
-        println!("OpenAPI JSON written to {:?}", path.canonicalize().unwrap_or(path.to_path_buf()));
+        tracing::info!("OpenAPI JSON written to {:?}", path.canonicalize().unwrap_or(path.to_path_buf()));
     }
 
-    let pool = if let Ok(database_url) = env::var("DATABASE_URL") {
-        let mut options = SqliteConnectOptions::from_str(&database_url)
-            .expect("invalid database url")
-            .create_if_missing(true);
-        let filename = options.get_filename();
-        if filename != Path::new(":memory:") && filename.extension().is_none() {
-            let mut with_ext = filename.to_path_buf();
-            with_ext.set_extension("db");
-            options = options.filename(with_ext);
+    let pool = connect_db(&config.database).await;
+
+    // Migration 0012 adds foreign keys that orphaned rows would violate
+    match integrity::check(&pool).await {
+        Ok(report) if report.blocks_migration() => {
+            tracing::error!("Refusing to migrate, found orphaned rows:\n{}", report);
+            tracing::error!("Run `backend check-integrity --repair` to fix them.");
+            std::process::exit(1);
         }
-        println!(
-            "Using sqlite db at {} (from DATABASE_URL)",
-            options.get_filename().display()
-        );
-        SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect_with(options)
-            .await
-            .expect("database connection failed")
-    } else {
-        let db_path = env::current_dir()
-            .expect("current dir unavailable")
-            .join("fastsales.db");
-        println!("Using local sqlite db at {}", db_path.display());
-        let options = SqliteConnectOptions::new()
-            .filename(db_path)
-            .create_if_missing(true);
-        SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect_with(options)
-            .await
-            .expect("database connection failed")
-    };
-    sqlx::migrate!()
-        .run(&pool)
-        .await
-        .expect("database migrations failed");
+        Ok(report) if !report.is_clean() => {
+            tracing::warn!("Found orphaned rows:\n{}", report);
+            tracing::warn!("Run `backend check-integrity --repair` to fix them.");
+        }
+        Ok(_) => {}
+        Err(err) => panic!("integrity check failed: {}", err),
+    }
+    pool.migrate().await.expect("database migrations failed");
+
+    // Scheduled price changes show on products from the next tick after they
+    // come due; sales look the due price up themselves
+    tokio::spawn({
+        let db = pool.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                if let Err(err) = repo::product_prices::apply_due(&db, chrono::Utc::now()).await {
+                    tracing::error!("Applying scheduled prices failed: {:?}", err);
+                }
+            }
+        }
+    });
+
+    tokio::spawn(webhooks::run_dispatcher(pool.clone()));
+    let business_tz = timezone::BusinessTz::new(config.business.timezone);
+    tracing::info!("Reporting in the {} timezone", business_tz.name());
+    let mailer = email_reports::Mailer::new(&config.smtp).unwrap_or_else(|err| {
+        eprintln!("Configuration error: {}", err);
+        std::process::exit(2);
+    });
+    tokio::spawn(email_reports::run_scheduler(pool.clone(), mailer.clone(), business_tz));
+    tokio::spawn(backup::run_scheduler(pool.clone(), config.backup.clone()));
 
     let state = AppState {
         db: pool,
-        jwt_secret: env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret".to_string()),
-        password_pepper: env::var("PASSWORD_PEPPER").unwrap_or_default(),
+        jwt_secret: config.auth.jwt_secret.clone(),
+        password_pepper: config.auth.password_pepper.clone(),
+        token_ttl_secs: config.auth.token_ttl_secs,
+        upload_dir: config.server.upload_dir.clone(),
+        backup_dir: config.backup.dir.clone(),
         leptos_options: leptos_options.clone(),
+        live: live::channel(),
+        mailer,
+        timezone: business_tz,
     };
 
+    let api = api_router(&state).layer(cors_layer(&config.server.cors_origins));
+
+    let app = Router::new()
+        .merge(
+            SwaggerUi::new("/swagger-ui")
+                .url("/api-doc/openapi.json", ApiDoc::openapi()),
+        )
+        .nest("/api", api)
+        .nest_service("/uploads", ServeDir::new(&config.server.upload_dir))
+        .leptos_routes(&state, leptos_routes, frontend::App)
+        .fallback_service(ServeDir::new(leptos_options.site_root.clone()))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span));
+
+    let app = app.with_state(state);
+
+    let bind_address = config.server.bind_address.unwrap_or(leptos_options.site_addr);
+    let listener = tokio::net::TcpListener::bind(bind_address)
+        .await
+        .unwrap_or_else(|err| panic!("cannot listen on {}: {}", bind_address, err));
+    tracing::info!("Listening on http://{}", bind_address);
+    axum::serve(listener, app)
+        .await
+        .expect("server failed");
+}
+
+/// The `/api` routes, without CORS so tests can call them directly.
+fn api_router(state: &AppState) -> Router<AppState> {
+    // Backups hold every record, so only admins may take or download them
+    let admin = Router::new()
+        .route("/admin/backups", get(list_backups).post(create_backup))
+        .route("/admin/backups/:file_name", get(download_backup))
+        .route("/admin/integrity", get(get_integrity_report))
+        .route("/admin/staff/:id/role", put(set_staff_role))
+        .route_layer(from_fn_with_state(state.clone(), admin_middleware));
+
     let protected = Router::new()
+        .route("/search", get(search))
         .route("/products", get(list_products).post(create_product))
+        .route("/products/low_stock", get(list_low_stock_products))
         .route(
             "/products/:id",
             get(get_product).put(update_product).delete(delete_product),
         )
+        .route("/products/:id/restore", post(restore_product))
+        .route("/products/:id/price_history", get(get_product_price_history))
+        .route("/products/:id/prices", post(create_product_price))
+        .route("/products/:id/prices/:price_id", delete(cancel_product_price))
+        .route("/stock_alerts", get(list_stock_alerts))
+        .route("/stock_alerts/:id/acknowledge", post(acknowledge_stock_alert))
         .route("/customers", get(list_customers).post(create_customer))
         .route(
             "/customers/:id",
             get(get_customer).put(update_customer).delete(delete_customer),
         )
+        .route("/customers/:id/restore", post(restore_customer))
+        .route("/customers/:id/stats", get(get_customer_stats))
+        .route("/customers/segments", get(get_customer_segments))
         .route("/sales", get(list_sales).post(create_sale))
         .route("/sales_transactions", get(list_sales_transactions).post(create_sales_transaction))
         .route("/sales_transactions/:id", get(get_sales_transaction))
+        .route("/sync/changes", get(get_sync_changes))
+        .route("/sync/sales", post(upload_sync_sales))
+        .route("/calendar", get(get_business_calendar))
         .route("/sales/stats/today", get(get_today_sales))
         .route("/sales/stats/week", get(get_weekly_sales_stats))
+        .route("/sales/stats/totals", get(get_sales_totals))
+        .route("/sales/stats/daily", get(get_daily_sales))
+        .route("/sales/stats/series", get(get_sales_series))
         .route("/sales_stats/top_products", get(get_top_products))
         .route("/sales/stats/by_product", get(get_sales_by_product))
+        .route("/sales/stats/margin", get(get_margin_report))
+        .route("/sales/stats/heatmap", get(get_sales_heatmap))
+        .route("/sales/stats/by_staff", get(get_staff_leaderboard))
+        .route("/sales/stats/by_staff/:id", get(get_staff_performance))
         .route(
             "/sales/:id",
             get(get_sale).put(update_sale).delete(delete_sale),
@@ -215,33 +523,67 @@ async fn main() {
             "/staff/:id",
             get(get_staff).put(update_staff).delete(delete_staff),
         )
+        .route("/staff/:id/restore", post(restore_staff))
         .route("/staff/:id/transactions", get(get_staff_transactions))
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route(
+            "/webhooks/:id",
+            get(get_webhook).put(update_webhook).delete(delete_webhook),
+        )
+        .route("/webhooks/:id/deliveries", get(list_webhook_deliveries))
+        .route("/webhook_deliveries/:id/retry", post(retry_webhook_delivery))
+        .route(
+            "/report_subscriptions",
+            get(list_report_subscriptions).post(create_report_subscription),
+        )
+        .route(
+            "/report_subscriptions/:id",
+            get(get_report_subscription)
+                .put(update_report_subscription)
+                .delete(delete_report_subscription),
+        )
+        .route("/report_subscriptions/:id/send", post(send_report_subscription))
+        .route("/report_subscriptions/:id/preview", get(preview_report_subscription))
+        .route("/shifts", get(list_shifts).post(create_shift))
+        .route("/shifts/current", get(get_current_shift))
+        .route("/shifts/:id", get(get_shift))
+        .route("/shifts/:id/cash_movements", post(create_cash_movement))
+        .route("/shifts/:id/close", post(close_shift))
+        .route("/shifts/:id/report", get(get_shift_report))
+        .route("/shifts/:id/report/print", get(print_shift_report))
         .route("/auth/profile", get(get_profile))
+        .merge(admin)
+        // Layers added later run first, so auth sets the claims before
+        // idempotency keys are looked up
+        .route_layer(from_fn_with_state(state.clone(), idempotency_middleware))
         .route_layer(from_fn_with_state(state.clone(), auth_middleware));
 
-    let api = Router::new()
+    Router::new()
         .route("/auth/login", post(login))
+        // Authenticates with a query token, as EventSource cannot set headers
+        .route("/live", get(live_events))
         .route("/upload", post(upload_file))
-        .merge(protected);
-
-    let app = Router::new()
-        .merge(
-            SwaggerUi::new("/swagger-ui")
-                .url("/api-doc/openapi.json", ApiDoc::openapi()),
-        )
-        .nest("/api", api)
-        .nest_service("/uploads", ServeDir::new("uploads"))
-        .leptos_routes(&state, leptos_routes, frontend::App)
-        .fallback_service(ServeDir::new(leptos_options.site_root.clone()));
-
-    let app = app.with_state(state);
+        .merge(protected)
+}
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .expect("listener bind failed");
-    axum::serve(listener, app)
-        .await
-        .expect("server failed");
+/// Lets browsers on the configured origins call the API; with none configured
+/// only same-origin requests (the bundled frontend) get through.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(origins.iter().map(|origin| {
+            HeaderValue::from_str(origin).expect("CORS origins are checked when the configuration loads")
+        }))
+    };
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(Any)
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(idempotency::IDEMPOTENCY_KEY_HEADER),
+        ])
 }
 
 #[cfg(test)]
@@ -259,7 +601,7 @@ mod tests {
         // actually let's just use ".."
         
         let path = std::path::Path::new("../openapi.json");
-        let mut file = std::fs::File::create(&path).expect("Failed to create openapi.json");
+        let mut file = std::fs::File::create(path).expect("Failed to create openapi.json");
         file.write_all(json.as_bytes()).expect("Failed to write openapi.json");
         println!("OpenAPI JSON written to {:?}", path);
     }
@@ -0,0 +1,57 @@
+//! The repository layer: how the API's records are stored and read back,
+//! one module per aggregate. Handlers validate input, check who is asking
+//! and shape responses; the modules here own the tables, the SQL and the
+//! conversion between rows and `shared::models` types.
+//!
+//! A repository function takes `impl Into<Conn>`, so the same call works on
+//! the pool or inside a handler's transaction. Its SQL is written once for
+//! SQLite and PostgreSQL with the placeholders and dialect helpers of
+//! [`crate::db`]; a query the two spell differently branches on
+//! [`Backend`](crate::db::Backend) there rather than here.
+//!
+//! Every handler query goes through here, with [`reports`] and [`search`]
+//! reading across aggregates. The infrastructure modules keep their own SQL:
+//! idempotency keys, full-text indexing, backups and the integrity check work
+//! on the schema as a whole rather than on records.
+
+pub mod customers;
+pub mod product_prices;
+pub mod products;
+pub mod reports;
+pub mod report_subscriptions;
+pub mod sale_items;
+pub mod sales;
+pub mod search;
+pub mod shifts;
+pub mod staff;
+pub mod stock_alerts;
+pub mod sync;
+pub mod webhooks;
+
+use uuid::Uuid;
+
+use crate::db::{self, Conn};
+use crate::error::AppError;
+
+/// A UUID column, read as text on both backends.
+pub fn parse_uuid(value: String) -> Result<Uuid, AppError> {
+    Uuid::parse_str(&value).map_err(AppError::internal)
+}
+
+/// ` WHERE ` and `conditions` joined by `AND`, or nothing without any.
+fn where_clause(conditions: &[&str]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+/// Whether the record `id` in `table` is archived, or `None` when it does
+/// not exist. `table` must be a trusted table name.
+async fn archived_state<'c>(conn: impl Into<Conn<'c>>, table: &str, id: Uuid) -> Result<Option<bool>, AppError> {
+    Ok(db::query_scalar(format!("SELECT archived_at IS NOT NULL FROM {} WHERE id = ?", table))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?)
+}
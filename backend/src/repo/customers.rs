@@ -0,0 +1,348 @@
+//! Customers, the free-form details recorded against them and the purchase
+//! history their stats and RFM segments are worked out from. Archived
+//! customers are hidden from lists but stay readable from their sales.
+
+use chrono::{DateTime, Utc};
+use shared::models::{Customer, CustomerDetails, CustomerStats};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Backend, Conn, Db, Row};
+use crate::error::AppError;
+
+const COLUMNS: &str = "customers.id, customers.first_name, customers.last_name, customers.middle_name, customers.mobile_number, customers.date_of_birth, customers.email, customers.archived_at";
+
+/// Which customers [`list`] returns.
+#[derive(Default)]
+pub struct CustomerFilter {
+    /// A full-text query from [`crate::fts::search_query`] over
+    /// `customers_fts`; matches are ranked best first
+    pub search: Option<String>,
+    pub include_archived: bool,
+}
+
+/// A page of the customers matching `filter`, with their details, and how
+/// many match in all.
+pub async fn list(db: &Db, filter: &CustomerFilter, limit: i64, offset: i64) -> Result<(Vec<Customer>, i64), AppError> {
+    let (count, query) = list_queries(db.backend(), filter);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    let mut customers = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut customer = from_row(&row)?;
+        customer.details = details(db, customer.id).await?;
+        customers.push(customer);
+    }
+    Ok((customers, total))
+}
+
+/// Every customer matching `filter`, in [`list`]'s order but without their
+/// details, as the database cursor yields them.
+pub fn stream(db: &Db, filter: &CustomerFilter) -> impl Stream<Item = Result<Customer, AppError>> + Send + 'static {
+    let (_, query) = list_queries(db.backend(), filter);
+    query.bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// The count and the listing of the customers matching `filter`, the
+/// listing still to be bound its `LIMIT` and `OFFSET`.
+fn list_queries(backend: Backend, filter: &CustomerFilter) -> (db::QueryScalar<i64>, db::Query) {
+    let by_name = "customers.last_name ASC, customers.first_name ASC, customers.id ASC";
+    let (from, order) = if filter.search.is_some() {
+        (
+            format!("customers JOIN customers_fts ON customers_fts.customer_id = customers.id{}", backend.fts_source()),
+            format!("{}, {}", backend.fts_rank("customers_fts", &[]), by_name),
+        )
+    } else {
+        ("customers".to_string(), by_name.to_string())
+    };
+    let fts_match = backend.fts_match("customers_fts");
+    let mut conditions = Vec::new();
+    if filter.search.is_some() {
+        conditions.push(fts_match.as_str());
+    }
+    if !filter.include_archived {
+        conditions.push("customers.archived_at IS NULL");
+    }
+    let conditions = super::where_clause(&conditions);
+
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) FROM {}{}", from, conditions));
+    let mut query = db::query(format!(
+        "SELECT {} FROM {}{} ORDER BY {} LIMIT ? OFFSET ?",
+        COLUMNS, from, conditions, order
+    ));
+    if let Some(search) = &filter.search {
+        count = count.bind(search);
+        query = query.bind(search);
+    }
+    (count, query)
+}
+
+/// The customers changed since sync version `since`, archived or not, or
+/// every active customer without it; by name, with their details.
+pub async fn changed_since(db: &Db, since: Option<i64>) -> Result<Vec<Customer>, AppError> {
+    let filter = match since {
+        Some(_) => "id IN (SELECT entity_id FROM sync_changes WHERE entity = 'customer' AND version > ?)",
+        None => "archived_at IS NULL",
+    };
+    let mut query = db::query(format!(
+        "SELECT {} FROM customers WHERE {} ORDER BY last_name ASC, first_name ASC, id ASC",
+        COLUMNS, filter
+    ));
+    if let Some(since) = since {
+        query = query.bind(since);
+    }
+    let rows = query.fetch_all(db).await?;
+    let mut customers = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut customer = from_row(&row)?;
+        customer.details = details(db, customer.id).await?;
+        customers.push(customer);
+    }
+    Ok(customers)
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Customer, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!("SELECT {} FROM customers WHERE id = ?", COLUMNS))
+        .bind(id)
+        .fetch_optional(&mut conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Customer"))?;
+    let mut customer = from_row(&row)?;
+    customer.details = details(conn, id).await?;
+    Ok(customer)
+}
+
+/// Whether customer `id` is archived, or `None` when there is none.
+pub async fn archived<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<bool>, AppError> {
+    super::archived_state(conn, "customers", id).await
+}
+
+/// Records a new customer and their details.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, customer: &Customer) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    db::query(
+        "INSERT INTO customers (id, first_name, last_name, middle_name, mobile_number, date_of_birth, email) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(customer.id)
+    .bind(&customer.first_name)
+    .bind(&customer.last_name)
+    .bind(&customer.middle_name)
+    .bind(&customer.mobile_number)
+    .bind(customer.date_of_birth)
+    .bind(&customer.email)
+    .execute(&mut conn)
+    .await?;
+    insert_details(conn, customer).await
+}
+
+/// Saves `customer`'s profile and replaces their details, returning when
+/// they were archived; the archived state itself is left as it is.
+pub async fn update<'c>(conn: impl Into<Conn<'c>>, customer: &Customer) -> Result<Option<DateTime<Utc>>, AppError> {
+    let mut conn = conn.into();
+    let archived_at = db::query_scalar(
+        "UPDATE customers SET first_name = ?, last_name = ?, middle_name = ?, mobile_number = ?, date_of_birth = ?, email = ? WHERE id = ? RETURNING archived_at",
+    )
+    .bind(&customer.first_name)
+    .bind(&customer.last_name)
+    .bind(&customer.middle_name)
+    .bind(&customer.mobile_number)
+    .bind(customer.date_of_birth)
+    .bind(&customer.email)
+    .bind(customer.id)
+    .fetch_optional(&mut conn)
+    .await?
+    .ok_or_else(|| AppError::not_found("Customer"))?;
+
+    db::query("DELETE FROM customer_details WHERE customer_id = ?")
+        .bind(customer.id)
+        .execute(&mut conn)
+        .await?;
+    insert_details(conn, customer).await?;
+    Ok(archived_at)
+}
+
+async fn insert_details<'c>(conn: impl Into<Conn<'c>>, customer: &Customer) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    for detail in &customer.details {
+        db::query("INSERT INTO customer_details (id, customer_id, detail_name, detail_value) VALUES (?, ?, ?, ?)")
+            .bind(Uuid::new_v4())
+            .bind(customer.id)
+            .bind(&detail.detail_name)
+            .bind(&detail.detail_value)
+            .execute(&mut conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Archives customer `id` as of `now` and returns them, or `None` when
+/// there is no active customer `id`.
+pub async fn archive<'c>(conn: impl Into<Conn<'c>>, id: Uuid, now: DateTime<Utc>) -> Result<Option<Customer>, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!(
+        "UPDATE customers SET archived_at = ? WHERE id = ? AND archived_at IS NULL RETURNING {}",
+        COLUMNS
+    ))
+    .bind(now)
+    .bind(id)
+    .fetch_optional(&mut conn)
+    .await?;
+    with_details(conn, row).await
+}
+
+/// Restores archived customer `id` and returns them, or `None` when there
+/// is no archived customer `id`.
+pub async fn restore<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<Customer>, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!(
+        "UPDATE customers SET archived_at = NULL WHERE id = ? AND archived_at IS NOT NULL RETURNING {}",
+        COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut conn)
+    .await?;
+    with_details(conn, row).await
+}
+
+async fn with_details<'c>(conn: impl Into<Conn<'c>>, row: Option<Row>) -> Result<Option<Customer>, AppError> {
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let mut customer = from_row(&row)?;
+    customer.details = details(conn, customer.id).await?;
+    Ok(Some(customer))
+}
+
+async fn details<'c>(conn: impl Into<Conn<'c>>, customer_id: Uuid) -> Result<Vec<CustomerDetails>, AppError> {
+    let rows = db::query("SELECT customer_id, detail_name, detail_value FROM customer_details WHERE customer_id = ?")
+        .bind(customer_id)
+        .fetch_all(conn)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| CustomerDetails {
+            customer_id: parse_uuid(row.get("customer_id")).unwrap_or_default(),
+            detail_name: row.get("detail_name"),
+            detail_value: row.get("detail_value"),
+        })
+        .collect())
+}
+
+/// Purchase history of every customer, or only of `customer_id`, as of
+/// `now`, with whether they are archived. RFM scores are left unset.
+pub async fn purchase_history(
+    db: &Db,
+    customer_id: Option<Uuid>,
+    now: DateTime<Utc>,
+) -> Result<Vec<(CustomerStats, bool)>, AppError> {
+    let mut query = db::query(format!(
+        "SELECT customers.id, customers.first_name, customers.last_name, customers.archived_at,
+                MIN(sales.date_and_time) AS first_purchase,
+                MAX(sales.date_and_time) AS last_purchase,
+                COUNT(sales.id) AS visits,
+                COALESCE(SUM(sales.total_resolved), 0) AS spend
+         FROM customers
+         LEFT JOIN sales ON sales.customer_id = customers.id{}
+         GROUP BY customers.id",
+        if customer_id.is_some() { " WHERE customers.id = ?" } else { "" }
+    ));
+    if let Some(customer_id) = customer_id {
+        query = query.bind(customer_id);
+    }
+    let rows = query.fetch_all(db).await?;
+
+    let mut customers = Vec::with_capacity(rows.len());
+    for row in rows {
+        let first_name: String = row.try_get("first_name")?;
+        let last_name: String = row.try_get("last_name")?;
+        let archived_at: Option<DateTime<Utc>> = row.try_get("archived_at")?;
+        let last_purchase: Option<DateTime<Utc>> = row.try_get("last_purchase")?;
+        let visit_count: i64 = row.try_get("visits")?;
+        let total_spend_cents: i64 = row.try_get("spend")?;
+        let stats = CustomerStats {
+            customer_id: parse_uuid(row.try_get("id")?)?,
+            customer_name: format!("{} {}", first_name, last_name),
+            first_purchase: row.try_get("first_purchase")?,
+            last_purchase,
+            days_since_last_purchase: last_purchase.map(|last| (now - last).num_days().max(0)),
+            visit_count,
+            total_spend_cents,
+            average_basket_cents: if visit_count > 0 { total_spend_cents / visit_count } else { 0 },
+            rfm: None,
+            segment: None,
+        };
+        customers.push((stats, archived_at.is_some()));
+    }
+    Ok(customers)
+}
+
+/// Where one buyer stands among every customer who has bought anything:
+/// for each RFM measure, how many buyers fall below them and how many,
+/// themselves included, are level with them.
+pub struct BuyerRank {
+    pub buyers: i64,
+    pub recency: (i64, i64),
+    pub frequency: (i64, i64),
+    pub monetary: (i64, i64),
+}
+
+/// Ranks the buyer with `stats` against every buyer, counting in SQL.
+pub async fn buyer_rank(db: &Db, stats: &CustomerStats) -> Result<BuyerRank, AppError> {
+    let position = |column: &str| {
+        format!(
+            "SUM(CASE WHEN {column} < ? THEN 1 ELSE 0 END) AS {column}_below, SUM(CASE WHEN {column} = ? THEN 1 ELSE 0 END) AS {column}_equal"
+        )
+    };
+    let query = format!(
+        "WITH buyers AS (
+             SELECT {} AS recency, COUNT(*) AS frequency, SUM(sales.total_resolved) AS monetary
+             FROM customers
+             JOIN sales ON sales.customer_id = customers.id
+             GROUP BY customers.id
+         )
+         SELECT COUNT(*) AS buyers, {}, {}, {}
+         FROM buyers",
+        db.backend().unix_seconds("MAX(sales.date_and_time)"),
+        position("recency"),
+        position("frequency"),
+        position("monetary")
+    );
+    let recency = stats.last_purchase.map(|last| last.timestamp()).unwrap_or(0);
+    let row = db::query(query)
+        .bind(recency)
+        .bind(recency)
+        .bind(stats.visit_count)
+        .bind(stats.visit_count)
+        .bind(stats.total_spend_cents)
+        .bind(stats.total_spend_cents)
+        .fetch_one(db)
+        .await?;
+
+    let position = |column: &str| -> Result<(i64, i64), AppError> {
+        let below: i64 = row.try_get(format!("{}_below", column).as_str())?;
+        let equal: i64 = row.try_get(format!("{}_equal", column).as_str())?;
+        Ok((below, equal))
+    };
+    Ok(BuyerRank {
+        buyers: row.try_get("buyers")?,
+        recency: position("recency")?,
+        frequency: position("frequency")?,
+        monetary: position("monetary")?,
+    })
+}
+
+fn from_row(row: &Row) -> Result<Customer, AppError> {
+    Ok(Customer {
+        id: parse_uuid(row.get("id"))?,
+        first_name: row.get("first_name"),
+        last_name: row.get("last_name"),
+        middle_name: row.get("middle_name"),
+        mobile_number: row.get("mobile_number"),
+        date_of_birth: row.get("date_of_birth"),
+        email: row.get("email"),
+        details: vec![],
+        archived_at: row.get("archived_at"),
+    })
+}
@@ -0,0 +1,162 @@
+//! Product price history. Every price a product has had or is scheduled to
+//! have is kept; `products.price_cents` follows the latest one in effect.
+
+use chrono::{DateTime, Utc};
+use shared::models::ProductPrice;
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+const SELECT: &str = "product_prices.id, product_prices.product_id, product_prices.price_cents, product_prices.effective_from, product_prices.changed_by, product_prices.created_at, staff.first_name || ' ' || staff.last_name AS changed_by_name
+     FROM product_prices
+     LEFT JOIN staff ON staff.id = product_prices.changed_by";
+
+/// A page of product `product_id`'s prices, latest first, and how many it
+/// has had.
+pub async fn list(db: &Db, product_id: Uuid, limit: i64, offset: i64) -> Result<(Vec<ProductPrice>, i64), AppError> {
+    let total = db::query_scalar("SELECT COUNT(*) FROM product_prices WHERE product_id = ?")
+        .bind(product_id)
+        .fetch_one(db)
+        .await?;
+    let rows = db::query(format!(
+        "SELECT {} WHERE product_prices.product_id = ?
+         ORDER BY product_prices.effective_from DESC, product_prices.created_at DESC, product_prices.id DESC
+         LIMIT ? OFFSET ?",
+        SELECT
+    ))
+    .bind(product_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await?;
+    let prices = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((prices, total))
+}
+
+/// The price from product `product_id`'s history in effect at `at`, if it
+/// had one by then.
+pub async fn recorded_at<'c>(
+    conn: impl Into<Conn<'c>>,
+    product_id: Uuid,
+    at: DateTime<Utc>,
+) -> Result<Option<i64>, AppError> {
+    Ok(db::query_scalar(
+        "SELECT price_cents FROM product_prices
+         WHERE product_id = ? AND effective_from <= ?
+         ORDER BY effective_from DESC, created_at DESC, id DESC
+         LIMIT 1",
+    )
+    .bind(product_id)
+    .bind(at)
+    .fetch_optional(conn)
+    .await?)
+}
+
+/// Price of product `product_id` in effect at `at` according to its price
+/// history, falling back to the current price for products without one.
+pub async fn price_at<'c>(conn: impl Into<Conn<'c>>, product_id: Uuid, at: DateTime<Utc>) -> Result<i64, AppError> {
+    Ok(db::query_scalar(
+        "SELECT COALESCE(
+             (SELECT price_cents FROM product_prices
+              WHERE product_id = ? AND effective_from <= ?
+              ORDER BY effective_from DESC, created_at DESC, id DESC
+              LIMIT 1),
+             (SELECT price_cents FROM products WHERE id = ?)
+         )",
+    )
+    .bind(product_id)
+    .bind(at)
+    .bind(product_id)
+    .fetch_one(conn)
+    .await?)
+}
+
+/// Appends a price to product `product_id`'s history, effective from
+/// `effective_from`, and returns it. Does not touch `products.price_cents`.
+pub async fn insert<'c>(
+    conn: impl Into<Conn<'c>>,
+    product_id: Uuid,
+    price_cents: i64,
+    effective_from: DateTime<Utc>,
+    changed_by: Option<Uuid>,
+    now: DateTime<Utc>,
+) -> Result<ProductPrice, AppError> {
+    let mut conn = conn.into();
+    let id = Uuid::new_v4();
+    db::query(
+        "INSERT INTO product_prices (id, product_id, price_cents, effective_from, changed_by, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(product_id)
+    .bind(price_cents)
+    .bind(effective_from)
+    .bind(changed_by)
+    .bind(now)
+    .execute(&mut conn)
+    .await?;
+
+    let row = db::query(format!("SELECT {} WHERE product_prices.id = ?", SELECT))
+        .bind(id)
+        .fetch_one(conn)
+        .await?;
+    from_row(&row)
+}
+
+/// Cancels price `id` of product `product_id`, which must still be
+/// scheduled after `now`.
+pub async fn cancel<'c>(conn: impl Into<Conn<'c>>, product_id: Uuid, id: Uuid, now: DateTime<Utc>) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    let deleted = db::query("DELETE FROM product_prices WHERE id = ? AND product_id = ? AND effective_from > ?")
+        .bind(id)
+        .bind(product_id)
+        .bind(now)
+        .execute(&mut conn)
+        .await?;
+    if deleted > 0 {
+        return Ok(());
+    }
+
+    let exists: Option<i64> = db::query_scalar("SELECT 1 FROM product_prices WHERE id = ? AND product_id = ?")
+        .bind(id)
+        .bind(product_id)
+        .fetch_optional(conn)
+        .await?;
+    match exists {
+        Some(_) => Err(AppError::Conflict("Only scheduled prices can be cancelled".to_string(), vec![])),
+        None => Err(AppError::not_found("Price")),
+    }
+}
+
+/// Copies the latest price in effect at `now` onto `products.price_cents`
+/// for every product where it differs, which is how scheduled prices go
+/// live, and returns how many products changed.
+pub async fn apply_due<'c>(conn: impl Into<Conn<'c>>, now: DateTime<Utc>) -> Result<u64, AppError> {
+    Ok(db::query(
+        "UPDATE products SET price_cents = due.price_cents
+         FROM (
+             SELECT product_id, price_cents,
+                    ROW_NUMBER() OVER (PARTITION BY product_id ORDER BY effective_from DESC, created_at DESC, id DESC) AS position
+             FROM product_prices
+             WHERE effective_from <= ?
+         ) AS due
+         WHERE due.product_id = products.id AND due.position = 1 AND products.price_cents <> due.price_cents",
+    )
+    .bind(now)
+    .execute(conn)
+    .await?)
+}
+
+fn from_row(row: &Row) -> Result<ProductPrice, AppError> {
+    let changed_by: Option<String> = row.get("changed_by");
+    Ok(ProductPrice {
+        id: parse_uuid(row.get("id"))?,
+        product_id: parse_uuid(row.get("product_id"))?,
+        price_cents: row.get("price_cents"),
+        effective_from: row.get("effective_from"),
+        changed_by: changed_by.map(parse_uuid).transpose()?,
+        changed_by_name: row.get("changed_by_name"),
+        created_at: row.get("created_at"),
+    })
+}
@@ -0,0 +1,339 @@
+//! Products, the free-form details recorded against them and their stock.
+//! Archived products are hidden from lists but stay readable from their
+//! sales; their price history lives in [`super::product_prices`].
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{Product, ProductDetails, ProductType};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Backend, Conn, Db, Row};
+use crate::error::AppError;
+
+const COLUMNS: &str = "products.id, products.name, products.description, products.price_cents, products.cost_price_cents, products.stock, products.product_type, products.reorder_point, products.reorder_quantity, products.archived_at";
+
+/// Which products [`list`] returns.
+#[derive(Default)]
+pub struct ProductFilter {
+    /// A full-text query from [`crate::fts::search_query`] over
+    /// `products_fts`; matches are ranked best first
+    pub search: Option<String>,
+    pub include_archived: bool,
+}
+
+/// A page of the products matching `filter`, with their details, and how
+/// many match in all.
+pub async fn list(db: &Db, filter: &ProductFilter, limit: i64, offset: i64) -> Result<(Vec<Product>, i64), AppError> {
+    let (count, query) = list_queries(db.backend(), filter);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    Ok((with_details(db, &rows).await?, total))
+}
+
+/// Every product matching `filter`, in [`list`]'s order but without their
+/// details, as the database cursor yields them.
+pub fn stream(db: &Db, filter: &ProductFilter) -> impl Stream<Item = Result<Product, AppError>> + Send + 'static {
+    let (_, query) = list_queries(db.backend(), filter);
+    query.bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// The count and the listing of the products matching `filter`, the listing
+/// still to be bound its `LIMIT` and `OFFSET`.
+fn list_queries(backend: Backend, filter: &ProductFilter) -> (db::QueryScalar<i64>, db::Query) {
+    // Searches are ranked by relevance; plain listings are alphabetical.
+    let (from, order) = if filter.search.is_some() {
+        (
+            format!("products JOIN products_fts ON products_fts.product_id = products.id{}", backend.fts_source()),
+            format!("{}, products.name ASC, products.id ASC", backend.fts_rank("products_fts", &[])),
+        )
+    } else {
+        ("products".to_string(), "products.name ASC, products.id ASC".to_string())
+    };
+    let fts_match = backend.fts_match("products_fts");
+    let mut conditions = Vec::new();
+    if filter.search.is_some() {
+        conditions.push(fts_match.as_str());
+    }
+    if !filter.include_archived {
+        conditions.push("products.archived_at IS NULL");
+    }
+    let conditions = super::where_clause(&conditions);
+
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) FROM {}{}", from, conditions));
+    let mut query = db::query(format!(
+        "SELECT {} FROM {}{} ORDER BY {} LIMIT ? OFFSET ?",
+        COLUMNS, from, conditions, order
+    ));
+    if let Some(search) = &filter.search {
+        count = count.bind(search);
+        query = query.bind(search);
+    }
+    (count, query)
+}
+
+const LOW_STOCK_FILTER: &str =
+    "WHERE product_type = 'physical_good' AND reorder_point > 0 AND stock <= reorder_point AND archived_at IS NULL";
+
+/// Physical goods at or below their reorder point, closest to running out
+/// first, with their details, and how many there are in all.
+pub async fn low_stock(db: &Db, limit: i64, offset: i64) -> Result<(Vec<Product>, i64), AppError> {
+    let total = db::query_scalar(format!("SELECT COUNT(*) FROM products {}", LOW_STOCK_FILTER))
+        .fetch_one(db)
+        .await?;
+    let rows = low_stock_query().bind(limit).bind(offset).fetch_all(db).await?;
+    Ok((with_details(db, &rows).await?, total))
+}
+
+/// Every product [`low_stock`] would list, without their details, as the
+/// database cursor yields them.
+pub fn stream_low_stock(db: &Db) -> impl Stream<Item = Result<Product, AppError>> + Send + 'static {
+    low_stock_query().bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+fn low_stock_query() -> db::Query {
+    db::query(format!(
+        "SELECT {} FROM products {} ORDER BY (stock - reorder_point) ASC, name ASC, id ASC LIMIT ? OFFSET ?",
+        COLUMNS, LOW_STOCK_FILTER
+    ))
+}
+
+/// The products changed since sync version `since`, archived or not, or
+/// every active product without it; by name, with their details.
+pub async fn changed_since(db: &Db, since: Option<i64>) -> Result<Vec<Product>, AppError> {
+    let filter = match since {
+        Some(_) => "id IN (SELECT entity_id FROM sync_changes WHERE entity = 'product' AND version > ?)",
+        None => "archived_at IS NULL",
+    };
+    let mut query = db::query(format!(
+        "SELECT {} FROM products WHERE {} ORDER BY name ASC, id ASC",
+        COLUMNS, filter
+    ));
+    if let Some(since) = since {
+        query = query.bind(since);
+    }
+    let rows = query.fetch_all(db).await?;
+    with_details(db, &rows).await
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Product, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!("SELECT {} FROM products WHERE id = ?", COLUMNS))
+        .bind(id)
+        .fetch_optional(&mut conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Product"))?;
+    let mut product = from_row(&row)?;
+    product.details = details(conn, id).await?;
+    Ok(product)
+}
+
+/// Fails with not found unless there is a product `id`, archived or not.
+pub async fn ensure_exists<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<(), AppError> {
+    db::query_scalar::<i64>("SELECT 1 FROM products WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| AppError::not_found("Product"))
+}
+
+/// Whether product `id` is archived, or `None` when there is none.
+pub async fn archived<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<bool>, AppError> {
+    super::archived_state(conn, "products", id).await
+}
+
+/// Records a new product and its details.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, product: &Product) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    db::query(
+        "INSERT INTO products (id, name, description, price_cents, cost_price_cents, stock, product_type, reorder_point, reorder_quantity) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(product.id)
+    .bind(&product.name)
+    .bind(&product.description)
+    .bind(product.price_cents)
+    .bind(product.cost_price_cents)
+    .bind(product.stock)
+    .bind(product.product_type.as_str())
+    .bind(product.reorder_point)
+    .bind(product.reorder_quantity)
+    .execute(&mut conn)
+    .await?;
+    insert_details(conn, product).await
+}
+
+/// Saves `product` and replaces its details, returning its price before
+/// the change and when it was archived; the archived state itself is left
+/// as it is.
+pub async fn update<'c>(
+    conn: impl Into<Conn<'c>>,
+    product: &Product,
+) -> Result<(i64, Option<DateTime<Utc>>), AppError> {
+    let mut conn = conn.into();
+    let previous: (i64, Option<DateTime<Utc>>) = db::query_as("SELECT price_cents, archived_at FROM products WHERE id = ?")
+        .bind(product.id)
+        .fetch_optional(&mut conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Product"))?;
+
+    db::query(
+        "UPDATE products SET name = ?, description = ?, price_cents = ?, cost_price_cents = ?, stock = ?, product_type = ?, reorder_point = ?, reorder_quantity = ? WHERE id = ?",
+    )
+    .bind(&product.name)
+    .bind(&product.description)
+    .bind(product.price_cents)
+    .bind(product.cost_price_cents)
+    .bind(product.stock)
+    .bind(product.product_type.as_str())
+    .bind(product.reorder_point)
+    .bind(product.reorder_quantity)
+    .bind(product.id)
+    .execute(&mut conn)
+    .await?;
+
+    db::query("DELETE FROM product_details WHERE product_id = ?")
+        .bind(product.id)
+        .execute(&mut conn)
+        .await?;
+    insert_details(conn, product).await?;
+    Ok(previous)
+}
+
+async fn insert_details<'c>(conn: impl Into<Conn<'c>>, product: &Product) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    for detail in &product.details {
+        db::query("INSERT INTO product_details (id, product_id, detail_name, detail_value) VALUES (?, ?, ?, ?)")
+            .bind(Uuid::new_v4())
+            .bind(product.id)
+            .bind(&detail.detail_name)
+            .bind(&detail.detail_value)
+            .execute(&mut conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Archives product `id` as of `now` and returns it, or `None` when there
+/// is no active product `id`.
+pub async fn archive<'c>(conn: impl Into<Conn<'c>>, id: Uuid, now: DateTime<Utc>) -> Result<Option<Product>, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!(
+        "UPDATE products SET archived_at = ? WHERE id = ? AND archived_at IS NULL RETURNING {}",
+        COLUMNS
+    ))
+    .bind(now)
+    .bind(id)
+    .fetch_optional(&mut conn)
+    .await?;
+    match row {
+        Some(row) => Ok(with_details(conn, &[row]).await?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// Restores archived product `id` and returns it, or `None` when there is
+/// no archived product `id`.
+pub async fn restore<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<Product>, AppError> {
+    let mut conn = conn.into();
+    let row = db::query(format!(
+        "UPDATE products SET archived_at = NULL WHERE id = ? AND archived_at IS NOT NULL RETURNING {}",
+        COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut conn)
+    .await?;
+    match row {
+        Some(row) => Ok(with_details(conn, &[row]).await?.pop()),
+        None => Ok(None),
+    }
+}
+
+/// Stock of a physical good after a change, for deciding whether it needs
+/// reordering.
+pub struct StockLevel {
+    pub name: String,
+    pub stock: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+}
+
+/// Takes `quantity` of product `id` out of stock, returning what is left,
+/// or `None` when it is not a physical good and keeps no stock.
+pub async fn remove_stock<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    quantity: i64,
+) -> Result<Option<StockLevel>, AppError> {
+    let row = db::query(
+        "UPDATE products SET stock = stock - ? WHERE id = ? AND product_type = 'physical_good' RETURNING name, stock, reorder_point, reorder_quantity",
+    )
+    .bind(quantity)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(|row| StockLevel {
+        name: row.get("name"),
+        stock: row.get("stock"),
+        reorder_point: row.get("reorder_point"),
+        reorder_quantity: row.get("reorder_quantity"),
+    }))
+}
+
+/// Puts `quantity` of product `id` back in stock, if it keeps any.
+pub async fn return_stock<'c>(conn: impl Into<Conn<'c>>, id: Uuid, quantity: i64) -> Result<(), AppError> {
+    db::query("UPDATE products SET stock = stock + ? WHERE id = ? AND product_type = 'physical_good'")
+        .bind(quantity)
+        .bind(id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn with_details<'c>(conn: impl Into<Conn<'c>>, rows: &[Row]) -> Result<Vec<Product>, AppError> {
+    let mut conn = conn.into();
+    let mut products = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut product = from_row(row)?;
+        product.details = details(&mut conn, product.id).await?;
+        products.push(product);
+    }
+    Ok(products)
+}
+
+async fn details<'c>(conn: impl Into<Conn<'c>>, product_id: Uuid) -> Result<Vec<ProductDetails>, AppError> {
+    let rows = db::query("SELECT product_id, detail_name, detail_value FROM product_details WHERE product_id = ?")
+        .bind(product_id)
+        .fetch_all(conn)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ProductDetails {
+            product_id: parse_uuid(row.get("product_id")).unwrap_or_default(),
+            detail_name: row.get("detail_name"),
+            detail_value: row.get("detail_value"),
+        })
+        .collect())
+}
+
+fn from_row(row: &Row) -> Result<Product, AppError> {
+    let product_type: String = row.get("product_type");
+    let product_type = ProductType::from_str(&product_type)
+        .map_err(|_| AppError::internal(format!("unknown product type {:?}", product_type)))?;
+
+    Ok(Product {
+        id: parse_uuid(row.get("id"))?,
+        name: row.get("name"),
+        description: row.get("description"),
+        price_cents: row.get("price_cents"),
+        cost_price_cents: row.get("cost_price_cents"),
+        stock: row.get("stock"),
+        product_type,
+        reorder_point: row.get("reorder_point"),
+        reorder_quantity: row.get("reorder_quantity"),
+        details: vec![],
+        archived_at: row.get("archived_at"),
+    })
+}
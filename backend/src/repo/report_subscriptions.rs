@@ -0,0 +1,157 @@
+//! Scheduled email reports and the outcome of their last send.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{ReportFrequency, ReportSubscription, ReportSubscriptionInput, ReportType};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+const COLUMNS: &str = "id, name, report_type, parameters, frequency, send_hour, recipients, active, next_run_at, last_sent_at, last_error, created_at";
+
+/// A page of the subscriptions, oldest first, and how many there are.
+pub async fn list(db: &Db, limit: i64, offset: i64) -> Result<(Vec<ReportSubscription>, i64), AppError> {
+    let total = db::query_scalar("SELECT COUNT(*) FROM report_subscriptions").fetch_one(db).await?;
+    let rows = list_query().bind(limit).bind(offset).fetch_all(db).await?;
+    let subscriptions = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((subscriptions, total))
+}
+
+/// Every subscription, in [`list`]'s order, as the database cursor yields them.
+pub fn stream(db: &Db) -> impl Stream<Item = Result<ReportSubscription, AppError>> + Send + 'static {
+    list_query().bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+fn list_query() -> db::Query {
+    db::query(format!(
+        "SELECT {} FROM report_subscriptions ORDER BY created_at ASC, id ASC LIMIT ? OFFSET ?",
+        COLUMNS
+    ))
+}
+
+/// Active subscriptions whose next run is at or before `now`, longest due first.
+pub async fn due(db: &Db, now: DateTime<Utc>) -> Result<Vec<ReportSubscription>, AppError> {
+    let rows = db::query(format!(
+        "SELECT {} FROM report_subscriptions WHERE active = TRUE AND next_run_at <= ? ORDER BY next_run_at",
+        COLUMNS
+    ))
+    .bind(now)
+    .fetch_all(db)
+    .await?;
+    rows.iter().map(from_row).collect()
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<ReportSubscription, AppError> {
+    let row = db::query(format!("SELECT {} FROM report_subscriptions WHERE id = ?", COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Report subscription"))?;
+    from_row(&row)
+}
+
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, subscription: &ReportSubscription) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO report_subscriptions (id, name, report_type, parameters, frequency, send_hour, recipients, active, next_run_at, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(subscription.id)
+    .bind(&subscription.name)
+    .bind(subscription.report_type.as_str())
+    .bind(serde_json::to_string(&subscription.parameters).map_err(AppError::internal)?)
+    .bind(subscription.frequency.as_str())
+    .bind(subscription.hour)
+    .bind(subscription.recipients.join(","))
+    .bind(subscription.active)
+    .bind(subscription.next_run_at)
+    .bind(subscription.created_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Replaces subscription `id`'s settings with `input`, to next run at
+/// `next_run_at`, and returns it.
+pub async fn update<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    input: &ReportSubscriptionInput,
+    next_run_at: DateTime<Utc>,
+) -> Result<ReportSubscription, AppError> {
+    let row = db::query(format!(
+        "UPDATE report_subscriptions SET name = ?, report_type = ?, parameters = ?, frequency = ?, send_hour = ?, recipients = ?, active = ?, next_run_at = ? WHERE id = ? RETURNING {}",
+        COLUMNS
+    ))
+    .bind(&input.name)
+    .bind(input.report_type.as_str())
+    .bind(serde_json::to_string(&input.parameters).map_err(AppError::internal)?)
+    .bind(input.frequency.as_str())
+    .bind(input.hour)
+    .bind(input.recipients.join(","))
+    .bind(input.active)
+    .bind(next_run_at)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::not_found("Report subscription"))?;
+    from_row(&row)
+}
+
+pub async fn delete<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<(), AppError> {
+    let deleted = db::query("DELETE FROM report_subscriptions WHERE id = ?")
+        .bind(id)
+        .execute(conn)
+        .await?;
+    if deleted == 0 {
+        return Err(AppError::not_found("Report subscription"));
+    }
+    Ok(())
+}
+
+/// Records the outcome of a send and when the subscription runs next.
+pub async fn record_send<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    next_run_at: DateTime<Utc>,
+    last_sent_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+) -> Result<(), AppError> {
+    db::query("UPDATE report_subscriptions SET next_run_at = ?, last_sent_at = ?, last_error = ? WHERE id = ?")
+        .bind(next_run_at)
+        .bind(last_sent_at)
+        .bind(last_error)
+        .bind(id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+fn from_row(row: &Row) -> Result<ReportSubscription, AppError> {
+    let report_type: String = row.get("report_type");
+    let frequency: String = row.get("frequency");
+    let parameters: String = row.get("parameters");
+    let recipients: String = row.get("recipients");
+    Ok(ReportSubscription {
+        id: parse_uuid(row.get("id"))?,
+        name: row.get("name"),
+        report_type: ReportType::from_str(&report_type)
+            .map_err(|_| AppError::internal(format!("unknown report type {:?}", report_type)))?,
+        parameters: serde_json::from_str(&parameters).map_err(AppError::internal)?,
+        frequency: ReportFrequency::from_str(&frequency)
+            .map_err(|_| AppError::internal(format!("unknown report frequency {:?}", frequency)))?,
+        hour: row.get("send_hour"),
+        recipients: recipients
+            .split(',')
+            .filter(|recipient| !recipient.is_empty())
+            .map(str::to_string)
+            .collect(),
+        active: row.get("active"),
+        next_run_at: row.get("next_run_at"),
+        last_sent_at: row.get("last_sent_at"),
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+    })
+}
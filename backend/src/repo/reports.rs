@@ -0,0 +1,427 @@
+//! Sales reports: totals, time series, product and staff rankings, margins
+//! and the weekly heatmap, aggregated over sale items between two local
+//! dates of the business timezone.
+
+use chrono::NaiveDate;
+use shared::models::{
+    HeatmapCell, MarginGrouping, MarginLine, ProductSalesSummary, SalesChannel, SeriesBreakdown, StaffPerformance,
+    StaffRanking, TimeBucket, TopProduct, WeekStart, margin_percent,
+};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Db, Row};
+use crate::error::AppError;
+use crate::timezone::BusinessTz;
+
+/// Sales total and item count from `start` to `end` inclusive.
+pub async fn totals(db: &Db, tz: BusinessTz, start: NaiveDate, end: NaiveDate) -> Result<(i64, i64), AppError> {
+    let (from, to) = tz.day_bounds(start, end);
+    let row = db::query(format!(
+        "SELECT COALESCE(SUM(total_resolved), 0) AS total, COUNT(*) AS count FROM sale_items WHERE {}",
+        db.backend().in_range("date_of_sale")
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_one(db)
+    .await?;
+    Ok((row.try_get("total").unwrap_or(0), row.try_get("count").unwrap_or(0)))
+}
+
+/// One bucket of one series in [`series`].
+pub struct SeriesRow {
+    /// The bucket's local start: `YYYY-MM-DDTHH:00` for hours, the first
+    /// day as `YYYY-MM-DD` otherwise
+    pub bucket: String,
+    /// The breakdown value, `None` without a breakdown or where it was not
+    /// recorded
+    pub key: Option<String>,
+    pub label: Option<String>,
+    pub total: i64,
+    pub count: i64,
+}
+
+/// Sales from `start` to `end` by `bucket` and `breakdown` value. Buckets
+/// without sales are left out.
+pub async fn series(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+    bucket: TimeBucket,
+    week_start: WeekStart,
+    breakdown: Option<SeriesBreakdown>,
+) -> Result<Vec<SeriesRow>, AppError> {
+    let backend = db.backend();
+    let local = backend.local_time(tz, "sale_items.date_of_sale", start, end);
+    let bucket_sql = match bucket {
+        TimeBucket::Hour => backend.strftime("%Y-%m-%dT%H:00", &local),
+        TimeBucket::Day => backend.date(&local),
+        TimeBucket::Week => backend.week_start(&local, week_start.weekday().num_days_from_sunday()),
+        TimeBucket::Month => backend.strftime("%Y-%m-01", &local),
+    };
+    // Sale items recorded without a transaction have no branch, channel or
+    // staff member, and fall into the series without a key
+    const SALES: &str = " LEFT JOIN sales ON sales.id = sale_items.sale_id";
+    let (key, label, joins) = match breakdown {
+        None => ("CAST(NULL AS TEXT)", "CAST(NULL AS TEXT)", String::new()),
+        Some(SeriesBreakdown::Branch) => ("sales.company_branch", "sales.company_branch", SALES.to_string()),
+        Some(SeriesBreakdown::Channel) => ("sales.sales_channel", "sales.sales_channel", SALES.to_string()),
+        Some(SeriesBreakdown::Staff) => (
+            "sales.staff_responsible",
+            "staff.first_name || ' ' || staff.last_name",
+            format!("{} LEFT JOIN staff ON staff.id = sales.staff_responsible", SALES),
+        ),
+        Some(SeriesBreakdown::ProductType) => (
+            "products.product_type",
+            "products.product_type",
+            " LEFT JOIN products ON products.id = sale_items.product_id".to_string(),
+        ),
+    };
+    let query = format!(
+        "SELECT {} AS bucket, {} AS series_key, MAX({}) AS series_label,
+                SUM(sale_items.total_resolved) AS total, COUNT(*) AS count
+         FROM sale_items{}
+         WHERE {}
+         GROUP BY bucket, series_key",
+        bucket_sql,
+        key,
+        label,
+        joins,
+        backend.in_range("sale_items.date_of_sale")
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(&query).bind(from).bind(to).fetch_all(db).await?;
+
+    let mut series = Vec::with_capacity(rows.len());
+    for row in rows {
+        series.push(SeriesRow {
+            bucket: row.try_get("bucket")?,
+            key: row.try_get("series_key")?,
+            label: row.try_get("series_label")?,
+            total: row.try_get("total").unwrap_or(0),
+            count: row.try_get("count").unwrap_or(0),
+        });
+    }
+    Ok(series)
+}
+
+/// The 20 best-selling product names from `start` to `end`.
+pub async fn top_products(db: &Db, tz: BusinessTz, start: NaiveDate, end: NaiveDate) -> Result<Vec<TopProduct>, AppError> {
+    let query = format!(
+        "SELECT p.name as product_name, SUM(s.total_resolved) as total
+         FROM sale_items s
+         JOIN products p ON s.product_id = p.id
+         WHERE {}
+         GROUP BY p.name ORDER BY total DESC LIMIT 20",
+        db.backend().in_range("s.date_of_sale")
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(&query).bind(from).bind(to).fetch_all(db).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| TopProduct {
+            product_name: row.try_get("product_name").unwrap_or_default(),
+            total_sales_cents: row.try_get("total").unwrap_or(0),
+        })
+        .collect())
+}
+
+/// Sales of each product from `start` to `end`, best-selling first, with
+/// the product's id.
+pub async fn product_sales(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<(Uuid, ProductSalesSummary)>, AppError> {
+    let query = format!(
+        "SELECT p.id as product_id, p.name as product_name, SUM(s.quantity) as total_quantity, SUM(s.total_resolved) as total_amount,
+                SUM(s.quantity * COALESCE(s.cost_per_item, p.cost_price_cents)) as total_cost
+         FROM sale_items s
+         JOIN products p ON s.product_id = p.id
+         WHERE {}
+         GROUP BY p.id, p.name
+         ORDER BY total_amount DESC, p.name",
+        db.backend().in_range("s.date_of_sale")
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(query).bind(from).bind(to).fetch_all(db).await?;
+
+    rows.iter()
+        .map(|row| Ok((parse_uuid(row.try_get("product_id")?)?, product_sales_from_row(row))))
+        .collect()
+}
+
+/// Revenue, cost and quantity from `start` to `end` per `group_by` value,
+/// highest revenue first for products and categories and in order for
+/// periods. Items sold before cost prices were tracked count at the
+/// product's current cost.
+pub async fn margin(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+    group_by: MarginGrouping,
+) -> Result<Vec<MarginLine>, AppError> {
+    let backend = db.backend();
+    let local = backend.local_time(tz, "sale_items.date_of_sale", start, end);
+    let (label, order) = match group_by {
+        MarginGrouping::Product => (
+            "COALESCE(sale_items.product_name, products.name, CAST(sale_items.product_id AS TEXT))".to_string(),
+            "revenue DESC, label",
+        ),
+        MarginGrouping::Category => ("COALESCE(products.product_type, 'unknown')".to_string(), "revenue DESC, label"),
+        MarginGrouping::Day => (backend.date(&local), "label"),
+        // Weeks start on Monday
+        MarginGrouping::Week => (backend.week_start(&local, 1), "label"),
+        MarginGrouping::Month => (backend.strftime("%Y-%m", &local), "label"),
+    };
+    let query = format!(
+        "SELECT {} AS label,
+                SUM(sale_items.quantity) AS quantity,
+                SUM(sale_items.total_resolved) AS revenue,
+                SUM(sale_items.quantity * COALESCE(sale_items.cost_per_item, products.cost_price_cents, 0)) AS cost
+         FROM sale_items
+         LEFT JOIN products ON products.id = sale_items.product_id
+         WHERE {}
+         GROUP BY label
+         ORDER BY {}",
+        label,
+        backend.in_range("sale_items.date_of_sale"),
+        order
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(&query).bind(from).bind(to).fetch_all(db).await?;
+
+    let mut lines = Vec::with_capacity(rows.len());
+    for row in rows {
+        lines.push(MarginLine::new(
+            row.try_get("label")?,
+            row.try_get("quantity").unwrap_or(0),
+            row.try_get("revenue").unwrap_or(0),
+            row.try_get("cost").unwrap_or(0),
+        ));
+    }
+    Ok(lines)
+}
+
+/// Sales from `start` to `end` by local weekday (0 for Monday) and hour,
+/// optionally only those of `company_branch` or through `sales_channel`.
+/// Cells without sales are left out.
+pub async fn heatmap(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+    company_branch: Option<&str>,
+    sales_channel: Option<SalesChannel>,
+) -> Result<Vec<HeatmapCell>, AppError> {
+    let backend = db.backend();
+    let local = backend.local_time(tz, "sale_items.date_of_sale", start, end);
+
+    // Sale items recorded without a transaction have no branch or channel,
+    // so they drop out as soon as either filter is set
+    let in_range = backend.in_range("sale_items.date_of_sale");
+    let mut conditions = vec![in_range.as_str()];
+    if company_branch.is_some() {
+        conditions.push("sales.company_branch = ?");
+    }
+    if sales_channel.is_some() {
+        conditions.push("sales.sales_channel = ?");
+    }
+    let query = format!(
+        "SELECT {} AS weekday,
+                {} AS hour,
+                SUM(sale_items.total_resolved) AS total,
+                COUNT(DISTINCT COALESCE(sale_items.sale_id, sale_items.id)) AS count
+         FROM sale_items
+         LEFT JOIN sales ON sales.id = sale_items.sale_id{}
+         GROUP BY weekday, hour",
+        backend.weekday(&local),
+        backend.hour(&local),
+        super::where_clause(&conditions)
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let mut sql_query = db::query(&query).bind(from).bind(to);
+    if let Some(branch) = company_branch {
+        sql_query = sql_query.bind(branch);
+    }
+    if let Some(channel) = sales_channel {
+        sql_query = sql_query.bind(channel.to_string());
+    }
+    let rows = sql_query.fetch_all(db).await?;
+
+    let mut cells = Vec::with_capacity(rows.len());
+    for row in rows {
+        let weekday: i64 = row.try_get("weekday")?;
+        let hour: i64 = row.try_get("hour")?;
+        cells.push(HeatmapCell {
+            weekday: weekday as u32,
+            hour: hour as u32,
+            total_sales_cents: row.try_get("total").unwrap_or(0),
+            count: row.try_get("count").unwrap_or(0),
+        });
+    }
+    Ok(cells)
+}
+
+/// Every current staff member, plus archived ones with activity in the
+/// range, ranked highest first, or only `staff_id`'s entry if given. Sales
+/// credited to unknown staff ids are left out.
+pub async fn staff_leaderboard(
+    db: &Db,
+    tz: BusinessTz,
+    start: NaiveDate,
+    end: NaiveDate,
+    ranking: StaffRanking,
+    staff_id: Option<Uuid>,
+) -> Result<Vec<StaffPerformance>, AppError> {
+    let (from, to) = tz.day_bounds(start, end);
+    let backend = db.backend();
+    let score = match ranking {
+        StaffRanking::Revenue => "revenue",
+        StaffRanking::NetRevenue => "net_revenue",
+        StaffRanking::Transactions => "transactions",
+        StaffRanking::AverageBasket => "average_basket",
+        StaffRanking::Discount => "discount",
+        StaffRanking::Refunds => "refunds",
+    };
+    let query = format!(
+        "WITH totals AS (
+             SELECT staff_responsible AS staff_id,
+                    CAST(SUM(total_resolved) AS BIGINT) AS revenue,
+                    COUNT(*) AS transactions,
+                    CAST(SUM(discount + (SELECT COALESCE(SUM(discount), 0) FROM sale_items WHERE sale_items.sale_id = sales.id)) AS BIGINT) AS discount
+             FROM sales
+             WHERE {}
+             GROUP BY staff_responsible
+         ),
+         refunds AS (
+             SELECT sales.staff_responsible AS staff_id, CAST(SUM(voided_sale_items.total_resolved) AS BIGINT) AS refunds
+             FROM voided_sale_items
+             JOIN sales ON sales.id = voided_sale_items.sale_id
+             WHERE {}
+             GROUP BY sales.staff_responsible
+         ),
+         entries AS (
+             SELECT staff.id, staff.first_name || ' ' || staff.last_name AS staff_name,
+                    COALESCE(totals.revenue, 0) AS revenue,
+                    COALESCE(totals.transactions, 0) AS transactions,
+                    COALESCE(totals.discount, 0) AS discount,
+                    COALESCE(refunds.refunds, 0) AS refunds
+             FROM staff
+             LEFT JOIN totals ON totals.staff_id = staff.id
+             LEFT JOIN refunds ON refunds.staff_id = staff.id
+             WHERE staff.archived_at IS NULL OR totals.staff_id IS NOT NULL OR refunds.staff_id IS NOT NULL
+         ),
+         scored AS (
+             SELECT entries.*,
+                    revenue - refunds AS net_revenue,
+                    CASE WHEN transactions > 0 THEN revenue / transactions ELSE 0 END AS average_basket
+             FROM entries
+         ),
+         ranked AS (
+             SELECT scored.*, ROW_NUMBER() OVER (ORDER BY {} DESC, staff_name, id) AS position
+             FROM scored
+         )
+         SELECT * FROM ranked{} ORDER BY position",
+        backend.in_range("date_and_time"),
+        backend.in_range("voided_sale_items.voided_at"),
+        score,
+        if staff_id.is_some() { " WHERE id = ?" } else { "" }
+    );
+    let mut sql_query = db::query(&query).bind(from).bind(to).bind(from).bind(to);
+    if let Some(staff_id) = staff_id {
+        sql_query = sql_query.bind(staff_id);
+    }
+    let rows = sql_query.fetch_all(db).await?;
+
+    let mut entries = Vec::with_capacity(rows.len());
+    for row in rows {
+        entries.push(StaffPerformance {
+            rank: row.try_get("position")?,
+            staff_id: parse_uuid(row.try_get("id")?)?,
+            staff_name: row.try_get("staff_name")?,
+            revenue_cents: row.try_get("revenue")?,
+            transactions: row.try_get("transactions")?,
+            average_basket_cents: row.try_get("average_basket")?,
+            discount_cents: row.try_get("discount")?,
+            refunds_cents: row.try_get("refunds")?,
+            net_revenue_cents: row.try_get("net_revenue")?,
+        });
+    }
+    Ok(entries)
+}
+
+/// Sales total and transaction count of staff member `staff_id` per local
+/// day from `start` to `end`, keyed by `YYYY-MM-DD`. Days without sales are
+/// left out.
+pub async fn staff_daily(
+    db: &Db,
+    tz: BusinessTz,
+    staff_id: Uuid,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<(String, i64, i64)>, AppError> {
+    let backend = db.backend();
+    let query = format!(
+        "SELECT {} AS day, SUM(total_resolved) AS total, COUNT(*) AS count
+         FROM sales
+         WHERE staff_responsible = ? AND {}
+         GROUP BY day",
+        backend.date(&backend.local_time(tz, "date_and_time", start, end)),
+        backend.in_range("date_and_time")
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(&query).bind(staff_id).bind(from).bind(to).fetch_all(db).await?;
+
+    let mut days = Vec::with_capacity(rows.len());
+    for row in rows {
+        days.push((row.try_get("day")?, row.try_get("total").unwrap_or(0), row.try_get("count").unwrap_or(0)));
+    }
+    Ok(days)
+}
+
+/// What staff member `staff_id` sold of each product from `start` to `end`,
+/// best-selling first, by the name each item was sold under.
+pub async fn staff_product_sales(
+    db: &Db,
+    tz: BusinessTz,
+    staff_id: Uuid,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<ProductSalesSummary>, AppError> {
+    let query = format!(
+        "SELECT COALESCE(sale_items.product_name, products.name) AS product_name, SUM(sale_items.quantity) AS total_quantity, SUM(sale_items.total_resolved) AS total_amount,
+                SUM(sale_items.quantity * COALESCE(sale_items.cost_per_item, products.cost_price_cents, 0)) AS total_cost
+         FROM sale_items
+         JOIN sales ON sales.id = sale_items.sale_id
+         LEFT JOIN products ON products.id = sale_items.product_id
+         WHERE sales.staff_responsible = ? AND {}
+         GROUP BY 1
+         ORDER BY total_amount DESC",
+        db.backend().in_range("sales.date_and_time")
+    );
+    let (from, to) = tz.day_bounds(start, end);
+    let rows = db::query(query).bind(staff_id).bind(from).bind(to).fetch_all(db).await?;
+    Ok(rows.iter().map(product_sales_from_row).collect())
+}
+
+/// Reads `product_name`, `total_quantity`, `total_amount` and `total_cost`.
+fn product_sales_from_row(row: &Row) -> ProductSalesSummary {
+    let total_amount_cents: i64 = row.try_get("total_amount").unwrap_or(0);
+    let total_cost_cents: i64 = row.try_get("total_cost").unwrap_or(0);
+    let gross_profit_cents = total_amount_cents - total_cost_cents;
+    ProductSalesSummary {
+        product_name: row.try_get("product_name").unwrap_or_default(),
+        total_quantity: row.try_get("total_quantity").unwrap_or(0),
+        total_amount_cents,
+        total_cost_cents,
+        gross_profit_cents,
+        margin_percent: margin_percent(total_amount_cents, gross_profit_cents),
+        quantity_change: None,
+        amount_change: None,
+    }
+}
@@ -0,0 +1,204 @@
+//! The items of sales. Each item snapshots the product's name, price and
+//! cost when it is sold, so it reads the same however the product changes
+//! later; voided items move to `voided_sale_items` for refund reporting.
+
+use chrono::{DateTime, Utc};
+use shared::models::SaleItem;
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Backend, Conn, Db, Row};
+use crate::error::AppError;
+
+/// Items with their snapshots, or the product's current values for items
+/// stored before snapshots were taken.
+const SELECT: &str = "SELECT sale_items.id, sale_items.sale_id, sale_items.product_id, sale_items.customer_id, sale_items.date_of_sale, sale_items.quantity, sale_items.discount, sale_items.total_cents, sale_items.total_resolved, sale_items.note, COALESCE(sale_items.product_name, products.name) AS product_name, COALESCE(sale_items.price_per_item, products.price_cents) AS price_per_item, COALESCE(sale_items.cost_per_item, products.cost_price_cents) AS cost_per_item
+     FROM sale_items
+     LEFT JOIN products ON sale_items.product_id = products.id";
+
+/// A page of the items sold from `from` up to `to`, latest first, and how
+/// many there are in all.
+pub async fn list(
+    db: &Db,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<SaleItem>, i64), AppError> {
+    let total = db::query_scalar(format!("SELECT COUNT(*) FROM sale_items{}", in_range(db.backend())))
+        .bind(from)
+        .bind(to)
+        .fetch_one(db)
+        .await?;
+    let rows = list_query(db.backend(), from, to).bind(limit).bind(offset).fetch_all(db).await?;
+    let items = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((items, total))
+}
+
+/// Every item [`list`] would list, as the database cursor yields them.
+pub fn stream(
+    db: &Db,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> impl Stream<Item = Result<SaleItem, AppError>> + Send + 'static {
+    list_query(db.backend(), from, to).bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// What the items sold from `from` up to `to` came to, in cents.
+pub async fn total_cents(db: &Db, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<i64, AppError> {
+    Ok(db::query_scalar(format!("SELECT COALESCE(SUM(total_cents), 0) FROM sale_items{}", in_range(db.backend())))
+        .bind(from)
+        .bind(to)
+        .fetch_one(db)
+        .await?)
+}
+
+fn in_range(backend: Backend) -> String {
+    format!(" WHERE {}", backend.in_range("date_of_sale"))
+}
+
+fn list_query(backend: Backend, from: DateTime<Utc>, to: DateTime<Utc>) -> db::Query {
+    db::query(format!(
+        "SELECT id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note FROM sale_items{} ORDER BY date_of_sale DESC, id DESC LIMIT ? OFFSET ?",
+        in_range(backend)
+    ))
+    .bind(from)
+    .bind(to)
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<SaleItem, AppError> {
+    let row = db::query(format!("{} WHERE sale_items.id = ?", SELECT))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Sale item"))?;
+    from_row(&row)
+}
+
+/// The items of sale `sale_id`.
+pub async fn for_sale<'c>(conn: impl Into<Conn<'c>>, sale_id: Uuid) -> Result<Vec<SaleItem>, AppError> {
+    let rows = db::query(format!("{} WHERE sale_items.sale_id = ?", SELECT))
+        .bind(sale_id)
+        .fetch_all(conn)
+        .await?;
+    rows.iter().map(from_row).collect()
+}
+
+/// Records a sold item. Its name, price and cost are snapshotted from the
+/// product as it is now wherever `item` leaves them unset.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, item: &SaleItem) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO sale_items (id, sale_id, product_id, customer_id, date_of_sale, quantity, discount, total_cents, total_resolved, note, product_name, price_per_item, cost_per_item) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, (SELECT name FROM products WHERE id = ?)), COALESCE(?, (SELECT price_cents FROM products WHERE id = ?)), COALESCE(?, (SELECT cost_price_cents FROM products WHERE id = ?)))",
+    )
+    .bind(item.id)
+    .bind(item.sale_id)
+    .bind(item.product_id)
+    .bind(item.customer_id)
+    .bind(item.date_of_sale)
+    .bind(item.quantity)
+    .bind(item.discount)
+    .bind(item.total_cents)
+    .bind(item.total_resolved)
+    .bind(&item.note)
+    .bind(&item.product_name)
+    .bind(item.product_id)
+    .bind(item.price_per_item)
+    .bind(item.product_id)
+    .bind(item.cost_per_item)
+    .bind(item.product_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Saves `item`'s product, customer, quantity and amounts; its snapshots
+/// are left as they are, see [`snapshot_product`].
+pub async fn update<'c>(conn: impl Into<Conn<'c>>, item: &SaleItem) -> Result<(), AppError> {
+    let updated = db::query(
+        "UPDATE sale_items SET product_id = ?, customer_id = ?, date_of_sale = ?, quantity = ?, discount = ?, total_cents = ?, total_resolved = ?, note = ? WHERE id = ?",
+    )
+    .bind(item.product_id)
+    .bind(item.customer_id)
+    .bind(item.date_of_sale)
+    .bind(item.quantity)
+    .bind(item.discount)
+    .bind(item.total_cents)
+    .bind(item.total_resolved)
+    .bind(&item.note)
+    .bind(item.id)
+    .execute(conn)
+    .await?;
+    if updated == 0 {
+        return Err(AppError::not_found("Sale item"));
+    }
+    Ok(())
+}
+
+/// Snapshots product `product_id`'s current name and cost onto item `id`,
+/// sold at `price_cents`.
+pub async fn snapshot_product<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    product_id: Uuid,
+    price_cents: i64,
+) -> Result<(), AppError> {
+    db::query(
+        "UPDATE sale_items SET product_name = (SELECT name FROM products WHERE id = ?), price_per_item = ?, cost_per_item = (SELECT cost_price_cents FROM products WHERE id = ?) WHERE id = ?",
+    )
+    .bind(product_id)
+    .bind(price_cents)
+    .bind(product_id)
+    .bind(id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Moves `item` to the voided items, voided by `voided_by` at `now` with the
+/// refund taken from the drawer of shift `shift_id`, if any.
+pub async fn void<'c>(
+    conn: impl Into<Conn<'c>>,
+    item: &SaleItem,
+    voided_by: Uuid,
+    now: DateTime<Utc>,
+    shift_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    let mut conn = conn.into();
+    db::query(
+        "INSERT INTO voided_sale_items (id, sale_id, product_id, date_of_sale, quantity, total_resolved, voided_by, voided_at, shift_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(item.id)
+    .bind(item.sale_id)
+    .bind(item.product_id)
+    .bind(item.date_of_sale)
+    .bind(item.quantity)
+    .bind(item.total_resolved)
+    .bind(voided_by)
+    .bind(now)
+    .bind(shift_id)
+    .execute(&mut conn)
+    .await?;
+    db::query("DELETE FROM sale_items WHERE id = ?").bind(item.id).execute(conn).await?;
+    Ok(())
+}
+
+fn from_row(row: &Row) -> Result<SaleItem, AppError> {
+    let sale_id: Option<String> = row.get("sale_id");
+    let customer_id: Option<String> = row.get("customer_id");
+    Ok(SaleItem {
+        id: parse_uuid(row.get("id"))?,
+        sale_id: sale_id.map(parse_uuid).transpose()?,
+        product_id: parse_uuid(row.get("product_id"))?,
+        customer_id: customer_id.map(parse_uuid).transpose()?,
+        date_of_sale: row.get("date_of_sale"),
+        quantity: row.get("quantity"),
+        discount: row.get("discount"),
+        total_cents: row.get("total_cents"),
+        total_resolved: row.get("total_resolved"),
+        note: row.get("note"),
+        product_name: row.try_get("product_name").ok(),
+        price_per_item: row.try_get("price_per_item").ok(),
+        cost_per_item: row.try_get("cost_per_item").ok(),
+    })
+}
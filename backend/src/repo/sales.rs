@@ -0,0 +1,207 @@
+//! Sales transactions: who sold what to whom, through which channel and
+//! shift. Their items live in [`super::sale_items`].
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{PaymentMethod, Sale, SalesChannel};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Backend, Conn, Db, Row};
+use crate::error::AppError;
+
+/// Which sales [`list`] returns; `None` fields match every sale.
+#[derive(Default)]
+pub struct SaleFilter {
+    /// A full-text query from [`crate::fts::search_query`] over `sales_fts`
+    /// and `customers_fts`, matching a sale or its customer
+    pub search: Option<String>,
+    /// Only sales made at or after this instant
+    pub start: Option<DateTime<Utc>>,
+    /// Only sales made at or before this instant
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// A page of the sales matching `filter`, latest first, and how many match
+/// in all. Their items are left out.
+pub async fn list(db: &Db, filter: &SaleFilter, limit: i64, offset: i64) -> Result<(Vec<Sale>, i64), AppError> {
+    let (count, query) = list_queries(db.backend(), filter);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    let sales = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((sales, total))
+}
+
+/// Every sale matching `filter`, in [`list`]'s order, as the database
+/// cursor yields them.
+pub fn stream(db: &Db, filter: &SaleFilter) -> impl Stream<Item = Result<Sale, AppError>> + Send + 'static {
+    let (_, query) = list_queries(db.backend(), filter);
+    query.bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// The count and the listing of the sales matching `filter`, the listing
+/// still to be bound its `LIMIT` and `OFFSET`.
+fn list_queries(backend: Backend, filter: &SaleFilter) -> (db::QueryScalar<i64>, db::Query) {
+    let mut conditions = Vec::new();
+    if filter.search.is_some() {
+        conditions.push(format!(
+            "(sales.id IN (SELECT sale_id FROM sales_fts{0} WHERE {1}) \
+              OR sales.customer_id IN (SELECT customer_id FROM customers_fts{0} WHERE {2}))",
+            backend.fts_source(),
+            backend.fts_match("sales_fts"),
+            backend.fts_match("customers_fts")
+        ));
+    }
+    if filter.start.is_some() {
+        conditions.push(format!("{} >= {}", backend.instant("sales.date_and_time"), backend.instant("?")));
+    }
+    if filter.end.is_some() {
+        conditions.push(format!("{} <= {}", backend.instant("sales.date_and_time"), backend.instant("?")));
+    }
+    let conditions = super::where_clause(&conditions.iter().map(String::as_str).collect::<Vec<_>>());
+
+    let from = "FROM sales LEFT JOIN customers ON sales.customer_id = customers.id";
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) {}{}", from, conditions));
+    let mut query = db::query(format!(
+        "SELECT sales.* {}{} ORDER BY sales.date_and_time DESC, sales.id DESC LIMIT ? OFFSET ?",
+        from, conditions
+    ));
+    if let Some(search) = &filter.search {
+        count = count.bind(search).bind(search);
+        query = query.bind(search).bind(search);
+    }
+    for instant in filter.start.iter().chain(&filter.end) {
+        count = count.bind(instant);
+        query = query.bind(instant);
+    }
+    (count, query)
+}
+
+/// A page of the sales staff member `staff_id` made from `from` up to `to`,
+/// latest first, and how many there are in all. Their items are left out.
+pub async fn list_by_staff(
+    db: &Db,
+    staff_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Sale>, i64), AppError> {
+    let total = db::query_scalar(format!("SELECT COUNT(*) FROM sales{}", by_staff(db.backend())))
+        .bind(staff_id)
+        .bind(from)
+        .bind(to)
+        .fetch_one(db)
+        .await?;
+    let rows = by_staff_query(db.backend(), staff_id, from, to)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(db)
+        .await?;
+    let sales = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((sales, total))
+}
+
+/// Every sale [`list_by_staff`] would list, as the database cursor yields
+/// them.
+pub fn stream_by_staff(
+    db: &Db,
+    staff_id: Uuid,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> impl Stream<Item = Result<Sale, AppError>> + Send + 'static {
+    by_staff_query(db.backend(), staff_id, from, to).bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+fn by_staff(backend: Backend) -> String {
+    format!(" WHERE staff_responsible = ? AND {}", backend.in_range("date_and_time"))
+}
+
+fn by_staff_query(backend: Backend, staff_id: Uuid, from: DateTime<Utc>, to: DateTime<Utc>) -> db::Query {
+    db::query(format!(
+        "SELECT * FROM sales{} ORDER BY date_and_time DESC, id DESC LIMIT ? OFFSET ?",
+        by_staff(backend)
+    ))
+    .bind(staff_id)
+    .bind(from)
+    .bind(to)
+}
+
+/// Sale `id` with its items.
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Sale, AppError> {
+    let mut conn = conn.into();
+    let mut sale = find(&mut conn, id).await?.ok_or_else(|| AppError::not_found("Sale"))?;
+    sale.sale_items = super::sale_items::for_sale(conn, id).await?;
+    Ok(sale)
+}
+
+/// Sale `id` without its items, if there is one.
+pub async fn find<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<Sale>, AppError> {
+    db::query("SELECT * FROM sales WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .as_ref()
+        .map(from_row)
+        .transpose()
+}
+
+/// Whether a sale with `id` has been recorded.
+pub async fn exists<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<bool, AppError> {
+    let found: Option<i64> = db::query_scalar("SELECT 1 FROM sales WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+    Ok(found.is_some())
+}
+
+/// Records `sale`; its items are stored with [`super::sale_items::insert`].
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, sale: &Sale) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO sales (id, customer_id, date_and_time, total_cents, discount, total_resolved, sales_channel, staff_responsible, company_branch, car_number, receipt_number, payment_method, shift_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(sale.id)
+    .bind(sale.customer_id)
+    .bind(sale.date_and_time)
+    .bind(sale.total_cents)
+    .bind(sale.discount)
+    .bind(sale.total_resolved)
+    .bind(sale.sales_channel.to_string())
+    .bind(sale.staff_responsible)
+    .bind(&sale.company_branch)
+    .bind(&sale.car_number)
+    .bind(&sale.receipt_number)
+    .bind(sale.payment_method.as_str())
+    .bind(sale.shift_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn from_row(row: &Row) -> Result<Sale, AppError> {
+    let sales_channel: String = row.get("sales_channel");
+    let customer_id: Option<String> = row.get("customer_id");
+    let payment_method: String = row.get("payment_method");
+    let shift_id: Option<String> = row.get("shift_id");
+
+    Ok(Sale {
+        id: parse_uuid(row.get("id"))?,
+        customer_id: customer_id.map(parse_uuid).transpose()?,
+        date_and_time: row.get("date_and_time"),
+        sale_items: vec![],
+        total_cents: row.get("total_cents"),
+        discount: row.get("discount"),
+        total_resolved: row.get("total_resolved"),
+        sales_channel: SalesChannel::from_str(&sales_channel)
+            .map_err(|_| AppError::internal(format!("unknown sales channel {:?}", sales_channel)))?,
+        staff_responsible: parse_uuid(row.get("staff_responsible"))?,
+        company_branch: row.get("company_branch"),
+        car_number: row.get("car_number"),
+        receipt_number: row.get("receipt_number"),
+        payment_method: PaymentMethod::from_str(&payment_method)
+            .map_err(|_| AppError::internal(format!("unknown payment method {:?}", payment_method)))?,
+        shift_id: shift_id.map(parse_uuid).transpose()?,
+    })
+}
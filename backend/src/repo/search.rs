@@ -0,0 +1,140 @@
+//! Ranked full-text search across products, customers and sales at once,
+//! over the `*_fts` indexes maintained by [`crate::fts`].
+
+use std::str::FromStr;
+
+use shared::models::{SearchHit, SearchKind};
+use tokio_stream::{Stream, StreamExt};
+
+use super::parse_uuid;
+use crate::db::{self, Backend, Db, Row};
+use crate::error::AppError;
+
+/// What [`list`] searches for.
+pub struct SearchFilter {
+    /// A full-text query from [`crate::fts::search_query`] over
+    /// `products_fts`, `customers_fts` and `sales_fts`
+    pub query: String,
+    /// Only hits of this kind; every kind when `None`
+    pub kind: Option<SearchKind>,
+    pub include_archived: bool,
+}
+
+/// A page of the hits for `filter`, best first, and how many there are in
+/// all.
+pub async fn list(db: &Db, filter: &SearchFilter, limit: i64, offset: i64) -> Result<(Vec<SearchHit>, i64), AppError> {
+    let (count, query) = list_queries(db.backend(), filter);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    let hits = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((hits, total))
+}
+
+/// Every hit for `filter`, in [`list`]'s order, as the database cursor
+/// yields them.
+pub fn stream(db: &Db, filter: &SearchFilter) -> impl Stream<Item = Result<SearchHit, AppError>> + Send + 'static {
+    let (_, query) = list_queries(db.backend(), filter);
+    query.bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// The count and the listing of the hits for `filter`, the listing still to
+/// be bound its `LIMIT` and `OFFSET`.
+fn list_queries(backend: Backend, filter: &SearchFilter) -> (db::QueryScalar<i64>, db::Query) {
+    let (products_archived, customers_archived) = if filter.include_archived {
+        ("", "")
+    } else {
+        (" AND products.archived_at IS NULL", " AND customers.archived_at IS NULL")
+    };
+
+    // Column weights favour names and identifiers over free text.
+    let sources = [
+        (
+            SearchKind::Product,
+            format!(
+                "SELECT 'product' AS kind, products_fts.product_id AS id, products_fts.name AS title, \
+                        {} AS snippet, {} AS rank \
+                 FROM products_fts JOIN products ON products.id = products_fts.product_id{} \
+                 WHERE {}{}",
+                backend.fts_snippet("products_fts", &["name", "description", "details"]),
+                backend.fts_rank("products_fts", &[0.0, 10.0, 2.0, 1.0]),
+                backend.fts_source(),
+                backend.fts_match("products_fts"),
+                products_archived
+            ),
+        ),
+        (
+            SearchKind::Customer,
+            format!(
+                "SELECT 'customer' AS kind, customers_fts.customer_id AS id, customers_fts.name AS title, \
+                        {} AS snippet, {} AS rank \
+                 FROM customers_fts JOIN customers ON customers.id = customers_fts.customer_id{} \
+                 WHERE {}{}",
+                backend.fts_snippet("customers_fts", &["name", "mobile_number", "email", "details"]),
+                backend.fts_rank("customers_fts", &[0.0, 10.0, 5.0, 5.0, 1.0]),
+                backend.fts_source(),
+                backend.fts_match("customers_fts"),
+                customers_archived
+            ),
+        ),
+        (
+            SearchKind::Sale,
+            format!(
+                "SELECT 'sale' AS kind, sales_fts.sale_id AS id, 'Receipt #' || sales_fts.receipt_number AS title, \
+                        {} AS snippet, {} AS rank \
+                 FROM sales_fts{} WHERE {}",
+                backend.fts_snippet("sales_fts", &["receipt_number", "car_number"]),
+                backend.fts_rank("sales_fts", &[0.0, 10.0, 5.0]),
+                backend.fts_source(),
+                backend.fts_match("sales_fts")
+            ),
+        ),
+    ];
+    let selected: Vec<&str> = sources
+        .iter()
+        .filter(|(source, _)| filter.kind.is_none_or(|kind| kind == *source))
+        .map(|(_, sql)| sql.as_str())
+        .collect();
+    let union = selected.join(" UNION ALL ");
+
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) FROM ({}) AS hits", union));
+    let mut query = db::query(format!("SELECT * FROM ({}) AS hits ORDER BY rank, title, id LIMIT ? OFFSET ?", union));
+    for _ in &selected {
+        count = count.bind(&filter.query);
+        query = query.bind(&filter.query);
+    }
+    (count, query)
+}
+
+// Snippet delimiters; replaced by `<mark>` tags after the text is escaped.
+const MARK_START: char = '\u{2}';
+const MARK_END: char = '\u{3}';
+
+fn highlight_snippet(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            MARK_START => out.push_str("<mark>"),
+            MARK_END => out.push_str("</mark>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn from_row(row: &Row) -> Result<SearchHit, AppError> {
+    let kind: String = row.get("kind");
+    let snippet: String = row.get("snippet");
+    Ok(SearchHit {
+        kind: SearchKind::from_str(&kind)
+            .map_err(|_| AppError::internal(format!("unknown search kind {:?}", kind)))?,
+        id: parse_uuid(row.get("id"))?,
+        title: row.get("title"),
+        snippet: highlight_snippet(&snippet),
+        rank: row.get("rank"),
+    })
+}
@@ -0,0 +1,516 @@
+//! Till shifts, the cash moved in and out of their drawers, and their X and
+//! Z reports. A Z report is stored on the shift as JSON when it closes, so it
+//! reads the same however the sales behind it change later.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{
+    CashMovement, CashMovementKind, ChannelTotal, PaymentMethod, SalesChannel, Shift, ShiftReport,
+    ShiftReportKind, TenderTotal,
+};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+const COLUMNS: &str = "id, register, company_branch, opened_by, opened_at, opening_float_cents, closed_by, closed_at, counted_cash_cents, note";
+
+const CASH_MOVEMENT_COLUMNS: &str = "id, shift_id, kind, amount_cents, reason, recorded_by, recorded_at";
+
+/// Which shifts [`list`] returns; `None` fields match every shift.
+#[derive(Default)]
+pub struct ShiftFilter {
+    /// Only open (`true`) or closed (`false`) shifts
+    pub open: Option<bool>,
+    pub register: Option<String>,
+    pub company_branch: Option<String>,
+}
+
+/// A page of the shifts matching `filter`, most recently opened first, and
+/// how many match in all.
+pub async fn list(db: &Db, filter: &ShiftFilter, limit: i64, offset: i64) -> Result<(Vec<Shift>, i64), AppError> {
+    let (count, query) = list_queries(filter);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    let shifts = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((shifts, total))
+}
+
+/// Every shift matching `filter`, in [`list`]'s order, as the database
+/// cursor yields them.
+pub fn stream(db: &Db, filter: &ShiftFilter) -> impl Stream<Item = Result<Shift, AppError>> + Send + 'static {
+    let (_, query) = list_queries(filter);
+    query.bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+/// The count and the listing of the shifts matching `filter`, the listing
+/// still to be bound its `LIMIT` and `OFFSET`.
+fn list_queries(filter: &ShiftFilter) -> (db::QueryScalar<i64>, db::Query) {
+    let mut conditions = Vec::new();
+    let mut args = Vec::new();
+    match filter.open {
+        Some(true) => conditions.push("closed_at IS NULL"),
+        Some(false) => conditions.push("closed_at IS NOT NULL"),
+        None => {}
+    }
+    if let Some(register) = &filter.register {
+        conditions.push("register = ?");
+        args.push(register);
+    }
+    if let Some(branch) = &filter.company_branch {
+        conditions.push("company_branch = ?");
+        args.push(branch);
+    }
+    let filter = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    };
+
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) FROM shifts{}", filter));
+    let mut query = db::query(format!(
+        "SELECT {} FROM shifts{} ORDER BY opened_at DESC, id DESC LIMIT ? OFFSET ?",
+        COLUMNS, filter
+    ));
+    for arg in args {
+        count = count.bind(arg);
+        query = query.bind(arg);
+    }
+    (count, query)
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Shift, AppError> {
+    let row = db::query(format!("SELECT {} FROM shifts WHERE id = ?", COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Shift"))?;
+    from_row(&row)
+}
+
+/// The shift with `id`, if it exists and is still open. In a transaction
+/// the shift stays locked open until it ends, see [`close`].
+pub async fn find_open<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<Shift>, AppError> {
+    let conn = conn.into();
+    let lock = conn.backend().for_update();
+    db::query(format!("SELECT {} FROM shifts WHERE id = ? AND closed_at IS NULL{}", COLUMNS, lock))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .as_ref()
+        .map(from_row)
+        .transpose()
+}
+
+/// The shift the staff member `staff_id` has open, if any, locked like
+/// [`find_open`].
+pub async fn open_for_staff<'c>(conn: impl Into<Conn<'c>>, staff_id: &str) -> Result<Option<Shift>, AppError> {
+    let conn = conn.into();
+    let lock = conn.backend().for_update();
+    db::query(format!("SELECT {} FROM shifts WHERE opened_by = ? AND closed_at IS NULL{}", COLUMNS, lock))
+        .bind(Uuid::parse_str(staff_id).ok())
+        .fetch_optional(conn)
+        .await?
+        .as_ref()
+        .map(from_row)
+        .transpose()
+}
+
+/// Whether `register` in `company_branch` has a shift open.
+pub async fn register_is_open<'c>(
+    conn: impl Into<Conn<'c>>,
+    company_branch: &str,
+    register: &str,
+) -> Result<bool, AppError> {
+    let open: Option<i64> =
+        db::query_scalar("SELECT 1 FROM shifts WHERE company_branch = ? AND register = ? AND closed_at IS NULL")
+            .bind(company_branch)
+            .bind(register)
+            .fetch_optional(conn)
+            .await?;
+    Ok(open.is_some())
+}
+
+/// Records a newly opened shift.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, shift: &Shift) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO shifts (id, register, company_branch, opened_by, opened_at, opening_float_cents) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(shift.id)
+    .bind(&shift.register)
+    .bind(&shift.company_branch)
+    .bind(shift.opened_by)
+    .bind(shift.opened_at)
+    .bind(shift.opening_float_cents)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn insert_cash_movement<'c>(conn: impl Into<Conn<'c>>, movement: &CashMovement) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO cash_movements (id, shift_id, kind, amount_cents, reason, recorded_by, recorded_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(movement.id)
+    .bind(movement.shift_id)
+    .bind(movement.kind.as_str())
+    .bind(movement.amount_cents)
+    .bind(&movement.reason)
+    .bind(movement.recorded_by)
+    .bind(movement.recorded_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Closes shift `id` if it is still open and returns it as closed. Anything
+/// written into a shift first reads it through [`find_open`] or
+/// [`open_for_staff`] in the same transaction, so taking the Z report in the
+/// transaction that closes it sees every sale and cash movement: on
+/// PostgreSQL the close waits for the row locks those took, and they then
+/// find the shift closed; on SQLite whichever transaction writes first holds
+/// the database until it commits.
+pub async fn close<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    closed_by: Uuid,
+    closed_at: DateTime<Utc>,
+    counted_cash_cents: i64,
+    note: Option<&str>,
+) -> Result<Option<Shift>, AppError> {
+    db::query(format!(
+        "UPDATE shifts SET closed_by = ?, closed_at = ?, counted_cash_cents = ?, note = ? WHERE id = ? AND closed_at IS NULL RETURNING {}",
+        COLUMNS
+    ))
+    .bind(closed_by)
+    .bind(closed_at)
+    .bind(counted_cash_cents)
+    .bind(note)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?
+    .as_ref()
+    .map(from_row)
+    .transpose()
+}
+
+/// Keeps the Z report taken when shift `report.shift` closed.
+pub async fn store_z_report<'c>(conn: impl Into<Conn<'c>>, report: &ShiftReport) -> Result<(), AppError> {
+    db::query("UPDATE shifts SET z_report = ? WHERE id = ?")
+        .bind(serde_json::to_string(report).map_err(AppError::internal)?)
+        .bind(report.shift.id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// The stored Z report of a closed shift, or an X report of an open one.
+pub async fn load_report(db: &Db, id: Uuid) -> Result<ShiftReport, AppError> {
+    let stored: Option<Option<String>> = db::query_scalar("SELECT z_report FROM shifts WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+    match stored {
+        None => Err(AppError::not_found("Shift")),
+        Some(Some(report)) => serde_json::from_str(&report).map_err(AppError::internal),
+        Some(None) => {
+            let shift = fetch(db, id).await?;
+            report(db, shift, ShiftReportKind::X, Utc::now()).await
+        }
+    }
+}
+
+/// The takings and cash of `shift` as of `generated_at`.
+pub async fn report<'c>(
+    conn: impl Into<Conn<'c>>,
+    shift: Shift,
+    kind: ShiftReportKind,
+    generated_at: DateTime<Utc>,
+) -> Result<ShiftReport, AppError> {
+    let mut conn = conn.into();
+    let id = shift.id;
+
+    let totals = db::query(
+        "SELECT COUNT(*) AS count, COALESCE(SUM(total_resolved), 0) AS total, COALESCE(SUM(discount), 0) AS discount FROM sales WHERE shift_id = ?",
+    )
+    .bind(id)
+    .fetch_one(&mut conn)
+    .await?;
+
+    let mut by_tender = Vec::new();
+    for row in db::query(
+        "SELECT payment_method, COUNT(*) AS count, SUM(total_resolved) AS total FROM sales WHERE shift_id = ? GROUP BY payment_method ORDER BY total DESC",
+    )
+    .bind(id)
+    .fetch_all(&mut conn)
+    .await?
+    {
+        let payment_method: String = row.get("payment_method");
+        by_tender.push(TenderTotal {
+            payment_method: PaymentMethod::from_str(&payment_method)
+                .map_err(|_| AppError::internal(format!("unknown payment method {:?}", payment_method)))?,
+            count: row.get("count"),
+            total_cents: row.get("total"),
+        });
+    }
+
+    let mut by_channel = Vec::new();
+    for row in db::query(
+        "SELECT sales_channel, COUNT(*) AS count, SUM(total_resolved) AS total FROM sales WHERE shift_id = ? GROUP BY sales_channel ORDER BY total DESC",
+    )
+    .bind(id)
+    .fetch_all(&mut conn)
+    .await?
+    {
+        let sales_channel: String = row.get("sales_channel");
+        by_channel.push(ChannelTotal {
+            sales_channel: SalesChannel::from_str(&sales_channel)
+                .map_err(|_| AppError::internal(format!("unknown sales channel {:?}", sales_channel)))?,
+            count: row.get("count"),
+            total_cents: row.get("total"),
+        });
+    }
+
+    // Items voided in this shift: voids when their sale was rung up in it
+    // too, refunds otherwise. Items sold without a transaction have no tender
+    // and are not taken to have been paid back in cash.
+    let (mut refunds_count, mut refunds_cents, mut voids_count, mut voids_cents, mut cash_returned_cents) = (0, 0, 0, 0, 0);
+    for row in db::query(
+        "SELECT sales.shift_id IS NOT DISTINCT FROM voided_sale_items.shift_id AS same_shift, COUNT(*) AS count,
+                SUM(voided_sale_items.total_resolved) AS total,
+                SUM(CASE WHEN sales.payment_method = 'cash' THEN voided_sale_items.total_resolved ELSE 0 END) AS cash
+         FROM voided_sale_items
+         LEFT JOIN sales ON sales.id = voided_sale_items.sale_id
+         WHERE voided_sale_items.shift_id = ?
+         GROUP BY same_shift",
+    )
+    .bind(id)
+    .fetch_all(&mut conn)
+    .await?
+    {
+        let (count, total): (i64, i64) = (row.get("count"), row.get("total"));
+        if row.get::<bool, _>("same_shift") {
+            (voids_count, voids_cents) = (count, total);
+        } else {
+            (refunds_count, refunds_cents) = (count, total);
+        }
+        cash_returned_cents += row.get::<i64, _>("cash");
+    }
+
+    let cash_movements = db::query(format!(
+        "SELECT {} FROM cash_movements WHERE shift_id = ? ORDER BY recorded_at ASC, id ASC",
+        CASH_MOVEMENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&mut conn)
+    .await?
+    .iter()
+    .map(cash_movement_from_row)
+    .collect::<Result<Vec<_>, _>>()?;
+    let moved = |kind: CashMovementKind| -> i64 {
+        cash_movements.iter().filter(|movement| movement.kind == kind).map(|movement| movement.amount_cents).sum()
+    };
+    let (cash_in_cents, payouts_cents, drops_cents) =
+        (moved(CashMovementKind::CashIn), moved(CashMovementKind::Payout), moved(CashMovementKind::Drop));
+
+    let gross_sales_cents: i64 = totals.get("total");
+    let cash_sales_cents = by_tender
+        .iter()
+        .filter(|tender| tender.payment_method == PaymentMethod::Cash)
+        .map(|tender| tender.total_cents)
+        .sum::<i64>();
+    let expected_cash_cents =
+        shift.opening_float_cents + cash_sales_cents + cash_in_cents - cash_returned_cents - payouts_cents - drops_cents;
+    let counted_cash_cents = shift.counted_cash_cents;
+
+    Ok(ShiftReport {
+        kind,
+        generated_at,
+        sales_count: totals.get("count"),
+        gross_sales_cents,
+        discount_cents: totals.get("discount"),
+        by_tender,
+        by_channel,
+        refunds_count,
+        refunds_cents,
+        voids_count,
+        voids_cents,
+        net_sales_cents: gross_sales_cents - refunds_cents - voids_cents,
+        opening_float_cents: shift.opening_float_cents,
+        cash_sales_cents,
+        cash_returned_cents,
+        cash_in_cents,
+        payouts_cents,
+        drops_cents,
+        expected_cash_cents,
+        counted_cash_cents,
+        over_short_cents: counted_cash_cents.map(|counted| counted - expected_cash_cents),
+        cash_movements,
+        shift,
+    })
+}
+
+fn from_row(row: &Row) -> Result<Shift, AppError> {
+    let closed_by: Option<String> = row.get("closed_by");
+    Ok(Shift {
+        id: parse_uuid(row.get("id"))?,
+        register: row.get("register"),
+        company_branch: row.get("company_branch"),
+        opened_by: parse_uuid(row.get("opened_by"))?,
+        opened_at: row.get("opened_at"),
+        opening_float_cents: row.get("opening_float_cents"),
+        closed_by: closed_by.map(parse_uuid).transpose()?,
+        closed_at: row.get("closed_at"),
+        counted_cash_cents: row.get("counted_cash_cents"),
+        note: row.get("note"),
+    })
+}
+
+fn cash_movement_from_row(row: &Row) -> Result<CashMovement, AppError> {
+    let kind: String = row.get("kind");
+    Ok(CashMovement {
+        id: parse_uuid(row.get("id"))?,
+        shift_id: parse_uuid(row.get("shift_id"))?,
+        kind: CashMovementKind::from_str(&kind)
+            .map_err(|_| AppError::internal(format!("unknown cash movement kind {:?}", kind)))?,
+        amount_cents: row.get("amount_cents"),
+        reason: row.get("reason"),
+        recorded_by: parse_uuid(row.get("recorded_by"))?,
+        recorded_at: row.get("recorded_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ADMIN_ID, databases, drop_database};
+
+    #[tokio::test]
+    async fn shifts_open_move_cash_and_close() {
+        for db in databases().await {
+            let backend = db.backend();
+            let admin = Uuid::parse_str(ADMIN_ID).unwrap();
+            let opened_at = Utc::now();
+            let shift = Shift {
+                id: Uuid::new_v4(),
+                register: "Till 1".to_string(),
+                company_branch: "Main".to_string(),
+                opened_by: admin,
+                opened_at,
+                opening_float_cents: 5000,
+                closed_by: None,
+                closed_at: None,
+                counted_cash_cents: None,
+                note: None,
+            };
+            let mut tx = db.begin().await.unwrap();
+            insert(&mut tx, &shift).await.unwrap();
+            insert_cash_movement(
+                &mut tx,
+                &CashMovement {
+                    id: Uuid::new_v4(),
+                    shift_id: shift.id,
+                    kind: CashMovementKind::Payout,
+                    amount_cents: 700,
+                    reason: "Milk".to_string(),
+                    recorded_by: admin,
+                    recorded_at: opened_at,
+                },
+            )
+            .await
+            .unwrap();
+            tx.commit().await.unwrap();
+
+            assert!(register_is_open(&db, "Main", "Till 1").await.unwrap(), "{:?}", backend);
+            assert!(!register_is_open(&db, "", "Till 1").await.unwrap(), "{:?}", backend);
+            assert_eq!(open_for_staff(&db, ADMIN_ID).await.unwrap().map(|open| open.id), Some(shift.id), "{:?}", backend);
+            assert_eq!(find_open(&db, shift.id).await.unwrap().map(|open| open.id), Some(shift.id), "{:?}", backend);
+
+            let x = load_report(&db, shift.id).await.unwrap();
+            assert!(matches!(x.kind, ShiftReportKind::X), "{:?}", backend);
+            assert_eq!((x.payouts_cents, x.expected_cash_cents), (700, 4300), "{:?}", backend);
+
+            let closed_at = Utc::now();
+            let mut tx = db.begin().await.unwrap();
+            let closed = close(&mut tx, shift.id, admin, closed_at, 4250, Some("Short")).await.unwrap().unwrap();
+            let z = report(&mut tx, closed, ShiftReportKind::Z, closed_at).await.unwrap();
+            store_z_report(&mut tx, &z).await.unwrap();
+            tx.commit().await.unwrap();
+            assert!(close(&db, shift.id, admin, closed_at, 0, None).await.unwrap().is_none(), "{:?}", backend);
+
+            assert!(find_open(&db, shift.id).await.unwrap().is_none(), "{:?}", backend);
+            assert!(open_for_staff(&db, ADMIN_ID).await.unwrap().is_none(), "{:?}", backend);
+            let stored = load_report(&db, shift.id).await.unwrap();
+            assert!(matches!(stored.kind, ShiftReportKind::Z), "{:?}", backend);
+            assert_eq!(stored.over_short_cents, Some(-50), "{:?}", backend);
+            assert_eq!(stored.shift.note.as_deref(), Some("Short"), "{:?}", backend);
+
+            let closed_only = ShiftFilter { open: Some(false), ..Default::default() };
+            let (shifts, total) = list(&db, &closed_only, 10, 0).await.unwrap();
+            assert_eq!((shifts.len(), total, shifts[0].closed_by), (1, 1, Some(admin)), "{:?}", backend);
+            let elsewhere = ShiftFilter { company_branch: Some("North".to_string()), ..Default::default() };
+            assert_eq!(list(&db, &elsewhere, 10, 0).await.unwrap().1, 0, "{:?}", backend);
+            assert!(matches!(fetch(&db, Uuid::new_v4()).await, Err(AppError::NotFound(_))), "{:?}", backend);
+
+            drop_database(db).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn closing_waits_for_writes_into_the_shift() {
+        for db in databases().await {
+            let backend = db.backend();
+            let admin = Uuid::parse_str(ADMIN_ID).unwrap();
+            let shift = Shift {
+                id: Uuid::new_v4(),
+                register: "Till 1".to_string(),
+                company_branch: String::new(),
+                opened_by: admin,
+                opened_at: Utc::now(),
+                opening_float_cents: 0,
+                closed_by: None,
+                closed_at: None,
+                counted_cash_cents: None,
+                note: None,
+            };
+            insert(&db, &shift).await.unwrap();
+
+            let mut payout = db.begin().await.unwrap();
+            assert!(find_open(&mut payout, shift.id).await.unwrap().is_some(), "{:?}", backend);
+            let movement = CashMovement {
+                id: Uuid::new_v4(),
+                shift_id: shift.id,
+                kind: CashMovementKind::Payout,
+                amount_cents: 700,
+                reason: "Milk".to_string(),
+                recorded_by: admin,
+                recorded_at: Utc::now(),
+            };
+            insert_cash_movement(&mut payout, &movement).await.unwrap();
+
+            let closing = tokio::spawn({
+                let db = db.clone();
+                async move {
+                    let mut tx = db.begin().await.unwrap();
+                    let closed_at = Utc::now();
+                    let closed = close(&mut tx, shift.id, admin, closed_at, 0, None).await.unwrap().unwrap();
+                    let z = report(&mut tx, closed, ShiftReportKind::Z, closed_at).await.unwrap();
+                    tx.commit().await.unwrap();
+                    z
+                }
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            assert!(!closing.is_finished(), "{:?}: closed while a payout was being written", backend);
+            payout.commit().await.unwrap();
+
+            let z = closing.await.unwrap();
+            assert_eq!(z.payouts_cents, 700, "{:?}", backend);
+            assert!(find_open(&db, shift.id).await.unwrap().is_none(), "{:?}", backend);
+
+            drop_database(db).await;
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Staff members, their sign-in details and roles. Archived staff cannot
+//! sign in but stay readable, since sales keep referring to them.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{Staff, StaffRole};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+/// A page of staff, by name, and how many there are; archived staff only
+/// with `include_archived`.
+pub async fn list(db: &Db, include_archived: bool, limit: i64, offset: i64) -> Result<(Vec<Staff>, i64), AppError> {
+    let total = db::query_scalar(format!("SELECT COUNT(*) FROM staff{}", filter(include_archived)))
+        .fetch_one(db)
+        .await?;
+    let rows = list_query(include_archived).bind(limit).bind(offset).fetch_all(db).await?;
+    Ok((rows.iter().map(from_row).collect(), total))
+}
+
+/// Every staff member [`list`] would list, as the database cursor yields them.
+pub fn stream(db: &Db, include_archived: bool) -> impl Stream<Item = Result<Staff, AppError>> + Send + 'static {
+    list_query(include_archived).bind(-1).bind(0).stream(db).map(|row| Ok(from_row(&row?)))
+}
+
+fn filter(include_archived: bool) -> &'static str {
+    if include_archived { "" } else { " WHERE archived_at IS NULL" }
+}
+
+fn list_query(include_archived: bool) -> db::Query {
+    db::query(format!(
+        "SELECT * FROM staff{} ORDER BY last_name ASC, first_name ASC, id ASC LIMIT ? OFFSET ?",
+        filter(include_archived)
+    ))
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Staff, AppError> {
+    db::query("SELECT * FROM staff WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .map(|row| from_row(&row))
+        .ok_or_else(|| AppError::not_found("Staff member"))
+}
+
+/// The id and password hash of the active staff member signing in as
+/// `username`.
+pub async fn credentials(db: &Db, username: &str) -> Result<Option<(String, String)>, AppError> {
+    Ok(db::query_as("SELECT id, password_hash FROM staff WHERE username = ? AND archived_at IS NULL")
+        .bind(username)
+        .fetch_optional(db)
+        .await?)
+}
+
+/// Whether staff member `id` is archived, or `None` when there is none.
+pub async fn archived<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<bool>, AppError> {
+    super::archived_state(conn, "staff", id).await
+}
+
+/// Staff member `id`'s role, or `None` when there is none.
+pub async fn role<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Option<StaffRole>, AppError> {
+    let role: Option<String> = db::query_scalar("SELECT role FROM staff WHERE id = ?")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?;
+    Ok(role.map(|role| StaffRole::from_str(&role).unwrap_or_default()))
+}
+
+/// The ids of the active admins, locked until the transaction ends on
+/// PostgreSQL.
+pub async fn lock_active_admins<'c>(conn: impl Into<Conn<'c>>) -> Result<Vec<Uuid>, AppError> {
+    let conn = conn.into();
+    let lock = conn.backend().for_update();
+    Ok(db::query_scalar(format!("SELECT id FROM staff WHERE role = ? AND archived_at IS NULL{}", lock))
+        .bind(StaffRole::Admin.as_str())
+        .fetch_all(conn)
+        .await?)
+}
+
+/// Records a new staff member with the staff role.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, staff: &Staff) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO staff (id, staff_id, first_name, last_name, mobile_number, photo_link, username, password_hash) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(staff.id)
+    .bind(&staff.staff_id)
+    .bind(&staff.first_name)
+    .bind(&staff.last_name)
+    .bind(&staff.mobile_number)
+    .bind(&staff.photo_link)
+    .bind(&staff.username)
+    .bind(&staff.password_hash)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Saves `staff`'s details and password hash; the role and archived state
+/// are changed on their own.
+pub async fn update<'c>(conn: impl Into<Conn<'c>>, staff: &Staff) -> Result<(), AppError> {
+    db::query(
+        "UPDATE staff SET staff_id = ?, first_name = ?, last_name = ?, mobile_number = ?, photo_link = ?, username = ?, password_hash = ? WHERE id = ?",
+    )
+    .bind(&staff.staff_id)
+    .bind(&staff.first_name)
+    .bind(&staff.last_name)
+    .bind(&staff.mobile_number)
+    .bind(&staff.photo_link)
+    .bind(&staff.username)
+    .bind(&staff.password_hash)
+    .bind(staff.id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Archives staff member `id` as of `now`, unless already archived.
+pub async fn archive<'c>(conn: impl Into<Conn<'c>>, id: Uuid, now: DateTime<Utc>) -> Result<(), AppError> {
+    let updated = db::query("UPDATE staff SET archived_at = COALESCE(archived_at, ?) WHERE id = ?")
+        .bind(now)
+        .bind(id)
+        .execute(conn)
+        .await?;
+    if updated == 0 {
+        return Err(AppError::not_found("Staff member"));
+    }
+    Ok(())
+}
+
+pub async fn restore<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Staff, AppError> {
+    db::query("UPDATE staff SET archived_at = NULL WHERE id = ? RETURNING *")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .map(|row| from_row(&row))
+        .ok_or_else(|| AppError::not_found("Staff member"))
+}
+
+pub async fn set_role<'c>(conn: impl Into<Conn<'c>>, id: Uuid, role: StaffRole) -> Result<Staff, AppError> {
+    db::query("UPDATE staff SET role = ? WHERE id = ? RETURNING *")
+        .bind(role.as_str())
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .map(|row| from_row(&row))
+        .ok_or_else(|| AppError::not_found("Staff member"))
+}
+
+fn from_row(row: &Row) -> Staff {
+    Staff {
+        id: row.try_get("id").unwrap_or_default(),
+        staff_id: row.get("staff_id"),
+        first_name: row.get("first_name"),
+        last_name: row.get("last_name"),
+        mobile_number: row.get("mobile_number"),
+        photo_link: row.get("photo_link"),
+        username: row.get("username"),
+        password_hash: row.get("password_hash"),
+        archived_at: row.get("archived_at"),
+        role: StaffRole::from_str(&row.get::<String, _>("role")).unwrap_or_default(),
+    }
+}
@@ -0,0 +1,83 @@
+//! Low-stock alerts raised by sales, kept until someone acknowledges them.
+
+use shared::models::StockAlert;
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+/// A page of the alerts, newest first, and how many there are; those
+/// already acknowledged only with `include_acknowledged`.
+pub async fn list(
+    db: &Db,
+    include_acknowledged: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<StockAlert>, i64), AppError> {
+    let total = db::query_scalar(format!("SELECT COUNT(*) FROM stock_alerts{}", filter(include_acknowledged)))
+        .fetch_one(db)
+        .await?;
+    let rows = list_query(include_acknowledged).bind(limit).bind(offset).fetch_all(db).await?;
+    let alerts = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((alerts, total))
+}
+
+/// Every alert [`list`] would list, as the database cursor yields them.
+pub fn stream(db: &Db, include_acknowledged: bool) -> impl Stream<Item = Result<StockAlert, AppError>> + Send + 'static {
+    list_query(include_acknowledged).bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+fn filter(include_acknowledged: bool) -> &'static str {
+    if include_acknowledged { "" } else { " WHERE acknowledged = FALSE" }
+}
+
+fn list_query(include_acknowledged: bool) -> db::Query {
+    db::query(format!(
+        "SELECT * FROM stock_alerts{} ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?",
+        filter(include_acknowledged)
+    ))
+}
+
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, alert: &StockAlert) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO stock_alerts (id, product_id, product_name, stock, reorder_point, reorder_quantity, sale_id, created_at, acknowledged) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(alert.id)
+    .bind(alert.product_id)
+    .bind(&alert.product_name)
+    .bind(alert.stock)
+    .bind(alert.reorder_point)
+    .bind(alert.reorder_quantity)
+    .bind(alert.sale_id)
+    .bind(alert.created_at)
+    .bind(alert.acknowledged)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn acknowledge<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<StockAlert, AppError> {
+    let row = db::query("UPDATE stock_alerts SET acknowledged = TRUE WHERE id = ? RETURNING *")
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Stock alert"))?;
+    from_row(&row)
+}
+
+fn from_row(row: &Row) -> Result<StockAlert, AppError> {
+    let sale_id: Option<String> = row.get("sale_id");
+    Ok(StockAlert {
+        id: parse_uuid(row.get("id"))?,
+        product_id: parse_uuid(row.get("product_id"))?,
+        product_name: row.get("product_name"),
+        stock: row.get("stock"),
+        reorder_point: row.get("reorder_point"),
+        reorder_quantity: row.get("reorder_quantity"),
+        sale_id: sale_id.map(parse_uuid).transpose()?,
+        created_at: row.get("created_at"),
+        acknowledged: row.get("acknowledged"),
+    })
+}
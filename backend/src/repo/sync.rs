@@ -0,0 +1,30 @@
+//! The change log offline devices sync against. Triggers append a version
+//! to `sync_changes` whenever a product or customer is written or deleted.
+
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Db};
+use crate::error::AppError;
+
+/// The latest version in the change log, or 0 before anything changed.
+pub async fn version(db: &Db) -> Result<i64, AppError> {
+    Ok(db::query_scalar("SELECT COALESCE(MAX(version), 0) FROM sync_changes")
+        .fetch_one(db)
+        .await?)
+}
+
+/// The ids of the `entity` records changed since version `since` that no
+/// longer exist in `table`, oldest change first. `table` must be the
+/// trusted name of the table `entity` records live in.
+pub async fn deleted_since(db: &Db, entity: &str, table: &str, since: i64) -> Result<Vec<Uuid>, AppError> {
+    let ids: Vec<String> = db::query_scalar(format!(
+        "SELECT entity_id FROM sync_changes WHERE entity = ? AND version > ? AND entity_id NOT IN (SELECT id FROM {}) ORDER BY version",
+        table
+    ))
+    .bind(entity)
+    .bind(since)
+    .fetch_all(db)
+    .await?;
+    ids.into_iter().map(parse_uuid).collect()
+}
@@ -0,0 +1,340 @@
+//! Webhook subscriptions, the outbox of events for them and the log of each
+//! delivery. Sending is [`crate::webhooks`]'s; this module only records it.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use shared::models::{Webhook, WebhookDelivery, WebhookDeliveryStatus, WebhookEventType, WebhookInput};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use super::parse_uuid;
+use crate::db::{self, Conn, Db, Row};
+use crate::error::AppError;
+
+/// Every column but the secret, which is only returned on create.
+const COLUMNS: &str = "id, url, description, event_types, active, created_at";
+
+const DELIVERY_COLUMNS: &str = "webhook_deliveries.id, webhook_deliveries.webhook_id, webhook_deliveries.event_id, webhook_events.event_type, webhook_deliveries.status, webhook_deliveries.attempts, webhook_deliveries.next_attempt_at, webhook_deliveries.last_attempt_at, webhook_deliveries.response_status, webhook_deliveries.last_error, webhook_deliveries.created_at";
+
+/// A page of the webhooks, oldest first, and how many there are. Secrets
+/// are never read back.
+pub async fn list(db: &Db, limit: i64, offset: i64) -> Result<(Vec<Webhook>, i64), AppError> {
+    let total = db::query_scalar("SELECT COUNT(*) FROM webhooks").fetch_one(db).await?;
+    let rows = list_query().bind(limit).bind(offset).fetch_all(db).await?;
+    let webhooks = rows.iter().map(from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((webhooks, total))
+}
+
+/// Every webhook, in [`list`]'s order, as the database cursor yields them.
+pub fn stream(db: &Db) -> impl Stream<Item = Result<Webhook, AppError>> + Send + 'static {
+    list_query().bind(-1).bind(0).stream(db).map(|row| from_row(&row?))
+}
+
+fn list_query() -> db::Query {
+    db::query(format!("SELECT {} FROM webhooks ORDER BY created_at ASC, id ASC LIMIT ? OFFSET ?", COLUMNS))
+}
+
+pub async fn fetch<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<Webhook, AppError> {
+    let row = db::query(format!("SELECT {} FROM webhooks WHERE id = ?", COLUMNS))
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or_else(|| AppError::not_found("Webhook"))?;
+    from_row(&row)
+}
+
+/// Records a new webhook with the secret it was created with.
+pub async fn insert<'c>(conn: impl Into<Conn<'c>>, webhook: &Webhook) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO webhooks (id, url, description, event_types, secret, active, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(webhook.id)
+    .bind(&webhook.url)
+    .bind(&webhook.description)
+    .bind(event_types_column(&webhook.event_types))
+    .bind(&webhook.secret)
+    .bind(webhook.active)
+    .bind(webhook.created_at)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Replaces webhook `id`'s settings with `input`, keeping its secret.
+pub async fn update<'c>(conn: impl Into<Conn<'c>>, id: Uuid, input: &WebhookInput) -> Result<Webhook, AppError> {
+    let row = db::query(format!(
+        "UPDATE webhooks SET url = ?, description = ?, event_types = ?, active = ? WHERE id = ? RETURNING {}",
+        COLUMNS
+    ))
+    .bind(&input.url)
+    .bind(&input.description)
+    .bind(event_types_column(&input.event_types))
+    .bind(input.active)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::not_found("Webhook"))?;
+    from_row(&row)
+}
+
+/// Deletes a webhook; its deliveries go with it.
+pub async fn delete<'c>(conn: impl Into<Conn<'c>>, id: Uuid) -> Result<(), AppError> {
+    let deleted = db::query("DELETE FROM webhooks WHERE id = ?").bind(id).execute(conn).await?;
+    if deleted == 0 {
+        return Err(AppError::not_found("Webhook"));
+    }
+    Ok(())
+}
+
+/// A page of webhook `webhook_id`'s deliveries, optionally only those with
+/// `status`, latest first, and how many match in all.
+pub async fn list_deliveries(
+    db: &Db,
+    webhook_id: Uuid,
+    status: Option<WebhookDeliveryStatus>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<WebhookDelivery>, i64), AppError> {
+    let (count, query) = deliveries_queries(webhook_id, status);
+    let total = count.fetch_one(db).await?;
+    let rows = query.bind(limit).bind(offset).fetch_all(db).await?;
+    let deliveries = rows.iter().map(delivery_from_row).collect::<Result<Vec<_>, _>>()?;
+    Ok((deliveries, total))
+}
+
+/// Every delivery [`list_deliveries`] would list, as the database cursor
+/// yields them.
+pub fn stream_deliveries(
+    db: &Db,
+    webhook_id: Uuid,
+    status: Option<WebhookDeliveryStatus>,
+) -> impl Stream<Item = Result<WebhookDelivery, AppError>> + Send + 'static {
+    let (_, query) = deliveries_queries(webhook_id, status);
+    query.bind(-1).bind(0).stream(db).map(|row| delivery_from_row(&row?))
+}
+
+fn deliveries_queries(webhook_id: Uuid, status: Option<WebhookDeliveryStatus>) -> (db::QueryScalar<i64>, db::Query) {
+    let filter = if status.is_some() {
+        " WHERE webhook_deliveries.webhook_id = ? AND webhook_deliveries.status = ?"
+    } else {
+        " WHERE webhook_deliveries.webhook_id = ?"
+    };
+    let mut count = db::query_scalar::<i64>(format!("SELECT COUNT(*) FROM webhook_deliveries{}", filter)).bind(webhook_id);
+    let mut query = db::query(format!(
+        "SELECT {} FROM webhook_deliveries JOIN webhook_events ON webhook_events.id = webhook_deliveries.event_id{}
+         ORDER BY webhook_deliveries.created_at DESC, webhook_deliveries.id DESC LIMIT ? OFFSET ?",
+        DELIVERY_COLUMNS, filter
+    ))
+    .bind(webhook_id);
+    if let Some(status) = status {
+        count = count.bind(status.as_str());
+        query = query.bind(status.as_str());
+    }
+    (count, query)
+}
+
+/// Queues delivery `id` to be sent again at `now` with a fresh set of
+/// attempts, and returns it.
+pub async fn retry_delivery<'c>(conn: impl Into<Conn<'c>>, id: Uuid, now: DateTime<Utc>) -> Result<WebhookDelivery, AppError> {
+    let mut conn = conn.into();
+    let updated = db::query("UPDATE webhook_deliveries SET status = 'pending', attempts = 0, next_attempt_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(id)
+        .execute(&mut conn)
+        .await?;
+    if updated == 0 {
+        return Err(AppError::not_found("Webhook delivery"));
+    }
+
+    let row = db::query(format!(
+        "SELECT {} FROM webhook_deliveries JOIN webhook_events ON webhook_events.id = webhook_deliveries.event_id WHERE webhook_deliveries.id = ?",
+        DELIVERY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&mut conn)
+    .await?;
+    delivery_from_row(&row)
+}
+
+/// Appends an event, already serialized as `payload`, to the outbox.
+pub async fn insert_event<'c>(
+    conn: impl Into<Conn<'c>>,
+    id: Uuid,
+    event_type: WebhookEventType,
+    payload: String,
+    created_at: DateTime<Utc>,
+) -> Result<(), AppError> {
+    db::query("INSERT INTO webhook_events (id, event_type, payload, created_at) VALUES (?, ?, ?, ?)")
+        .bind(id)
+        .bind(event_type.as_str())
+        .bind(payload)
+        .bind(created_at)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Up to `limit` events not yet fanned out to their webhooks, oldest first,
+/// as their ids and event types.
+pub async fn undispatched_events<'c>(conn: impl Into<Conn<'c>>, limit: i64) -> Result<Vec<(Uuid, String)>, AppError> {
+    Ok(db::query_as("SELECT id, event_type FROM webhook_events WHERE dispatched_at IS NULL ORDER BY created_at LIMIT ?")
+        .bind(limit)
+        .fetch_all(conn)
+        .await?)
+}
+
+/// The active webhooks subscribed to `event_type`.
+pub async fn subscribers<'c>(conn: impl Into<Conn<'c>>, event_type: &str) -> Result<Vec<Uuid>, AppError> {
+    let conn = conn.into();
+    let contains = conn.backend().list_contains("event_types");
+    Ok(db::query_scalar(format!("SELECT id FROM webhooks WHERE active = TRUE AND {}", contains))
+        .bind(event_type)
+        .fetch_all(conn)
+        .await?)
+}
+
+/// Queues event `event_id` for webhook `webhook_id`, due at `now`.
+pub async fn insert_delivery<'c>(
+    conn: impl Into<Conn<'c>>,
+    webhook_id: Uuid,
+    event_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    db::query(
+        "INSERT INTO webhook_deliveries (id, webhook_id, event_id, next_attempt_at, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(Uuid::new_v4())
+    .bind(webhook_id)
+    .bind(event_id)
+    .bind(now)
+    .bind(now)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn mark_dispatched<'c>(conn: impl Into<Conn<'c>>, event_id: Uuid, now: DateTime<Utc>) -> Result<(), AppError> {
+    db::query("UPDATE webhook_events SET dispatched_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(event_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// A pending delivery with what it takes to send it.
+pub struct DueDelivery {
+    pub id: Uuid,
+    /// Attempts made so far
+    pub attempts: i64,
+    pub url: String,
+    pub secret: String,
+    pub event_type: String,
+    pub payload: String,
+}
+
+/// Up to `limit` pending deliveries to active webhooks due by `now`,
+/// longest due first.
+pub async fn due_deliveries(db: &Db, now: DateTime<Utc>, limit: i64) -> Result<Vec<DueDelivery>, AppError> {
+    let rows = db::query(
+        "SELECT webhook_deliveries.id, webhook_deliveries.attempts, webhooks.url, webhooks.secret, webhook_events.event_type, webhook_events.payload
+         FROM webhook_deliveries
+         JOIN webhooks ON webhooks.id = webhook_deliveries.webhook_id
+         JOIN webhook_events ON webhook_events.id = webhook_deliveries.event_id
+         WHERE webhook_deliveries.status = 'pending' AND webhook_deliveries.next_attempt_at <= ? AND webhooks.active = TRUE
+         ORDER BY webhook_deliveries.next_attempt_at
+         LIMIT ?",
+    )
+    .bind(now)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .iter()
+        .map(|row| DueDelivery {
+            id: row.get("id"),
+            attempts: row.get("attempts"),
+            url: row.get("url"),
+            secret: row.get("secret"),
+            event_type: row.get("event_type"),
+            payload: row.get("payload"),
+        })
+        .collect())
+}
+
+/// The outcome of an attempt to send delivery `id`.
+pub struct Attempt<'a> {
+    pub status: WebhookDeliveryStatus,
+    /// Attempts made, this one included
+    pub attempts: i64,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub attempted_at: DateTime<Utc>,
+    pub response_status: Option<u16>,
+    pub error: Option<&'a str>,
+}
+
+pub async fn record_attempt<'c>(conn: impl Into<Conn<'c>>, id: Uuid, attempt: Attempt<'_>) -> Result<(), AppError> {
+    db::query(
+        "UPDATE webhook_deliveries SET status = ?, attempts = ?, next_attempt_at = ?, last_attempt_at = ?, response_status = ?, last_error = ? WHERE id = ?",
+    )
+    .bind(attempt.status.as_str())
+    .bind(attempt.attempts)
+    .bind(attempt.next_attempt_at)
+    .bind(attempt.attempted_at)
+    .bind(attempt.response_status)
+    .bind(attempt.error)
+    .bind(id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn from_row(row: &Row) -> Result<Webhook, AppError> {
+    let event_types: String = row.get("event_types");
+    let event_types = event_types
+        .split(',')
+        .filter(|event_type| !event_type.is_empty())
+        .map(|event_type| {
+            WebhookEventType::from_str(event_type)
+                .map_err(|_| AppError::internal(format!("unknown webhook event type {:?}", event_type)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Webhook {
+        id: parse_uuid(row.get("id"))?,
+        url: row.get("url"),
+        description: row.get("description"),
+        event_types,
+        active: row.get("active"),
+        created_at: row.get("created_at"),
+        secret: None,
+    })
+}
+
+fn event_types_column(event_types: &[WebhookEventType]) -> String {
+    let mut names: Vec<&str> = event_types.iter().map(WebhookEventType::as_str).collect();
+    names.sort_unstable();
+    names.dedup();
+    names.join(",")
+}
+
+fn delivery_from_row(row: &Row) -> Result<WebhookDelivery, AppError> {
+    let event_type: String = row.get("event_type");
+    let status: String = row.get("status");
+    Ok(WebhookDelivery {
+        id: parse_uuid(row.get("id"))?,
+        webhook_id: parse_uuid(row.get("webhook_id"))?,
+        event_id: parse_uuid(row.get("event_id"))?,
+        event_type: WebhookEventType::from_str(&event_type)
+            .map_err(|_| AppError::internal(format!("unknown webhook event type {:?}", event_type)))?,
+        status: WebhookDeliveryStatus::from_str(&status)
+            .map_err(|_| AppError::internal(format!("unknown delivery status {:?}", status)))?,
+        attempts: row.get("attempts"),
+        next_attempt_at: row.get("next_attempt_at"),
+        last_attempt_at: row.get("last_attempt_at"),
+        response_status: row.get("response_status"),
+        last_error: row.get("last_error"),
+        created_at: row.get("created_at"),
+    })
+}
@@ -0,0 +1,293 @@
+//! Test support: migrated databases on each backend, and the API router
+//! served over them.
+//!
+//! Every test gets a fresh SQLite file and, when `TEST_POSTGRES_URL` is set, a
+//! fresh schema in that PostgreSQL database, e.g. one started with
+//! `docker run -e POSTGRES_HOST_AUTH_METHOD=trust -p 5432:5432 postgres`.
+//! With `CI` set the PostgreSQL URL is required, so a misconfigured pipeline
+//! fails instead of quietly testing SQLite alone.
+
+use crate::db::{self, Backend, Db};
+use crate::{AppState, api_router, email_reports, live, timezone};
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request, Response, StatusCode, header};
+use chrono::Utc;
+use jsonwebtoken::{EncodingKey, Header};
+use leptos_config::LeptosOptions;
+use serde_json::{Value, json};
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+/// The staff member seeded by the first migration.
+pub const ADMIN_ID: &str = "550e8400-e29b-41d4-a716-446655440000";
+
+const JWT_SECRET: &str = "test-secret";
+
+/// A migrated database on every backend under test, SQLite first.
+pub async fn databases() -> Vec<Db> {
+    let path = std::env::temp_dir().join(format!("fastsales-test-{}.db", Uuid::new_v4().simple()));
+    let options = SqliteConnectOptions::new().filename(path);
+    let mut databases = vec![Db::connect_sqlite(options, 4).await.unwrap()];
+    match std::env::var("TEST_POSTGRES_URL") {
+        Ok(url) => {
+            let options: PgConnectOptions = url.parse().expect("TEST_POSTGRES_URL is not a valid URL");
+            let schema = format!("test_{}", Uuid::new_v4().simple());
+            let pool = PgPool::connect_with(options.clone()).await.unwrap();
+            sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(&pool).await.unwrap();
+            pool.close().await;
+            let options = options.options([("search_path", schema.as_str())]);
+            databases.push(Db::Postgres(
+                PgPoolOptions::new().max_connections(4).connect_with(options).await.unwrap(),
+            ));
+        }
+        Err(_) if std::env::var_os("CI").is_some() => {
+            panic!("TEST_POSTGRES_URL must be set in CI so the tests also run on PostgreSQL")
+        }
+        Err(_) => {}
+    }
+    for db in &databases {
+        db.migrate().await.unwrap();
+    }
+    databases
+}
+
+/// Drops a database returned by [`databases`].
+pub async fn drop_database(db: Db) {
+    match &db {
+        Db::Sqlite(pool) => {
+            let path: String = db::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            pool.close().await;
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+            }
+        }
+        Db::Postgres(pool) => {
+            let schema: String = sqlx::query_scalar("SELECT current_schema()").fetch_one(pool).await.unwrap();
+            sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema)).execute(pool).await.unwrap();
+            pool.close().await;
+        }
+    }
+}
+
+/// The API over one test database, signed in as the seeded admin.
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+    token: String,
+}
+
+/// A [`TestApp`] on every backend under test. Call [`TestApp::finish`] at
+/// the end of each so the database is dropped.
+pub async fn apps() -> Vec<TestApp> {
+    apps_in(chrono_tz::Tz::UTC).await
+}
+
+/// [`apps`] for a business in `timezone`.
+pub async fn apps_in(timezone: chrono_tz::Tz) -> Vec<TestApp> {
+    let mut apps = Vec::new();
+    for db in databases().await {
+        let state = AppState {
+            db,
+            jwt_secret: JWT_SECRET.to_string(),
+            password_pepper: String::new(),
+            token_ttl_secs: 3600,
+            upload_dir: std::env::temp_dir(),
+            backup_dir: std::env::temp_dir().join(format!("fastsales-test-backups-{}", Uuid::new_v4().simple())),
+            leptos_options: LeptosOptions::builder().output_name("fastsales").build(),
+            live: live::channel(),
+            mailer: email_reports::Mailer::new(&Default::default()).unwrap(),
+            timezone: timezone::BusinessTz::new(timezone),
+        };
+        let router = Router::new().nest("/api", api_router(&state)).with_state(state.clone());
+        let token = token_for(ADMIN_ID, 3600);
+        apps.push(TestApp { state, router, token });
+    }
+    apps
+}
+
+/// A bearer token for `staff_id`, valid for `ttl_secs`.
+pub fn token_for(staff_id: &str, ttl_secs: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let claims = crate::auth::Claims { sub: staff_id.to_string(), exp: (now + ttl_secs) as usize };
+    jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_bytes())).unwrap()
+}
+
+impl TestApp {
+    pub fn backend(&self) -> Backend {
+        self.state.db.backend()
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.state.db
+    }
+
+    /// Sends `request` as is.
+    pub async fn send(&self, request: Request<Body>) -> Response<Body> {
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Sends a request with an optional JSON body and returns the status and
+    /// the JSON response, `Value::Null` when the body is empty or not JSON.
+    pub async fn call(&self, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.call_as(&self.token, method, uri, body).await
+    }
+
+    /// [`TestApp::call`] with another bearer token, see [`token_for`].
+    pub async fn call_as(&self, token: &str, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let (status, body) = text(self.send(request.unwrap()).await).await;
+        (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.call(Method::GET, uri, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.call(Method::POST, uri, Some(body)).await
+    }
+
+    pub async fn put(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.call(Method::PUT, uri, Some(body)).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> (StatusCode, Value) {
+        self.call(Method::DELETE, uri, None).await
+    }
+
+    /// Creates whatever `uri` lists from `body`, asserting a 201, and returns
+    /// the new row.
+    pub async fn create(&self, uri: &str, body: Value) -> Value {
+        let (status, created) = self.post(uri, body).await;
+        assert_eq!(status, StatusCode::CREATED, "POST {} on {:?}: {}", uri, self.backend(), created);
+        created
+    }
+
+    pub async fn finish(self) {
+        let _ = std::fs::remove_dir_all(&self.state.backup_dir);
+        drop_database(self.state.db).await;
+    }
+}
+
+/// The status and body of `response` as text.
+pub async fn text(response: Response<Body>) -> (StatusCode, String) {
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Fixtures, made through the API like a client would
+
+pub async fn create_product(app: &TestApp, name: &str, price_cents: i64, stock: i64) -> Value {
+    app.create(
+        "/api/products",
+        json!({
+            "name": name,
+            "description": "",
+            "price_cents": price_cents,
+            "cost_price_cents": price_cents / 2,
+            "stock": stock,
+            "product_type": "physical_good",
+            "details": [],
+        }),
+    )
+    .await
+}
+
+pub async fn create_customer(app: &TestApp, first_name: &str) -> Value {
+    app.create(
+        "/api/customers",
+        json!({
+            "first_name": first_name,
+            "last_name": "Tester",
+            "middle_name": null,
+            "mobile_number": "0123456789",
+            "date_of_birth": "1990-01-01",
+            "email": "test@example.com",
+            "details": [],
+        }),
+    )
+    .await
+}
+
+pub async fn create_staff(app: &TestApp, username: &str) -> Value {
+    app.create(
+        "/api/staff",
+        json!({
+            "id": null,
+            "first_name": username,
+            "last_name": "Tester",
+            "mobile_number": "0123456789",
+            "photo_link": "",
+            "staff_id": format!("staff-{}", username),
+            "username": username,
+            "password": "password123",
+        }),
+    )
+    .await
+}
+
+pub async fn open_shift(app: &TestApp) -> Value {
+    app.create(
+        "/api/shifts",
+        json!({ "register": "Till 1", "company_branch": "", "opening_float_cents": 0 }),
+    )
+    .await
+}
+
+/// A sale of `quantity` of each product at its list price.
+pub fn sale_input(products: &[(&Value, i64)]) -> Value {
+    let now = Utc::now();
+    let items: Vec<Value> = products
+        .iter()
+        .map(|(product, quantity)| {
+            let total = product["price_cents"].as_i64().unwrap() * quantity;
+            json!({
+                "sale_id": null,
+                "product_id": product["id"],
+                "customer_id": null,
+                "date_of_sale": now,
+                "quantity": quantity,
+                "discount": 0,
+                "total_cents": total,
+                "total_resolved": total,
+                "note": null,
+            })
+        })
+        .collect();
+    let total: i64 = items.iter().map(|item| item["total_cents"].as_i64().unwrap()).sum();
+    json!({
+        "customer_id": null,
+        "date_and_time": now,
+        "sale_items": items,
+        "total_cents": total,
+        "discount": 0,
+        "total_resolved": total,
+        "sales_channel": "mobile",
+        "staff_responsible": ADMIN_ID,
+        "company_branch": "",
+        "car_number": "",
+        "receipt_number": "R-1",
+    })
+}
+
+pub async fn stock_of(app: &TestApp, product: &Value) -> i64 {
+    let (_, product) = app.get(&format!("/api/products/{}", product["id"].as_str().unwrap())).await;
+    product["stock"].as_i64().unwrap()
+}
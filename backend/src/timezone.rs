@@ -0,0 +1,272 @@
+//! The business timezone. Sales are stored as UTC instants, but "today",
+//! date range filters and groupings by day, week, month or hour follow the
+//! shop's local calendar, set with `business.timezone` or `BUSINESS_TIMEZONE`
+//! (an IANA name such as `Africa/Accra` or `Europe/London`, default `UTC`).
+//!
+//! Queries compare instants against the UTC bounds of the local days asked
+//! for ([`BusinessTz::day_bounds`]). PostgreSQL converts to local time with
+//! its own timezone database; SQLite has none, so it gets an expression that
+//! spells out the UTC offset in force over each stretch of the range
+//! ([`BusinessTz::local_sql`]).
+
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// Format of SQLite's `datetime()`, which offset changes are compared with
+const SQL_DATETIME: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Clone, Copy, Debug)]
+pub struct BusinessTz(Tz);
+
+impl BusinessTz {
+    pub fn new(tz: Tz) -> Self {
+        BusinessTz(tz)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.date_of(Utc::now())
+    }
+
+    /// The local calendar date at `instant`.
+    pub fn date_of(&self, instant: DateTime<Utc>) -> NaiveDate {
+        instant.with_timezone(&self.0).date_naive()
+    }
+
+    /// The local wall-clock time at `instant`.
+    pub fn local_time(&self, instant: DateTime<Utc>) -> NaiveDateTime {
+        instant.with_timezone(&self.0).naive_local()
+    }
+
+    /// The instant the local `time` on `day` comes round. A time skipped by a
+    /// DST change moves forward to the end of the gap; a time that happens
+    /// twice resolves to the first occurrence.
+    pub fn at(&self, day: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+        let mut local = day.and_time(time);
+        loop {
+            if let Some(instant) = self.0.from_local_datetime(&local).earliest() {
+                return instant.with_timezone(&Utc);
+            }
+            local += Duration::minutes(1);
+        }
+    }
+
+    /// The instant the local `day` begins, usually its midnight.
+    pub fn start_of_day(&self, day: NaiveDate) -> DateTime<Utc> {
+        self.at(day, NaiveTime::MIN)
+    }
+
+    /// UTC bounds `[from, to)` of the local days `start..=end`, for
+    /// [`Backend::in_range`](crate::db::Backend::in_range).
+    pub fn day_bounds(&self, start: NaiveDate, end: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let to = end.checked_add_days(Days::new(1)).unwrap_or(end);
+        (self.start_of_day(start), self.start_of_day(to))
+    }
+
+    /// The local start of every hour of the days `start..=end`, in order. A
+    /// day the clocks go forward has 23; one they go back has 24, as the
+    /// repeated hour is listed once.
+    pub fn local_hours(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDateTime> {
+        let to = self.start_of_day(end.checked_add_days(Days::new(1)).unwrap_or(end));
+        let mut hours: Vec<NaiveDateTime> = Vec::new();
+        let mut cursor = self.start_of_day(start);
+        while cursor < to {
+            let local = self.local_time(cursor);
+            let hour = local.date().and_time(NaiveTime::from_hms_opt(local.hour(), 0, 0).unwrap_or(NaiveTime::MIN));
+            if hours.last() != Some(&hour) {
+                hours.push(hour);
+            }
+            cursor += Duration::hours(1);
+        }
+        hours
+    }
+
+    /// SQL expression for the local time of the UTC timestamp in `column`,
+    /// as `YYYY-MM-DD HH:MM:SS`, exact for instants within the local days
+    /// `start..=end`. Wrap it in `date()` or `strftime()` to group by it.
+    pub fn local_sql(&self, column: &str, start: NaiveDate, end: NaiveDate) -> String {
+        let modifier = |offset: i32| format!("'{:+} seconds'", offset);
+        let from = self.start_of_day(start);
+        let to = self.start_of_day(end.checked_add_days(Days::new(1)).unwrap_or(end));
+
+        // Each change of offset within the range, as (instant, offset before it)
+        let mut changes = Vec::new();
+        let mut cursor = from;
+        let mut offset = self.offset_at(cursor);
+        while cursor < to {
+            let step = (cursor + Duration::days(1)).min(to);
+            if self.offset_at(step) == offset {
+                cursor = step;
+                continue;
+            }
+            let (mut before, mut after) = (cursor, step);
+            while after - before > Duration::seconds(1) {
+                let middle = before + (after - before) / 2;
+                if self.offset_at(middle) == offset {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            changes.push((after, offset));
+            offset = self.offset_at(after);
+            cursor = after;
+        }
+
+        if changes.is_empty() {
+            return format!("datetime({}, {})", column, modifier(offset));
+        }
+        let arms: String = changes
+            .iter()
+            .map(|(change, before)| {
+                format!(
+                    " WHEN datetime({}) < '{}' THEN {}",
+                    column,
+                    change.format(SQL_DATETIME),
+                    modifier(*before)
+                )
+            })
+            .collect();
+        format!("datetime({}, CASE{} ELSE {} END)", column, arms, modifier(offset))
+    }
+
+    /// Seconds east of UTC at `instant`.
+    fn offset_at(&self, instant: DateTime<Utc>) -> i32 {
+        self.0.offset_from_utc_datetime(&instant.naive_utc()).fix().local_minus_utc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn tz(name: &str) -> BusinessTz {
+        BusinessTz::new(name.parse().unwrap())
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn day_bounds_follow_the_local_offset() {
+        assert_eq!(
+            tz("UTC").day_bounds(date("2026-10-18"), date("2026-10-18")),
+            (utc("2026-10-18T00:00:00Z"), utc("2026-10-19T00:00:00Z"))
+        );
+        // East of UTC the local day starts the evening before
+        assert_eq!(
+            tz("Asia/Tokyo").day_bounds(date("2026-01-01"), date("2026-01-31")),
+            (utc("2025-12-31T15:00:00Z"), utc("2026-01-31T15:00:00Z"))
+        );
+        // Half-hour offsets
+        assert_eq!(
+            tz("Asia/Kolkata").day_bounds(date("2026-06-01"), date("2026-06-01")),
+            (utc("2026-05-31T18:30:00Z"), utc("2026-06-01T18:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn days_across_dst_changes_are_23_and_25_hours_long() {
+        let london = tz("Europe/London");
+        // Clocks went forward at 01:00 UTC on 29 March 2026
+        assert_eq!(london.start_of_day(date("2026-03-29")), utc("2026-03-29T00:00:00Z"));
+        assert_eq!(london.start_of_day(date("2026-03-30")), utc("2026-03-29T23:00:00Z"));
+        // And back at 01:00 UTC on 25 October 2026
+        assert_eq!(london.start_of_day(date("2026-10-25")), utc("2026-10-24T23:00:00Z"));
+        assert_eq!(london.start_of_day(date("2026-10-26")), utc("2026-10-26T00:00:00Z"));
+
+        let new_york = tz("America/New_York");
+        assert_eq!(
+            new_york.day_bounds(date("2026-03-08"), date("2026-03-08")),
+            (utc("2026-03-08T05:00:00Z"), utc("2026-03-09T04:00:00Z"))
+        );
+        assert_eq!(
+            new_york.day_bounds(date("2026-11-01"), date("2026-11-01")),
+            (utc("2026-11-01T04:00:00Z"), utc("2026-11-02T05:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn skipped_and_repeated_local_times() {
+        let london = tz("Europe/London");
+        let half_past_one = NaiveTime::from_hms_opt(1, 30, 0).unwrap();
+        // 01:30 never happened on the spring change; the gap ends at 01:00 UTC
+        assert_eq!(london.at(date("2026-03-29"), half_past_one), utc("2026-03-29T01:00:00Z"));
+        // and happened twice on the autumn change, first at 00:30 UTC
+        assert_eq!(london.at(date("2026-10-25"), half_past_one), utc("2026-10-25T00:30:00Z"));
+
+        // Where DST starts at midnight, the day begins at 01:00
+        let havana = tz("America/Havana");
+        let start = havana.start_of_day(date("2026-03-08"));
+        assert_eq!(start, utc("2026-03-08T05:00:00Z"));
+        assert_eq!(start.with_timezone(&havana.0).time(), NaiveTime::from_hms_opt(1, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn date_of_rolls_over_at_local_midnight() {
+        let lagos = tz("Africa/Lagos");
+        assert_eq!(lagos.date_of(utc("2026-10-18T22:59:59Z")), date("2026-10-18"));
+        assert_eq!(lagos.date_of(utc("2026-10-18T23:00:00Z")), date("2026-10-19"));
+        let los_angeles = tz("America/Los_Angeles");
+        assert_eq!(los_angeles.date_of(utc("2026-10-19T06:59:59Z")), date("2026-10-18"));
+        assert_eq!(los_angeles.date_of(utc("2026-10-19T07:00:00Z")), date("2026-10-19"));
+    }
+
+    #[test]
+    fn local_hours_skip_and_merge_dst_hours() {
+        let london = tz("Europe/London");
+        let spring = london.local_hours(date("2026-03-29"), date("2026-03-29"));
+        assert_eq!(spring.len(), 23);
+        assert_eq!(spring[1].to_string(), "2026-03-29 02:00:00");
+        let autumn = london.local_hours(date("2026-10-25"), date("2026-10-25"));
+        assert_eq!(autumn.len(), 24);
+        assert_eq!(autumn[23].to_string(), "2026-10-25 23:00:00");
+        assert_eq!(tz("Asia/Kolkata").local_hours(date("2026-06-01"), date("2026-06-02")).len(), 48);
+    }
+
+    #[test]
+    fn local_sql_without_offset_changes_is_a_fixed_shift() {
+        assert_eq!(
+            tz("Asia/Tokyo").local_sql("created_at", date("2026-01-01"), date("2026-12-31")),
+            "datetime(created_at, '+32400 seconds')"
+        );
+        assert_eq!(
+            tz("UTC").local_sql("created_at", date("2026-01-01"), date("2026-12-31")),
+            "datetime(created_at, '+0 seconds')"
+        );
+    }
+
+    #[tokio::test]
+    async fn local_sql_converts_across_dst_changes() {
+        let db = SqlitePoolOptions::new().connect("sqlite::memory:").await.unwrap();
+        let london = tz("Europe/London");
+        let expression = london.local_sql("?", date("2026-03-01"), date("2026-11-30"));
+        let query = format!("SELECT {}", expression.replace("?", "?1"));
+
+        let cases = [
+            // Still GMT just before the spring change
+            ("2026-03-29T00:59:59+00:00", "2026-03-29 00:59:59"),
+            ("2026-03-29T01:00:00+00:00", "2026-03-29 02:00:00"),
+            // BST until the autumn change; both 01:30s land on the same day
+            ("2026-10-24T23:30:00+00:00", "2026-10-25 00:30:00"),
+            ("2026-10-25T00:30:00+00:00", "2026-10-25 01:30:00"),
+            ("2026-10-25T01:30:00+00:00", "2026-10-25 01:30:00"),
+            // Stored timestamps with fractional seconds or other offsets
+            ("2026-07-01T22:59:59.250Z", "2026-07-01 23:59:59"),
+            ("2026-07-02T01:00:00+02:00", "2026-07-02 00:00:00"),
+        ];
+        for (stored, local) in cases {
+            let converted: String = sqlx::query_scalar(&query).bind(stored).fetch_one(&db).await.unwrap();
+            assert_eq!(converted, local, "{}", stored);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Outbound webhooks: the outbox handlers append events to, and the
+//! dispatcher that delivers them. Subscriptions are managed through the
+//! `/api/webhooks` handlers.
+//!
+//! Each delivery is a `POST` of the event as JSON with these headers:
+//! - `X-FastSales-Event`: the event type, e.g. `sale.created`
+//! - `X-FastSales-Delivery`: the delivery id, stable across retries
+//! - `X-FastSales-Signature`: `t=<unix seconds>,v1=<hex HMAC-SHA256>` of
+//!   `"<t>.<body>"` keyed with the webhook's secret
+//!
+//! A webhook's host must resolve to public addresses only, both when it is
+//! registered and on every delivery, so registering one cannot reach
+//! services on the server's own network. Each delivery connects to the
+//! addresses that were checked, and redirects are not followed.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use shared::models::{WebhookDeliveryStatus, WebhookEventType};
+use shared::validation::{is_public_address, url_host};
+use uuid::Uuid;
+
+use crate::db::{Conn, Db};
+use crate::error::AppError;
+use crate::repo;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Deliveries attempted per poll
+const BATCH_SIZE: i64 = 50;
+/// Attempts before a delivery is marked failed
+const MAX_ATTEMPTS: i64 = 8;
+/// Wait before the first retry; doubles with each attempt
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+const MAX_ERROR_LENGTH: usize = 500;
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    id: Uuid,
+    #[serde(rename = "type")]
+    event_type: WebhookEventType,
+    created_at: DateTime<Utc>,
+    data: &'a T,
+}
+
+/// Appends an event to the outbox. Pass the handler's transaction so the
+/// event is only published if the change it describes is committed.
+pub async fn enqueue<'c, T: Serialize>(
+    conn: impl Into<Conn<'c>>,
+    event_type: WebhookEventType,
+    data: &T,
+) -> Result<(), AppError> {
+    let envelope = Envelope {
+        id: Uuid::new_v4(),
+        event_type,
+        created_at: Utc::now(),
+        data,
+    };
+    let payload = serde_json::to_string(&envelope).map_err(AppError::internal)?;
+    repo::webhooks::insert_event(conn, envelope.id, event_type, payload, envelope.created_at).await
+}
+
+/// A webhook URL's host and the addresses it was checked to resolve to.
+pub struct Target {
+    /// `None` when the URL names an IP address
+    domain: Option<String>,
+    addresses: Vec<SocketAddr>,
+}
+
+/// Resolves the host of a webhook URL, failing if it resolves to nothing or,
+/// unless `allow_private`, to any address that is not public. Only tests,
+/// which deliver to a local server, allow private addresses.
+pub async fn resolve_target(url: &str, allow_private: bool) -> Result<Target, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|err| format!("is not a valid URL: {}", err))?;
+    let port = parsed.port_or_known_default().ok_or("has no port")?;
+    let host = url_host(url).ok_or("has no host")?;
+    let (domain, addresses) = match host.parse::<IpAddr>() {
+        Ok(address) => (None, vec![SocketAddr::new(address, port)]),
+        Err(_) => {
+            let addresses = tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .map_err(|err| format!("host {} cannot be resolved: {}", host, err))?
+                .collect();
+            (Some(host), addresses)
+        }
+    };
+    if addresses.is_empty() {
+        return Err("host resolves to no addresses".to_string());
+    }
+    if !allow_private && addresses.iter().any(|address| !is_public_address(address.ip())) {
+        return Err("must not point to a local or private network address".to_string());
+    }
+    Ok(Target { domain, addresses })
+}
+
+/// A client that connects to `target`'s checked addresses and does not
+/// follow redirects, which could lead anywhere.
+fn client_for(target: &Target) -> reqwest::Result<reqwest::Client> {
+    let builder = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    match &target.domain {
+        Some(domain) => builder.resolve_to_addrs(domain, &target.addresses),
+        None => builder,
+    }
+    .build()
+}
+
+/// Runs forever, fanning new events out to the webhooks subscribed to them
+/// and delivering whatever is due.
+pub async fn run_dispatcher(db: Db) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = fan_out(&db).await {
+            tracing::error!("Dispatching webhook events failed: {:?}", err);
+        }
+        if let Err(err) = deliver_due(&db, false).await {
+            tracing::error!("Delivering webhooks failed: {:?}", err);
+        }
+    }
+}
+
+/// Creates a pending delivery for every active webhook subscribed to each
+/// undispatched event.
+async fn fan_out(db: &Db) -> Result<(), AppError> {
+    let mut tx = db.begin().await?;
+    let now = Utc::now();
+    for (event_id, event_type) in repo::webhooks::undispatched_events(&mut tx, BATCH_SIZE).await? {
+        for webhook_id in repo::webhooks::subscribers(&mut tx, &event_type).await? {
+            repo::webhooks::insert_delivery(&mut tx, webhook_id, event_id, now).await?;
+        }
+        repo::webhooks::mark_dispatched(&mut tx, event_id, now).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn deliver_due(db: &Db, allow_private: bool) -> Result<(), AppError> {
+    let due = repo::webhooks::due_deliveries(db, Utc::now(), BATCH_SIZE).await?;
+    for delivery in due {
+        let attempts = delivery.attempts + 1;
+        let timestamp = Utc::now().timestamp();
+
+        let client = match resolve_target(&delivery.url, allow_private).await {
+            Ok(target) => client_for(&target).map_err(|err| err.to_string()),
+            Err(err) => Err(format!("Webhook URL {}", err)),
+        };
+        let response = match client {
+            Ok(client) => client
+                .post(&delivery.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-FastSales-Event", &delivery.event_type)
+                .header("X-FastSales-Delivery", delivery.id.to_string())
+                .header("X-FastSales-Signature", signature(&delivery.secret, timestamp, &delivery.payload))
+                .body(delivery.payload)
+                .send()
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+
+        let (response_status, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("Endpoint answered {}", response.status())),
+            ),
+            Err(err) => (None, Some(err)),
+        };
+
+        let now = Utc::now();
+        let (status, next_attempt_at) = match &error {
+            None => (WebhookDeliveryStatus::Succeeded, None),
+            Some(_) if attempts >= MAX_ATTEMPTS => (WebhookDeliveryStatus::Failed, None),
+            Some(_) => (WebhookDeliveryStatus::Pending, Some(now + backoff(attempts))),
+        };
+        let error = error.map(|error| error.chars().take(MAX_ERROR_LENGTH).collect::<String>());
+        let attempt = repo::webhooks::Attempt {
+            status,
+            attempts,
+            next_attempt_at,
+            attempted_at: now,
+            response_status,
+            error: error.as_deref(),
+        };
+        repo::webhooks::record_attempt(db, delivery.id, attempt).await?;
+    }
+    Ok(())
+}
+
+/// Wait before retrying after the given number of failed attempts.
+fn backoff(attempts: i64) -> chrono::Duration {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    chrono::Duration::seconds((BASE_BACKOFF_SECS << exponent).min(MAX_BACKOFF_SECS))
+}
+
+/// `X-FastSales-Signature` value for a payload sent at `timestamp`.
+fn signature(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("t={},v1={}", timestamp, digest)
+}
+
+/// Random signing key for a new webhook.
+pub fn generate_secret() -> String {
+    use rand_core::RngCore;
+    let mut bytes = [0u8; 32];
+    rand_core::OsRng.fill_bytes(&mut bytes);
+    let key: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("whsec_{}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::testing::{self, create_product, open_shift};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Headers, lowercased, and body of a request the endpoint received.
+    type Received = (Vec<(String, String)>, String);
+
+    /// A webhook endpoint on loopback that answers each request with the next
+    /// of `statuses`. Returns its URL and what it has received.
+    async fn endpoint(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let (head, body) = loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).into_owned();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                            .map_or(0, |length| length.trim().parse().unwrap());
+                        if body.len() >= length {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let headers = head
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| line.split_once(": "))
+                    .map(|(name, value)| (name.to_ascii_lowercase(), value.to_string()))
+                    .collect();
+                log.lock().unwrap().push((headers, body));
+                let response = format!("HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn header<'a>(request: &'a Received, name: &str) -> &'a str {
+        request.0.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str()).unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_an_hour() {
+        let waits: Vec<i64> = (1..=9).map(|attempts| backoff(attempts).num_seconds()).collect();
+        assert_eq!(waits, [30, 60, 120, 240, 480, 960, 1920, 3600, 3600]);
+    }
+
+    #[tokio::test]
+    async fn targets_must_resolve_to_public_addresses() {
+        assert!(resolve_target("http://93.184.216.34/hooks", false).await.is_ok());
+        assert!(resolve_target("http://localhost:9/hooks", false).await.is_err());
+        assert!(resolve_target("http://[::ffff:10.0.0.1]/", false).await.is_err());
+        assert!(resolve_target("http://localhost:9/hooks", true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_and_retried_with_backoff() {
+        for app in testing::apps().await {
+            let (status, body) = app
+                .post("/api/webhooks", json!({ "url": "http://127.0.0.1:9/", "event_types": ["sale.created"] }))
+                .await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}: {}", app.backend(), body);
+            assert_eq!(body["details"][0]["field"], "url", "{:?}", app.backend());
+
+            let webhook = app
+                .create("/api/webhooks", json!({ "url": "http://93.184.216.34/hooks", "event_types": ["sale.created"] }))
+                .await;
+            let secret = webhook["secret"].as_str().unwrap();
+            let (url, received) = endpoint(vec![500, 204]).await;
+            db::query("UPDATE webhooks SET url = ?")
+                .bind(&url)
+                .execute(app.db())
+                .await
+                .unwrap();
+
+            let tea = create_product(&app, "Tea", 250, 10).await;
+            open_shift(&app).await;
+            let item = app
+                .create(
+                    "/api/sales",
+                    json!({
+                        "sale_id": null,
+                        "product_id": tea["id"],
+                        "customer_id": null,
+                        "date_of_sale": Utc::now(),
+                        "quantity": 1,
+                        "discount": 0,
+                        "total_cents": 250,
+                        "total_resolved": 250,
+                        "note": null,
+                    }),
+                )
+                .await;
+
+            fan_out(app.db()).await.unwrap();
+            deliver_due(app.db(), false).await.unwrap();
+            let deliveries_uri = format!("/api/webhooks/{}/deliveries", webhook["id"].as_str().unwrap());
+            let (_, deliveries) = app.get(&deliveries_uri).await;
+            let delivery = &deliveries["items"][0];
+            assert_eq!((&delivery["status"], &delivery["attempts"]), (&json!("pending"), &json!(1)), "{:?}", app.backend());
+            assert!(delivery["last_error"].as_str().unwrap().contains("private"), "{:?}: {}", app.backend(), delivery);
+            assert!(received.lock().unwrap().is_empty(), "{:?}", app.backend());
+
+            let due = |delivery: &Value| {
+                db::query("UPDATE webhook_deliveries SET next_attempt_at = ? WHERE id = ?")
+                    .bind(Utc::now())
+                    .bind(delivery["id"].as_str().unwrap().parse::<Uuid>().unwrap())
+            };
+            due(delivery).execute(app.db()).await.unwrap();
+            deliver_due(app.db(), true).await.unwrap();
+            let (_, deliveries) = app.get(&deliveries_uri).await;
+            let delivery = &deliveries["items"][0];
+            assert_eq!((&delivery["status"], &delivery["attempts"]), (&json!("pending"), &json!(2)), "{:?}", app.backend());
+            assert_eq!(delivery["response_status"], 500, "{:?}", app.backend());
+            let wait = delivery["next_attempt_at"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap()
+                - delivery["last_attempt_at"].as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+            assert_eq!(wait.num_seconds(), backoff(2).num_seconds(), "{:?}", app.backend());
+
+            deliver_due(app.db(), true).await.unwrap();
+            assert_eq!(received.lock().unwrap().len(), 1, "not due yet on {:?}", app.backend());
+            due(delivery).execute(app.db()).await.unwrap();
+            deliver_due(app.db(), true).await.unwrap();
+            let (_, deliveries) = app.get(&deliveries_uri).await;
+            assert_eq!(deliveries["items"][0]["status"], "succeeded", "{:?}", app.backend());
+
+            let received = std::mem::take(&mut *received.lock().unwrap());
+            assert_eq!(received.len(), 2, "{:?}", app.backend());
+            assert_eq!(header(&received[0], "x-fastsales-delivery"), header(&received[1], "x-fastsales-delivery"));
+            for request in received.iter() {
+                assert_eq!(header(request, "x-fastsales-event"), "sale.created");
+                let signature = header(request, "x-fastsales-signature");
+                let (timestamp, digest) = signature.split_once(",v1=").unwrap();
+                let timestamp = timestamp.strip_prefix("t=").unwrap();
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+                mac.update(format!("{}.{}", timestamp, request.1).as_bytes());
+                let expected: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+                assert_eq!(digest, expected, "{:?}", app.backend());
+                let event: Value = serde_json::from_str(&request.1).unwrap();
+                assert_eq!((&event["type"], &event["data"]["id"]), (&json!("sale.created"), &item["sale_id"]));
+                assert_eq!(event["data"]["sale_items"][0]["id"], item["id"], "{:?}", app.backend());
+            }
+            app.finish().await;
+        }
+    }
+}
@@ -32,7 +32,7 @@ pub fn Button(
             style=base_style
             disabled=disabled
             on:click=move |ev| {
-                if let Some(cb) = on_click.clone() {
+                if let Some(cb) = on_click {
                     cb.call(ev);
                 }
             }
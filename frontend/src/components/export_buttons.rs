@@ -0,0 +1,31 @@
+use leptos::*;
+
+/// CSV and XLSX download buttons for a report or list endpoint. `params`
+/// are the filters in effect; exports hold every row, so no page is passed.
+#[component]
+pub fn ExportButtons(
+    /// API path, e.g. `/api/products`
+    #[prop(into)]
+    path: MaybeSignal<String>,
+    #[prop(into, optional)] params: Option<Signal<Vec<(&'static str, String)>>>,
+) -> impl IntoView {
+    let button_style = "padding: 0.35rem 0.75rem; background: var(--bg-page); color: var(--text-main); border: 1px solid var(--border-subtle); border-radius: var(--radius-md); font-size: 0.8rem; font-weight: 600; cursor: pointer;";
+
+    let path = store_value(path);
+    #[allow(unused_variables)]
+    let download = move |format: &'static str| {
+        let path = path.with_value(|path| path.get_untracked());
+        let params = params.map(|params| params.get_untracked()).unwrap_or_default();
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            crate::utils::download_export(&path, params, format).await;
+        });
+    };
+
+    view! {
+        <div style="display: inline-flex; gap: 0.5rem;">
+            <button type="button" style=button_style on:click=move |_| download("csv")>"CSV"</button>
+            <button type="button" style=button_style on:click=move |_| download("xlsx")>"XLSX"</button>
+        </div>
+    }
+}
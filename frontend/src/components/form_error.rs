@@ -0,0 +1,31 @@
+use leptos::*;
+use shared::models::ApiError;
+
+#[component]
+pub fn FormError(#[prop(into)] error: Signal<Option<ApiError>>) -> impl IntoView {
+    let box_style = "
+        padding: 1rem;
+        margin-bottom: 1.5rem;
+        border-radius: var(--radius-md);
+        border: 1px solid var(--state-error);
+        color: var(--state-error);
+        background: var(--bg-surface);
+    ";
+
+    move || {
+        error.get().map(|err| {
+            view! {
+                <div role="alert" style=box_style>
+                    <p style="font-weight: 600; margin: 0;">{err.message}</p>
+                    <ul style="margin: 0.5rem 0 0 1.25rem; padding: 0;">
+                        {err
+                            .details
+                            .into_iter()
+                            .map(|detail| view! { <li>{format!("{}: {}", detail.field.replace('_', " "), detail.message)}</li> })
+                            .collect_view()}
+                    </ul>
+                </div>
+            }
+        })
+    }
+}
@@ -1,4 +1,7 @@
 pub mod button;
+pub mod export_buttons;
+pub mod form_error;
 pub mod input;
 pub mod layout;
+pub mod pagination;
 pub mod sidebar;
@@ -0,0 +1,35 @@
+use leptos::*;
+
+#[component]
+pub fn Pagination(
+    #[prop(into)] page: Signal<i64>,
+    #[prop(into)] total_pages: Signal<i64>,
+    #[prop(into)] total: Signal<i64>,
+    #[prop(into)] set_page: WriteSignal<i64>,
+) -> impl IntoView {
+    let button_style = "padding: 0.5rem 1rem; background: var(--bg-surface); color: var(--text-heading); border: 1px solid var(--border-subtle); border-radius: var(--radius-md); font-weight: 500; cursor: pointer;";
+
+    view! {
+        <div style="display: flex; align-items: center; justify-content: flex-end; gap: 1rem; margin-top: 1rem;">
+            <span style="color: var(--text-muted); font-size: 0.9rem;">
+                {move || format!("Page {} of {} ({} total)", page.get(), total_pages.get(), total.get())}
+            </span>
+            <button
+                type="button"
+                style=button_style
+                disabled=move || page.get() <= 1
+                on:click=move |_| set_page.update(|p| *p = (*p - 1).max(1))
+            >
+                "Previous"
+            </button>
+            <button
+                type="button"
+                style=button_style
+                disabled=move || page.get() >= total_pages.get()
+                on:click=move |_| set_page.update(|p| *p += 1)
+            >
+                "Next"
+            </button>
+        </div>
+    }
+}
@@ -51,6 +51,12 @@ pub fn Sidebar() -> impl IntoView {
                             "Dashboard"
                         </A>
                     </li>
+                    <li>
+                        <A href="/search" class={move || if location.pathname.get().starts_with("/search") { "sidebar-link active" } else { "sidebar-link" }}>
+                            <svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="11" cy="11" r="8"></circle><line x1="21" y1="21" x2="16.65" y2="16.65"></line></svg>
+                            "Search"
+                        </A>
+                    </li>
                     <li>
                         <A href="/products" class={move || if location.pathname.get().starts_with("/products") { "sidebar-link active" } else { "sidebar-link" }}>
                             <svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M21 16V8a2 2 0 0 0-1-1.73l-7-4a2 2 0 0 0-2 0l-7 4A2 2 0 0 0 3 8v8a2 2 0 0 0 1 1.73l7 4a2 2 0 0 0 2 0l7-4A2 2 0 0 0 21 16z"></path><polyline points="3.27 6.96 12 12.01 20.73 6.96"></polyline><line x1="12" y1="22.08" x2="12" y2="12"></line></svg>
@@ -63,6 +69,12 @@ pub fn Sidebar() -> impl IntoView {
                             "Sales"
                         </A>
                     </li>
+                    <li>
+                        <A href="/shifts" class={move || if location.pathname.get().starts_with("/shifts") { "sidebar-link active" } else { "sidebar-link" }}>
+                            <svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><rect x="2" y="6" width="20" height="12" rx="2"></rect><circle cx="12" cy="12" r="2"></circle><path d="M6 12h.01M18 12h.01"></path></svg>
+                            "Shifts"
+                        </A>
+                    </li>
                     <li>
                         <A href="/reports" class={move || if location.pathname.get().starts_with("/reports") { "sidebar-link active" } else { "sidebar-link" }}>
                             <svg xmlns="http://www.w3.org/2000/svg" width="20" height="20" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M21.21 15.89A10 10 0 1 1 8 2.83"></path><path d="M22 12A10 10 0 0 0 12 2v10z"></path></svg>
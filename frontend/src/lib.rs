@@ -15,6 +15,8 @@ use pages::customers::{CustomersListPage, CustomerEditPage};
 use pages::staff::{StaffListPage, StaffEditPage};
 use pages::profile::ProfilePage;
 use pages::reports::SalesReportsPage;
+use pages::search::SearchPage;
+use pages::shifts::ShiftsPage;
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -31,12 +33,14 @@ pub fn App() -> impl IntoView {
                     <Route path="" view=DashboardLayout>
                         <Route path="/dashboard" view=DashboardPage/>
                         <Route path="/profile" view=ProfilePage/>
+                        <Route path="/search" view=SearchPage/>
                         <Route path="/products" view=ProductListPage/>
                         <Route path="/products/create" view=ProductEditPage/>
                         <Route path="/products/:id" view=ProductEditPage/>
                         <Route path="/sales" view=SalesListPage/>
                         <Route path="/sales/create" view=SalesCreatePage/>
                         <Route path="/sales/:id" view=SalesDetailPage/>
+                        <Route path="/shifts" view=ShiftsPage/>
                         <Route path="/reports" view=SalesReportsPage/>
                         <Route path="/customers" view=CustomersListPage/>
                         <Route path="/customers/create" view=CustomerEditPage/>
@@ -1,19 +1,29 @@
 use leptos::*;
 use leptos_router::*;
-use shared::models::{Customer, CustomerInput, CustomerDetailsInput};
+use shared::models::{ApiError, Customer, CustomerInput, CustomerDetailsInput, CustomerStats, Page};
 use uuid::Uuid;
+use shared::validation::Validate;
 use chrono::NaiveDate;
 
 #[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error};
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::spawn_local;
 
+use crate::components::form_error::FormError;
+use crate::components::pagination::Pagination;
+use crate::components::export_buttons::ExportButtons;
+use crate::utils::CURRENCY;
+
 #[component]
 pub fn CustomersListPage() -> impl IntoView {
     #[allow(unused_variables)]
-    let (customers, set_customers) = create_signal(Vec::<Customer>::new());
+    let (customers, set_customers) = create_signal(Page::<Customer>::default());
     let (search_query, set_search_query) = create_signal(String::new());
+    let (show_archived, set_show_archived) = create_signal(false);
+    let (page, set_page) = create_signal(1i64);
     
     let navigate = use_navigate();
 
@@ -23,9 +33,13 @@ pub fn CustomersListPage() -> impl IntoView {
         spawn_local(async move {
             let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
             
-            let mut url = "/api/customers".to_string();
-            if !search_query.get().is_empty() {
-                url.push_str(&format!("?search={}", search_query.get()));
+            let mut url = format!("/api/customers?page={}", page.get_untracked());
+            let search = search_query.get_untracked();
+            if !search.is_empty() {
+                url.push_str(&format!("&search={}", search));
+            }
+            if show_archived.get_untracked() {
+                url.push_str("&include_archived=true");
             }
 
             if let Ok(res) = Request::get(&url)
@@ -37,7 +51,7 @@ pub fn CustomersListPage() -> impl IntoView {
                     return;
                 }
 
-                if let Ok(data) = res.json::<Vec<Customer>>().await {
+                if let Ok(data) = res.json::<Page<Customer>>().await {
                     set_customers.set(data);
                 }
             }
@@ -47,16 +61,29 @@ pub fn CustomersListPage() -> impl IntoView {
     create_effect({
         let _fetch_customers = _fetch_customers.clone();
         move |_| {
+            page.track();
             _fetch_customers();
         }
     });
 
+    // A new search starts again from the first page
+    let _search_customers = {
+        let _fetch_customers = _fetch_customers.clone();
+        move || {
+            if page.get_untracked() == 1 {
+                _fetch_customers();
+            } else {
+                set_page.set(1);
+            }
+        }
+    };
+
     let delete_action = {
         let _fetch_customers = _fetch_customers.clone();
         move |id: Uuid| {
             let _fetch_customers = _fetch_customers.clone();
-            #[allow(unused_variables)]
-            let id = id;
+            #[cfg(not(target_arch = "wasm32"))]
+            let _ = id;
             #[cfg(target_arch = "wasm32")]
             spawn_local(async move {
                 let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
@@ -69,6 +96,24 @@ pub fn CustomersListPage() -> impl IntoView {
         }
     };
 
+    let restore_action = {
+        let _fetch_customers = _fetch_customers.clone();
+        move |id: Uuid| {
+            let _fetch_customers = _fetch_customers.clone();
+            #[cfg(not(target_arch = "wasm32"))]
+            let _ = id;
+            #[cfg(target_arch = "wasm32")]
+            spawn_local(async move {
+                let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+                let _ = Request::post(&format!("/api/customers/{}/restore", id))
+                    .header("Authorization", &format!("Bearer {}", token))
+                    .send()
+                    .await;
+                _fetch_customers();
+            });
+        }
+    };
+
     view! {
         <div>
             <div style="width: 100%; display: flex; align-items: center; margin-bottom: 2rem;">
@@ -76,6 +121,13 @@ pub fn CustomersListPage() -> impl IntoView {
                 <A href="/customers/create" class="btn-primary" attr:style="margin-left: auto; text-decoration: none; display: inline-block; padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); font-weight: 600;">
                     "Add Customer"
                 </A>
+                <div style="margin-left: 1rem;">
+                    <ExportButtons path="/api/customers" params=Signal::derive(move || {
+                        let mut params = vec![("include_archived", show_archived.get().to_string())];
+                        if !search_query.get().is_empty() { params.push(("search", search_query.get())); }
+                        params
+                    }) />
+                </div>
             </div>
 
             <div style="margin-bottom: 2rem; display: flex; gap: 1rem;">
@@ -85,10 +137,10 @@ pub fn CustomersListPage() -> impl IntoView {
                     prop:value=search_query
                     on:input=move |ev| set_search_query.set(event_target_value(&ev))
                     on:keydown={
-                        let _fetch_customers = _fetch_customers.clone();
+                        let _search_customers = _search_customers.clone();
                         move |ev| {
                             if ev.key() == "Enter" {
-                                _fetch_customers();
+                                _search_customers();
                             }
                         }
                     }
@@ -97,13 +149,27 @@ pub fn CustomersListPage() -> impl IntoView {
                 <button 
                     class="btn-primary"
                     on:click={
-                        let _fetch_customers = _fetch_customers.clone();
-                        move |_| _fetch_customers()
+                        let _search_customers = _search_customers.clone();
+                        move |_| _search_customers()
                     }
                     style="padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); border: none; font-weight: 600; cursor: pointer;"
                 >
                     "Search"
                 </button>
+                <label style="display: flex; align-items: center; gap: 0.5rem; color: var(--text-muted);">
+                    <input
+                        type="checkbox"
+                        prop:checked=show_archived
+                        on:change={
+                            let _search_customers = _search_customers.clone();
+                            move |ev| {
+                                set_show_archived.set(event_target_checked(&ev));
+                                _search_customers();
+                            }
+                        }
+                    />
+                    "Show archived"
+                </label>
             </div>
 
             <div style="overflow-x: auto; background: var(--bg-surface); border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
@@ -119,30 +185,60 @@ pub fn CustomersListPage() -> impl IntoView {
                     </thead>
                     <tbody>
                         <For
-                            each=move || customers.get()
+                            each=move || customers.get().items
                             key=|customer| customer.id
                             children=move |customer| {
                                 let _c_id = customer.id;
                                 let _delete_handler = delete_action.clone();
+                                let restore_handler = restore_action.clone();
+                                let archived = customer.archived_at.is_some();
                                 view! {
                                     <tr style="border-bottom: 1px solid var(--border-subtle);">
-                                        <td style="padding: 1rem;">{format!("{} {}", customer.first_name, customer.last_name)}</td>
+                                        <td style="padding: 1rem;">
+                                            {format!("{} {}", customer.first_name, customer.last_name)}
+                                            <Show when=move || archived>
+                                                <span style="margin-left: 0.5rem; padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: var(--bg-subtle); color: var(--text-muted); font-size: 0.75rem; font-weight: 600;">"Archived"</span>
+                                            </Show>
+                                        </td>
                                         <td style="padding: 1rem;">{customer.email}</td>
                                         <td style="padding: 1rem;">{customer.mobile_number}</td>
                                         <td style="padding: 1rem;">{customer.date_of_birth.format("%d-%m-%Y").to_string()}</td>
                                         <td style="padding: 1rem; display: flex; gap: 0.5rem;">
                                             <A href=format!("/customers/{}", customer.id) attr:style="color: var(--brand-primary); text-decoration: none; font-weight: 500;">"Edit"</A>
-                                            <button 
-                                                on:click=move |_| {
-                                                    #[cfg(target_arch = "wasm32")]
-                                                    if web_sys::window().unwrap().confirm_with_message("Are you sure?").unwrap() {
-                                                        _delete_handler(_c_id);
+                                            <Show
+                                                when=move || archived
+                                                fallback={
+                                                    let _delete_handler = _delete_handler.clone();
+                                                    move || {
+                                                        let _delete_handler = _delete_handler.clone();
+                                                        view! {
+                                                            <button 
+                                                                on:click=move |_| {
+                                                                    #[cfg(target_arch = "wasm32")]
+                                                                    if web_sys::window().unwrap().confirm_with_message("Archive this customer?").unwrap() {
+                                                                        _delete_handler(_c_id);
+                                                                    }
+                                                                }
+                                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
+                                                            >
+                                                                "Archive"
+                                                            </button>
+                                                        }
                                                     }
                                                 }
-                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
                                             >
-                                                "Delete"
-                                            </button>
+                                                {
+                                                    let restore_handler = restore_handler.clone();
+                                                    view! {
+                                                        <button
+                                                            on:click=move |_| restore_handler(_c_id)
+                                                            style="background: none; border: none; color: var(--brand-primary); cursor: pointer; font-weight: 500;"
+                                                        >
+                                                            "Restore"
+                                                        </button>
+                                                    }
+                                                }
+                                            </Show>
                                         </td>
                                     </tr>
                                 }
@@ -151,6 +247,12 @@ pub fn CustomersListPage() -> impl IntoView {
                     </tbody>
                 </table>
             </div>
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || customers.with(|p| p.total_pages()))
+                total=Signal::derive(move || customers.with(|p| p.total))
+                set_page=set_page
+            />
         </div>
     }
 }
@@ -172,6 +274,8 @@ pub fn CustomerEditPage() -> impl IntoView {
     let (details, set_details) = create_signal(Vec::<CustomerDetailsInput>::new());
     let (new_detail_name, set_new_detail_name) = create_signal(String::new());
     let (new_detail_value, set_new_detail_value) = create_signal(String::new());
+    #[allow(unused_variables)]
+    let (stats, set_stats) = create_signal(None::<CustomerStats>);
 
     #[allow(unused_variables)]
     let navigate = use_navigate();
@@ -211,6 +315,12 @@ pub fn CustomerEditPage() -> impl IntoView {
                             set_details.set(mapped_details);
                         }
                     }
+
+                    if let Ok(res) = Request::get(&format!("/api/customers/{}/stats", current_id))
+                        .header("Authorization", &format!("Bearer {}", token))
+                        .send().await {
+                        set_stats.set(res.json::<CustomerStats>().await.ok());
+                    }
                 });
             }
         }
@@ -228,10 +338,11 @@ pub fn CustomerEditPage() -> impl IntoView {
 
 
 
+    let (error, set_error) = create_signal(None::<ApiError>);
+
     let save_customer = move |_| {
         #[allow(unused_variables)]
         let current_id = id();
-        #[allow(unused_variables)]
         let input = CustomerInput {
             first_name: first_name.get(),
             last_name: last_name.get(),
@@ -244,6 +355,11 @@ pub fn CustomerEditPage() -> impl IntoView {
             date_of_birth: NaiveDate::parse_from_str(&date_of_birth.get(), "%Y-%m-%d").unwrap_or_default(),
             details: details.get(),
         };
+
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
         
         #[allow(unused_mut)]
         let mut _navigate = navigate.clone();
@@ -257,10 +373,13 @@ pub fn CustomerEditPage() -> impl IntoView {
                 Request::put(&format!("/api/customers/{}", current_id))
             };
 
-            if let Ok(_) = req
+            set_error.set(None);
+            match req
                 .header("Authorization", &format!("Bearer {}", token))
                 .json(&input).unwrap().send().await {
-                 _navigate("/customers", Default::default());
+                Ok(res) if res.ok() => _navigate("/customers", Default::default()),
+                Ok(res) => set_error.set(Some(api_error(res).await)),
+                Err(_) => set_error.set(Some(network_error())),
             }
         });
     };
@@ -274,6 +393,34 @@ pub fn CustomerEditPage() -> impl IntoView {
                 </h1>
             </div>
 
+            <FormError error=error />
+
+            {move || stats.get().map(|stats| {
+                let format_currency = |cents: i64| format!("{} {:.2}", CURRENCY, cents as f64 / 100.0);
+                let format_date = |date: Option<chrono::DateTime<chrono::Utc>>| date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "–".to_string());
+                let rfm = stats.rfm.map(|rfm| format!("R{} F{} M{}", rfm.recency, rfm.frequency, rfm.monetary)).unwrap_or_else(|| "–".to_string());
+                let segment = stats.segment.map(|segment| segment.to_string().replace('_', " ")).unwrap_or_else(|| "no purchases".to_string());
+                let metrics = vec![
+                    ("Lifetime Spend", format_currency(stats.total_spend_cents)),
+                    ("Visits", stats.visit_count.to_string()),
+                    ("Average Basket", format_currency(stats.average_basket_cents)),
+                    ("First Purchase", format_date(stats.first_purchase)),
+                    ("Last Purchase", format_date(stats.last_purchase)),
+                    ("RFM", rfm),
+                    ("Segment", segment),
+                ];
+                view! {
+                    <div style="background: var(--bg-surface); padding: 1.5rem 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle); margin-bottom: 2rem; display: flex; flex-wrap: wrap; gap: 2rem;">
+                        {metrics.into_iter().map(|(label, value)| view! {
+                            <div>
+                                <div style="color: var(--text-muted); font-size: 0.8rem; text-transform: uppercase;">{label}</div>
+                                <div style="font-size: 1.25rem; font-weight: 700; color: var(--text-heading); text-transform: capitalize;">{value}</div>
+                            </div>
+                        }).collect::<Vec<_>>()}
+                    </div>
+                }
+            })}
+
             <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 2rem;">
                 // Left Column: Customer Information
                 <div style="background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
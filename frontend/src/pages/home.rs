@@ -1,18 +1,22 @@
 use leptos::*;
+use leptos_router::A;
 
-use shared::models::SalesStats;
+use shared::models::{Product, SalesStats};
+#[cfg(target_arch = "wasm32")]
+use shared::models::Page;
 
 #[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::spawn_local;
 
-use crate::utils::CURRENCY;
+use crate::utils::{CURRENCY, subscribe_live};
 
 #[component]
 pub fn DashboardPage() -> impl IntoView {
-    let (today_sales, _set_today_sales) = create_signal(SalesStats::default());
-    let (weekly_sales, _set_weekly_sales) = create_signal(Vec::<shared::models::DailySales>::new());
+    let (today_sales, set_today_sales) = create_signal(SalesStats::default());
+    let (weekly_sales, set_weekly_sales) = create_signal(Vec::<shared::models::DailySales>::new());
+    let (low_stock, _set_low_stock) = create_signal(Vec::<Product>::new());
     
     let _navigate = leptos_router::use_navigate();
 
@@ -31,7 +35,7 @@ pub fn DashboardPage() -> impl IntoView {
                      return;
                  }
                  if let Ok(stats) = resp.json::<SalesStats>().await {
-                     _set_today_sales.set(stats);
+                     set_today_sales.set(stats);
                  }
             }
 
@@ -44,12 +48,26 @@ pub fn DashboardPage() -> impl IntoView {
                      return;
                  }
                  if let Ok(stats) = resp.json::<Vec<shared::models::DailySales>>().await {
-                     _set_weekly_sales.set(stats);
+                     set_weekly_sales.set(stats);
                  }
             }
+
+            // Fetch Low Stock Products
+            if let Ok(resp) = Request::get("/api/products/low_stock")
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(products) = resp.json::<Page<Product>>().await {
+                 _set_low_stock.set(products.items);
+            }
         });
     });
 
+    // Totals follow new sales as they are recorded
+    subscribe_live(|_| {}, move |stats| {
+        set_today_sales.set(stats.today);
+        set_weekly_sales.set(stats.week);
+    });
+
     // Helper to format currency
     let format_currency = |cents: i64| format!("{} {:.2}", CURRENCY, cents as f64 / 100.0);
 
@@ -72,8 +90,32 @@ pub fn DashboardPage() -> impl IntoView {
                     </p>
                 </div>
                 
-                 // Total Sales Count Today Card (Bonus?) - reusing the space since products count is gone?
-                 // Let's just keep today's sales for now as requested.
+                // Low Stock Card
+                <div style="background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle); box-shadow: 0 1px 3px rgba(0,0,0,0.05);">
+                    <div style="display: flex; align-items: center; justify-content: space-between; margin-bottom: 1rem;">
+                         <h3 style="font-size: 1rem; font-weight: 600; color: var(--text-muted); text-transform: uppercase; letter-spacing: 0.05em;">"Low Stock"</h3>
+                         <span style="font-size: 1.5rem;">"📦"</span>
+                    </div>
+                    <Show
+                        when=move || !low_stock.get().is_empty()
+                        fallback=|| view! { <p style="color: var(--text-muted);">"All products are above their reorder point."</p> }
+                    >
+                        <ul style="list-style: none; padding: 0; margin: 0; display: flex; flex-direction: column; gap: 0.5rem;">
+                            <For
+                                each=move || low_stock.get()
+                                key=|product| product.id
+                                children=move |product| view! {
+                                    <li style="display: flex; justify-content: space-between; gap: 1rem;">
+                                        <A href=format!("/products/{}", product.id) attr:style="color: var(--brand-dark); text-decoration: none; font-weight: 500;">{product.name}</A>
+                                        <span style="color: #b45309; font-weight: 600; white-space: nowrap;">
+                                            {format!("{} left · reorder {}", product.stock, product.reorder_quantity)}
+                                        </span>
+                                    </li>
+                                }
+                            />
+                        </ul>
+                    </Show>
+                </div>
             </div>
              
             // Chart Section
@@ -6,6 +6,8 @@ use gloo_net::http::Request;
 use wasm_bindgen_futures::spawn_local;
 #[cfg(target_arch = "wasm32")]
 use serde::{Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use crate::utils::api_error;
 
 use crate::components::button::Button;
 use crate::components::input::Input;
@@ -37,7 +39,6 @@ pub fn LoginPage() -> impl IntoView {
         ev.prevent_default();
         let username = username.get_untracked();
         let password = password.get_untracked();
-        let set_error = set_error.clone();
         let _navigate = _navigate.clone();
         
         #[cfg(target_arch = "wasm32")]
@@ -65,7 +66,7 @@ pub fn LoginPage() -> impl IntoView {
                 };
 
                 if !response.ok() {
-                    set_error.set(Some("Invalid credentials".to_string()));
+                    set_error.set(Some(api_error(response).await.to_string()));
                     return;
                 }
 
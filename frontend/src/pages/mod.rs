@@ -6,3 +6,5 @@ pub mod customers;
 pub mod staff;
 pub mod profile;
 pub mod reports;
+pub mod search;
+pub mod shifts;
@@ -1,20 +1,31 @@
 use leptos::*;
 use leptos_router::*;
-use shared::models::{Product, ProductInput, ProductType, ProductDetailsInput};
+use shared::models::{
+    ApiError, Page, Product, ProductInput, ProductType, ProductDetailsInput, ProductPrice,
+    ProductPriceHistory, ProductPriceInput,
+};
 use uuid::Uuid;
+use shared::validation::Validate;
 
 #[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error};
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::spawn_local;
 
+use crate::components::form_error::FormError;
+use crate::components::pagination::Pagination;
+use crate::components::export_buttons::ExportButtons;
 use crate::utils::CURRENCY;
 
 #[component]
 pub fn ProductListPage() -> impl IntoView {
     #[allow(unused_variables)]
-    let (products, set_products) = create_signal(Vec::<Product>::new());
+    let (products, set_products) = create_signal(Page::<Product>::default());
     let (search_query, set_search_query) = create_signal(String::new());
+    let (show_archived, set_show_archived) = create_signal(false);
+    let (page, set_page) = create_signal(1i64);
     
     let _navigate = use_navigate();
     
@@ -26,11 +37,14 @@ pub fn ProductListPage() -> impl IntoView {
             #[cfg(target_arch = "wasm32")]
             spawn_local(async move {
                 let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
-                let url = if search_query.get().is_empty() {
-                    "/api/products".to_string()
-                } else {
-                    format!("/api/products?search={}", search_query.get())
-                };
+                let mut url = format!("/api/products?page={}", page.get_untracked());
+                let search = search_query.get_untracked();
+                if !search.is_empty() {
+                    url.push_str(&format!("&search={}", search));
+                }
+                if show_archived.get_untracked() {
+                    url.push_str("&include_archived=true");
+                }
 
                 if let Ok(res) = Request::get(&url)
                     .header("Authorization", &format!("Bearer {}", token))
@@ -39,7 +53,7 @@ pub fn ProductListPage() -> impl IntoView {
                         _navigate("/", Default::default());
                         return;
                     }
-                    if let Ok(data) = res.json::<Vec<Product>>().await {
+                    if let Ok(data) = res.json::<Page<Product>>().await {
                         set_products.set(data);
                     }
                 }
@@ -47,18 +61,29 @@ pub fn ProductListPage() -> impl IntoView {
         }
     });
 
-    // Initial fetch
+    // Initial fetch, and again whenever the page changes
     let fetch_products_effect = fetch_products.clone();
     create_effect(move |_| {
+        page.track();
         fetch_products_effect();
     });
 
+    // A new search starts again from the first page
+    let search_products = std::rc::Rc::new({
+        let fetch_products = fetch_products.clone();
+        move || {
+            if page.get_untracked() == 1 {
+                fetch_products();
+            } else {
+                set_page.set(1);
+            }
+        }
+    });
+
     // Delete action also needs to be shared
     let fetch_products_delete = fetch_products.clone();
     let delete_action = std::rc::Rc::new(move |id: Uuid| {
         let fetch_products = fetch_products_delete.clone();
-        #[allow(unused_variables)]
-        let id = id;
         #[cfg(target_arch = "wasm32")]
         spawn_local(async move {
             let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
@@ -76,6 +101,24 @@ pub fn ProductListPage() -> impl IntoView {
         }
     });
 
+    let fetch_products_restore = fetch_products.clone();
+    let restore_action = std::rc::Rc::new(move |id: Uuid| {
+        let fetch_products = fetch_products_restore.clone();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            let _ = Request::post(&format!("/api/products/{}/restore", id))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send()
+                .await;
+            fetch_products();
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+             let _ = (id, fetch_products);
+        }
+    });
+
     view! {
         <div>
             <div style="width: 100%; display: flex; align-items: center; margin-bottom: 2rem;">
@@ -83,12 +126,20 @@ pub fn ProductListPage() -> impl IntoView {
                 <A href="/products/create" class="btn-primary" attr:style="margin-left: auto; text-decoration: none; display: inline-block; padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); font-weight: 600;">
                     "Add Product"
                 </A>
+                <div style="margin-left: 1rem;">
+                    <ExportButtons path="/api/products" params=Signal::derive(move || {
+                        let mut params = vec![("include_archived", show_archived.get().to_string())];
+                        if !search_query.get().is_empty() { params.push(("search", search_query.get())); }
+                        params
+                    }) />
+                </div>
             </div>
 
             <div style="margin-bottom: 1rem; display: flex; gap: 0.5rem;">
                 {
-                    let fetch_products_enter = fetch_products.clone();
-                    let fetch_products_click = fetch_products.clone();
+                    let fetch_products_enter = search_products.clone();
+                    let fetch_products_click = search_products.clone();
+                    let fetch_products_archived = search_products.clone();
                     view! {
                         <>
                         <input 
@@ -110,6 +161,17 @@ pub fn ProductListPage() -> impl IntoView {
                         >
                             "Search"
                         </button>
+                        <label style="display: flex; align-items: center; gap: 0.5rem; margin-left: 1rem; color: var(--text-muted);">
+                            <input
+                                type="checkbox"
+                                prop:checked=show_archived
+                                on:change=move |ev| {
+                                    set_show_archived.set(event_target_checked(&ev));
+                                    fetch_products_archived();
+                                }
+                            />
+                            "Show archived"
+                        </label>
                         </>
                     }
                 }
@@ -128,30 +190,66 @@ pub fn ProductListPage() -> impl IntoView {
                     </thead>
                     <tbody>
                         <For
-                            each=move || products.get()
+                            each=move || products.get().items
                             key=|product| product.id
                             children=move |product| {
                                 let _p_id = product.id;
                                 let _delete_handler = delete_action.clone();
+                                let restore_handler = restore_action.clone();
+                                let low_stock = product.is_low_stock();
+                                let archived = product.archived_at.is_some();
                                 view! {
                                     <tr style="border-bottom: 1px solid var(--border-subtle);">
-                                        <td style="padding: 1rem;">{product.name}</td>
+                                        <td style="padding: 1rem;">
+                                            {product.name}
+                                            <Show when=move || archived>
+                                                <span style="margin-left: 0.5rem; padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: var(--bg-subtle); color: var(--text-muted); font-size: 0.75rem; font-weight: 600;">"Archived"</span>
+                                            </Show>
+                                        </td>
                                         <td style="padding: 1rem;">{product.product_type.as_str()}</td>
                                         <td style="padding: 1rem;">{format!("{} {:.2}", CURRENCY, product.price_cents as f64 / 100.0)}</td>
-                                        <td style="padding: 1rem;">{product.stock}</td>
+                                        <td style="padding: 1rem;">
+                                            {product.stock}
+                                            <Show when=move || low_stock>
+                                                <span style="margin-left: 0.5rem; padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: #fef3c7; color: #b45309; font-size: 0.75rem; font-weight: 600;">"Low"</span>
+                                            </Show>
+                                        </td>
                                         <td style="padding: 1rem; display: flex; gap: 0.5rem;">
                                             <A href=format!("/products/{}", product.id) attr:style="color: var(--brand-primary); text-decoration: none; font-weight: 500;">"Edit"</A>
-                                            <button 
-                                                on:click=move |_| {
-                                                    #[cfg(target_arch = "wasm32")]
-                                                    if web_sys::window().unwrap().confirm_with_message("Are you sure?").unwrap() {
-                                                        _delete_handler(_p_id);
+                                            <Show
+                                                when=move || archived
+                                                fallback={
+                                                    let _delete_handler = _delete_handler.clone();
+                                                    move || {
+                                                        let _delete_handler = _delete_handler.clone();
+                                                        view! {
+                                                            <button 
+                                                                on:click=move |_| {
+                                                                    #[cfg(target_arch = "wasm32")]
+                                                                    if web_sys::window().unwrap().confirm_with_message("Archive this product?").unwrap() {
+                                                                        _delete_handler(_p_id);
+                                                                    }
+                                                                }
+                                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
+                                                            >
+                                                                "Archive"
+                                                            </button>
+                                                        }
                                                     }
                                                 }
-                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
                                             >
-                                                "Delete"
-                                            </button>
+                                                {
+                                                    let restore_handler = restore_handler.clone();
+                                                    view! {
+                                                        <button
+                                                            on:click=move |_| restore_handler(_p_id)
+                                                            style="background: none; border: none; color: var(--brand-primary); cursor: pointer; font-weight: 500;"
+                                                        >
+                                                            "Restore"
+                                                        </button>
+                                                    }
+                                                }
+                                            </Show>
                                         </td>
                                     </tr>
                                 }
@@ -160,6 +258,12 @@ pub fn ProductListPage() -> impl IntoView {
                     </tbody>
                 </table>
             </div>
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || products.with(|p| p.total_pages()))
+                total=Signal::derive(move || products.with(|p| p.total))
+                set_page=set_page
+            />
         </div>
     }
 }
@@ -173,13 +277,17 @@ pub fn ProductEditPage() -> impl IntoView {
     let (name, set_name) = create_signal(String::new());
     let (description, set_description) = create_signal(String::new());
     let (price, set_price) = create_signal(0.0);
+    let (cost_price, set_cost_price) = create_signal(0.0);
     let (stock, set_stock) = create_signal(0);
+    let (reorder_point, set_reorder_point) = create_signal(0);
+    let (reorder_quantity, set_reorder_quantity) = create_signal(0);
     let (prod_type, set_prod_type) = create_signal("physical_good".to_string());
     
     // Product Details State
     let (details, set_details) = create_signal(Vec::<ProductDetailsInput>::new());
     let (new_detail_name, set_new_detail_name) = create_signal(String::new());
     let (new_detail_value, set_new_detail_value) = create_signal(String::new());
+    let (error, set_error) = create_signal(None::<ApiError>);
     
     #[allow(unused_variables)]
     let _navigate = use_navigate();
@@ -205,7 +313,10 @@ pub fn ProductEditPage() -> impl IntoView {
                             set_name.set(product.name);
                             set_description.set(product.description);
                             set_price.set(product.price_cents as f64 / 100.0);
+                            set_cost_price.set(product.cost_price_cents as f64 / 100.0);
                             set_stock.set(product.stock);
+                            set_reorder_point.set(product.reorder_point);
+                            set_reorder_quantity.set(product.reorder_quantity);
                             set_prod_type.set(product.product_type.as_str().to_string());
                             
                             // Map ProductDetails to ProductDetailsInput
@@ -242,15 +353,22 @@ pub fn ProductEditPage() -> impl IntoView {
     let save_product = move |_| {
         #[allow(unused_variables)]
         let current_id = id();
-        #[allow(unused_variables)]
         let input = ProductInput {
             name: name.get(),
             description: description.get(),
             price_cents: (price.get() * 100.0) as i64,
+            cost_price_cents: (cost_price.get() * 100.0) as i64,
             stock: stock.get(),
             product_type: if prod_type.get() == "service" { ProductType::Service } else { ProductType::PhysicalGood },
+            reorder_point: reorder_point.get(),
+            reorder_quantity: reorder_quantity.get(),
             details: details.get(),
         };
+
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
         
         #[allow(unused_mut)]
         let mut _navigate = _navigate.clone();
@@ -264,10 +382,13 @@ pub fn ProductEditPage() -> impl IntoView {
                 Request::put(&format!("/api/products/{}", current_id))
             };
 
-            if let Ok(_) = req
+            set_error.set(None);
+            match req
                 .header("Authorization", &format!("Bearer {}", token))
                 .json(&input).unwrap().send().await {
-                 _navigate("/products", Default::default());
+                Ok(res) if res.ok() => _navigate("/products", Default::default()),
+                Ok(res) => set_error.set(Some(api_error(res).await)),
+                Err(_) => set_error.set(Some(network_error())),
             }
         });
     };
@@ -281,6 +402,8 @@ pub fn ProductEditPage() -> impl IntoView {
                 </h1>
             </div>
 
+            <FormError error=error />
+
             <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 2rem;">
                 // Left Column: Product Information
                 <div style="background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
@@ -315,6 +438,16 @@ pub fn ProductEditPage() -> impl IntoView {
                                     on:input=move |ev| set_price.set(event_target_value(&ev).parse().unwrap_or(0.0))
                                 />
                             </div>
+                            <div style="display: flex; flex-direction: column; gap: 0.5rem; flex: 1;">
+                                <label style="font-weight: 500;">{format!("Cost Price ({})", CURRENCY)}</label>
+                                <input 
+                                    type="number" 
+                                    step="0.01"
+                                    min="0"
+                                    prop:value=cost_price
+                                    on:input=move |ev| set_cost_price.set(event_target_value(&ev).parse().unwrap_or(0.0))
+                                />
+                            </div>
                             <div style="display: flex; flex-direction: column; gap: 0.5rem; flex: 1;">
                                 <label style="font-weight: 500;">"Stock"</label>
                                 <input 
@@ -335,6 +468,29 @@ pub fn ProductEditPage() -> impl IntoView {
                                 <option value="service">"Service"</option>
                             </select>
                         </div>
+
+                        <Show when=move || prod_type.get() == "physical_good">
+                            <div style="display: flex; gap: 1rem;">
+                                <div style="display: flex; flex-direction: column; gap: 0.5rem; flex: 1;">
+                                    <label style="font-weight: 500;">"Reorder Point"</label>
+                                    <input 
+                                        type="number" 
+                                        min="0"
+                                        prop:value=reorder_point
+                                        on:input=move |ev| set_reorder_point.set(event_target_value(&ev).parse().unwrap_or(0))
+                                    />
+                                </div>
+                                <div style="display: flex; flex-direction: column; gap: 0.5rem; flex: 1;">
+                                    <label style="font-weight: 500;">"Reorder Quantity"</label>
+                                    <input 
+                                        type="number" 
+                                        min="0"
+                                        prop:value=reorder_quantity
+                                        on:input=move |ev| set_reorder_quantity.set(event_target_value(&ev).parse().unwrap_or(0))
+                                    />
+                                </div>
+                            </div>
+                        </Show>
                     </div>
                 </div>
 
@@ -406,6 +562,195 @@ pub fn ProductEditPage() -> impl IntoView {
                     "Save Product"
                 </button>
             </div>
+
+            <Show when=move || !is_create()>
+                <PriceHistoryPanel product_id=Signal::derive(id) />
+            </Show>
+        </div>
+    }
+}
+
+/// Past, current and scheduled prices of a product, with a form to schedule
+/// a new price. Effective times are entered and shown in UTC.
+#[component]
+fn PriceHistoryPanel(#[prop(into)] product_id: Signal<String>) -> impl IntoView {
+    #[allow(unused_variables)]
+    let (history, set_history) = create_signal(None::<ProductPriceHistory>);
+    let (page, set_page) = create_signal(1i64);
+    #[allow(unused_variables)]
+    let (refresh, set_refresh) = create_signal(0u32);
+    let (new_price, set_new_price) = create_signal(0.0f64);
+    let (effective_from, set_effective_from) = create_signal(String::new());
+    #[allow(unused_variables)]
+    let (error, set_error) = create_signal(None::<ApiError>);
+
+    create_effect(move |_| {
+        refresh.track();
+        #[allow(unused_variables)]
+        let current_page = page.get();
+        #[allow(unused_variables)]
+        let current_id = product_id.get();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            if let Ok(res) = Request::get(&format!("/api/products/{}/price_history?page={}", current_id, current_page))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = res.json::<ProductPriceHistory>().await {
+                set_history.set(Some(data));
+            }
+        });
+    });
+
+    let schedule_price = move |_| {
+        let effective_from = effective_from.get();
+        let effective_from = if effective_from.is_empty() {
+            None
+        } else {
+            match chrono::NaiveDateTime::parse_from_str(&effective_from, "%Y-%m-%dT%H:%M") {
+                Ok(naive) => Some(naive.and_utc()),
+                Err(_) => {
+                    set_error.set(Some(ApiError::new(shared::models::ErrorCode::ValidationFailed, "Effective from is not a valid date and time")));
+                    return;
+                }
+            }
+        };
+        let input = ProductPriceInput {
+            price_cents: (new_price.get() * 100.0).round() as i64,
+            effective_from,
+        };
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+
+        #[allow(unused_variables)]
+        let current_id = product_id.get_untracked();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            set_error.set(None);
+            match Request::post(&format!("/api/products/{}/prices", current_id))
+                .header("Authorization", &format!("Bearer {}", token))
+                .json(&input).unwrap().send().await {
+                Ok(res) if res.ok() => {
+                    set_effective_from.set(String::new());
+                    set_refresh.update(|n| *n += 1);
+                }
+                Ok(res) => set_error.set(Some(api_error(res).await)),
+                Err(_) => set_error.set(Some(network_error())),
+            }
+        });
+    };
+
+    #[allow(unused_variables)]
+    let cancel_price = move |price_id: Uuid| {
+        #[allow(unused_variables)]
+        let current_id = product_id.get_untracked();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            match Request::delete(&format!("/api/products/{}/prices/{}", current_id, price_id))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await {
+                Ok(res) if res.ok() => set_refresh.update(|n| *n += 1),
+                Ok(res) => set_error.set(Some(api_error(res).await)),
+                Err(_) => set_error.set(Some(network_error())),
+            }
+        });
+    };
+
+    view! {
+        <div style="margin-top: 2rem; background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+            <h2 style="font-size: 1.25rem; font-weight: 600; margin-bottom: 1.5rem; color: var(--text-heading);">"Price History"</h2>
+
+            <FormError error=error />
+
+            <div style="display: flex; gap: 1rem; align-items: flex-end; margin-bottom: 1.5rem;">
+                <div style="display: flex; flex-direction: column; gap: 0.5rem;">
+                    <label style="font-weight: 500;">{format!("New Price ({})", CURRENCY)}</label>
+                    <input
+                        type="number"
+                        step="0.01"
+                        prop:value=new_price
+                        on:input=move |ev| set_new_price.set(event_target_value(&ev).parse().unwrap_or(0.0))
+                    />
+                </div>
+                <div style="display: flex; flex-direction: column; gap: 0.5rem;">
+                    <label style="font-weight: 500;">"Effective From (UTC, empty for now)"</label>
+                    <input
+                        type="datetime-local"
+                        prop:value=effective_from
+                        on:input=move |ev| set_effective_from.set(event_target_value(&ev))
+                    />
+                </div>
+                <button
+                    on:click=schedule_price
+                    style="padding: 0.75rem 1.5rem; background-color: var(--brand-secondary); color: var(--brand-dark); border: none; border-radius: var(--radius-md); font-weight: 600; cursor: pointer;"
+                >
+                    "Set Price"
+                </button>
+            </div>
+
+            <Show when=move || history.with(|h| h.as_ref().and_then(|h| h.price_cents_at).is_some())>
+                <p style="margin-bottom: 1rem; color: var(--text-muted);">
+                    {move || history.with(|h| {
+                        let cents = h.as_ref().and_then(|h| h.price_cents_at).unwrap_or_default();
+                        format!("Price in effect now: {} {:.2}", CURRENCY, cents as f64 / 100.0)
+                    })}
+                </p>
+            </Show>
+
+            <table style="width: 100%; border-collapse: collapse;">
+                <thead>
+                    <tr style="background: var(--bg-subtle); text-align: left;">
+                        <th style="padding: 0.75rem;">"Effective From"</th>
+                        <th style="padding: 0.75rem;">"Price"</th>
+                        <th style="padding: 0.75rem;">"Set By"</th>
+                        <th style="padding: 0.75rem;">"Recorded"</th>
+                        <th style="padding: 0.75rem;"></th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || history.get().map(|h| h.page.items).unwrap_or_default()
+                        key=|price: &ProductPrice| price.id
+                        children=move |price| {
+                            let price_id = price.id;
+                            let scheduled = price.is_scheduled();
+                            view! {
+                                <tr style="border-bottom: 1px solid var(--border-subtle);">
+                                    <td style="padding: 0.75rem;">
+                                        {price.effective_from.format("%Y-%m-%d %H:%M UTC").to_string()}
+                                        <Show when=move || scheduled>
+                                            <span style="margin-left: 0.5rem; padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: #e0f2fe; color: #0369a1; font-size: 0.75rem; font-weight: 600;">"Scheduled"</span>
+                                        </Show>
+                                    </td>
+                                    <td style="padding: 0.75rem;">{format!("{} {:.2}", CURRENCY, price.price_cents as f64 / 100.0)}</td>
+                                    <td style="padding: 0.75rem;">{price.changed_by_name.unwrap_or_else(|| "—".to_string())}</td>
+                                    <td style="padding: 0.75rem;">{price.created_at.format("%Y-%m-%d %H:%M UTC").to_string()}</td>
+                                    <td style="padding: 0.75rem;">
+                                        <Show when=move || scheduled>
+                                            <button
+                                                on:click=move |_| cancel_price(price_id)
+                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
+                                            >
+                                                "Cancel"
+                                            </button>
+                                        </Show>
+                                    </td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </table>
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || history.with(|h| h.as_ref().map(|h| h.page.total_pages()).unwrap_or(1)))
+                total=Signal::derive(move || history.with(|h| h.as_ref().map(|h| h.page.total).unwrap_or(0)))
+                set_page=set_page
+            />
         </div>
     }
 }
@@ -1,34 +1,87 @@
 use leptos::*;
-use chrono::prelude::*;
-use shared::models::{TopProduct, ProductSalesSummary};
+#[cfg(target_arch = "wasm32")]
+use chrono::Datelike;
+use shared::models::{TopProduct, ProductSalesSummary, HeatmapCell, StaffPerformance, StaffPerformanceDetail, MarginReport, DailySales, TimeSeries, Change, CustomerSegmentReport, CustomerSegment};
+use crate::components::pagination::Pagination;
+use crate::components::export_buttons::ExportButtons;
 use crate::utils::CURRENCY;
 #[cfg(target_arch = "wasm32")]
+use shared::models::{BusinessCalendar, SalesStats};
+#[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::spawn_local;
 
 #[component]
 pub fn SalesReportsPage() -> impl IntoView {
-    let now = Local::now();
-    let today_str = now.format("%Y-%m-%d").to_string();
-    let first_day_str = format!("{}-{:02}-01", now.year(), now.month());
-
-    let (start_date, set_start_date) = create_signal(first_day_str.clone());
-    let (end_date, set_end_date) = create_signal(today_str.clone());
+    // Empty dates leave the range to the server: month to date in the
+    // business timezone, which may not be the browser's
+    let (start_date, set_start_date) = create_signal(String::new());
+    let (end_date, set_end_date) = create_signal(String::new());
     
-    // Temporary state for inputs, init with defaults
-    let (input_start_date, set_input_start_date) = create_signal(first_day_str);
-    let (input_end_date, set_input_end_date) = create_signal(today_str);
+    // Temporary state for inputs, filled with the default range once the
+    // business calendar has loaded
+    let (input_start_date, set_input_start_date) = create_signal(String::new());
+    let (input_end_date, set_input_end_date) = create_signal(String::new());
+
+    #[cfg(target_arch = "wasm32")]
+    spawn_local(async move {
+        let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+        if let Ok(resp) = Request::get("/api/calendar")
+            .header("Authorization", &format!("Bearer {}", token))
+            .send().await
+            && let Ok(calendar) = resp.json::<BusinessCalendar>().await {
+            if input_start_date.get_untracked().is_empty() {
+                set_input_start_date.set(calendar.today.with_day(1).unwrap_or(calendar.today).to_string());
+            }
+            if input_end_date.get_untracked().is_empty() {
+                set_input_end_date.set(calendar.today.to_string());
+            }
+        }
+    });
+    // Comparison as (period, custom start, custom end); an empty period means none
+    let (comparison, set_comparison) = create_signal((String::new(), String::new(), String::new()));
+    let (input_compare, set_input_compare) = create_signal(String::new());
+    let (input_compare_start, set_input_compare_start) = create_signal(String::new());
+    let (input_compare_end, set_input_compare_end) = create_signal(String::new());
 
     let (top_products, _set_top_products) = create_signal(Vec::<TopProduct>::new());
     let (product_sales, _set_product_sales) = create_signal(Vec::<ProductSalesSummary>::new());
+    #[allow(unused_variables)]
     let (total_period_sales, set_total_period_sales) = create_signal(0i64);
+    #[allow(unused_variables)]
+    let (total_period_change, set_total_period_change) = create_signal(None::<Change>);
+    #[allow(unused_variables)]
+    let (daily_sales, set_daily_sales) = create_signal(Vec::<DailySales>::new());
+    #[allow(unused_variables)]
+    let (heatmap, set_heatmap) = create_signal(Vec::<HeatmapCell>::new());
+    #[allow(unused_variables)]
+    let (time_series, set_time_series) = create_signal(None::<TimeSeries>);
+    let (series_bucket, set_series_bucket) = create_signal("day".to_string());
+    let (series_week_start, set_series_week_start) = create_signal("monday".to_string());
+    let (series_breakdown, set_series_breakdown) = create_signal(String::new());
+    let (heatmap_branch, set_heatmap_branch) = create_signal(String::new());
+    let (heatmap_channel, set_heatmap_channel) = create_signal(String::new());
+    #[allow(unused_variables)]
+    let (leaderboard, set_leaderboard) = create_signal(Vec::<StaffPerformance>::new());
+    let (leaderboard_sort, set_leaderboard_sort) = create_signal("revenue".to_string());
+    let (selected_staff, set_selected_staff) = create_signal(None::<uuid::Uuid>);
+    #[allow(unused_variables)]
+    let (staff_detail, set_staff_detail) = create_signal(None::<StaffPerformanceDetail>);
+    #[allow(unused_variables)]
+    let (margin_report, set_margin_report) = create_signal(None::<MarginReport>);
+    let (margin_group_by, set_margin_group_by) = create_signal("product".to_string());
+    #[allow(unused_variables)]
+    let (segment_report, set_segment_report) = create_signal(None::<CustomerSegmentReport>);
+    let (segment_filter, set_segment_filter) = create_signal(None::<CustomerSegment>);
+    let (segment_page, set_segment_page) = create_signal(1i64);
 
     
     // Fetch Data Effect
     create_effect(move |_| {
         let _s_date = start_date.get();
         let _e_date = end_date.get();
+        let (_compare, _compare_start, _compare_end) = comparison.get();
         
         #[cfg(target_arch = "wasm32")]
         spawn_local(async move {
@@ -38,30 +91,231 @@ pub fn SalesReportsPage() -> impl IntoView {
             let mut stats_query = String::new();
             if !_s_date.is_empty() { stats_query.push_str(&format!("start_date={}&", _s_date)); }
             if !_e_date.is_empty() { stats_query.push_str(&format!("end_date={}", _e_date)); }
+
+            let mut compare_query = stats_query.clone();
+            if !_compare.is_empty() {
+                compare_query.push_str(&format!("&compare={}", _compare));
+                if _compare == "custom" {
+                    compare_query.push_str(&format!("&compare_start_date={}&compare_end_date={}", _compare_start, _compare_end));
+                }
+            }
             
             // Fetch Sales By Product
-            if let Ok(resp) = Request::get(&format!("/api/sales/stats/by_product?{}", stats_query))
+            if let Ok(resp) = Request::get(&format!("/api/sales/stats/by_product?{}", compare_query))
                 .header("Authorization", &format!("Bearer {}", token))
-                .send().await {
-                 if let Ok(data) = resp.json::<Vec<ProductSalesSummary>>().await {
-                     let total: i64 = data.iter().map(|d| d.total_amount_cents).sum();
-                     set_total_period_sales.set(total);
-                     _set_product_sales.set(data);
-                 }
+                .send().await
+                && let Ok(data) = resp.json::<Vec<ProductSalesSummary>>().await {
+                _set_product_sales.set(data);
+            }
+
+            // Fetch Period Totals
+            if let Ok(resp) = Request::get(&format!("/api/sales/stats/totals?{}", compare_query))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = resp.json::<SalesStats>().await {
+                set_total_period_sales.set(data.total_sales_cents);
+                set_total_period_change.set(data.total_change);
+            }
+
+            // Fetch Daily Series
+            if let Ok(resp) = Request::get(&format!("/api/sales/stats/daily?{}", compare_query))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = resp.json::<Vec<DailySales>>().await {
+                set_daily_sales.set(data);
             }
             
             // Fetch Top Products
             if let Ok(resp) = Request::get(&format!("/api/sales_stats/top_products?{}", stats_query))
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = resp.json::<Vec<TopProduct>>().await {
+                _set_top_products.set(data);
+            }
+        });
+    });
+
+    // Sales over time, bucketed and broken down as picked above the chart
+    create_effect(move |_| {
+        let _s_date = start_date.get();
+        let _e_date = end_date.get();
+        let _bucket = series_bucket.get();
+        let _week_start = series_week_start.get();
+        let _breakdown = series_breakdown.get();
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            let mut params = vec![("start_date", _s_date), ("end_date", _e_date), ("bucket", _bucket), ("week_start", _week_start)];
+            if !_breakdown.is_empty() { params.push(("breakdown", _breakdown)); }
+
+            if let Ok(resp) = Request::get("/api/sales/stats/series")
+                .query(params)
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await {
+                 set_time_series.set(resp.json::<TimeSeries>().await.ok());
+            }
+        });
+    });
+
+    // The heatmap has its own branch and channel filters on top of the dates
+    create_effect(move |_| {
+        let _s_date = start_date.get();
+        let _e_date = end_date.get();
+        let _branch = heatmap_branch.get();
+        let _channel = heatmap_channel.get();
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            let mut params = vec![("start_date", _s_date), ("end_date", _e_date)];
+            if !_branch.is_empty() { params.push(("company_branch", _branch)); }
+            if !_channel.is_empty() { params.push(("sales_channel", _channel)); }
+
+            if let Ok(resp) = Request::get("/api/sales/stats/heatmap")
+                .query(params)
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = resp.json::<Vec<HeatmapCell>>().await {
+                set_heatmap.set(data);
+            }
+        });
+    });
+
+    create_effect(move |_| {
+        let _s_date = start_date.get();
+        let _e_date = end_date.get();
+        let _sort_by = leaderboard_sort.get();
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            if let Ok(resp) = Request::get("/api/sales/stats/by_staff")
+                .query([("start_date", _s_date), ("end_date", _e_date), ("sort_by", _sort_by)])
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await
+                && let Ok(data) = resp.json::<Vec<StaffPerformance>>().await {
+                set_leaderboard.set(data);
+            }
+        });
+    });
+
+    create_effect(move |_| {
+        let _s_date = start_date.get();
+        let _e_date = end_date.get();
+        let _group_by = margin_group_by.get();
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            if let Ok(resp) = Request::get("/api/sales/stats/margin")
+                .query([("start_date", _s_date), ("end_date", _e_date), ("group_by", _group_by)])
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await {
+                 set_margin_report.set(resp.json::<MarginReport>().await.ok());
+            }
+        });
+    });
+
+    // Drill-down into the staff member picked from the leaderboard
+    create_effect(move |_| {
+        let _s_date = start_date.get();
+        let _e_date = end_date.get();
+        let _sort_by = leaderboard_sort.get();
+        let Some(_staff_id) = selected_staff.get() else {
+            set_staff_detail.set(None);
+            return;
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            if let Ok(resp) = Request::get(&format!("/api/sales/stats/by_staff/{}", _staff_id))
+                .query([("start_date", _s_date), ("end_date", _e_date), ("sort_by", _sort_by)])
+                .header("Authorization", &format!("Bearer {}", token))
+                .send().await {
+                 set_staff_detail.set(resp.json::<StaffPerformanceDetail>().await.ok());
+            }
+        });
+    });
+
+    // RFM segments cover every customer's whole history, so the dates do not apply
+    create_effect(move |_| {
+        let _segment = segment_filter.get();
+        let _page = segment_page.get();
+
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+            let mut params = vec![("page", _page.to_string())];
+            if let Some(segment) = _segment { params.push(("segment", segment.to_string())); }
+            if let Ok(resp) = Request::get("/api/customers/segments")
+                .query(params)
                 .header("Authorization", &format!("Bearer {}", token))
                 .send().await {
-                 if let Ok(data) = resp.json::<Vec<TopProduct>>().await {
-                     _set_top_products.set(data);
-                 }
+                 set_segment_report.set(resp.json::<CustomerSegmentReport>().await.ok());
             }
         });
     });
 
+    // Filters in effect, for the export buttons
+    let range_params = Signal::derive(move || vec![("start_date", start_date.get()), ("end_date", end_date.get())]);
+    let compare_params = Signal::derive(move || {
+        let mut params = range_params.get();
+        let (compare, compare_start, compare_end) = comparison.get();
+        if compare == "custom" {
+            params.push(("compare_start_date", compare_start));
+            params.push(("compare_end_date", compare_end));
+        }
+        if !compare.is_empty() { params.push(("compare", compare)); }
+        params
+    });
+    let series_params = Signal::derive(move || {
+        let mut params = range_params.get();
+        params.push(("bucket", series_bucket.get()));
+        params.push(("week_start", series_week_start.get()));
+        if !series_breakdown.get().is_empty() { params.push(("breakdown", series_breakdown.get())); }
+        params
+    });
+    let heatmap_params = Signal::derive(move || {
+        let mut params = range_params.get();
+        if !heatmap_branch.get().is_empty() { params.push(("company_branch", heatmap_branch.get())); }
+        if !heatmap_channel.get().is_empty() { params.push(("sales_channel", heatmap_channel.get())); }
+        params
+    });
+    let leaderboard_params = Signal::derive(move || {
+        let mut params = range_params.get();
+        params.push(("sort_by", leaderboard_sort.get()));
+        params
+    });
+    let margin_params = Signal::derive(move || {
+        let mut params = range_params.get();
+        params.push(("group_by", margin_group_by.get()));
+        params
+    });
+    let segment_params = Signal::derive(move || segment_filter.get().map(|segment| vec![("segment", segment.to_string())]).unwrap_or_default());
+
     let format_currency = |cents: i64| format!("{} {:.2}", CURRENCY, cents as f64 / 100.0);
+    let format_margin = |margin: Option<f64>| margin.map(|m| format!("{:.1}%", m)).unwrap_or_else(|| "–".to_string());
+    // Percentage change against the comparison period, coloured by direction
+    let change_view = |change: Option<Change>| match change {
+        None => view! { <span style="color: var(--text-muted);">"–"</span> }.into_view(),
+        Some(change) => {
+            let color = if change.delta > 0 { "#16a34a" } else if change.delta < 0 { "#dc2626" } else { "var(--text-muted)" };
+            let text = match change.percent_change {
+                Some(percent) => format!("{:+.1}%", percent),
+                None if change.delta == 0 => "0.0%".to_string(),
+                None => "new".to_string(),
+            };
+            view! { <span style=format!("color: {}; font-weight: 600;", color)>{text}</span> }.into_view()
+        }
+    };
 
     view! {
         <div style="display: flex; flex-direction: column; gap: 2rem;">
@@ -88,11 +342,43 @@ pub fn SalesReportsPage() -> impl IntoView {
                             style="width: 100%; padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
                         />
                     </div>
+                    <div style="flex: 1;">
+                        <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Compare To"</label>
+                        <select
+                            on:change=move |ev| set_input_compare.set(event_target_value(&ev))
+                            prop:value=input_compare
+                            style="width: 100%; padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                        >
+                            <option value="">"No comparison"</option>
+                            <option value="previous_period">"Previous period"</option>
+                            <option value="last_year">"Same period last year"</option>
+                            <option value="custom">"Custom range"</option>
+                        </select>
+                    </div>
+                    <Show when=move || input_compare.get() == "custom">
+                        <div style="flex: 1;">
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Compare Start"</label>
+                            <input type="date"
+                                on:input=move |ev| set_input_compare_start.set(event_target_value(&ev))
+                                prop:value=input_compare_start
+                                style="width: 100%; padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            />
+                        </div>
+                        <div style="flex: 1;">
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Compare End"</label>
+                            <input type="date"
+                                on:input=move |ev| set_input_compare_end.set(event_target_value(&ev))
+                                prop:value=input_compare_end
+                                style="width: 100%; padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            />
+                        </div>
+                    </Show>
                     <div>
                         <button 
                             on:click=move |_| {
                                 set_start_date.set(input_start_date.get());
                                 set_end_date.set(input_end_date.get());
+                                set_comparison.set((input_compare.get(), input_compare_start.get(), input_compare_end.get()));
                             }
                             style="padding: 0.5rem 1.5rem; background: var(--bg-page); color: var(--text-main); border: 1px solid var(--border-subtle); border-radius: var(--radius-md); font-weight: 600; cursor: pointer;"
                         >
@@ -105,7 +391,10 @@ pub fn SalesReportsPage() -> impl IntoView {
                 <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 1.5rem; align-items: stretch;">
                    // Chart
                    <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
-                       <h3 style="margin-bottom: 1rem;">"Top Products"</h3>
+                       <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 1rem;">
+                           <h3>"Top Products"</h3>
+                           <ExportButtons path="/api/sales_stats/top_products" params=range_params />
+                       </div>
                        <div style="height: 300px; position: relative; display: flex; flex-direction: column; align-items: center; justify-content: center;">
                            {move || {
                                let data = top_products.get();
@@ -128,7 +417,7 @@ pub fn SalesReportsPage() -> impl IntoView {
                                    }
                                    
                                    let mut cumulative_percent = 0.0;
-                                   let colors = vec!["#69BEEB", "#052850", "#f59e0b", "#9CA3AF"];
+                                   let colors = ["#69BEEB", "#052850", "#f59e0b", "#9CA3AF"];
                                    
                                    view! {
                                        <svg viewBox="-1.2 -1.2 2.4 2.4" style="width: 100%; height: 100%; max-height: 250px; transform: rotate(-90deg);">
@@ -171,7 +460,7 @@ pub fn SalesReportsPage() -> impl IntoView {
                                let others_sum: i64 = data.iter().skip(3).map(|d| d.total_sales_cents).sum();
                                if others_sum > 0 { refined_data.push(TopProduct { product_name: "Others".to_string(), total_sales_cents: others_sum }); }
                                
-                               let colors = vec!["#69BEEB", "#052850", "#f59e0b", "#9CA3AF"];
+                               let colors = ["#69BEEB", "#052850", "#f59e0b", "#9CA3AF"];
                                refined_data.into_iter().enumerate().map(|(i, item)| {
                                    let percent = (item.total_sales_cents as f64 / total_all as f64) * 100.0;
                                    view! {
@@ -191,12 +480,398 @@ pub fn SalesReportsPage() -> impl IntoView {
                         <div style="font-size: 3.5rem; font-weight: 800; color: var(--brand-dark); margin: 1rem 0;">
                             {move || format_currency(total_period_sales.get())}
                         </div>
+                        <ExportButtons path="/api/sales/stats/totals" params=compare_params />
+                        {move || total_period_change.get().map(|change| view! {
+                            <div style="color: var(--text-muted);">
+                                {change_view(Some(change))} " vs " {format_currency(change.previous)}
+                            </div>
+                        })}
                    </div>
                 </div>
+
+                // Daily sales, with the comparison period overlaid as a dashed line
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 1rem;">
+                        <h3>"Daily Sales"</h3>
+                        <ExportButtons path="/api/sales/stats/daily" params=compare_params />
+                    </div>
+                    {move || {
+                        let days = daily_sales.get();
+                        if days.is_empty() {
+                            return view! { <div style="color: var(--text-muted);">"No data available"</div> }.into_view();
+                        }
+                        let compared: Vec<(usize, i64)> = days.iter().enumerate()
+                            .filter_map(|(i, day)| day.total_change.as_ref().map(|change| (i, change.previous)))
+                            .collect();
+                        let max = days.iter().map(|d| d.total_sales_cents)
+                            .chain(compared.iter().map(|(_, previous)| *previous))
+                            .max().unwrap_or(0).max(1) as f64;
+                        let (width, height) = (600.0, 200.0);
+                        let step = if days.len() > 1 { width / (days.len() - 1) as f64 } else { 0.0 };
+                        let point = |i: usize, cents: i64| format!("{:.1},{:.1}", i as f64 * step, height - cents as f64 / max * height);
+                        let current = days.iter().enumerate().map(|(i, d)| point(i, d.total_sales_cents)).collect::<Vec<_>>().join(" ");
+                        let previous = compared.iter().map(|(i, cents)| point(*i, *cents)).collect::<Vec<_>>().join(" ");
+                        let first = days.first().map(|d| d.date.clone()).unwrap_or_default();
+                        let last = days.last().map(|d| d.date.clone()).unwrap_or_default();
+                        let has_comparison = !compared.is_empty();
+                        view! {
+                            <svg viewBox="-5 -5 610 210" preserveAspectRatio="none" style="width: 100%; height: 220px;">
+                                <Show when=move || has_comparison>
+                                    <polyline points=previous.clone() fill="none" stroke="#9CA3AF" stroke-width="2" stroke-dasharray="6 4" />
+                                </Show>
+                                <polyline points=current fill="none" stroke="#69BEEB" stroke-width="2.5" />
+                            </svg>
+                            <div style="display: flex; justify-content: space-between; font-size: 0.8rem; color: var(--text-muted);">
+                                <span>{first}</span>
+                                <span>{last}</span>
+                            </div>
+                            <div style="display: flex; gap: 1rem; font-size: 0.8rem; margin-top: 0.5rem;">
+                                <span style="display: flex; align-items: center; gap: 0.5rem;">
+                                    <span style="width: 16px; height: 3px; background: #69BEEB;"></span>"Selected period"
+                                </span>
+                                <Show when=move || has_comparison>
+                                    <span style="display: flex; align-items: center; gap: 0.5rem;">
+                                        <span style="width: 16px; border-top: 3px dashed #9CA3AF;"></span>"Comparison period"
+                                    </span>
+                                </Show>
+                            </div>
+                        }.into_view()
+                    }}
+                </div>
                 
-                // Pane 3: Product Sales Table
+                // Sales over time by hour, day, week or month, one line per breakdown value
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; align-items: flex-end; gap: 1rem; margin-bottom: 1rem;">
+                        <h3 style="margin-right: auto;">"Sales Over Time"</h3>
+                        <ExportButtons path="/api/sales/stats/series" params=series_params />
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Interval"</label>
+                            <select
+                                on:change=move |ev| set_series_bucket.set(event_target_value(&ev))
+                                prop:value=series_bucket
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            >
+                                <option value="hour">"Hourly"</option>
+                                <option value="day">"Daily"</option>
+                                <option value="week">"Weekly"</option>
+                                <option value="month">"Monthly"</option>
+                            </select>
+                        </div>
+                        <Show when=move || series_bucket.get() == "week">
+                            <div>
+                                <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Week starts"</label>
+                                <select
+                                    on:change=move |ev| set_series_week_start.set(event_target_value(&ev))
+                                    prop:value=series_week_start
+                                    style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                                >
+                                    <option value="monday">"Monday"</option>
+                                    <option value="saturday">"Saturday"</option>
+                                    <option value="sunday">"Sunday"</option>
+                                </select>
+                            </div>
+                        </Show>
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Split by"</label>
+                            <select
+                                on:change=move |ev| set_series_breakdown.set(event_target_value(&ev))
+                                prop:value=series_breakdown
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            >
+                                <option value="">"Nothing"</option>
+                                <option value="branch">"Branch"</option>
+                                <option value="channel">"Channel"</option>
+                                <option value="staff">"Staff"</option>
+                                <option value="product_type">"Product type"</option>
+                            </select>
+                        </div>
+                    </div>
+                    {move || {
+                        let Some(report) = time_series.get() else {
+                            return view! { <div style="color: var(--text-muted);">"No data available"</div> }.into_view();
+                        };
+                        const COLORS: [&str; 6] = ["#69BEEB", "#f59e0b", "#10b981", "#8b5cf6", "#ef4444", "#6b7280"];
+                        let buckets = report.series.first().map(|series| series.points.len()).unwrap_or(0);
+                        let max = report.series.iter()
+                            .flat_map(|series| series.points.iter().map(|point| point.total_sales_cents))
+                            .max().unwrap_or(0).max(1) as f64;
+                        let (width, height) = (600.0, 200.0);
+                        let step = if buckets > 1 { width / (buckets - 1) as f64 } else { 0.0 };
+                        let first = report.series.first().and_then(|series| series.points.first()).map(|point| point.start.clone()).unwrap_or_default();
+                        let last = report.series.first().and_then(|series| series.points.last()).map(|point| point.start.clone()).unwrap_or_default();
+                        let lines = report.series.iter().enumerate().map(|(i, series)| {
+                            let points = series.points.iter().enumerate()
+                                .map(|(j, point)| format!("{:.1},{:.1}", j as f64 * step, height - point.total_sales_cents as f64 / max * height))
+                                .collect::<Vec<_>>().join(" ");
+                            view! { <polyline points=points fill="none" stroke=COLORS[i % COLORS.len()] stroke-width="2.5" /> }
+                        }).collect::<Vec<_>>();
+                        let legend = report.series.iter().enumerate().map(|(i, series)| view! {
+                            <span style="display: flex; align-items: center; gap: 0.5rem;">
+                                <span style=format!("width: 16px; height: 3px; background: {};", COLORS[i % COLORS.len()])></span>
+                                {format!("{} · {}", series.label, format_currency(series.total_sales_cents))}
+                            </span>
+                        }).collect::<Vec<_>>();
+                        view! {
+                            <svg viewBox="-5 -5 610 210" preserveAspectRatio="none" style="width: 100%; height: 220px;">
+                                {lines}
+                            </svg>
+                            <div style="display: flex; justify-content: space-between; font-size: 0.8rem; color: var(--text-muted);">
+                                <span>{first}</span>
+                                <span>{last}</span>
+                            </div>
+                            <div style="display: flex; flex-wrap: wrap; gap: 1rem; font-size: 0.8rem; margin-top: 0.5rem;">
+                                {legend}
+                            </div>
+                        }.into_view()
+                    }}
+                </div>
+
+                // Pane 3: Hour x Weekday Heatmap
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; align-items: flex-end; gap: 1rem; margin-bottom: 1rem;">
+                        <h3 style="margin-right: auto;">"Sales by Hour and Weekday"</h3>
+                        <ExportButtons path="/api/sales/stats/heatmap" params=heatmap_params />
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Branch"</label>
+                            <input type="text"
+                                placeholder="All branches"
+                                on:change=move |ev| set_heatmap_branch.set(event_target_value(&ev).trim().to_string())
+                                prop:value=heatmap_branch
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            />
+                        </div>
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Channel"</label>
+                            <select
+                                on:change=move |ev| set_heatmap_channel.set(event_target_value(&ev))
+                                prop:value=heatmap_channel
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            >
+                                <option value="">"All channels"</option>
+                                <option value="web">"Web"</option>
+                                <option value="mobile">"Mobile"</option>
+                            </select>
+                        </div>
+                    </div>
+                    <div style="overflow-x: auto;">
+                        {move || {
+                            let cells = heatmap.get();
+                            let max_total = cells.iter().map(|c| c.total_sales_cents).max().unwrap_or(0).max(1) as f64;
+                            let weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+                            view! {
+                                <div style="display: grid; grid-template-columns: 3rem repeat(24, minmax(1.75rem, 1fr)); gap: 2px; font-size: 0.75rem; min-width: 48rem;">
+                                    <div></div>
+                                    {(0..24).map(|hour| view! {
+                                        <div style="text-align: center; color: var(--text-muted);">{format!("{:02}", hour)}</div>
+                                    }).collect::<Vec<_>>()}
+                                    {weekdays.into_iter().enumerate().map(|(weekday, label)| {
+                                        let row: Vec<HeatmapCell> = cells.iter().filter(|c| c.weekday as usize == weekday).cloned().collect();
+                                        view! {
+                                            <div style="color: var(--text-muted); display: flex; align-items: center;">{label}</div>
+                                            {row.into_iter().map(|cell| {
+                                                let intensity = cell.total_sales_cents as f64 / max_total;
+                                                let background = if cell.count == 0 {
+                                                    "var(--bg-page)".to_string()
+                                                } else {
+                                                    format!("rgba(105, 190, 235, {:.2})", 0.15 + intensity * 0.85)
+                                                };
+                                                let title = format!("{} {:02}:00 · {} · {} sales", label, cell.hour, format_currency(cell.total_sales_cents), cell.count);
+                                                view! {
+                                                    <div title=title style=format!("height: 1.75rem; border-radius: 3px; background: {};", background)></div>
+                                                }
+                                            }).collect::<Vec<_>>()}
+                                        }
+                                    }).collect::<Vec<_>>()}
+                                </div>
+                            }
+                        }}
+                    </div>
+                    <p style="margin-top: 0.75rem; color: var(--text-muted); font-size: 0.8rem;">"Hours are in the business timezone. Darker cells took more money; hover a cell for its totals."</p>
+                </div>
+
+                // Pane 4: Staff Leaderboard
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; align-items: flex-end; gap: 1rem; margin-bottom: 1rem;">
+                        <h3 style="margin-right: auto;">"Staff Leaderboard"</h3>
+                        <ExportButtons path="/api/sales/stats/by_staff" params=leaderboard_params />
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Rank by"</label>
+                            <select
+                                on:change=move |ev| set_leaderboard_sort.set(event_target_value(&ev))
+                                prop:value=leaderboard_sort
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            >
+                                <option value="revenue">"Revenue"</option>
+                                <option value="net_revenue">"Net revenue"</option>
+                                <option value="transactions">"Transactions"</option>
+                                <option value="average_basket">"Average basket"</option>
+                                <option value="discount">"Discount given"</option>
+                                <option value="refunds">"Refunds"</option>
+                            </select>
+                        </div>
+                    </div>
+                    <div style="overflow-x: auto;">
+                        <table style="width: 100%; border-collapse: collapse; font-size: 0.9rem;">
+                            <thead>
+                                <tr style="border-bottom: 2px solid var(--border-subtle); text-align: left;">
+                                    <th style="padding: 0.75rem;">"#"</th>
+                                    <th style="padding: 0.75rem;">"Staff"</th>
+                                    <th style="padding: 0.75rem;">"Revenue"</th>
+                                    <th style="padding: 0.75rem;">"Transactions"</th>
+                                    <th style="padding: 0.75rem;">"Average Basket"</th>
+                                    <th style="padding: 0.75rem;">"Discount Given"</th>
+                                    <th style="padding: 0.75rem;">"Refunds"</th>
+                                    <th style="padding: 0.75rem;">"Net Revenue"</th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {move || {
+                                    leaderboard.get().into_iter().map(|entry| {
+                                        let staff_id = entry.staff_id;
+                                        let background = move || if selected_staff.get() == Some(staff_id) { "var(--bg-page)" } else { "transparent" };
+                                        view! {
+                                            <tr
+                                                on:click=move |_| set_selected_staff.update(|selected| {
+                                                    *selected = if *selected == Some(staff_id) { None } else { Some(staff_id) };
+                                                })
+                                                style=move || format!("border-bottom: 1px solid var(--border-subtle); cursor: pointer; background: {};", background())
+                                            >
+                                                <td style="padding: 0.75rem;">{entry.rank}</td>
+                                                <td style="padding: 0.75rem; font-weight: 500;">{entry.staff_name}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(entry.revenue_cents)}</td>
+                                                <td style="padding: 0.75rem;">{entry.transactions}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(entry.average_basket_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(entry.discount_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(entry.refunds_cents)}</td>
+                                                <td style="padding: 0.75rem; font-weight: 600;">{format_currency(entry.net_revenue_cents)}</td>
+                                            </tr>
+                                        }
+                                    }).collect::<Vec<_>>()
+                                }}
+                            </tbody>
+                        </table>
+                    </div>
+                    <p style="margin-top: 0.75rem; color: var(--text-muted); font-size: 0.8rem;">"Refunds count against whoever made the original sale. Click a row for a breakdown."</p>
+
+                    {move || staff_detail.get().map(|detail| view! {
+                        <div style="margin-top: 1.5rem; padding-top: 1.5rem; border-top: 1px solid var(--border-subtle); display: grid; grid-template-columns: 1fr 1fr; gap: 1.5rem;">
+                            <div>
+                                <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 0.75rem;">
+                                    <h4>{format!("{}: Daily Sales", detail.performance.staff_name)}</h4>
+                                    <ExportButtons path=format!("/api/sales/stats/by_staff/{}", detail.performance.staff_id) params=leaderboard_params />
+                                </div>
+                                <table style="width: 100%; border-collapse: collapse; font-size: 0.85rem;">
+                                    <thead>
+                                        <tr style="border-bottom: 2px solid var(--border-subtle); text-align: left;">
+                                            <th style="padding: 0.5rem;">"Date"</th>
+                                            <th style="padding: 0.5rem;">"Transactions"</th>
+                                            <th style="padding: 0.5rem;">"Revenue"</th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {detail.daily.into_iter().filter(|day| day.count > 0).map(|day| view! {
+                                            <tr style="border-bottom: 1px solid var(--border-subtle);">
+                                                <td style="padding: 0.5rem;">{day.date}</td>
+                                                <td style="padding: 0.5rem;">{day.count}</td>
+                                                <td style="padding: 0.5rem;">{format_currency(day.total_sales_cents)}</td>
+                                            </tr>
+                                        }).collect::<Vec<_>>()}
+                                    </tbody>
+                                </table>
+                            </div>
+                            <div>
+                                <h4 style="margin-bottom: 0.75rem;">"Products Sold"</h4>
+                                <table style="width: 100%; border-collapse: collapse; font-size: 0.85rem;">
+                                    <thead>
+                                        <tr style="border-bottom: 2px solid var(--border-subtle); text-align: left;">
+                                            <th style="padding: 0.5rem;">"Product"</th>
+                                            <th style="padding: 0.5rem;">"Quantity"</th>
+                                            <th style="padding: 0.5rem;">"Amount"</th>
+                                        </tr>
+                                    </thead>
+                                    <tbody>
+                                        {detail.products.into_iter().map(|product| view! {
+                                            <tr style="border-bottom: 1px solid var(--border-subtle);">
+                                                <td style="padding: 0.5rem;">{product.product_name}</td>
+                                                <td style="padding: 0.5rem;">{product.total_quantity}</td>
+                                                <td style="padding: 0.5rem;">{format_currency(product.total_amount_cents)}</td>
+                                            </tr>
+                                        }).collect::<Vec<_>>()}
+                                    </tbody>
+                                </table>
+                            </div>
+                        </div>
+                    })}
+                </div>
+
+                // Pane 5: Gross Margin
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; align-items: flex-end; gap: 1rem; margin-bottom: 1rem;">
+                        <h3 style="margin-right: auto;">"Gross Margin"</h3>
+                        <ExportButtons path="/api/sales/stats/margin" params=margin_params />
+                        <div>
+                            <label style="display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;">"Group by"</label>
+                            <select
+                                on:change=move |ev| set_margin_group_by.set(event_target_value(&ev))
+                                prop:value=margin_group_by
+                                style="padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);"
+                            >
+                                <option value="product">"Product"</option>
+                                <option value="category">"Category"</option>
+                                <option value="day">"Day"</option>
+                                <option value="week">"Week"</option>
+                                <option value="month">"Month"</option>
+                            </select>
+                        </div>
+                    </div>
+                    <div style="overflow-x: auto;">
+                        <table style="width: 100%; border-collapse: collapse; font-size: 0.9rem;">
+                            <thead>
+                                <tr style="border-bottom: 2px solid var(--border-subtle); text-align: left;">
+                                    <th style="padding: 0.75rem;">""</th>
+                                    <th style="padding: 0.75rem;">"Quantity"</th>
+                                    <th style="padding: 0.75rem;">"Revenue"</th>
+                                    <th style="padding: 0.75rem;">"Cost"</th>
+                                    <th style="padding: 0.75rem;">"Gross Profit"</th>
+                                    <th style="padding: 0.75rem;">"Margin"</th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {move || margin_report.get().map(|report| {
+                                    let total = report.total;
+                                    view! {
+                                        {report.lines.into_iter().map(|line| view! {
+                                            <tr style="border-bottom: 1px solid var(--border-subtle);">
+                                                <td style="padding: 0.75rem;">{line.label}</td>
+                                                <td style="padding: 0.75rem;">{line.quantity}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(line.revenue_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(line.cost_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(line.gross_profit_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_margin(line.margin_percent)}</td>
+                                            </tr>
+                                        }).collect::<Vec<_>>()}
+                                        <tr style="font-weight: 700;">
+                                            <td style="padding: 0.75rem;">{total.label}</td>
+                                            <td style="padding: 0.75rem;">{total.quantity}</td>
+                                            <td style="padding: 0.75rem;">{format_currency(total.revenue_cents)}</td>
+                                            <td style="padding: 0.75rem;">{format_currency(total.cost_cents)}</td>
+                                            <td style="padding: 0.75rem;">{format_currency(total.gross_profit_cents)}</td>
+                                            <td style="padding: 0.75rem;">{format_margin(total.margin_percent)}</td>
+                                        </tr>
+                                    }
+                                })}
+                            </tbody>
+                        </table>
+                    </div>
+                </div>
+
+                // Pane 6: Product Sales Table
                 <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
-                    <h3 style="margin-bottom: 1rem;">"Sales Details"</h3>
+                    <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 1rem;">
+                        <h3>"Sales Details"</h3>
+                        <ExportButtons path="/api/sales/stats/by_product" params=compare_params />
+                    </div>
                     <div style="overflow-x: auto;">
                         <table style="width: 100%; border-collapse: collapse; font-size: 0.9rem;">
                             <thead>
@@ -204,6 +879,10 @@ pub fn SalesReportsPage() -> impl IntoView {
                                     <th style="padding: 0.75rem;">"Product"</th>
                                     <th style="padding: 0.75rem;">"Quantity Purchased"</th>
                                     <th style="padding: 0.75rem;">"Total Amount Generated"</th>
+                                    <th style="padding: 0.75rem;">"Cost"</th>
+                                    <th style="padding: 0.75rem;">"Gross Profit"</th>
+                                    <th style="padding: 0.75rem;">"Margin"</th>
+                                    <th style="padding: 0.75rem;">"Change"</th>
                                 </tr>
                             </thead>
                             <tbody>
@@ -214,6 +893,10 @@ pub fn SalesReportsPage() -> impl IntoView {
                                                 <td style="padding: 0.75rem;">{sale.product_name}</td>
                                                 <td style="padding: 0.75rem;">{sale.total_quantity}</td>
                                                 <td style="padding: 0.75rem;">{format_currency(sale.total_amount_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(sale.total_cost_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_currency(sale.gross_profit_cents)}</td>
+                                                <td style="padding: 0.75rem;">{format_margin(sale.margin_percent)}</td>
+                                                <td style="padding: 0.75rem;">{change_view(sale.amount_change)}</td>
                                             </tr>
                                         }
                                     }).collect::<Vec<_>>()
@@ -222,6 +905,79 @@ pub fn SalesReportsPage() -> impl IntoView {
                         </table>
                     </div>
                 </div>
+
+                // Pane 7: Customer Segments
+                <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+                    <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 0.25rem;">
+                        <h3>"Customer Segments"</h3>
+                        <ExportButtons path="/api/customers/segments" params=segment_params />
+                    </div>
+                    <p style="color: var(--text-muted); font-size: 0.85rem; margin-bottom: 1rem;">"Recency, frequency and spend over each customer's whole history. Click a segment to list its customers."</p>
+                    <div style="display: grid; grid-template-columns: repeat(6, 1fr); gap: 0.75rem; margin-bottom: 1rem;">
+                        {move || segment_report.get().map(|report| report.segments.into_iter().map(|summary| {
+                            let segment = summary.segment;
+                            let selected = move || segment_filter.get() == Some(segment);
+                            view! {
+                                <div
+                                    on:click=move |_| {
+                                        set_segment_filter.update(|filter| *filter = if *filter == Some(segment) { None } else { Some(segment) });
+                                        set_segment_page.set(1);
+                                    }
+                                    style=move || format!(
+                                        "padding: 0.75rem; border-radius: var(--radius-md); cursor: pointer; border: 1px solid {}; background: {};",
+                                        if selected() { "var(--brand-primary)" } else { "var(--border-subtle)" },
+                                        if selected() { "var(--bg-subtle)" } else { "var(--bg-page)" },
+                                    )
+                                >
+                                    <div style="color: var(--text-muted); font-size: 0.8rem; text-transform: capitalize;">{segment.to_string().replace('_', " ")}</div>
+                                    <div style="font-size: 1.5rem; font-weight: 700;">{summary.customers}</div>
+                                    <div style="font-size: 0.8rem;">{format_currency(summary.total_spend_cents)}</div>
+                                </div>
+                            }
+                        }).collect::<Vec<_>>())}
+                    </div>
+                    <div style="overflow-x: auto;">
+                        <table style="width: 100%; border-collapse: collapse; font-size: 0.9rem;">
+                            <thead>
+                                <tr style="border-bottom: 2px solid var(--border-subtle); text-align: left;">
+                                    <th style="padding: 0.75rem;">"Customer"</th>
+                                    <th style="padding: 0.75rem;">"Segment"</th>
+                                    <th style="padding: 0.75rem;">"RFM"</th>
+                                    <th style="padding: 0.75rem;">"Visits"</th>
+                                    <th style="padding: 0.75rem;">"Lifetime Spend"</th>
+                                    <th style="padding: 0.75rem;">"Average Basket"</th>
+                                    <th style="padding: 0.75rem;">"Last Purchase"</th>
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {move || segment_report.get().map(|report| report.customers.items.into_iter().map(|stats| {
+                                    let rfm = stats.rfm.map(|rfm| format!("{}{}{}", rfm.recency, rfm.frequency, rfm.monetary)).unwrap_or_default();
+                                    let segment = stats.segment.map(|segment| segment.to_string().replace('_', " ")).unwrap_or_default();
+                                    let last_purchase = stats.last_purchase.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default();
+                                    view! {
+                                        <tr style="border-bottom: 1px solid var(--border-subtle);">
+                                            <td style="padding: 0.75rem;">
+                                                <a href=format!("/customers/{}", stats.customer_id) style="color: var(--text-main);">{stats.customer_name}</a>
+                                            </td>
+                                            <td style="padding: 0.75rem; text-transform: capitalize;">{segment}</td>
+                                            <td style="padding: 0.75rem;">{rfm}</td>
+                                            <td style="padding: 0.75rem;">{stats.visit_count}</td>
+                                            <td style="padding: 0.75rem;">{format_currency(stats.total_spend_cents)}</td>
+                                            <td style="padding: 0.75rem;">{format_currency(stats.average_basket_cents)}</td>
+                                            <td style="padding: 0.75rem;">{last_purchase}</td>
+                                        </tr>
+                                    }
+                                }).collect::<Vec<_>>())}
+                            </tbody>
+                        </table>
+                    </div>
+                    <Pagination
+                        page=segment_page
+                        total_pages=Signal::derive(move || segment_report.with(|report| report.as_ref().map(|r| r.customers.total_pages()).unwrap_or(1)))
+                        total=Signal::derive(move || segment_report.with(|report| report.as_ref().map(|r| r.customers.total).unwrap_or(0)))
+                        set_page=set_segment_page
+                    />
+                </div>
             </div>
     }
 }
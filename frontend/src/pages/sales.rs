@@ -1,22 +1,31 @@
 use leptos::*;
 use leptos_router::*;
-use shared::models::{Sale, SaleInput, SaleItem, SaleItemInput, SalesChannel, Product, Customer}; 
+use shared::models::{ApiError, Page, PaymentMethod, Sale, SaleInput, SaleItemInput, SalesChannel, Product, Customer};
 use uuid::Uuid;
-use chrono::{Utc, NaiveDate, DateTime};
-use crate::utils::CURRENCY;
+use shared::validation::Validate;
+use chrono::Utc;
+use crate::components::form_error::FormError;
+use crate::components::pagination::Pagination;
+use crate::components::export_buttons::ExportButtons;
+use crate::utils::{CURRENCY, subscribe_live};
 
 #[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error};
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::spawn_local;
+#[cfg(target_arch = "wasm32")]
+use shared::models::Staff;
 
 #[component]
 pub fn SalesListPage() -> impl IntoView {
     #[allow(unused_variables)]
-    let (sales, set_sales) = create_signal(Vec::<Sale>::new());
+    let (sales, set_sales) = create_signal(Page::<Sale>::default());
     let (start_date, set_start_date) = create_signal(String::new());
     let (end_date, set_end_date) = create_signal(String::new());
     let (search_query, set_search_query) = create_signal(String::new());
+    let (page, set_page) = create_signal(1i64);
     
     let navigate = use_navigate();
     
@@ -27,7 +36,7 @@ pub fn SalesListPage() -> impl IntoView {
             let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
             
             let mut url = "/api/sales_transactions".to_string();
-            let mut params = Vec::new();
+            let mut params = vec![format!("page={}", page.get_untracked())];
             if !start_date.get().is_empty() {
                 params.push(format!("start_date={}", start_date.get()));
             }
@@ -37,10 +46,8 @@ pub fn SalesListPage() -> impl IntoView {
             if !search_query.get().is_empty() {
                 params.push(format!("query={}", search_query.get()));
             }
-            if !params.is_empty() {
-                url.push('?');
-                url.push_str(&params.join("&"));
-            }
+            url.push('?');
+            url.push_str(&params.join("&"));
 
             if let Ok(res) = Request::get(&url)
                 .header("Authorization", &format!("Bearer {}", token))
@@ -49,7 +56,7 @@ pub fn SalesListPage() -> impl IntoView {
                     _navigate("/", Default::default());
                     return;
                 }
-                if let Ok(data) = res.json::<Vec<Sale>>().await {
+                if let Ok(data) = res.json::<Page<Sale>>().await {
                     set_sales.set(data);
                 }
             }
@@ -59,10 +66,36 @@ pub fn SalesListPage() -> impl IntoView {
     create_effect({
         let fetch_sales = fetch_sales.clone();
         move |_| {
+            page.track();
             fetch_sales();
         }
     });
 
+    // New transactions appear at the top of the first page
+    subscribe_live(
+        {
+            let fetch_sales = fetch_sales.clone();
+            move |_| {
+                if page.get_untracked() == 1 {
+                    fetch_sales();
+                }
+            }
+        },
+        |_| {},
+    );
+
+    // Changing the filter starts again from the first page
+    let filter_sales = {
+        let fetch_sales = fetch_sales.clone();
+        move || {
+            if page.get_untracked() == 1 {
+                fetch_sales();
+            } else {
+                set_page.set(1);
+            }
+        }
+    };
+
     view! {
         <div>
             <div style="width: 100%; display: flex; align-items: center; margin-bottom: 2rem;">
@@ -70,6 +103,15 @@ pub fn SalesListPage() -> impl IntoView {
                 <A href="/sales/create" class="btn-primary" attr:style="margin-left: auto; text-decoration: none; display: inline-block; padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); font-weight: 600;">
                     "New Transaction"
                 </A>
+                <div style="margin-left: 1rem;">
+                    <ExportButtons path="/api/sales_transactions" params=Signal::derive(move || {
+                        let mut params = Vec::new();
+                        if !start_date.get().is_empty() { params.push(("start_date", start_date.get())); }
+                        if !end_date.get().is_empty() { params.push(("end_date", end_date.get())); }
+                        if !search_query.get().is_empty() { params.push(("query", search_query.get())); }
+                        params
+                    }) />
+                </div>
             </div>
 
             <div style="background: var(--bg-surface); padding: 1rem; border-radius: var(--radius-md); border: 1px solid var(--border-subtle); margin-bottom: 2rem;">
@@ -102,8 +144,8 @@ pub fn SalesListPage() -> impl IntoView {
                     <button 
                         class="btn-primary"
                         on:click={
-                            let fetch_sales = fetch_sales.clone();
-                            move |_| fetch_sales()
+                            let filter_sales = filter_sales.clone();
+                            move |_| filter_sales()
                         }
                         style="padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); border: none; font-weight: 600; cursor: pointer;"
                     >
@@ -124,7 +166,7 @@ pub fn SalesListPage() -> impl IntoView {
                     </thead>
                     <tbody>
                         <For
-                            each=move || sales.get()
+                            each=move || sales.get().items
                             key=|sale| sale.id
                             children=move |sale| {
                                 view! {
@@ -142,6 +184,12 @@ pub fn SalesListPage() -> impl IntoView {
                     </tbody>
                 </table>
             </div>
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || sales.with(|p| p.total_pages()))
+                total=Signal::derive(move || sales.with(|p| p.total))
+                set_page=set_page
+            />
         </div>
     }
 }
@@ -151,7 +199,9 @@ pub fn SalesDetailPage() -> impl IntoView {
     let params = use_params_map();
     let id = move || params.get().get("id").cloned().unwrap_or_default();
     
+    #[allow(unused_variables)]
     let (sale, set_sale) = create_signal(None::<Sale>);
+    #[allow(unused_variables)]
     let (customer_name, set_customer_name) = create_signal(String::new());
 
     create_effect(move |_| {
@@ -202,6 +252,7 @@ pub fn SalesDetailPage() -> impl IntoView {
                                 <div><span style="color: var(--text-muted);">"Date:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{s.date_and_time.format("%Y-%m-%d %H:%M").to_string()}</span></div>
                                 <div><span style="color: var(--text-muted);">"Customer:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{customer_name.get()}</span></div>
                                 <div><span style="color: var(--text-muted);">"Channel:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{s.sales_channel.to_string()}</span></div>
+                                <div><span style="color: var(--text-muted);">"Payment:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{s.payment_method.title()}</span></div>
                                 <div><span style="color: var(--text-muted);">"Branch:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{s.company_branch}</span></div>
                                 <div><span style="color: var(--text-muted);">"Receipt #:"</span> <span style="font-weight: 500; margin-left: 0.5rem;">{s.receipt_number}</span></div>
                                 <div style="margin-top: 1rem; padding-top: 1rem; border-top: 1px solid var(--border-subtle);">
@@ -254,12 +305,17 @@ pub fn SalesDetailPage() -> impl IntoView {
     }
 }
 
+/// How many matches the product and customer pickers list at a time.
+#[cfg(target_arch = "wasm32")]
+const PICKER_LIMIT: i64 = 20;
+
 #[component]
 pub fn SalesCreatePage() -> impl IntoView {
     // State
     let (customer_id, set_customer_id) = create_signal(String::new());
     let (channel, set_channel) = create_signal("mobile".to_string());
-    let (branch, set_branch) = create_signal("Main Branch".to_string());
+    let (payment_method, set_payment_method) = create_signal("cash".to_string());
+    let (branch, _set_branch) = create_signal("Main Branch".to_string());
     
     // Items List
     // We need a struct to hold temporary item state before creating the final SaleItemInput
@@ -281,28 +337,63 @@ pub fn SalesCreatePage() -> impl IntoView {
     }]);
 
     // Data lists
+    #[allow(unused_variables)]
     let (products, set_products) = create_signal(Vec::<Product>::new());
+    #[allow(unused_variables)]
     let (customers, set_customers) = create_signal(Vec::<Customer>::new());
+    // The signed-in staff member, credited with the sale
+    #[allow(unused_variables)]
+    let (staff_id, set_staff_id) = create_signal(Uuid::nil());
     
     create_effect(move |_| {
          #[cfg(target_arch = "wasm32")]
          spawn_local(async move {
             let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
-            // Fetch Products
-            if let Ok(res) = Request::get("/api/products").header("Authorization", &format!("Bearer {}", token)).send().await {
-                if let Ok(data) = res.json::<Vec<Product>>().await {
-                    set_products.set(data);
-                }
-            }
-            // Fetch Customers
-            if let Ok(res) = Request::get("/api/customers").header("Authorization", &format!("Bearer {}", token)).send().await {
-                if let Ok(data) = res.json::<Vec<Customer>>().await {
-                    set_customers.set(data);
-                }
+            if let Ok(res) = Request::get("/api/auth/profile").header("Authorization", &format!("Bearer {}", token)).send().await
+                && let Ok(staff) = res.json::<Staff>().await {
+                set_staff_id.set(staff.id);
             }
          });
     });
 
+    // The pickers list the first matches for their search box; the server
+    // searches, so neither list is loaded in full. What the sale already
+    // uses stays listed so its selection still shows.
+    let (product_search, set_product_search) = create_signal(String::new());
+    let (customer_search, set_customer_search) = create_signal(String::new());
+    create_effect(move |_| {
+        #[allow(unused_variables)]
+        let search = product_search.get();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            if let Ok(res) = Request::get(&format!("/api/products?limit={}&search={}", PICKER_LIMIT, search)).header("Authorization", &format!("Bearer {}", token)).send().await
+                && let Ok(data) = res.json::<Page<Product>>().await {
+                let picked: Vec<String> = items.get_untracked().into_iter().map(|item| item.product_id).collect();
+                set_products.update(|list| {
+                    list.retain(|product| picked.contains(&product.id.to_string()));
+                    list.extend(data.items.into_iter().filter(|found| !picked.contains(&found.id.to_string())));
+                });
+            }
+        });
+    });
+    create_effect(move |_| {
+        #[allow(unused_variables)]
+        let search = customer_search.get();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            if let Ok(res) = Request::get(&format!("/api/customers?limit={}&search={}", PICKER_LIMIT, search)).header("Authorization", &format!("Bearer {}", token)).send().await
+                && let Ok(data) = res.json::<Page<Customer>>().await {
+                let picked = customer_id.get_untracked();
+                set_customers.update(|list| {
+                    list.retain(|customer| customer.id.to_string() == picked);
+                    list.extend(data.items.into_iter().filter(|found| found.id.to_string() != picked));
+                });
+            }
+        });
+    });
+
     let add_item = move |_| {
         set_items.update(|list| list.push(TempItem {
             id: Uuid::new_v4(),
@@ -344,8 +435,47 @@ pub fn SalesCreatePage() -> impl IntoView {
         })
     };
 
+    let (error, set_error) = create_signal(None::<ApiError>);
+
+    // The request last sent for this form and its Idempotency-Key. Submitting
+    // again after a failure resends it unchanged so the backend can replay
+    // the first response instead of recording the sale twice.
+    let attempt = store_value(None::<(String, String)>);
+    create_effect(move |_| {
+        items.track();
+        customer_id.track();
+        channel.track();
+        payment_method.track();
+        attempt.set_value(None);
+    });
+
     let navigate = use_navigate();
+    #[allow(unused_variables)]
+    let send_transaction = move |key: String, body: String| {
+        let _navigate = navigate.clone();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+            let res = Request::post("/api/sales_transactions")
+                .header("Authorization", &format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .header("Idempotency-Key", &key)
+                .body(body).unwrap()
+                .send().await;
+            
+            match res {
+                Ok(r) if r.ok() => _navigate("/sales", Default::default()),
+                Ok(r) => set_error.set(Some(api_error(r).await)),
+                Err(_) => set_error.set(Some(network_error())),
+            }
+        });
+    };
+
     let save_transaction = move |_| {
+        if let Some((key, body)) = attempt.get_value() {
+            send_transaction(key, body);
+            return;
+        }
         let current_items = items.get();
         let total_val = calculate_total();
         
@@ -362,6 +492,7 @@ pub fn SalesCreatePage() -> impl IntoView {
                 total_cents,
                 total_resolved: total_cents - (item.discount * 100.0) as i64, 
                 note: None,
+                sales_channel: None,
             }
         }).collect();
 
@@ -373,29 +504,23 @@ pub fn SalesCreatePage() -> impl IntoView {
             discount: 0, // Global discount not implemented yet
             total_resolved: (total_val * 100.0) as i64,
             sales_channel: if channel.get() == "mobile" { SalesChannel::Mobile } else { SalesChannel::Web },
-            staff_responsible: Uuid::nil(), // TODO: Get from auth context?
+            staff_responsible: staff_id.get(),
             company_branch: branch.get(),
             car_number: "".to_string(),
             receipt_number: Uuid::new_v4().to_string().chars().take(8).collect(),
+            payment_method: payment_method.get().parse().unwrap_or_default(),
+            shift_id: None, // The server uses the signed-in staff member's open shift
         };
 
-        let _navigate = navigate.clone();
-        #[cfg(target_arch = "wasm32")]
-        spawn_local(async move {
-            let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
-            let res = Request::post("/api/sales_transactions")
-                .header("Authorization", &format!("Bearer {}", token))
-                .json(&input).unwrap()
-                .send().await;
-            
-            if let Ok(r) = res {
-                if r.ok() {
-                    _navigate("/sales", Default::default());
-                } else {
-                    // Show error?
-                }
-            }
-        });
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+
+        let key = Uuid::new_v4().to_string();
+        let body = serde_json::to_string(&input).unwrap();
+        attempt.set_value(Some((key.clone(), body.clone())));
+        send_transaction(key, body);
     };
 
     view! {
@@ -405,11 +530,19 @@ pub fn SalesCreatePage() -> impl IntoView {
                 <h1 style="font-size: 2rem; font-weight: 700; color: var(--text-heading); margin-top: 0.5rem;">"New Sales Transaction"</h1>
             </div>
 
+            <FormError error=error />
+
             <div style="background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle); max-width: 800px;">
                 // Header Info
                 <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 1rem; margin-bottom: 2rem;">
                      <div style="display: flex; flex-direction: column; gap: 0.5rem;">
                         <label style="font-weight: 500;">"Customer"</label>
+                        <input
+                            type="search"
+                            placeholder="Search customers..."
+                            prop:value=customer_search
+                            on:input=move |ev| set_customer_search.set(event_target_value(&ev))
+                        />
                         <select 
                             on:change=move |ev| set_customer_id.set(event_target_value(&ev))
                             prop:value=customer_id
@@ -432,19 +565,37 @@ pub fn SalesCreatePage() -> impl IntoView {
                             <option value="web">"Web"</option>
                         </select>
                     </div>
+                     <div style="display: flex; flex-direction: column; gap: 0.5rem;">
+                        <label style="font-weight: 500;">"Payment"</label>
+                        <select
+                            on:change=move |ev| set_payment_method.set(event_target_value(&ev))
+                            prop:value=payment_method
+                        >
+                            {[PaymentMethod::Cash, PaymentMethod::Card, PaymentMethod::MobileMoney, PaymentMethod::Other].into_iter().map(|method| view! {
+                                <option value=method.as_str()>{method.title()}</option>
+                            }).collect::<Vec<_>>()}
+                        </select>
+                    </div>
                 </div>
 
                 // Items
                 <h3 style="font-size: 1.1rem; font-weight: 600; margin-bottom: 1rem;">"Items"</h3>
+                <input
+                    type="search"
+                    placeholder="Search products..."
+                    prop:value=product_search
+                    on:input=move |ev| set_product_search.set(event_target_value(&ev))
+                    style="margin-bottom: 1rem;"
+                />
                 <div style="display: flex; flex-direction: column; gap: 1rem;">
                     <For
                         each=move || items.get()
                         key=|item| item.id
                         children=move |item| {
                             let i_id = item.id;
-                            let _remove = remove_item.clone();
-                            let _update_prod = update_item_product.clone();
-                            let _update_qty = update_item_qty.clone();
+                            let _remove = remove_item;
+                            let _update_prod = update_item_product;
+                            let _update_qty = update_item_qty;
                             view! {
                                 <div style="display: flex; gap: 1rem; align-items: flex-end; padding: 1rem; background: var(--bg-subtle); border-radius: var(--radius-md);">
                                     <div style="flex: 2; display: flex; flex-direction: column; gap: 0.25rem;">
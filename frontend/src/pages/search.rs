@@ -0,0 +1,163 @@
+use leptos::*;
+use leptos_router::*;
+use shared::models::{ApiError, Page, SearchHit, SearchKind};
+
+#[cfg(target_arch = "wasm32")]
+use gloo_net::http::Request;
+#[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_futures::spawn_local;
+
+use crate::components::form_error::FormError;
+use crate::components::pagination::Pagination;
+
+fn hit_href(hit: &SearchHit) -> String {
+    match hit.kind {
+        SearchKind::Product => format!("/products/{}", hit.id),
+        SearchKind::Customer => format!("/customers/{}", hit.id),
+        SearchKind::Sale => format!("/sales/{}", hit.id),
+    }
+}
+
+#[component]
+pub fn SearchPage() -> impl IntoView {
+    #[allow(unused_variables)]
+    let (results, set_results) = create_signal(Page::<SearchHit>::default());
+    let (search_query, set_search_query) = create_signal(String::new());
+    let (kind, set_kind) = create_signal(String::new());
+    let (page, set_page) = create_signal(1i64);
+    #[allow(unused_variables)]
+    let (error, set_error) = create_signal(None::<ApiError>);
+
+    let navigate = use_navigate();
+
+    let fetch_results = move || {
+        let _navigate = navigate.clone();
+        let query = search_query.get_untracked();
+        if query.trim().is_empty() {
+            set_results.set(Page::default());
+        } else {
+            #[cfg(target_arch = "wasm32")]
+            spawn_local(async move {
+                let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+
+                let page = page.get_untracked().to_string();
+                let kind = kind.get_untracked();
+                let mut params = vec![("page", page.as_str()), ("q", query.as_str())];
+                if !kind.is_empty() {
+                    params.push(("kind", kind.as_str()));
+                }
+
+                set_error.set(None);
+                match Request::get("/api/search")
+                    .query(params)
+                    .header("Authorization", &format!("Bearer {}", token))
+                    .send().await {
+                    Ok(res) if res.status() == 401 => _navigate("/", Default::default()),
+                    Ok(res) if res.ok() => {
+                        if let Ok(data) = res.json::<Page<SearchHit>>().await {
+                            set_results.set(data);
+                        }
+                    }
+                    Ok(res) => set_error.set(Some(api_error(res).await)),
+                    Err(_) => set_error.set(Some(network_error())),
+                }
+            });
+        }
+    };
+
+    create_effect({
+        let fetch_results = fetch_results.clone();
+        move |_| {
+            page.track();
+            fetch_results();
+        }
+    });
+
+    // A new query starts again from the first page
+    let run_search = move || {
+        if page.get_untracked() == 1 {
+            fetch_results();
+        } else {
+            set_page.set(1);
+        }
+    };
+
+    view! {
+        <div>
+            <div style="width: 100%; display: flex; align-items: center; margin-bottom: 2rem;">
+                <h1 style="font-size: 2rem; font-weight: 700; color: var(--text-heading);">"Search"</h1>
+            </div>
+
+            <div style="margin-bottom: 2rem; display: flex; gap: 1rem;">
+                <input
+                    type="text"
+                    placeholder="Search products, customers and receipts..."
+                    prop:value=search_query
+                    on:input=move |ev| set_search_query.set(event_target_value(&ev))
+                    on:keydown={
+                        let run_search = run_search.clone();
+                        move |ev| {
+                            if ev.key() == "Enter" {
+                                run_search();
+                            }
+                        }
+                    }
+                    style="width: 40%;"
+                />
+                <select
+                    prop:value=kind
+                    on:change={
+                        let run_search = run_search.clone();
+                        move |ev| {
+                            set_kind.set(event_target_value(&ev));
+                            run_search();
+                        }
+                    }
+                >
+                    <option value="">"Everything"</option>
+                    <option value=SearchKind::Product.as_str()>"Products"</option>
+                    <option value=SearchKind::Customer.as_str()>"Customers"</option>
+                    <option value=SearchKind::Sale.as_str()>"Sales"</option>
+                </select>
+                <button
+                    class="btn-primary"
+                    on:click=move |_| run_search()
+                    style="padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); border: none; font-weight: 600; cursor: pointer;"
+                >
+                    "Search"
+                </button>
+            </div>
+
+            <FormError error=error />
+
+            <div style="display: flex; flex-direction: column; gap: 0.75rem;">
+                <For
+                    each=move || results.get().items
+                    key=|hit| (hit.kind.as_str(), hit.id)
+                    children=move |hit| {
+                        let href = hit_href(&hit);
+                        view! {
+                            <A href=href attr:style="display: block; padding: 1rem; background: var(--bg-surface); border: 1px solid var(--border-subtle); border-radius: var(--radius-md); text-decoration: none; color: var(--text-body);">
+                                <div style="display: flex; align-items: center; gap: 0.75rem;">
+                                    <span style="font-weight: 600; color: var(--text-heading);">{hit.title}</span>
+                                    <span style="padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: var(--bg-subtle); color: var(--text-muted); font-size: 0.75rem;">{hit.kind.as_str()}</span>
+                                </div>
+                                // The snippet is escaped server-side; only <mark> tags are markup.
+                                <p style="margin: 0.5rem 0 0; color: var(--text-muted);" inner_html=hit.snippet></p>
+                            </A>
+                        }
+                    }
+                />
+            </div>
+
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || results.with(|p| p.total_pages()))
+                total=Signal::derive(move || results.with(|p| p.total))
+                set_page=set_page
+            />
+        </div>
+    }
+}
@@ -0,0 +1,373 @@
+use leptos::*;
+use shared::models::{
+    ApiError, CashMovementInput, CashMovementKind, Shift, ShiftCloseInput, ShiftInput, ShiftReport, ShiftReportKind,
+};
+use shared::validation::Validate;
+use crate::components::form_error::FormError;
+use crate::components::export_buttons::ExportButtons;
+use crate::utils::CURRENCY;
+
+#[cfg(target_arch = "wasm32")]
+use gloo_net::http::Request;
+#[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error, open_printable};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_futures::spawn_local;
+#[cfg(target_arch = "wasm32")]
+use shared::models::Page;
+
+fn format_currency(cents: i64) -> String {
+    format!("{} {:.2}", CURRENCY, cents as f64 / 100.0)
+}
+
+/// `12.50` as 1250 cents; blank or unreadable amounts are `None`.
+fn parse_cents(value: &str) -> Option<i64> {
+    value.trim().parse::<f64>().ok().map(|amount| (amount * 100.0).round() as i64)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn auth_header() -> String {
+    let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+    format!("Bearer {}", token)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn post_json<T: serde::Serialize>(path: &str, body: &T) -> Result<gloo_net::http::Response, ApiError> {
+    let res = Request::post(path)
+        .header("Authorization", &auth_header())
+        .json(body).unwrap()
+        .send().await
+        .map_err(|_| network_error())?;
+    if res.ok() { Ok(res) } else { Err(api_error(res).await) }
+}
+
+#[allow(unused_variables)]
+fn print_report(shift_id: uuid::Uuid) {
+    #[cfg(target_arch = "wasm32")]
+    spawn_local(async move {
+        open_printable(&format!("/api/shifts/{}/report/print", shift_id)).await;
+    });
+}
+
+/// The figures of an X or Z report, with the cash reconciliation last.
+#[component]
+fn ShiftReportView(report: ShiftReport) -> impl IntoView {
+    let title = match report.kind {
+        ShiftReportKind::X => "X report (shift still open)",
+        ShiftReportKind::Z => "Z report",
+    };
+    let shift_id = report.shift.id;
+    let line = |label: &'static str, value: String| view! {
+        <div style="display: flex; justify-content: space-between; padding: 0.35rem 0; border-bottom: 1px solid var(--border-subtle);">
+            <span style="color: var(--text-muted);">{label}</span>
+            <span style="font-weight: 600;">{value}</span>
+        </div>
+    };
+    let over_short = report.over_short_cents.map(|cents| {
+        let (label, color) = match cents {
+            0 => ("Balanced", "var(--text-main)"),
+            cents if cents > 0 => ("Over", "#047857"),
+            _ => ("Short", "#b91c1c"),
+        };
+        view! {
+            <div style=format!("display: flex; justify-content: space-between; padding: 0.5rem 0; font-weight: 700; color: {};", color)>
+                <span>{label}</span>
+                <span>{format_currency(cents)}</span>
+            </div>
+        }
+    });
+
+    view! {
+        <div style="background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
+            <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 1rem;">
+                <h3>{title}</h3>
+                <div style="display: flex; gap: 0.5rem;">
+                    <ExportButtons path=format!("/api/shifts/{}/report", shift_id) />
+                    <button type="button" class="btn btn-secondary" on:click=move |_| print_report(shift_id)>"Print"</button>
+                </div>
+            </div>
+            <div style="display: grid; grid-template-columns: repeat(auto-fit, minmax(260px, 1fr)); gap: 2rem;">
+                <div>
+                    {line("Sales", report.sales_count.to_string())}
+                    {line("Gross sales", format_currency(report.gross_sales_cents))}
+                    {line("Discounts", format_currency(report.discount_cents))}
+                    {line("Refunds", format!("{} ({})", format_currency(report.refunds_cents), report.refunds_count))}
+                    {line("Voids", format!("{} ({})", format_currency(report.voids_cents), report.voids_count))}
+                    {line("Net sales", format_currency(report.net_sales_cents))}
+                    <h4 style="margin-top: 1rem;">"By tender"</h4>
+                    {report.by_tender.into_iter().map(|tender| line(tender.payment_method.title(), format_currency(tender.total_cents))).collect_view()}
+                    <h4 style="margin-top: 1rem;">"By channel"</h4>
+                    {report.by_channel.into_iter().map(|channel| view! {
+                        <div style="display: flex; justify-content: space-between; padding: 0.35rem 0;">
+                            <span style="color: var(--text-muted);">{channel.sales_channel.to_string()}</span>
+                            <span style="font-weight: 600;">{format_currency(channel.total_cents)}</span>
+                        </div>
+                    }).collect_view()}
+                </div>
+                <div>
+                    {line("Opening float", format_currency(report.opening_float_cents))}
+                    {line("Cash sales", format_currency(report.cash_sales_cents))}
+                    {line("Cash returned", format_currency(report.cash_returned_cents))}
+                    {line("Cash in", format_currency(report.cash_in_cents))}
+                    {line("Payouts", format_currency(report.payouts_cents))}
+                    {line("Drops", format_currency(report.drops_cents))}
+                    {line("Expected cash", format_currency(report.expected_cash_cents))}
+                    {report.counted_cash_cents.map(|counted| line("Counted cash", format_currency(counted)))}
+                    {over_short}
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+pub fn ShiftsPage() -> impl IntoView {
+    // Bumped after every change to reload the shift, its report and the history
+    #[allow(unused_variables)]
+    let (refresh, set_refresh) = create_signal(0u32);
+    #[allow(unused_variables)]
+    let (current, set_current) = create_signal(None::<Shift>);
+    #[allow(unused_variables)]
+    let (report, set_report) = create_signal(None::<ShiftReport>);
+    #[allow(unused_variables)]
+    let (closed_report, set_closed_report) = create_signal(None::<ShiftReport>);
+    #[allow(unused_variables)]
+    let (history, set_history) = create_signal(Vec::<Shift>::new());
+    let (error, set_error) = create_signal(None::<ApiError>);
+
+    let (register, set_register) = create_signal("Till 1".to_string());
+    let (branch, set_branch) = create_signal(String::new());
+    let (opening_float, set_opening_float) = create_signal(String::new());
+    let (movement_kind, set_movement_kind) = create_signal("payout".to_string());
+    let (movement_amount, set_movement_amount) = create_signal(String::new());
+    let (movement_reason, set_movement_reason) = create_signal(String::new());
+    let (counted, set_counted) = create_signal(String::new());
+    let (note, set_note) = create_signal(String::new());
+
+    create_effect(move |_| {
+        refresh.track();
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let shift = match Request::get("/api/shifts/current").header("Authorization", &auth_header()).send().await {
+                Ok(resp) if resp.ok() => resp.json::<Shift>().await.ok(),
+                _ => None,
+            };
+            let report = match &shift {
+                Some(shift) => match Request::get(&format!("/api/shifts/{}/report", shift.id))
+                    .header("Authorization", &auth_header())
+                    .send().await {
+                    Ok(resp) => resp.json::<ShiftReport>().await.ok(),
+                    Err(_) => None,
+                },
+                None => None,
+            };
+            set_current.set(shift);
+            set_report.set(report);
+
+            if let Ok(resp) = Request::get("/api/shifts")
+                .query([("open", "false"), ("limit", "10")])
+                .header("Authorization", &auth_header())
+                .send().await
+                && let Ok(page) = resp.json::<Page<Shift>>().await {
+                set_history.set(page.items);
+            }
+        });
+    });
+
+    let open_shift = move |_| {
+        let input = ShiftInput {
+            register: register.get(),
+            company_branch: branch.get(),
+            opening_float_cents: parse_cents(&opening_float.get()).unwrap_or(0),
+        };
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            match post_json("/api/shifts", &input).await {
+                Ok(_) => {
+                    set_error.set(None);
+                    set_closed_report.set(None);
+                    set_opening_float.set(String::new());
+                    set_refresh.update(|n| *n += 1);
+                }
+                Err(err) => set_error.set(Some(err)),
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = input;
+    };
+
+    let record_movement = move |_| {
+        let Some(shift) = current.get() else { return };
+        let kind = match movement_kind.get().as_str() {
+            "cash_in" => CashMovementKind::CashIn,
+            "drop" => CashMovementKind::Drop,
+            _ => CashMovementKind::Payout,
+        };
+        let input = CashMovementInput {
+            kind,
+            amount_cents: parse_cents(&movement_amount.get()).unwrap_or(0),
+            reason: movement_reason.get(),
+        };
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            match post_json(&format!("/api/shifts/{}/cash_movements", shift.id), &input).await {
+                Ok(_) => {
+                    set_error.set(None);
+                    set_movement_amount.set(String::new());
+                    set_movement_reason.set(String::new());
+                    set_refresh.update(|n| *n += 1);
+                }
+                Err(err) => set_error.set(Some(err)),
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = (shift, input);
+    };
+
+    let close_shift = move |_| {
+        let Some(shift) = current.get() else { return };
+        let input = ShiftCloseInput {
+            counted_cash_cents: parse_cents(&counted.get()).unwrap_or(-1),
+            note: Some(note.get()).filter(|note| !note.trim().is_empty()),
+        };
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            match post_json(&format!("/api/shifts/{}/close", shift.id), &input).await {
+                Ok(resp) => {
+                    set_error.set(None);
+                    set_closed_report.set(resp.json::<ShiftReport>().await.ok());
+                    set_counted.set(String::new());
+                    set_note.set(String::new());
+                    set_refresh.update(|n| *n += 1);
+                }
+                Err(err) => set_error.set(Some(err)),
+            }
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = (shift, input);
+    };
+
+    let field_style = "padding: 0.5rem; border: 1px solid var(--border-subtle); border-radius: var(--radius-md);";
+    let label_style = "display: block; margin-bottom: 0.5rem; color: var(--text-muted); font-size: 0.9rem;";
+    let card_style = "background: var(--bg-surface); padding: 1.5rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);";
+
+    view! {
+        <div style="display: flex; flex-direction: column; gap: 1.5rem;">
+            <div>
+                <h1 style="font-size: 2rem; font-weight: 700; color: var(--text-heading);">"Till Shift"</h1>
+                <p style="color: var(--text-muted); margin-top: 0.5rem;">"Sales you ring up go into your open shift."</p>
+            </div>
+
+            <FormError error=error />
+
+            {move || closed_report.get().map(|report| view! { <ShiftReportView report=report /> })}
+
+            {move || match current.get() {
+                None => view! {
+                    <div style=card_style>
+                        <h3 style="margin-bottom: 1rem;">"Open a shift"</h3>
+                        <div style="display: flex; gap: 1rem; align-items: flex-end; flex-wrap: wrap;">
+                            <div>
+                                <label style=label_style>"Till"</label>
+                                <input type="text" prop:value=register on:input=move |ev| set_register.set(event_target_value(&ev)) style=field_style />
+                            </div>
+                            <div>
+                                <label style=label_style>"Branch"</label>
+                                <input type="text" prop:value=branch on:input=move |ev| set_branch.set(event_target_value(&ev)) style=field_style />
+                            </div>
+                            <div>
+                                <label style=label_style>{format!("Opening float ({})", CURRENCY)}</label>
+                                <input type="number" min="0" step="0.01" placeholder="0.00" prop:value=opening_float on:input=move |ev| set_opening_float.set(event_target_value(&ev)) style=field_style />
+                            </div>
+                            <button type="button" class="btn btn-primary" on:click=open_shift>"Open shift"</button>
+                        </div>
+                    </div>
+                }.into_view(),
+                Some(shift) => view! {
+                    <div style=card_style>
+                        <h3>{format!("{} {}", shift.register, shift.company_branch)}</h3>
+                        <p style="color: var(--text-muted);">
+                            {format!("Opened {} with a float of {}", shift.opened_at.format("%Y-%m-%d %H:%M UTC"), format_currency(shift.opening_float_cents))}
+                        </p>
+                    </div>
+
+                    {move || report.get().map(|report| view! { <ShiftReportView report=report /> })}
+
+                    <div style="display: grid; grid-template-columns: repeat(auto-fit, minmax(320px, 1fr)); gap: 1.5rem;">
+                        <div style=card_style>
+                            <h3 style="margin-bottom: 1rem;">"Cash in / out"</h3>
+                            <div style="display: flex; flex-direction: column; gap: 0.75rem;">
+                                <select prop:value=movement_kind on:change=move |ev| set_movement_kind.set(event_target_value(&ev)) style=field_style>
+                                    <option value="payout">"Payout"</option>
+                                    <option value="drop">"Drop"</option>
+                                    <option value="cash_in">"Cash in"</option>
+                                </select>
+                                <input type="number" min="0" step="0.01" placeholder="Amount" prop:value=movement_amount on:input=move |ev| set_movement_amount.set(event_target_value(&ev)) style=field_style />
+                                <input type="text" placeholder="Reason" prop:value=movement_reason on:input=move |ev| set_movement_reason.set(event_target_value(&ev)) style=field_style />
+                                <button type="button" class="btn btn-secondary" on:click=record_movement>"Record"</button>
+                            </div>
+                        </div>
+                        <div style=card_style>
+                            <h3 style="margin-bottom: 1rem;">"Close shift"</h3>
+                            <div style="display: flex; flex-direction: column; gap: 0.75rem;">
+                                <input type="number" min="0" step="0.01" placeholder="Counted cash" prop:value=counted on:input=move |ev| set_counted.set(event_target_value(&ev)) style=field_style />
+                                <input type="text" placeholder="Note (optional)" prop:value=note on:input=move |ev| set_note.set(event_target_value(&ev)) style=field_style />
+                                <button type="button" class="btn btn-primary" on:click=close_shift>"Close and print Z report"</button>
+                            </div>
+                        </div>
+                    </div>
+                }.into_view(),
+            }}
+
+            <div style=card_style>
+                <div style="display: flex; justify-content: space-between; align-items: center; margin-bottom: 1rem;">
+                    <h3>"Recent closed shifts"</h3>
+                    <ExportButtons path="/api/shifts" params=Signal::derive(|| vec![("open", "false".to_string())]) />
+                </div>
+                <table style="width: 100%; border-collapse: collapse;">
+                    <thead>
+                        <tr style="text-align: left; color: var(--text-muted);">
+                            <th style="padding: 0.5rem;">"Till"</th>
+                            <th style="padding: 0.5rem;">"Opened"</th>
+                            <th style="padding: 0.5rem;">"Closed"</th>
+                            <th style="padding: 0.5rem;">"Counted"</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        <For
+                            each=move || history.get()
+                            key=|shift| shift.id
+                            children=move |shift| {
+                                let id = shift.id;
+                                view! {
+                                    <tr style="border-top: 1px solid var(--border-subtle);">
+                                        <td style="padding: 0.5rem;">{format!("{} {}", shift.register, shift.company_branch)}</td>
+                                        <td style="padding: 0.5rem;">{shift.opened_at.format("%Y-%m-%d %H:%M").to_string()}</td>
+                                        <td style="padding: 0.5rem;">{shift.closed_at.map(|at| at.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()}</td>
+                                        <td style="padding: 0.5rem;">{shift.counted_cash_cents.map(format_currency).unwrap_or_default()}</td>
+                                        <td style="padding: 0.5rem; text-align: right;">
+                                            <button type="button" class="btn btn-secondary" on:click=move |_| print_report(id)>"Print Z report"</button>
+                                        </td>
+                                    </tr>
+                                }
+                            }
+                        />
+                    </tbody>
+                </table>
+            </div>
+        </div>
+    }
+}
@@ -1,13 +1,16 @@
 use leptos::*;
 use leptos_router::*;
-use shared::models::{Staff, StaffInput};
+use shared::models::{ApiError, Page, Staff, StaffInput};
 #[cfg(target_arch = "wasm32")]
 use shared::models::UploadResponse;
 use uuid::Uuid;
+use shared::validation::Validate;
 
 #[cfg(target_arch = "wasm32")]
 use gloo_net::http::Request;
 #[cfg(target_arch = "wasm32")]
+use crate::utils::{api_error, network_error};
+#[cfg(target_arch = "wasm32")]
 use wasm_bindgen::JsCast;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen_futures::JsFuture;
@@ -15,16 +18,29 @@ use wasm_bindgen_futures::JsFuture;
 use web_sys::{FormData, RequestInit, Response};
 use web_sys::Event;
 
+use crate::components::form_error::FormError;
+use crate::components::pagination::Pagination;
+use crate::components::export_buttons::ExportButtons;
+
 #[component]
 pub fn StaffListPage() -> impl IntoView {
     #[allow(unused_variables)]
-    let (staff_list, set_staff_list) = create_signal(Vec::<Staff>::new());
+    let (staff_list, set_staff_list) = create_signal(Page::<Staff>::default());
+    let (page, set_page) = create_signal(1i64);
+    let (show_archived, set_show_archived) = create_signal(false);
+    #[allow(unused_variables)]
+    let (refresh, set_refresh) = create_signal(0u32);
 
     let navigate = use_navigate();
 
     create_effect(move |_| {
         #[allow(unused_variables)]
         let navigate = navigate.clone();
+        #[allow(unused_variables)]
+        let current_page = page.get();
+        #[allow(unused_variables)]
+        let include_archived = show_archived.get();
+        refresh.track();
         #[cfg(target_arch = "wasm32")]
         spawn_local(async move {
             let token = window().local_storage().ok().flatten().and_then(|s| s.get_item("jwt_token").ok().flatten()).unwrap_or_default();
@@ -32,12 +48,12 @@ pub fn StaffListPage() -> impl IntoView {
                 navigate("/", Default::default());
                 return;
             }
-            if let Ok(res) = Request::get("/api/staff").header("Authorization", &format!("Bearer {}", token)).send().await {
+            if let Ok(res) = Request::get(&format!("/api/staff?page={}&include_archived={}", current_page, include_archived)).header("Authorization", &format!("Bearer {}", token)).send().await {
                  if res.status() == 401 {
                      navigate("/", Default::default());
                      return;
                  }
-                 if let Ok(list) = res.json::<Vec<Staff>>().await {
+                 if let Ok(list) = res.json::<Page<Staff>>().await {
                      set_staff_list.set(list);
                  }
             }
@@ -45,13 +61,25 @@ pub fn StaffListPage() -> impl IntoView {
     });
 
     let delete_staff = move |id: String| {
-        #[allow(unused_variables)]
-        let id = id;
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = id;
         #[cfg(target_arch = "wasm32")]
         spawn_local(async move {
             let token = window().local_storage().ok().flatten().and_then(|s| s.get_item("jwt_token").ok().flatten()).unwrap_or_default();
             if Request::delete(&format!("/api/staff/{}", id)).header("Authorization", &format!("Bearer {}", token)).send().await.is_ok() {
-                set_staff_list.update(|list| list.retain(|s| s.id.to_string() != id));
+                set_refresh.update(|n| *n += 1);
+            }
+        });
+    };
+
+    let restore_staff = move |id: String| {
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = id;
+        #[cfg(target_arch = "wasm32")]
+        spawn_local(async move {
+            let token = window().local_storage().ok().flatten().and_then(|s| s.get_item("jwt_token").ok().flatten()).unwrap_or_default();
+            if Request::post(&format!("/api/staff/{}/restore", id)).header("Authorization", &format!("Bearer {}", token)).send().await.is_ok() {
+                set_refresh.update(|n| *n += 1);
             }
         });
     };
@@ -63,6 +91,23 @@ pub fn StaffListPage() -> impl IntoView {
                 <A href="/staff/create" class="btn-primary" attr:style="margin-left: auto; text-decoration: none; display: inline-block; padding: 0.75rem 1.5rem; background-color: var(--brand-primary); color: white; border-radius: var(--radius-md); font-weight: 600;">
                     "Add Staff"
                 </A>
+                <div style="margin-left: 1rem;">
+                    <ExportButtons path="/api/staff" params=Signal::derive(move || vec![("include_archived", show_archived.get().to_string())]) />
+                </div>
+            </div>
+
+            <div style="margin-bottom: 1rem; display: flex;">
+                <label style="display: flex; align-items: center; gap: 0.5rem; color: var(--text-muted);">
+                    <input
+                        type="checkbox"
+                        prop:checked=show_archived
+                        on:change=move |ev| {
+                            set_show_archived.set(event_target_checked(&ev));
+                            set_page.set(1);
+                        }
+                    />
+                    "Show archived"
+                </label>
             </div>
 
             <div style="overflow-x: auto; background: var(--bg-surface); border-radius: var(--radius-lg); border: 1px solid var(--border-subtle);">
@@ -78,10 +123,13 @@ pub fn StaffListPage() -> impl IntoView {
                     </thead>
                     <tbody>
                         <For
-                            each=move || staff_list.get()
+                            each=move || staff_list.get().items
                             key=|staff| staff.id
                             children=move |staff| {
-                                let delete_action = delete_staff.clone();
+                                let delete_action = delete_staff;
+                                let restore_action = restore_staff;
+                                let archived = staff.archived_at.is_some();
+                                let staff_uuid = staff.id.to_string();
                                 view! {
                                     <tr style="border-bottom: 1px solid var(--border-subtle);">
                                         <td style="padding: 1rem;">
@@ -92,6 +140,9 @@ pub fn StaffListPage() -> impl IntoView {
                                                     style="width: 32px; height: 32px; border-radius: 50%; object-fit: cover;"
                                                 />
                                                 <span>{format!("{} {}", staff.first_name, staff.last_name)}</span>
+                                                <Show when=move || archived>
+                                                    <span style="padding: 0.125rem 0.5rem; border-radius: var(--radius-full); background: var(--bg-subtle); color: var(--text-muted); font-size: 0.75rem; font-weight: 600;">"Archived"</span>
+                                                </Show>
                                             </div>
                                         </td>
                                         <td style="padding: 1rem;">{staff.staff_id}</td>
@@ -99,12 +150,35 @@ pub fn StaffListPage() -> impl IntoView {
                                         <td style="padding: 1rem;">"Staff"</td>
                                         <td style="padding: 1rem; display: flex; gap: 0.5rem;">
                                             <A href=format!("/staff/{}", staff.id) attr:style="text-decoration: none; color: var(--brand-primary); font-weight: 600;">"Edit"</A>
-                                            <button 
-                                                on:click=move |_| delete_action(staff.id.to_string())
-                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
+                                            <Show
+                                                when=move || archived
+                                                fallback={
+                                                    let staff_uuid = staff_uuid.clone();
+                                                    move || {
+                                                        let staff_uuid = staff_uuid.clone();
+                                                        view! {
+                                                            <button 
+                                                                on:click=move |_| delete_action(staff_uuid.clone())
+                                                                style="background: none; border: none; color: var(--state-error); cursor: pointer; font-weight: 500;"
+                                                            >
+                                                                "Archive"
+                                                            </button>
+                                                        }
+                                                    }
+                                                }
                                             >
-                                                "Delete"
-                                            </button>
+                                                {
+                                                    let staff_uuid = staff_uuid.clone();
+                                                    view! {
+                                                        <button
+                                                            on:click=move |_| restore_action(staff_uuid.clone())
+                                                            style="background: none; border: none; color: var(--brand-primary); cursor: pointer; font-weight: 500;"
+                                                        >
+                                                            "Restore"
+                                                        </button>
+                                                    }
+                                                }
+                                            </Show>
                                         </td>
                                     </tr>
                                 }
@@ -113,6 +187,12 @@ pub fn StaffListPage() -> impl IntoView {
                     </tbody>
                 </table>
             </div>
+            <Pagination
+                page=page
+                total_pages=Signal::derive(move || staff_list.with(|p| p.total_pages()))
+                total=Signal::derive(move || staff_list.with(|p| p.total))
+                set_page=set_page
+            />
         </div>
     }
 }
@@ -164,12 +244,11 @@ pub fn StaffEditPage() -> impl IntoView {
         });
     });
 
+    let (error, set_error) = create_signal(None::<ApiError>);
+
     let save_staff = move |_| {
-        #[allow(unused_variables)]
-        let navigate = navigate.clone();
         #[allow(unused_variables)]
         let current_id = id();
-        #[allow(unused_variables)]
         let input = StaffInput {
             id: if current_id == "create" || current_id.is_empty() { None } else { Uuid::parse_str(&current_id).ok() },
             staff_id: staff_id_field.get(),
@@ -181,6 +260,14 @@ pub fn StaffEditPage() -> impl IntoView {
             password: if password.get().is_empty() { None } else { Some(password.get()) },
         };
 
+        if let Err(errors) = input.validate() {
+            set_error.set(Some(ApiError::from(errors)));
+            return;
+        }
+
+        #[allow(unused_variables)]
+        let navigate = navigate.clone();
+
         #[cfg(target_arch = "wasm32")]
         spawn_local(async move {
             let token = window().local_storage().ok().flatten().and_then(|s| s.get_item("jwt_token").ok().flatten()).unwrap_or_default();
@@ -209,7 +296,11 @@ pub fn StaffEditPage() -> impl IntoView {
                 }
                 if res.ok() {
                     navigate("/staff", Default::default());
+                } else {
+                    set_error.set(Some(api_error(res).await));
                 }
+            } else {
+                set_error.set(Some(network_error()));
             }
         });
     };
@@ -264,6 +355,8 @@ pub fn StaffEditPage() -> impl IntoView {
                 </h1>
             </div>
 
+            <FormError error=error />
+
             <div style="background: var(--bg-surface); padding: 2rem; border-radius: var(--radius-lg); border: 1px solid var(--border-subtle); display: flex; flex-direction: column; gap: 1.5rem;">
                 <div style="display: flex; gap: 1rem;">
                     <div style="display: flex; flex-direction: column; gap: 0.5rem; flex: 1;">
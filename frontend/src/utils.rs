@@ -2,3 +2,154 @@ pub const CURRENCY: &str = match option_env!("CURRENCY") {
     Some(c) => c,
     None => "GHS",
 };
+
+/// Reads the API's JSON error body, falling back to the HTTP status for
+/// responses that did not come from our handlers.
+#[cfg(target_arch = "wasm32")]
+pub async fn api_error(res: gloo_net::http::Response) -> shared::models::ApiError {
+    use shared::models::{ApiError, ErrorCode};
+
+    let status = res.status();
+    let status_text = res.status_text();
+    res.json::<ApiError>().await.unwrap_or_else(|_| {
+        ApiError::new(
+            ErrorCode::Internal,
+            format!("Request failed ({} {})", status, status_text),
+        )
+    })
+}
+
+/// Error shown when the request never reached the server.
+#[cfg(target_arch = "wasm32")]
+pub fn network_error() -> shared::models::ApiError {
+    shared::models::ApiError::new(shared::models::ErrorCode::Internal, "Network error")
+}
+
+/// Listens to the `/api/live` event stream until the calling component is
+/// cleaned up. The browser reconnects by itself if the connection drops.
+#[allow(unused_variables)]
+pub fn subscribe_live(
+    on_sale: impl Fn(shared::models::Sale) + 'static,
+    on_stats: impl Fn(shared::models::DashboardStats) + 'static,
+) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        use serde::de::DeserializeOwned;
+        use shared::models::{LIVE_EVENT_SALE, LIVE_EVENT_STATS};
+        use wasm_bindgen::{JsCast, closure::Closure};
+        use web_sys::{EventSource, MessageEvent};
+
+        fn listener<T: DeserializeOwned>(handler: impl Fn(T) + 'static) -> Closure<dyn Fn(MessageEvent)> {
+            Closure::new(move |event: MessageEvent| {
+                if let Some(data) = event.data().as_string()
+                    && let Ok(value) = serde_json::from_str::<T>(&data)
+                {
+                    handler(value);
+                }
+            })
+        }
+
+        // EventSource cannot send headers, so the token goes in the query
+        let token = web_sys::window().unwrap().local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+        let Ok(source) = EventSource::new(&format!("/api/live?token={}", token)) else {
+            return;
+        };
+        let on_sale = listener(on_sale);
+        let on_stats = listener(on_stats);
+        let _ = source.add_event_listener_with_callback(LIVE_EVENT_SALE, on_sale.as_ref().unchecked_ref());
+        let _ = source.add_event_listener_with_callback(LIVE_EVENT_STATS, on_stats.as_ref().unchecked_ref());
+
+        leptos::on_cleanup(move || {
+            source.close();
+            drop((on_sale, on_stats));
+        });
+    }
+}
+
+/// Downloads `path` as a `csv` or `xlsx` export through the browser, with
+/// the auth header a plain link could not send.
+#[cfg(target_arch = "wasm32")]
+pub async fn download_export(path: &str, mut params: Vec<(&'static str, String)>, format: &str) {
+    use wasm_bindgen::JsCast;
+
+    let window = web_sys::window().unwrap();
+    let token = window.local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+    params.push(("format", format.to_string()));
+    let response = gloo_net::http::Request::get(path)
+        .query(params)
+        .header("Authorization", &format!("Bearer {}", token))
+        .send()
+        .await;
+    let res = match response {
+        Ok(res) if res.ok() => res,
+        Ok(res) => {
+            let _ = window.alert_with_message(&api_error(res).await.message);
+            return;
+        }
+        Err(_) => {
+            let _ = window.alert_with_message(&network_error().message);
+            return;
+        }
+    };
+
+    // The server names the file in Content-Disposition
+    let filename = res
+        .headers()
+        .get("content-disposition")
+        .and_then(|value| value.split("filename=\"").nth(1).map(|name| name.trim_end_matches('"').to_string()))
+        .unwrap_or_else(|| format!("export.{}", format));
+    let Ok(bytes) = res.binary().await else {
+        return;
+    };
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes.as_slice()));
+    let Ok(blob) = web_sys::Blob::new_with_u8_array_sequence(&parts) else {
+        return;
+    };
+    let Ok(url) = web_sys::Url::create_object_url_with_blob(&blob) else {
+        return;
+    };
+    if let Some(link) = window
+        .document()
+        .and_then(|document| document.create_element("a").ok())
+        .and_then(|element| element.dyn_into::<web_sys::HtmlAnchorElement>().ok())
+    {
+        link.set_href(&url);
+        link.set_download(&filename);
+        link.click();
+    }
+    let _ = web_sys::Url::revoke_object_url(&url);
+}
+
+/// Opens the HTML page at `path` in a new tab to be printed, fetched with
+/// the auth header a plain link could not send.
+#[cfg(target_arch = "wasm32")]
+pub async fn open_printable(path: &str) {
+    let window = web_sys::window().unwrap();
+    let token = window.local_storage().unwrap().unwrap().get_item("jwt_token").unwrap().unwrap_or_default();
+    let response = gloo_net::http::Request::get(path)
+        .header("Authorization", &format!("Bearer {}", token))
+        .send()
+        .await;
+    let html = match response {
+        Ok(res) if res.ok() => res.text().await.unwrap_or_default(),
+        Ok(res) => {
+            let _ = window.alert_with_message(&api_error(res).await.message);
+            return;
+        }
+        Err(_) => {
+            let _ = window.alert_with_message(&network_error().message);
+            return;
+        }
+    };
+
+    let parts = js_sys::Array::of1(&wasm_bindgen::JsValue::from_str(&html));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("text/html");
+    let Ok(blob) = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options) else {
+        return;
+    };
+    // The tab keeps its own reference, so the URL is not revoked here
+    if let Ok(url) = web_sys::Url::create_object_url_with_blob(&blob) {
+        let _ = window.open_with_url_and_target(&url, "_blank");
+    }
+}
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use utoipa::ToSchema;
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::validation::{email_list, http_url, not_in_future, not_in_past, phone_number};
 
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub struct Product {
@@ -10,10 +13,27 @@ pub struct Product {
     pub name: String,
     pub description: String,
     pub price_cents: i64,
+    /// What one unit costs us, for margin reporting
+    #[serde(default)]
+    pub cost_price_cents: i64,
     pub stock: i64,
     pub product_type: ProductType,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
     #[schema(no_recursion)]
     pub details: Vec<ProductDetails>,
+    /// Set when the product was deleted; archived products stay resolvable from sales
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+impl Product {
+    /// Physical goods with a reorder point are low on stock once they reach it.
+    pub fn is_low_stock(&self) -> bool {
+        matches!(self.product_type, ProductType::PhysicalGood)
+            && self.reorder_point > 0
+            && self.stock <= self.reorder_point
+    }
 }
 
 impl std::fmt::Display for Product {
@@ -22,14 +42,28 @@ impl std::fmt::Display for Product {
     }
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
 pub struct ProductInput {
+    #[validate(length(min = 1, max = 200, message = "must be between 1 and 200 characters"))]
     pub name: String,
+    #[validate(length(max = 2000, message = "must be at most 2000 characters"))]
     pub description: String,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub price_cents: i64,
+    #[serde(default)]
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub cost_price_cents: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub stock: i64,
     pub product_type: ProductType,
+    #[serde(default)]
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub reorder_point: i64,
+    #[serde(default)]
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub reorder_quantity: i64,
     #[schema(no_recursion)]
+    #[validate(nested)]
     pub details: Vec<ProductDetailsInput>,
 }
 
@@ -40,8 +74,9 @@ pub struct ProductDetails {
     pub detail_value: String,
 }
 
-#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Serialize, Deserialize, ToSchema, Validate)]
 pub struct ProductDetailsInput {
+    #[validate(length(min = 1, message = "must not be empty"))]
     pub detail_name: String,
     pub detail_value: String,
 }
@@ -74,6 +109,55 @@ impl FromStr for ProductType {
     }
 }
 
+/// A price a product had, has, or is scheduled to have from `effective_from`.
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
+pub struct ProductPrice {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub price_cents: i64,
+    pub effective_from: DateTime<Utc>,
+    /// Staff member who set the price; unknown for prices that predate the history
+    pub changed_by: Option<Uuid>,
+    pub changed_by_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ProductPrice {
+    pub fn is_scheduled(&self) -> bool {
+        self.effective_from > Utc::now()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct ProductPriceInput {
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub price_cents: i64,
+    /// When the price takes effect; omit to change it now
+    #[validate(custom(function = "not_in_past"))]
+    pub effective_from: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ProductPriceHistory {
+    #[serde(flatten)]
+    pub page: Page<ProductPrice>,
+    /// Price in effect at the requested time, if the product had one then
+    pub price_cents_at: Option<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
+pub struct StockAlert {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub product_name: String,
+    pub stock: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub sale_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub acknowledged: bool,
+}
+
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
 pub struct Customer {
     pub id: Uuid,
@@ -85,6 +169,9 @@ pub struct Customer {
     pub email: String,
     #[schema(no_recursion)]
     pub details: Vec<CustomerDetails>,
+    /// Set when the customer was deleted; archived customers stay resolvable from sales
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>,
 }
 
 impl std::fmt::Display for Customer {
@@ -93,15 +180,22 @@ impl std::fmt::Display for Customer {
     }
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
 pub struct CustomerInput {
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
     pub first_name: String,
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
     pub last_name: String,
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
     pub middle_name: Option<String>,
+    #[validate(custom(function = "phone_number"))]
     pub mobile_number: String,
+    #[validate(custom(function = "not_in_future"))]
     pub date_of_birth: NaiveDate,
+    #[validate(email(message = "must be a valid email address"))]
     pub email: String,
     #[schema(no_recursion)]
+    #[validate(nested)]
     pub details: Vec<CustomerDetailsInput>,
 }
 
@@ -112,8 +206,9 @@ pub struct CustomerDetails {
     pub detail_value: String,
 }
 
-#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Clone, Serialize, Deserialize, ToSchema, Validate)]
 pub struct CustomerDetailsInput {
+    #[validate(length(min = 1, message = "must not be empty"))]
     pub detail_name: String,
     pub detail_value: String,
 }
@@ -137,6 +232,9 @@ pub struct SaleItem {
     pub note: Option<String>,
     pub product_name: Option<String>,
     pub price_per_item: Option<i64>,
+    /// Product cost at the time of sale
+    #[serde(default)]
+    pub cost_per_item: Option<i64>,
 }
 
 impl std::fmt::Display for SaleItem {
@@ -149,17 +247,25 @@ impl std::fmt::Display for SaleItem {
     }
 }
 
-#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
 pub struct SaleItemInput {
     pub sale_id: Option<Uuid>,
     pub product_id: Uuid,
     pub customer_id: Option<Uuid>,
     pub date_of_sale: DateTime<Utc>,
+    #[validate(range(min = 1, message = "must be at least 1"))]
     pub quantity: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub discount: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub total_cents: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub total_resolved: i64, // Amount resolved in cents
     pub note: Option<String>,
+    /// Channel of the sale made for an item without `sale_id`; defaults to
+    /// mobile. Ignored within a sale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sales_channel: Option<SalesChannel>,
 }
 
 #[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
@@ -191,6 +297,51 @@ impl FromStr for SalesChannel {
     }
 }
 
+/// How a sale was paid for, its tender.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    #[default]
+    Cash,
+    Card,
+    MobileMoney,
+    Other,
+}
+
+impl PaymentMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentMethod::Cash => "cash",
+            PaymentMethod::Card => "card",
+            PaymentMethod::MobileMoney => "mobile_money",
+            PaymentMethod::Other => "other",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            PaymentMethod::Cash => "Cash",
+            PaymentMethod::Card => "Card",
+            PaymentMethod::MobileMoney => "Mobile money",
+            PaymentMethod::Other => "Other",
+        }
+    }
+}
+
+impl FromStr for PaymentMethod {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cash" => Ok(PaymentMethod::Cash),
+            "card" => Ok(PaymentMethod::Card),
+            "mobile_money" => Ok(PaymentMethod::MobileMoney),
+            "other" => Ok(PaymentMethod::Other),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, ToSchema, Debug)]
 pub struct Sale {
     pub id: Uuid,
@@ -205,6 +356,9 @@ pub struct Sale {
     pub company_branch: String,
     pub car_number: String,
     pub receipt_number: String,
+    pub payment_method: PaymentMethod,
+    /// Till shift the sale was rung up in; absent for sales made outside one
+    pub shift_id: Option<Uuid>,
 }
 
 impl std::fmt::Display for Sale {
@@ -213,19 +367,31 @@ impl std::fmt::Display for Sale {
     }
 }
 
-#[derive(Serialize, Deserialize, ToSchema, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
 pub struct SaleInput {
     pub customer_id: Option<Uuid>,
     pub date_and_time: DateTime<Utc>,
+    #[validate(length(min = 1, message = "must contain at least one item"), nested)]
     pub sale_items: Vec<SaleItemInput>,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub total_cents: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub discount: i64,
+    #[validate(range(min = 0, message = "must not be negative"))]
     pub total_resolved: i64,
     pub sales_channel: SalesChannel,
     pub staff_responsible: Uuid,
     pub company_branch: String,
     pub car_number: String,
+    #[validate(length(min = 1, message = "must not be empty"))]
     pub receipt_number: String,
+    /// Defaults to cash
+    #[serde(default)]
+    pub payment_method: PaymentMethod,
+    /// Open shift to record the sale in; defaults to the signed-in staff
+    /// member's open shift, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shift_id: Option<Uuid>,
 }
 
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
@@ -238,6 +404,47 @@ pub struct Staff {
     pub staff_id: String,
     pub username: String,
     pub password_hash: String,
+    /// Set when the staff member was deleted; archived staff cannot log in
+    #[serde(default)]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub role: StaffRole,
+}
+
+/// What a staff member may do beyond selling.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StaffRole {
+    #[default]
+    Staff,
+    /// Also manages staff accounts, roles, backups and integrity checks
+    Admin,
+}
+
+impl StaffRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StaffRole::Staff => "staff",
+            StaffRole::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for StaffRole {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "staff" => Ok(StaffRole::Staff),
+            "admin" => Ok(StaffRole::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct StaffRoleInput {
+    pub role: StaffRole,
 }
 
 impl std::fmt::Display for Staff {
@@ -246,15 +453,21 @@ impl std::fmt::Display for Staff {
     }
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
 pub struct StaffInput {
     pub id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
     pub first_name: String,
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
     pub last_name: String,
+    #[validate(custom(function = "phone_number"))]
     pub mobile_number: String,
     pub photo_link: String,
+    #[validate(length(min = 1, message = "must not be empty"))]
     pub staff_id: String,
+    #[validate(length(min = 3, max = 64, message = "must be between 3 and 64 characters"))]
     pub username: String,
+    #[validate(length(min = 8, message = "must be at least 8 characters"))]
     pub password: Option<String>,
 }
 
@@ -262,18 +475,339 @@ pub struct StaffInput {
 pub struct SalesStats {
     pub total_sales_cents: i64,
     pub count: i64,
+    /// Set when a comparison period was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_change: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count_change: Option<Change>,
 }
 
-#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
 pub struct DailySales {
     pub date: String, // YYYY-MM-DD
     pub total_sales_cents: i64,
     pub count: i64,
+    /// Day of the comparison period in the same position, e.g. the same
+    /// weekday a week earlier for `previous_period` over a week
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compared_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_change: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count_change: Option<Change>,
+}
+
+/// Period a report is compared against.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ComparisonPeriod {
+    /// The same number of days right before the report's range
+    PreviousPeriod,
+    /// The report's range one year earlier
+    LastYear,
+    /// `compare_start_date` to `compare_end_date`
+    Custom,
+}
+
+/// How a figure moved against the comparison period.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+pub struct Change {
+    /// The figure over the comparison period
+    pub previous: i64,
+    /// Current minus previous
+    pub delta: i64,
+    /// Delta as a percentage of the previous figure, to two decimals;
+    /// `None` when there was nothing to compare against
+    pub percent_change: Option<f64>,
+}
+
+impl Change {
+    pub fn between(current: i64, previous: i64) -> Self {
+        let delta = current - previous;
+        Change {
+            previous,
+            delta,
+            percent_change: (previous != 0)
+                .then(|| (delta as f64 * 10_000.0 / previous as f64).round() / 100.0),
+        }
+    }
+}
+
+/// Sales in one hour of one weekday, summed over a date range.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct HeatmapCell {
+    /// 0 = Monday … 6 = Sunday, in the business timezone
+    pub weekday: u32,
+    /// 0–23, in the business timezone
+    pub hour: u32,
+    pub total_sales_cents: i64,
+    /// Sales transactions; sale items recorded on their own count once each
+    pub count: i64,
+}
+
+/// Length of the buckets a sales time series is grouped into, in the
+/// business timezone.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeBucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+/// Day `week` buckets of a time series start on.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl WeekStart {
+    pub fn weekday(self) -> chrono::Weekday {
+        match self {
+            WeekStart::Monday => chrono::Weekday::Mon,
+            WeekStart::Tuesday => chrono::Weekday::Tue,
+            WeekStart::Wednesday => chrono::Weekday::Wed,
+            WeekStart::Thursday => chrono::Weekday::Thu,
+            WeekStart::Friday => chrono::Weekday::Fri,
+            WeekStart::Saturday => chrono::Weekday::Sat,
+            WeekStart::Sunday => chrono::Weekday::Sun,
+        }
+    }
+}
+
+/// What a sales time series is split into one series per value of.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SeriesBreakdown {
+    /// The transaction's `company_branch`
+    Branch,
+    /// The transaction's `sales_channel`
+    Channel,
+    /// The staff member responsible for the transaction
+    Staff,
+    /// The product's `product_type`
+    ProductType,
+}
+
+/// Sales within one bucket of a time series.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq)]
+pub struct SeriesPoint {
+    /// Local start of the bucket: YYYY-MM-DD, or YYYY-MM-DDTHH:00 for hours
+    pub start: String,
+    pub total_sales_cents: i64,
+    /// Sale items
+    pub count: i64,
+}
+
+/// One line of a time series, with a point for every bucket in the range.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct SalesSeries {
+    /// Breakdown value, e.g. the branch name or staff id; absent for the
+    /// overall series and for sales without a value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub label: String,
+    pub total_sales_cents: i64,
+    pub count: i64,
+    pub points: Vec<SeriesPoint>,
+}
+
+/// Sales over a date range, bucketed by time and optionally broken down.
+/// The first and last buckets can reach outside the range, e.g. the week
+/// the range starts in, but only count sales within it.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct TimeSeries {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub bucket: TimeBucket,
+    pub week_start: WeekStart,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<SeriesBreakdown>,
+    /// One series when there is no breakdown, otherwise one per value,
+    /// highest total first
+    pub series: Vec<SalesSeries>,
+}
+
+/// What the staff leaderboard is ranked by, highest first.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StaffRanking {
+    #[default]
+    Revenue,
+    NetRevenue,
+    Transactions,
+    AverageBasket,
+    Discount,
+    Refunds,
 }
 
+/// One staff member's sales over a date range. Sales count towards the staff
+/// member responsible for them; refunds count towards the staff member who
+/// made the refunded sale, in the period the refund happened.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct StaffPerformance {
+    /// 1 for the top of the leaderboard
+    pub rank: i64,
+    pub staff_id: Uuid,
+    pub staff_name: String,
+    pub revenue_cents: i64,
+    pub transactions: i64,
+    pub average_basket_cents: i64,
+    pub discount_cents: i64,
+    pub refunds_cents: i64,
+    /// Revenue less refunds, the basis for commission
+    pub net_revenue_cents: i64,
+}
+
+/// Drill-down into one staff member's leaderboard entry.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct StaffPerformanceDetail {
+    pub performance: StaffPerformance,
+    /// Every day of the range, including days without sales
+    pub daily: Vec<DailySales>,
+    pub products: Vec<ProductSalesSummary>,
+}
+
+/// Recency, frequency and monetary scores from 1 to 5. Scores are relative
+/// to every customer who has bought something: 5 is the most recent, most
+/// frequent or highest-spending fifth.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RfmScore {
+    pub recency: u8,
+    pub frequency: u8,
+    pub monetary: u8,
+}
+
+impl RfmScore {
+    pub fn segment(&self) -> CustomerSegment {
+        let RfmScore { recency, frequency, monetary } = *self;
+        if recency >= 4 && frequency >= 4 && monetary >= 4 {
+            CustomerSegment::Champions
+        } else if recency >= 3 && frequency >= 4 {
+            CustomerSegment::Loyal
+        } else if recency >= 4 && frequency <= 2 {
+            CustomerSegment::New
+        } else if recency <= 2 && frequency >= 3 {
+            CustomerSegment::AtRisk
+        } else if recency <= 2 {
+            CustomerSegment::Lapsed
+        } else {
+            CustomerSegment::Potential
+        }
+    }
+}
+
+/// Customer groups derived from RFM scores.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomerSegment {
+    /// Bought recently, often and the most
+    Champions,
+    /// Buy often and still around
+    Loyal,
+    /// Bought recently for the first few times
+    New,
+    /// Recent customers who could buy more often
+    Potential,
+    /// Used to buy often but have not for a while
+    AtRisk,
+    /// Bought rarely and not for a while
+    Lapsed,
+}
+
+impl CustomerSegment {
+    pub const ALL: [CustomerSegment; 6] = [
+        CustomerSegment::Champions,
+        CustomerSegment::Loyal,
+        CustomerSegment::New,
+        CustomerSegment::Potential,
+        CustomerSegment::AtRisk,
+        CustomerSegment::Lapsed,
+    ];
+}
+
+impl std::fmt::Display for CustomerSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CustomerSegment::Champions => "champions",
+            CustomerSegment::Loyal => "loyal",
+            CustomerSegment::New => "new",
+            CustomerSegment::Potential => "potential",
+            CustomerSegment::AtRisk => "at_risk",
+            CustomerSegment::Lapsed => "lapsed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A customer's purchase history. Spend is what was resolved on their sales.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct CustomerStats {
+    pub customer_id: Uuid,
+    pub customer_name: String,
+    pub first_purchase: Option<DateTime<Utc>>,
+    pub last_purchase: Option<DateTime<Utc>>,
+    pub days_since_last_purchase: Option<i64>,
+    /// Sales transactions
+    pub visit_count: i64,
+    pub total_spend_cents: i64,
+    pub average_basket_cents: i64,
+    /// `None` until the customer has bought something
+    pub rfm: Option<RfmScore>,
+    pub segment: Option<CustomerSegment>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct SegmentSummary {
+    pub segment: CustomerSegment,
+    pub customers: i64,
+    pub total_spend_cents: i64,
+}
+
+/// Customers grouped by RFM segment.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct CustomerSegmentReport {
+    /// Every segment, including empty ones
+    pub segments: Vec<SegmentSummary>,
+    /// Customers in the requested segment, or in any segment, highest spend first
+    pub customers: Page<CustomerStats>,
+}
+
+/// The calendar reports follow: "today" and every date filter are days in
+/// the business timezone.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct BusinessCalendar {
+    /// IANA timezone name, e.g. `Africa/Accra`
+    pub timezone: String,
+    pub today: NaiveDate,
+}
+
+/// Aggregates pushed to dashboards by the `/api/live` event stream.
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct DashboardStats {
+    pub today: SalesStats,
+    pub week: Vec<DailySales>,
+}
+
+/// `/api/live` event carrying a [`Sale`] that was just recorded
+pub const LIVE_EVENT_SALE: &str = "sale";
+/// `/api/live` event carrying refreshed [`DashboardStats`]
+pub const LIVE_EVENT_STATS: &str = "stats";
+
 #[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
 pub struct SalesItemsListResponse {
-    pub sales: Vec<SaleItem>,
+    #[serde(flatten)]
+    pub page: Page<SaleItem>,
     pub total_sales_period_cents: i64,
 }
 
@@ -288,4 +822,793 @@ pub struct ProductSalesSummary {
     pub product_name: String,
     pub total_quantity: i64,
     pub total_amount_cents: i64,
+    #[serde(default)]
+    pub total_cost_cents: i64,
+    #[serde(default)]
+    pub gross_profit_cents: i64,
+    #[serde(default)]
+    pub margin_percent: Option<f64>,
+    /// Set when a comparison period was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity_change: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_change: Option<Change>,
+}
+
+/// Gross profit as a percentage of revenue, to two decimals; `None` without
+/// revenue.
+pub fn margin_percent(revenue_cents: i64, gross_profit_cents: i64) -> Option<f64> {
+    (revenue_cents != 0)
+        .then(|| (gross_profit_cents as f64 * 10_000.0 / revenue_cents as f64).round() / 100.0)
+}
+
+/// What the margin report adds up sales by.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarginGrouping {
+    #[default]
+    Product,
+    /// The product type
+    Category,
+    Day,
+    /// Weeks starting on Monday
+    Week,
+    Month,
+}
+
+/// Revenue against cost for one product, category or period.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct MarginLine {
+    /// Product name, product type, or the period's first day (`YYYY-MM` for months)
+    pub label: String,
+    pub quantity: i64,
+    pub revenue_cents: i64,
+    pub cost_cents: i64,
+    pub gross_profit_cents: i64,
+    pub margin_percent: Option<f64>,
+}
+
+impl MarginLine {
+    pub fn new(label: String, quantity: i64, revenue_cents: i64, cost_cents: i64) -> Self {
+        let gross_profit_cents = revenue_cents - cost_cents;
+        MarginLine {
+            label,
+            quantity,
+            revenue_cents,
+            cost_cents,
+            gross_profit_cents,
+            margin_percent: margin_percent(revenue_cents, gross_profit_cents),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct MarginReport {
+    pub group_by: MarginGrouping,
+    pub lines: Vec<MarginLine>,
+    pub total: MarginLine,
+}
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Envelope returned by every list endpoint.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of matching rows across all pages
+    pub total: i64,
+    /// 1-based page number
+    pub page: i64,
+    pub limit: i64,
+    pub next_page: Option<i64>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, page: i64, limit: i64) -> Self {
+        let next_page = if page * limit < total { Some(page + 1) } else { None };
+        Self {
+            items,
+            total,
+            page,
+            limit,
+            next_page,
+        }
+    }
+
+    pub fn total_pages(&self) -> i64 {
+        if self.limit <= 0 {
+            return 1;
+        }
+        ((self.total + self.limit - 1) / self.limit).max(1)
+    }
+}
+
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Self::new(Vec::new(), 0, 1, DEFAULT_PAGE_LIMIT)
+    }
+}
+
+/// Machine-readable error category carried by every error response.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    ValidationFailed,
+    Internal,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// JSON body returned by the API for any non-2xx response.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: vec![],
+        }
+    }
+
+    /// Message for a single field, if the server reported one.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.details
+            .iter()
+            .find(|detail| detail.field == name)
+            .map(|detail| detail.message.as_str())
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for detail in &self.details {
+            write!(f, "; {}: {}", detail.field, detail.message)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Product,
+    Customer,
+    Sale,
+}
+
+impl SearchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchKind::Product => "product",
+            SearchKind::Customer => "customer",
+            SearchKind::Sale => "sale",
+        }
+    }
+}
+
+impl FromStr for SearchKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "product" => Ok(SearchKind::Product),
+            "customer" => Ok(SearchKind::Customer),
+            "sale" => Ok(SearchKind::Sale),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub id: Uuid,
+    pub title: String,
+    /// HTML-escaped excerpt with the matched terms wrapped in `<mark>` tags
+    pub snippet: String,
+    /// bm25 relevance score; lower is a better match
+    pub rank: f64,
+}
+
+/// Catalogue and customer records a device needs to record sales offline.
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct SyncChanges {
+    /// Pass back as `since` to fetch only what changed after this response
+    pub version: i64,
+    /// True when this is a complete snapshot that replaces everything the
+    /// device holds, rather than the changes since its version
+    pub full: bool,
+    /// Changed products; archived ones should no longer be offered for sale
+    pub products: Vec<Product>,
+    pub customers: Vec<Customer>,
+    pub deleted_product_ids: Vec<Uuid>,
+    pub deleted_customer_ids: Vec<Uuid>,
+}
+
+/// Most sales accepted in one upload.
+pub const MAX_SYNC_BATCH: u64 = 500;
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct SyncUpload {
+    /// Each sale is validated and stored on its own, so one bad sale does
+    /// not hold back the rest of the batch
+    #[validate(length(min = 1, max = MAX_SYNC_BATCH, message = "must contain between 1 and 500 sales"))]
+    pub sales: Vec<SyncSaleInput>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SyncSaleInput {
+    /// Id the device gave the sale; becomes the sale's id, so uploading the
+    /// same sale again is harmless
+    pub client_id: Uuid,
+    pub sale: SaleInput,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncSaleStatus {
+    /// Recorded now, possibly with conflicts that were resolved
+    Accepted,
+    /// Already recorded by an earlier upload; nothing changed
+    Duplicate,
+    /// Not recorded; see `conflicts` and `error`
+    Rejected,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncConflictKind {
+    /// The product was archived; the sale is still recorded
+    ProductArchived,
+    /// The product does not exist on the server; the sale is rejected
+    ProductMissing,
+    /// The customer was archived; the sale is still recorded
+    CustomerArchived,
+    /// The customer does not exist on the server; the sale is recorded
+    /// without a customer
+    CustomerMissing,
+    /// The device charged a different price than the server had in effect
+    /// at the time of sale; the amounts charged are kept as recorded
+    PriceChanged,
+    /// The shift the sale was rung up in has closed or does not exist; the
+    /// sale is recorded outside any shift, leaving the Z report unchanged
+    ShiftClosed,
+    /// The staff member responsible was archived; the sale is rejected
+    StaffArchived,
+    /// The staff member responsible does not exist on the server; the sale
+    /// is rejected
+    StaffMissing,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct SyncConflict {
+    pub kind: SyncConflictKind,
+    /// Offending item, or `None` when the conflict is about the whole sale
+    pub item_index: Option<usize>,
+    /// The product, customer, shift or staff member the conflict is about
+    pub record_id: Uuid,
+    /// Unit price the device charged, for `price_changed`
+    pub client_price_cents: Option<i64>,
+    /// Unit price in effect on the server at the time of sale, for `price_changed`
+    pub server_price_cents: Option<i64>,
+}
+
+impl SyncConflict {
+    /// A conflict about a record that involves no prices.
+    pub fn record(kind: SyncConflictKind, item_index: Option<usize>, record_id: Uuid) -> Self {
+        Self {
+            kind,
+            item_index,
+            record_id,
+            client_price_cents: None,
+            server_price_cents: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct SyncSaleResult {
+    pub client_id: Uuid,
+    pub status: SyncSaleStatus,
+    pub conflicts: Vec<SyncConflict>,
+    /// Why the sale was rejected, when it failed validation
+    pub error: Option<ApiError>,
+}
+
+/// Per-sale outcome of an upload, in the order the sales were sent.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct SyncUploadResult {
+    pub results: Vec<SyncSaleResult>,
+}
+
+/// Something that happened in the shop that webhooks can subscribe to.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEventType {
+    /// A sale was recorded, including the one-item sale `POST /api/sales`
+    /// makes for an item without a sale; data is the `Sale`
+    #[serde(rename = "sale.created")]
+    SaleCreated,
+    /// A sale line was deleted; data is the removed `SaleItem`
+    #[serde(rename = "sale.voided")]
+    SaleVoided,
+    /// Data is the `Product` for every product event
+    #[serde(rename = "product.created")]
+    ProductCreated,
+    #[serde(rename = "product.updated")]
+    ProductUpdated,
+    #[serde(rename = "product.archived")]
+    ProductArchived,
+    #[serde(rename = "product.restored")]
+    ProductRestored,
+    /// Data is the `Customer` for every customer event
+    #[serde(rename = "customer.created")]
+    CustomerCreated,
+    #[serde(rename = "customer.updated")]
+    CustomerUpdated,
+    #[serde(rename = "customer.archived")]
+    CustomerArchived,
+    #[serde(rename = "customer.restored")]
+    CustomerRestored,
+    /// A sale took a product down to its reorder point; data is the `StockAlert`
+    #[serde(rename = "stock.low")]
+    StockLow,
+}
+
+impl WebhookEventType {
+    pub const ALL: [WebhookEventType; 11] = [
+        WebhookEventType::SaleCreated,
+        WebhookEventType::SaleVoided,
+        WebhookEventType::ProductCreated,
+        WebhookEventType::ProductUpdated,
+        WebhookEventType::ProductArchived,
+        WebhookEventType::ProductRestored,
+        WebhookEventType::CustomerCreated,
+        WebhookEventType::CustomerUpdated,
+        WebhookEventType::CustomerArchived,
+        WebhookEventType::CustomerRestored,
+        WebhookEventType::StockLow,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::SaleCreated => "sale.created",
+            WebhookEventType::SaleVoided => "sale.voided",
+            WebhookEventType::ProductCreated => "product.created",
+            WebhookEventType::ProductUpdated => "product.updated",
+            WebhookEventType::ProductArchived => "product.archived",
+            WebhookEventType::ProductRestored => "product.restored",
+            WebhookEventType::CustomerCreated => "customer.created",
+            WebhookEventType::CustomerUpdated => "customer.updated",
+            WebhookEventType::CustomerArchived => "customer.archived",
+            WebhookEventType::CustomerRestored => "customer.restored",
+            WebhookEventType::StockLow => "stock.low",
+        }
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        WebhookEventType::ALL
+            .into_iter()
+            .find(|event_type| event_type.as_str() == value)
+            .ok_or(())
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    pub description: Option<String>,
+    pub event_types: Vec<WebhookEventType>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    /// Key for verifying the `X-FastSales-Signature` header; only returned
+    /// when the webhook is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct WebhookInput {
+    #[validate(custom(function = "http_url"))]
+    pub url: String,
+    #[validate(length(max = 200, message = "must be at most 200 characters"))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, message = "must contain at least one event type"))]
+    pub event_types: Vec<WebhookEventType>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    Succeeded,
+    /// Gave up after the last retry
+    Failed,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Succeeded => "succeeded",
+            WebhookDeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for WebhookDeliveryStatus {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(WebhookDeliveryStatus::Pending),
+            "succeeded" => Ok(WebhookDeliveryStatus::Succeeded),
+            "failed" => Ok(WebhookDeliveryStatus::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One event sent, or being sent, to one webhook.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: WebhookEventType,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i64,
+    /// When the next attempt is due, while the delivery is pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// HTTP status of the last attempt, if the endpoint answered
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportType {
+    /// Sales total and count for each day of the period
+    DailyTakings,
+    /// Best-selling products of the period by amount
+    TopProducts,
+    /// Physical goods at or below their reorder point when the report is sent
+    LowStock,
+}
+
+impl ReportType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportType::DailyTakings => "daily_takings",
+            ReportType::TopProducts => "top_products",
+            ReportType::LowStock => "low_stock",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ReportType::DailyTakings => "Daily takings",
+            ReportType::TopProducts => "Top products",
+            ReportType::LowStock => "Low stock",
+        }
+    }
+}
+
+impl FromStr for ReportType {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "daily_takings" => Ok(ReportType::DailyTakings),
+            "top_products" => Ok(ReportType::TopProducts),
+            "low_stock" => Ok(ReportType::LowStock),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How often a subscription is sent. Each run reports on the period that
+/// just ended: yesterday, the last seven days or the last calendar month.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFrequency {
+    Daily,
+    /// Sent on Mondays
+    Weekly,
+    /// Sent on the first of the month
+    Monthly,
+}
+
+impl ReportFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFrequency::Daily => "daily",
+            ReportFrequency::Weekly => "weekly",
+            ReportFrequency::Monthly => "monthly",
+        }
+    }
+}
+
+impl FromStr for ReportFrequency {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "daily" => Ok(ReportFrequency::Daily),
+            "weekly" => Ok(ReportFrequency::Weekly),
+            "monthly" => Ok(ReportFrequency::Monthly),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default, PartialEq, Validate)]
+pub struct ReportParameters {
+    /// Rows to include in `top_products`, default 10
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// Attach the report as a CSV file as well
+    #[serde(default)]
+    pub attach_csv: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ReportSubscription {
+    pub id: Uuid,
+    pub name: String,
+    pub report_type: ReportType,
+    pub parameters: ReportParameters,
+    pub frequency: ReportFrequency,
+    /// Hour of the day the report is sent at, 0-23 in the business timezone
+    pub hour: u32,
+    pub recipients: Vec<String>,
+    pub active: bool,
+    pub next_run_at: DateTime<Utc>,
+    pub last_sent_at: Option<DateTime<Utc>>,
+    /// Why the last attempt failed; cleared once a send succeeds
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct ReportSubscriptionInput {
+    #[validate(length(min = 1, max = 200, message = "must be between 1 and 200 characters"))]
+    pub name: String,
+    pub report_type: ReportType,
+    #[serde(default)]
+    #[validate(nested)]
+    pub parameters: ReportParameters,
+    pub frequency: ReportFrequency,
+    #[validate(range(max = 23, message = "must be between 0 and 23"))]
+    pub hour: u32,
+    #[validate(
+        length(min = 1, max = 50, message = "must contain between 1 and 50 addresses"),
+        custom(function = "email_list")
+    )]
+    pub recipients: Vec<String>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+}
+
+/// A till session: a register opened with a float of cash and closed with
+/// the cash counted in the drawer.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Shift {
+    pub id: Uuid,
+    /// Name of the till, e.g. `Till 1`; one open shift per till and branch
+    pub register: String,
+    pub company_branch: String,
+    pub opened_by: Uuid,
+    pub opened_at: DateTime<Utc>,
+    pub opening_float_cents: i64,
+    pub closed_by: Option<Uuid>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub counted_cash_cents: Option<i64>,
+    pub note: Option<String>,
+}
+
+impl Shift {
+    pub fn is_open(&self) -> bool {
+        self.closed_at.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct ShiftInput {
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
+    pub register: String,
+    #[serde(default)]
+    pub company_branch: String,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub opening_float_cents: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct ShiftCloseInput {
+    /// Cash counted in the drawer at close
+    #[validate(range(min = 0, message = "must not be negative"))]
+    pub counted_cash_cents: i64,
+    #[validate(length(max = 1000, message = "must be at most 1000 characters"))]
+    pub note: Option<String>,
+}
+
+/// Cash put into or taken out of the drawer other than by a sale.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CashMovementKind {
+    /// Cash added, e.g. more change
+    CashIn,
+    /// Cash paid out of the drawer for an expense
+    Payout,
+    /// Cash taken to the safe or the bank
+    Drop,
+}
+
+impl CashMovementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CashMovementKind::CashIn => "cash_in",
+            CashMovementKind::Payout => "payout",
+            CashMovementKind::Drop => "drop",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            CashMovementKind::CashIn => "Cash in",
+            CashMovementKind::Payout => "Payout",
+            CashMovementKind::Drop => "Drop",
+        }
+    }
+}
+
+impl FromStr for CashMovementKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cash_in" => Ok(CashMovementKind::CashIn),
+            "payout" => Ok(CashMovementKind::Payout),
+            "drop" => Ok(CashMovementKind::Drop),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct CashMovement {
+    pub id: Uuid,
+    pub shift_id: Uuid,
+    pub kind: CashMovementKind,
+    /// Always positive; `kind` says which way the cash went
+    pub amount_cents: i64,
+    pub reason: String,
+    pub recorded_by: Uuid,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Validate)]
+pub struct CashMovementInput {
+    pub kind: CashMovementKind,
+    #[validate(range(min = 1, message = "must be positive"))]
+    pub amount_cents: i64,
+    #[validate(length(min = 1, max = 500, message = "must be between 1 and 500 characters"))]
+    pub reason: String,
+}
+
+/// X reports are read while the shift is open and change as it goes on;
+/// the Z report is taken when the shift closes and never changes.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShiftReportKind {
+    X,
+    Z,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct TenderTotal {
+    pub payment_method: PaymentMethod,
+    pub count: i64,
+    pub total_cents: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ChannelTotal {
+    pub sales_channel: SalesChannel,
+    pub count: i64,
+    pub total_cents: i64,
+}
+
+/// What went through a till over a shift. Sales count at the amount taken;
+/// items voided in the shift are subtracted as voids when their sale was
+/// rung up in the same shift and as refunds otherwise.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ShiftReport {
+    pub kind: ShiftReportKind,
+    pub shift: Shift,
+    pub generated_at: DateTime<Utc>,
+    pub sales_count: i64,
+    pub gross_sales_cents: i64,
+    pub discount_cents: i64,
+    pub by_tender: Vec<TenderTotal>,
+    pub by_channel: Vec<ChannelTotal>,
+    pub refunds_count: i64,
+    pub refunds_cents: i64,
+    pub voids_count: i64,
+    pub voids_cents: i64,
+    /// Gross sales less refunds and voids
+    pub net_sales_cents: i64,
+    pub opening_float_cents: i64,
+    pub cash_sales_cents: i64,
+    /// Refunds and voids of cash sales, paid back from the drawer
+    pub cash_returned_cents: i64,
+    pub cash_in_cents: i64,
+    pub payouts_cents: i64,
+    pub drops_cents: i64,
+    /// Float plus cash sales and cash in, less cash returned, payouts and drops
+    pub expected_cash_cents: i64,
+    /// Only on the Z report
+    pub counted_cash_cents: Option<i64>,
+    /// Counted less expected: positive when the drawer is over
+    pub over_short_cents: Option<i64>,
+    pub cash_movements: Vec<CashMovement>,
+}
+
+/// A copy of the SQLite database in the server's backup directory.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Backup {
+    /// Name of the file in the backup directory, e.g.
+    /// `fastsales-20261018T230000123Z.db`
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct OrphanCount {
+    /// The reference checked, e.g. `sale_items.product_id -> products.id`
+    pub reference: String,
+    pub orphans: i64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct IntegrityReport {
+    /// Whether both checks came out clean
+    pub ok: bool,
+    /// What SQLite's `PRAGMA integrity_check` found wrong with the database
+    /// file; always empty on PostgreSQL
+    pub storage_errors: Vec<String>,
+    /// Rows whose foreign keys point nowhere
+    pub orphans: Vec<OrphanCount>,
 }
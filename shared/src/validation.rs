@@ -0,0 +1,315 @@
+//! Custom rules used by the `#[validate(...)]` attributes in [`crate::models`],
+//! and conversion of validation failures into the API error body.
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use validator::{ValidationError, ValidationErrorsKind};
+
+pub use validator::{Validate, ValidationErrors};
+
+use crate::models::{ApiError, ErrorCode, FieldError};
+
+pub fn not_in_future(date: &NaiveDate) -> Result<(), ValidationError> {
+    if *date > Utc::now().date_naive() {
+        return Err(ValidationError::new("not_in_future")
+            .with_message(Cow::Borrowed("must not be in the future")));
+    }
+    Ok(())
+}
+
+pub fn not_in_past(instant: &DateTime<Utc>) -> Result<(), ValidationError> {
+    if *instant < Utc::now() {
+        return Err(ValidationError::new("not_in_past")
+            .with_message(Cow::Borrowed("must not be in the past")));
+    }
+    Ok(())
+}
+
+/// Accepts digits with optional `+`, spaces, dashes and parentheses, and at
+/// least seven digits overall.
+pub fn phone_number(value: &str) -> Result<(), ValidationError> {
+    let allowed = value
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')'));
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    if !allowed || !(7..=15).contains(&digits) {
+        return Err(ValidationError::new("phone_number")
+            .with_message(Cow::Borrowed("must be a valid phone number")));
+    }
+    Ok(())
+}
+
+/// Accepts absolute `http` and `https` URLs with a host, unless the host is
+/// `localhost` or an address [`is_public_address`] rejects. Names are not
+/// resolved here; the server checks what they resolve to before using them.
+pub fn http_url(value: &str) -> Result<(), ValidationError> {
+    let Some(host) = url_host(value).filter(|_| !value.chars().any(char::is_whitespace)) else {
+        return Err(ValidationError::new("http_url")
+            .with_message(Cow::Borrowed("must be an http or https URL")));
+    };
+    let local = host == "localhost"
+        || host.ends_with(".localhost")
+        || host.parse::<IpAddr>().is_ok_and(|address| !is_public_address(address));
+    if local {
+        return Err(ValidationError::new("http_url")
+            .with_message(Cow::Borrowed("must not point to a local or private network address")));
+    }
+    Ok(())
+}
+
+/// The lowercased host of an `http` or `https` URL, without brackets around
+/// IPv6 addresses or a trailing dot, or `None` if there is none.
+pub fn url_host(value: &str) -> Option<String> {
+    let rest = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0,
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// False for loopback, private, link-local, shared, unspecified, multicast,
+/// documentation and reserved addresses, including IPv4 ones written as IPv6.
+pub fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                || v4.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && v6.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+/// Accepts a list of plain `local@domain.tld` addresses, without display
+/// names.
+pub fn email_list(values: &[String]) -> Result<(), ValidationError> {
+    let valid = values.iter().all(|value| {
+        let Some((local, domain)) = value.split_once('@') else {
+            return false;
+        };
+        !local.is_empty()
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+            && !domain.contains('@')
+            && !value.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ',' | ';'))
+    });
+    if !valid {
+        return Err(ValidationError::new("email_list")
+            .with_message(Cow::Borrowed("must be valid email addresses")));
+    }
+    Ok(())
+}
+
+/// Flattens nested validation errors into `field` paths such as
+/// `sale_items[0].quantity`, sorted for stable output.
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut out = Vec::new();
+    collect(errors, "", &mut out);
+    out.sort_by(|a, b| a.field.cmp(&b.field));
+    out
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(list) => {
+                out.extend(list.iter().map(|error| FieldError {
+                    field: path.clone(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(|message| message.to_string())
+                        .unwrap_or_else(|| format!("is invalid ({})", error.code)),
+                }));
+            }
+            ValidationErrorsKind::Struct(inner) => collect(inner, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, inner) in items {
+                    collect(inner, &format!("{}[{}]", path, index), out);
+                }
+            }
+        }
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        ApiError {
+            code: ErrorCode::ValidationFailed,
+            message: "Request validation failed".to_string(),
+            details: field_errors(&errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        CustomerInput, PaymentMethod, ProductInput, ProductType, SaleInput, SaleItemInput, SalesChannel,
+    };
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    fn fields(errors: ValidationErrors) -> Vec<String> {
+        field_errors(&errors).into_iter().map(|error| error.field).collect()
+    }
+
+    fn sale_item(quantity: i64) -> SaleItemInput {
+        SaleItemInput {
+            sale_id: None,
+            product_id: Uuid::new_v4(),
+            customer_id: None,
+            date_of_sale: Utc::now(),
+            quantity,
+            discount: 0,
+            total_cents: 100,
+            total_resolved: 100,
+            note: None,
+            sales_channel: None,
+        }
+    }
+
+    #[test]
+    fn products_need_a_name_and_no_negative_amounts() {
+        let mut product = ProductInput {
+            name: "Tea".to_string(),
+            description: String::new(),
+            price_cents: 300,
+            cost_price_cents: 150,
+            stock: 0,
+            product_type: ProductType::PhysicalGood,
+            reorder_point: 0,
+            reorder_quantity: 0,
+            details: vec![],
+        };
+        assert!(product.validate().is_ok());
+
+        product.name = String::new();
+        product.price_cents = -1;
+        product.stock = -5;
+        assert_eq!(fields(product.validate().unwrap_err()), ["name", "price_cents", "stock"]);
+    }
+
+    #[test]
+    fn customers_need_an_email_a_phone_number_and_a_past_birthday() {
+        let mut customer = CustomerInput {
+            first_name: "Ada".to_string(),
+            last_name: "Lovelace".to_string(),
+            middle_name: None,
+            mobile_number: "+44 (0)20 7946-0000".to_string(),
+            date_of_birth: NaiveDate::from_ymd_opt(1815, 12, 10).unwrap(),
+            email: "ada@example.com".to_string(),
+            details: vec![],
+        };
+        assert!(customer.validate().is_ok());
+
+        customer.mobile_number = "12345".to_string();
+        customer.email = "ada.example.com".to_string();
+        customer.date_of_birth = Utc::now().date_naive() + Duration::days(1);
+        let errors = field_errors(&customer.validate().unwrap_err());
+        let fields: Vec<_> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, ["date_of_birth", "email", "mobile_number"]);
+        assert_eq!(errors[0].message, "must not be in the future");
+    }
+
+    #[test]
+    fn sale_errors_name_the_item() {
+        let sale = SaleInput {
+            customer_id: None,
+            date_and_time: Utc::now(),
+            sale_items: vec![sale_item(1), sale_item(0)],
+            total_cents: 200,
+            discount: -1,
+            total_resolved: 200,
+            sales_channel: SalesChannel::Mobile,
+            staff_responsible: Uuid::new_v4(),
+            company_branch: String::new(),
+            car_number: String::new(),
+            receipt_number: "R-1".to_string(),
+            payment_method: PaymentMethod::default(),
+            shift_id: None,
+        };
+        assert_eq!(fields(sale.validate().unwrap_err()), ["discount", "sale_items[1].quantity"]);
+
+        let empty = SaleInput { sale_items: vec![], discount: 0, ..sale };
+        assert_eq!(fields(empty.validate().unwrap_err()), ["sale_items"]);
+    }
+
+    #[test]
+    fn phone_numbers_are_seven_to_fifteen_digits() {
+        assert!(phone_number("555 0100").is_ok());
+        assert!(phone_number("+1-555-0100").is_ok());
+        assert!(phone_number("555-01").is_err());
+        assert!(phone_number("1234567890123456").is_err());
+        assert!(phone_number("call 5550100").is_err());
+    }
+
+    #[test]
+    fn email_lists_reject_display_names_and_separators() {
+        assert!(email_list(&["a@example.com".to_string(), "b@shop.co.uk".to_string()]).is_ok());
+        assert!(email_list(&["Ada <a@example.com>".to_string()]).is_err());
+        assert!(email_list(&["a@example.com, b@example.com".to_string()]).is_err());
+        assert!(email_list(&["a@localhost".to_string()]).is_err());
+    }
+
+    #[test]
+    fn webhook_urls_must_not_point_at_local_networks() {
+        assert!(http_url("https://hooks.example.com/fastsales?x=1").is_ok());
+        assert!(http_url("http://93.184.216.34:8080/").is_ok());
+        assert!(http_url("ftp://example.com").is_err());
+        for local in [
+            "http://localhost:3000/",
+            "http://api.LOCALHOST./",
+            "http://127.0.0.1/",
+            "http://user@10.1.2.3/",
+            "http://172.16.0.1/",
+            "http://192.168.1.10/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]:8080/",
+            "http://[fe80::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(http_url(local).is_err(), "{}", local);
+        }
+        assert_eq!(url_host("https://user:pw@[2001:db8::1]:443/x").as_deref(), Some("2001:db8::1"));
+    }
+}